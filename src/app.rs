@@ -1,33 +1,163 @@
-use std::borrow::Cow;
-use std::time::Duration;
-use arboard::{Clipboard, ImageData};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 use eframe::egui;
 use eframe::egui::vec2;
 use global_hotkey::{GlobalHotKeyManager, GlobalHotKeyEvent, HotKeyState};
 use global_hotkey::hotkey::{Code, HotKey, Modifiers};
 use image::{RgbaImage};
-use tray_icon::menu::{MenuEvent, MenuId};
-use tray_icon::{TrayIcon};
-use std::sync::mpsc::{channel, Receiver};
+use tray_icon::menu::MenuEvent;
+use std::sync::mpsc::{channel, Receiver, Sender};
 #[allow(unused_imports)]
 use rayon::prelude::*;
 
-use crate::config::AppConfig;
+#[cfg(target_os = "windows")]
+use crate::config::ShutterWithOtherAudio;
+use crate::config::{AfterCaptureFromSettings, AlignmentGuide, AppConfig, CAPTURE_SCALE_RANGE, ChordAction, ClipboardTarget, CloseButtonAction, CollisionPolicy, ColorManagement, FilterKind, GpuPowerPreference, LongPathStrategy, MontageAlign, PngFilter, Profile, RecaptureHotkeyBehavior, RegionPreset, SelectionStyle, WindowTransparency};
 use crate::utils;
+use crate::color::{self, ColorPart};
 use crate::audio::SoundEngine;
-use crate::capture::MonitorData;
+use crate::capture::{CaptureError, CaptureGeometry, MonitorData, TopmostWindow};
+use crate::cursor::HiddenCursor;
+use crate::editor::{Editor, Tool, QUICK_FILTERS};
+use crate::stats::{self, CaptureMode, CaptureStats};
+use crate::montage::MontageSettings;
+use crate::filters::FilterPipeline;
+use crate::pipeline::{CaptureJob, PipelineReport, PipelineWorker};
+use crate::import::{self, ImportedSettings};
+use crate::interval::{IntervalCapture, IntervalEvent, IntervalPlan};
+use crate::tray::{MenuEntries, TrayCommand, TrayIconKind, TrayLink, TrayMenuIds};
+use crate::state::{AppState, Effect, StateMachine};
 
+/// Which configurable hotkey the settings page is currently recording.
 #[derive(Clone, Copy, Debug, PartialEq)]
-enum AppState {
-    Idle,
-    Snapping,
-    Config,
+enum HotkeyTarget {
+    Snap,
+    Annotate,
+    ExternalEdit,
+    Panic,
+    Preset(usize),
+    /// Switches to the next capture profile.
+    NextProfile,
+    /// First step of the two-step shortcut.
+    Chord,
 }
 
+/// The chord hotkey was pressed: its follow-up keys are registered until one is pressed, Escape
+/// cancels or the time runs out.
+struct ChordArmed {
+    started: Instant,
+    // None = Escape
+    followers: Vec<(HotKey, Option<ChordAction>)>,
+}
+
+/// The window properties last sent to the viewport, so unchanged values aren't sent again.
+/// On Windows every OuterPosition/InnerSize is a SetWindowPos, and a stream of them (one per
+/// idle frame) fights with the compositor and makes a ghost of the window flicker.
+#[derive(Default)]
+struct ViewportState {
+    position: Option<egui::Pos2>,
+    size: Option<egui::Vec2>,
+    decorations: Option<bool>,
+    transparent: Option<bool>,
+    visible: Option<bool>,
+}
+
+impl ViewportState {
+    fn set_position(&mut self, ctx: &egui::Context, position: egui::Pos2) {
+        if self.position.replace(position) != Some(position) {
+            ctx.send_viewport_cmd(egui::ViewportCommand::OuterPosition(position));
+        }
+    }
+
+    fn set_size(&mut self, ctx: &egui::Context, size: egui::Vec2) {
+        if self.size.replace(size) != Some(size) {
+            ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(size));
+        }
+    }
+
+    fn set_decorations(&mut self, ctx: &egui::Context, decorations: bool) {
+        if self.decorations.replace(decorations) != Some(decorations) {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Decorations(decorations));
+        }
+    }
+
+    fn set_transparent(&mut self, ctx: &egui::Context, transparent: bool) {
+        if self.transparent.replace(transparent) != Some(transparent) {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Transparent(transparent));
+        }
+    }
+
+    fn set_visible(&mut self, ctx: &egui::Context, visible: bool) {
+        if self.visible.replace(visible) != Some(visible) {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Visible(visible));
+        }
+    }
+
+    /// Position and size are sent again next time, for when they may have changed without us
+    /// (the user moving the settings window, the OS moving windows off a removed monitor).
+    fn forget_geometry(&mut self) {
+        self.position = None;
+        self.size = None;
+    }
+}
+
+/// A released selection flashing white before it is captured.
+#[derive(Clone, Copy)]
+struct CaptureFlash {
+    started: Instant,
+    rect: egui::Rect,
+    window_size: egui::Vec2,
+}
+
+/// A released selection above `confirm_captures_above_mp`, waiting for Save / Save downscaled / Cancel.
+#[derive(Clone, Copy)]
+struct LargeCapturePrompt {
+    rect: egui::Rect,
+    window_size: egui::Vec2,
+    // Of the capture as it would be saved (with capture_scale)
+    megapixels: f32,
+    // capture_scale that fits it into downscaled_max_dimension
+    downscaled_scale: f32,
+}
+
+/// How long the post-capture action bar waits before committing the default action.
+const ACTION_BAR_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long the note prompt may sit idle before the capture is saved without a note.
+const NOTE_INPUT_TIMEOUT: Duration = Duration::from_secs(30);
+/// How often the monitor layout is re-probed while idle.
+const LAYOUT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// Minimum time between tray tooltip changes, so the shell isn't bothered every frame.
+const TRAY_TOOLTIP_INTERVAL: Duration = Duration::from_secs(1);
+/// How long other programs are turned down around the shutter sound.
+#[cfg(target_os = "windows")]
+const SHUTTER_DUCK_DURATION: Duration = Duration::from_millis(400);
+/// How long the tray shows the error icon after a failed capture.
+const TRAY_ERROR_DURATION: Duration = Duration::from_secs(4);
+/// Failed captures in a row after which the settings open with a diagnostic banner.
+const FAILURES_BEFORE_SETTINGS: u32 = 3;
+/// How long a capture waits for the pre-capture command.
+const PRE_CAPTURE_COMMAND_TIMEOUT: Duration = Duration::from_millis(500);
+/// How long the selection flashes white before the overlay hides (see `capture_flash`).
+const CAPTURE_FLASH_DURATION: Duration = Duration::from_millis(100);
+/// How long the overlay is hidden before the screens are captured again (the compositor has
+/// to take it off the screen first).
+const RECAPTURE_DELAY: Duration = Duration::from_millis(150);
+/// How long quitting waits for captures that are still being saved.
+const EXIT_FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long the chord waits for its second key.
+const CHORD_TIMEOUT: Duration = Duration::from_millis(1500);
+/// How long "Identify Displays" shows the monitor numbers.
+const IDENTIFY_DISPLAYS_DURATION: Duration = Duration::from_secs(2);
+/// Longest side of the editor's filter preview. Filters re-run on it at every click, the
+/// capture itself is filtered once when the editor is done.
+const EDITOR_PREVIEW_SIZE: u32 = 1024;
+/// Region the settings' Test Capture grabs from the middle of the monitor (physical px).
+const TEST_CAPTURE_SIZE: (u32, u32) = (320, 200);
+
 pub struct CrabGrabApp {
-    state: AppState,
-    previous_state: AppState,
-    restore_rect: Option<egui::Rect>, // Stores position/size of settings window
+    machine: StateMachine,
+    restore_rect: Option<egui::Rect>, // Stores position/size of settings window (physical px)
 
     hotkey_manager: GlobalHotKeyManager,
     cancel_hotkey: HotKey,
@@ -36,50 +166,197 @@ pub struct CrabGrabApp {
 
     raw_image: Option<RgbaImage>,
     tiles: Option<Vec<(egui::Rect, egui::TextureHandle)>>,
+    // Decoded tiles still waiting for upload, moved into `tiles` a few per frame
+    pending_tiles: VecDeque<(egui::Rect, egui::ColorImage)>,
+    pending_tiles_total: usize,
     monitor_layout: Vec<egui::Rect>,
-    start_pos: Option<egui::Pos2>,
-    current_pos: Option<egui::Pos2>,
+    // Where the snapping tools (grid toggle) were drawn last frame; presses there don't start a selection
+    snapping_tools_rect: Option<egui::Rect>,
+    // Same for the shortcut hints bar
+    hints_rect: Option<egui::Rect>,
+    // Other programs' always-on-top windows at capture time (physical px)
+    topmost_windows: Vec<TopmostWindow>,
+    // The released selection while it flashes, it is captured once the flash is over
+    capture_flash: Option<CaptureFlash>,
+    // The capture hotkey was pressed again while selecting: the screens are captured again
+    // RECAPTURE_DELAY after the press, once the hidden overlay is off the screen
+    recapture_at: Option<Instant>,
+    // The released selection is too large, captured once the prompt is answered
+    large_capture_prompt: Option<LargeCapturePrompt>,
+    // Replaces capture_scale for the pending capture ("Save downscaled")
+    pending_capture_scale: Option<f32>,
     virtual_origin: (f32, f32),
+    // Scale factor of the monitor at virtual_origin, plus when the layout was last probed
+    origin_scale: f32,
+    layout_checked_at: Instant,
     physical_origin: (i32, i32),
+    physical_size: (u32, u32),
 
     // Store predicted PPI and last captured monitor data so we can re-build
     // tiles/hitboxes once the window's actual pixels_per_point is available.
     predicted_ppi: f32,
+    // The first Snapping frame of a capture compares (and logs) predicted vs actual PPI
+    ppi_checked: bool,
     last_monitors: Option<Vec<MonitorData>>,
 
-    quit_id: MenuId,
-    settings_id: MenuId,
-    capture_id: MenuId,
+    // Set when a capture is triggered, cleared once the overlay is shown (timing logs only)
+    capture_triggered_at: Option<Instant>,
+    // While Some(n), the overlay is hidden and still has n frames to render before it is shown.
+    // This makes sure the first visible frame already contains the dimmed tiles (no white flash).
+    hidden_overlay_frames: Option<u8>,
+    // Keeps the system cursor hidden while snapping (when enabled); dropping it restores the cursor
+    hidden_cursor: Option<HiddenCursor>,
+
+    // Post-capture action bar: the cropped image waits here until the user picks an action
+    pending_capture: Option<RgbaImage>,
+    // Monitor (and color profile) behind each part of the pending capture, empty with color management off
+    pending_color_parts: Vec<ColorPart>,
+    // The pending capture's selection, formatted for the Coordinates clipboard target
+    pending_coordinates: Option<String>,
+    action_bar_timeout: Instant,
+    pending_note: String,
+    note_timeout: Instant,
+    // Set by the annotate hotkey: the next finished selection opens the editor
+    annotate_requested: bool,
+    // Set by the "Capture & Edit" hotkey/tray item: the selection goes to an external editor
+    external_edit_requested: bool,
+    // The note prompt was already shown for the pending capture (editor opened from a later step)
+    note_prompted: bool,
+    editor: Option<Editor>,
+    // The capture with the editor's filters applied (downscaled), for the filters it was made with
+    editor_filter_preview: Option<(Vec<FilterKind>, egui::TextureHandle)>,
+    // Set by "Pick Region" in settings: the next selection defines this region preset
+    picking_preset: Option<usize>,
+    // Set by "Show alignment test" in settings: the overlay shows test patterns instead of a capture
+    alignment_test: bool,
+    // Uploaded when entering the preview screen, dropped when leaving it
+    preview_texture: Option<egui::TextureHandle>,
+    checker_texture: Option<egui::TextureHandle>,
 
-    _tray_handle: Option<TrayIcon>,
+    menu_ids: TrayMenuIds,
+
+    tray: Option<TrayLink>,
+    // What the tray tooltip shows now and when it was last changed (see update_tray_tooltip)
+    tray_tooltip: String,
+    tray_tooltip_sent_at: Instant,
+    // Hotkeys and tray clicks, forwarded by event handlers that also wake the event loop
+    // With when the event came in, the capture timings start there
+    hotkey_events: Receiver<(Instant, GlobalHotKeyEvent)>,
+    menu_events: Receiver<(Instant, MenuEvent)>,
+    // Energy saver: when the app went idle, and whether it stopped repainting since
+    idle_since: Instant,
+    deep_sleep: bool,
+    // Set while the tray shows the error icon
+    tray_error_until: Option<Instant>,
+    // The icon the tray shows right now
+    tray_icon_kind: TrayIconKind,
+    // Failed captures in a row, and the banner the settings page shows once there are too many
+    capture_failures: u32,
+    capture_failure_banner: Option<String>,
+    // Monitor layout shown by "Identify Displays" and when it started
+    identify_displays: Option<(CaptureGeometry, Instant)>,
+    // Settings found by "Import from other tools", shown for confirmation while Some
+    import_candidates: Option<Vec<ImportedSettings>>,
+    // What "Clear all app data" is about to delete, shown for confirmation while Some
+    clear_data_paths: Option<Vec<PathBuf>>,
+    // Set once quitting started, so the close request isn't turned into "close settings"
+    quitting: bool,
+    chord_armed: Option<ChordArmed>,
+    // Regions the interval capture dialog offers; Some while it is open
+    interval_regions: Option<Vec<(String, RegionPreset)>>,
+    interval_region: usize,
+    interval_capture: Option<IntervalCapture>,
+    // Some while a montage runs: captures stacked onto it so far (the canvas is on the pipeline worker)
+    montage_captures: Option<u32>,
+    // The last selection, monitor-relative like a preset (for interval capture)
+    last_region: Option<RegionPreset>,
+    last_capture_at: Option<chrono::DateTime<chrono::Local>>,
 
     config: AppConfig,
-    is_recording_hotkey: bool,
-    file_picker_receiver: Option<Receiver<String>>,
+    // The config as it was last written, saving is skipped while nothing differs from it
+    saved_config: AppConfig,
+    // Snapshot for the settings page, reloaded whenever it opens
+    stats: CaptureStats,
+    recording_hotkey: Option<HotkeyTarget>,
+    // Set while a folder dialog is open, None arrives when it was cancelled
+    file_picker_receiver: Option<Receiver<Option<String>>>,
+    log_picker_receiver: Option<Receiver<Option<String>>>,
+    // With how long grabbing the pixels took, the pipeline reports the rest
+    test_capture_receiver: Option<(Receiver<PipelineReport>, Duration)>,
+    // Err when the test capture didn't make it to the pipeline's end
+    test_capture_result: Option<Result<PipelineReport, String>>,
+    pipeline: PipelineWorker,
     sound_engine: SoundEngine,
     cursor_texture: Option<egui::TextureHandle>,
+    // False without a compositor: the window stays opaque, the overlay is the captured screen anyway
+    transparent_window: bool,
+    // Window changes go through it, see show_overlay, hide_overlay and show_settings
+    viewport: ViewportState,
+    // Our native window, to hide it to the tray while idle
+    #[cfg(target_os = "windows")]
+    hwnd: Option<isize>,
 }
 
 impl CrabGrabApp {
     pub fn new(
         cc: &eframe::CreationContext,
-        tray_handle: Option<TrayIcon>,
-        quit_id: MenuId,
-        settings_id: MenuId,
-        capture_id: MenuId) -> Self {
+        tray: Option<TrayLink>,
+        menu_ids: TrayMenuIds) -> Self {
         let loaded_config = AppConfig::load();
 
+        // Files handed to an external editor last time are not needed anymore
+        if loaded_config.secure_delete_temp_files {
+            log::warn!("Secure deletion of temp files is on. On SSDs overwriting may not reach the old data (wear leveling).");
+        }
+        utils::clean_external_edit_dir(loaded_config.secure_delete_temp_files);
+
+        // The handlers wake the event loop, so hotkeys and the tray work without polling for
+        // them (see deep sleep). They have to be set before the first event arrives.
+        let (hotkey_sender, hotkey_events) = channel();
+        let repaint_ctx = cc.egui_ctx.clone();
+        GlobalHotKeyEvent::set_event_handler(Some(move |event| {
+            let _ = hotkey_sender.send((Instant::now(), event));
+            repaint_ctx.request_repaint();
+        }));
+        let (menu_sender, menu_events) = channel();
+        let repaint_ctx = cc.egui_ctx.clone();
+        MenuEvent::set_event_handler(Some(move |event| {
+            let _ = menu_sender.send((Instant::now(), event));
+            repaint_ctx.request_repaint();
+        }));
+
+        let repaint_ctx = cc.egui_ctx.clone();
+        let pipeline = PipelineWorker::new(move || repaint_ctx.request_repaint());
+
         let hotkey_manager = GlobalHotKeyManager::new().unwrap();
         let cancel_hotkey = HotKey::new(None, Code::Escape);
         let settings_hotkey = HotKey::new(Some(Modifiers::CONTROL | Modifiers::SHIFT), Code::KeyS);
 
-        for hk in [loaded_config.snap_hotkey, settings_hotkey] {
+        let preset_hotkeys = loaded_config.region_presets.iter().filter_map(RegionPreset::hotkey);
+        let main_hotkeys = [
+            loaded_config.snap_hotkey,
+            loaded_config.annotate_hotkey,
+            loaded_config.external_edit_hotkey,
+            loaded_config.panic_hotkey,
+            settings_hotkey,
+        ];
+        for hk in main_hotkeys.into_iter().chain(preset_hotkeys).chain(loaded_config.profile_hotkey()).chain(loaded_config.chord_hotkey()) {
             match hotkey_manager.register(hk) {
                 Ok(_) => log::info!("Hotkey registered: {:?}", hk),
                 Err(e) => log::error!("Failed to register hotkey {:?}: {:?}", hk, e),
             }
         }
 
+        // The menu starts without profiles and save folders, see build_menu
+        if let Some(tray) = &tray
+            && (!loaded_config.profiles.is_empty() || !loaded_config.recent_save_directories.is_empty())
+        {
+            tray.send(TrayCommand::RebuildMenu(MenuEntries::from_config(&loaded_config)));
+        }
+
+        // Same as main decided for the window, a changed setting applies after a restart
+        let transparent_window = loaded_config.window_transparency.enabled();
+
         let cursor_texture = {
             // 1. Load the bytes (Compile-time asset)
             // Make sure 'assets/cursor.png' exists!
@@ -110,20 +387,17 @@ impl CrabGrabApp {
             }
         };
 
-        let (virtual_origin, _) = if let Ok(data) = crate::capture::capture_all_screens() {
+        let mut viewport = ViewportState::default();
+        // Geometry only: the pixels are not needed for the DPI handshake
+        let (virtual_origin, origin_scale) = if let Ok(data) = crate::capture::capture_geometry_only() {
             log::debug!("Warmup: Detected Origin at ({}, {}) with Scale {}",
             data.logical_origin.0, data.logical_origin.1, data.origin_scale_factor);
 
-            // 2. Move the hidden window to that monitor immediately.
-            // This forces Egui/Windows to handshake on the DPI (1.5) right now.
-            cc.egui_ctx.send_viewport_cmd(egui::ViewportCommand::OuterPosition(
-                egui::pos2(data.logical_origin.0, data.logical_origin.1)
-            ));
-
-            // 3. Set a tiny non-zero size so the OS actually processes the move
-            cc.egui_ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(
-                egui::vec2(1.0, 1.0)
-            ));
+            if loaded_config.dpi_warmup {
+                Self::dpi_handshake(&cc.egui_ctx, &mut viewport, data.logical_origin);
+            } else {
+                log::debug!("Warmup: window move disabled, the first capture relies on the geometry alone.");
+            }
 
             (data.logical_origin, data.origin_scale_factor)
         } else {
@@ -134,101 +408,519 @@ impl CrabGrabApp {
             raw_image: None,
             tiles: None,
             monitor_layout: Vec::new(),
-            start_pos: None,
-            current_pos: None,
-            state: AppState::Idle,
+            snapping_tools_rect: None,
+            hints_rect: None,
+            topmost_windows: Vec::new(),
+            capture_flash: None,
+            recapture_at: None,
+            large_capture_prompt: None,
+            pending_capture_scale: None,
+            machine: StateMachine::default(),
             hotkey_manager,
             virtual_origin,
+            origin_scale,
+            layout_checked_at: Instant::now(),
             physical_origin: (0, 0),
+            physical_size: (0, 0),
             predicted_ppi: 1.0,
+            ppi_checked: false,
             last_monitors: None,
+            capture_triggered_at: None,
+            hidden_overlay_frames: None,
+            pending_tiles: VecDeque::new(),
+            pending_tiles_total: 0,
+            hidden_cursor: None,
+            pending_capture: None,
+            pending_color_parts: Vec::new(),
+            pending_coordinates: None,
+            action_bar_timeout: Instant::now(),
+            pending_note: String::new(),
+            note_timeout: Instant::now(),
+            annotate_requested: false,
+            external_edit_requested: false,
+            note_prompted: false,
+            editor: None,
+            editor_filter_preview: None,
+            picking_preset: None,
+            alignment_test: false,
+            preview_texture: None,
+            checker_texture: None,
             cancel_hotkey,
             cancel_registered: false,
             settings_hotkey,
-            _tray_handle: tray_handle,
-            quit_id,
-            settings_id,
-            capture_id,
+            tray,
+            tray_tooltip: "Crab Grab".to_string(),
+            tray_tooltip_sent_at: Instant::now(),
+            hotkey_events,
+            menu_events,
+            idle_since: Instant::now(),
+            deep_sleep: false,
+            tray_error_until: None,
+            tray_icon_kind: TrayIconKind::Normal,
+            capture_failures: 0,
+            capture_failure_banner: None,
+            identify_displays: None,
+            import_candidates: None,
+            clear_data_paths: None,
+            quitting: false,
+            chord_armed: None,
+            interval_regions: None,
+            interval_region: 0,
+            interval_capture: None,
+            montage_captures: None,
+            last_region: None,
+            last_capture_at: None,
+            menu_ids,
+            saved_config: loaded_config.clone(),
             config: loaded_config,
-            is_recording_hotkey: false,
-            previous_state: AppState::Idle,
+            stats: CaptureStats::default(),
+            recording_hotkey: None,
             restore_rect: None,
             file_picker_receiver: None,
+            log_picker_receiver: None,
+            test_capture_receiver: None,
+            test_capture_result: None,
+            pipeline,
             sound_engine: SoundEngine::new(),
             cursor_texture,
+            transparent_window,
+            viewport,
+            #[cfg(target_os = "windows")]
+            hwnd: native_window_handle(cc),
         }
     }
 
-    fn handle_open_settings(&mut self, ctx: &egui::Context) {
-        log::debug!("Opening Settings Window...");
+    /// Moves the hidden window onto the origin monitor, so Egui/Windows negotiate that
+    /// monitor's DPI before the first capture needs it.
+    fn dpi_handshake(ctx: &egui::Context, viewport: &mut ViewportState, logical_origin: (f32, f32)) {
+        // 1. Move the hidden window to that monitor immediately.
+        // This forces Egui/Windows to handshake on the DPI (1.5) right now.
+        viewport.set_position(ctx, egui::pos2(logical_origin.0, logical_origin.1));
+
+        // 2. Set a tiny non-zero size so the OS actually processes the move
+        viewport.set_size(ctx, egui::vec2(1.0, 1.0));
+    }
+
+    /// Re-probes the monitor layout (geometry only) and redoes the DPI handshake when the
+    /// origin monitor moved or changed its scale. Returns true if a handshake was sent.
+    fn check_monitor_layout(&mut self, ctx: &egui::Context) -> bool {
+        if self.layout_checked_at.elapsed() < LAYOUT_POLL_INTERVAL {
+            return false;
+        }
+        self.layout_checked_at = Instant::now();
+        // Also re-sends where the idle window is parked now and then, in case the OS moved it
+        self.viewport.forget_geometry();
+
+        match crate::capture::capture_geometry_only() {
+            Ok(data) if self.origin_changed(data.logical_origin, data.origin_scale_factor) => {
+                log::info!("Monitor layout changed: origin ({}, {}) @ {} -> ({}, {}) @ {}. Redoing DPI handshake.",
+                    self.virtual_origin.0, self.virtual_origin.1, self.origin_scale,
+                    data.logical_origin.0, data.logical_origin.1, data.origin_scale_factor);
+                self.virtual_origin = data.logical_origin;
+                self.origin_scale = data.origin_scale_factor;
+                if !self.config.dpi_warmup {
+                    return false;
+                }
+                Self::dpi_handshake(ctx, &mut self.viewport, data.logical_origin);
+                true
+            }
+            Ok(_) => false,
+            Err(e) => {
+                log::warn!("Failed to probe monitor layout: {}", e);
+                false
+            }
+        }
+    }
 
-        self.state = AppState::Config;
+    fn origin_changed(&self, logical_origin: (f32, f32), scale: f32) -> bool {
+        (logical_origin.0 - self.virtual_origin.0).abs() > 0.5
+            || (logical_origin.1 - self.virtual_origin.1).abs() > 0.5
+            || (scale - self.origin_scale).abs() > 0.001
+    }
 
-        // Apply window settings
-        ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
-        ctx.send_viewport_cmd(egui::ViewportCommand::Decorations(true));
-        ctx.send_viewport_cmd(egui::ViewportCommand::Transparent(false));
+    fn handle_open_settings(&mut self, ctx: &egui::Context) {
+        log::debug!("Opening Settings Window...");
+        self.disarm_chord();
+        self.show_from_tray();
 
-        ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(vec2(600.0, 400.0)));
-        ctx.send_viewport_cmd(egui::ViewportCommand::OuterPosition(egui::pos2(100.0, 100.0)));
+        self.stats = CaptureStats::load();
+        let effects = self.machine.open_settings();
+        self.apply(ctx, effects);
     }
 
     fn handle_close_settings(&mut self, ctx: &egui::Context) {
         log::debug!("Closing Settings Window...");
+        let effects = self.machine.close_settings();
+        self.apply(ctx, effects);
+    }
 
-        self.state = AppState::Idle;
+    /// Carries out the side effects of a state transition.
+    fn apply(&mut self, ctx: &egui::Context, effects: Vec<Effect>) {
+        for effect in effects {
+            match effect {
+                Effect::RememberSettingsRect => {
+                    // Stored in physical pixels, because the overlay may move the window to a
+                    // monitor with a different scale factor before we restore it.
+                    if let Some(rect) = ctx.input(|i| i.viewport().outer_rect) {
+                        self.restore_rect = Some(rect * ctx.pixels_per_point());
+                    }
+                }
+                Effect::StoreSettingsRect => {
+                    // The size comes from the inner rect, because it is re-applied with
+                    // InnerSize (the outer rect would grow by the title bar every time).
+                    let (outer_rect, inner_rect) = ctx.input(|i| (i.viewport().outer_rect, i.viewport().inner_rect));
+                    if let (Some(outer), Some(inner)) = (outer_rect, inner_rect) {
+                        self.config.settings_last_x = outer.min.x;
+                        self.config.settings_last_y = outer.min.y;
+                        self.config.settings_last_w = inner.width();
+                        self.config.settings_last_h = inner.height();
+                    }
+                }
+                Effect::ShowSettings => {
+                    let position = egui::pos2(self.config.settings_last_x, self.config.settings_last_y);
+                    let size = vec2(self.config.settings_last_w, self.config.settings_last_h);
+                    self.show_settings(ctx, position, size);
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+                }
+                Effect::RestoreSettings => self.restore_settings_window(ctx),
+                Effect::HideWindow => self.hide_overlay(ctx),
+                Effect::SaveConfig => self.save_config(),
+                Effect::PlayActivationSound => {
+                    if self.config.play_sound {
+                        self.sound_engine.play_activation();
+                    }
+                }
+                Effect::ClearCaptureState => self.clear_capture_state(),
+            }
+        }
+    }
 
-        // Revert window settings
-        ctx.send_viewport_cmd(egui::ViewportCommand::Decorations(false));
-        ctx.send_viewport_cmd(egui::ViewportCommand::Transparent(true));
-        ctx.send_viewport_cmd(egui::ViewportCommand::OuterPosition(egui::pos2(10000.0, 10000.0)));
-        ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(vec2(0.0, 0.0)));
+    /// Writes the config, unless nothing changed since the last write (keeps the file, its
+    /// backup and its modification time untouched).
+    fn save_config(&mut self) {
+        if let Some(index) = self.config.active_profile
+            && !self.config.profiles.get(index).is_some_and(|profile| profile.matches(&self.config))
+        {
+            log::info!("Settings no longer match the active profile, no profile is active now.");
+            self.config.active_profile = None;
+            self.send_tray_menu();
+        }
+        if self.config == self.saved_config {
+            log::debug!("Config unchanged, not saving.");
+            return;
+        }
         self.config.save();
+        self.saved_config = self.config.clone();
+    }
+
+    /// Exits the app once the queued captures are written.
+    fn quit(&mut self, ctx: &egui::Context) {
+        self.flush_pipeline();
+        self.quitting = true;
+        ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+        self.save_config();
+    }
+
+    /// Toggles window transparency, unless the window can't be transparent at all (then the
+    /// command is skipped, some window managers flicker or misbehave on it).
+    fn set_window_transparent(&mut self, ctx: &egui::Context, transparent: bool) {
+        if self.transparent_window {
+            self.viewport.set_transparent(ctx, transparent);
+        }
+    }
+
+    /// Turns the window into the borderless overlay covering the given logical rect.
+    fn show_overlay(&mut self, ctx: &egui::Context, logical_origin: (f32, f32), logical_size: egui::Vec2) {
+        self.viewport.set_decorations(ctx, false);
+        self.set_window_transparent(ctx, true);
+        self.viewport.set_position(ctx, egui::pos2(logical_origin.0, logical_origin.1));
+        self.viewport.set_size(ctx, logical_size);
+    }
+
+    /// Makes the window the borderless, empty idle window and hides it.
+    fn hide_overlay(&mut self, ctx: &egui::Context) {
+        self.viewport.set_decorations(ctx, false);
+        self.set_window_transparent(ctx, true);
+        self.park_window(ctx);
+        self.hide_to_tray();
+    }
+
+    /// Moves the idle window off-screen with no size. Cheap to call every frame.
+    fn park_window(&mut self, ctx: &egui::Context) {
+        self.viewport.set_position(ctx, egui::pos2(10000.0, 10000.0));
+        self.viewport.set_size(ctx, vec2(0.0, 0.0));
+    }
+
+    /// Shows the window as the decorated settings window at the given logical rect.
+    fn show_settings(&mut self, ctx: &egui::Context, position: egui::Pos2, size: egui::Vec2) {
+        self.viewport.set_visible(ctx, true);
+        self.viewport.set_decorations(ctx, true);
+        self.set_window_transparent(ctx, false);
+        // Always sent and not remembered: the user moves and resizes the settings window
+        self.viewport.forget_geometry();
+        self.viewport.set_position(ctx, position);
+        self.viewport.set_size(ctx, size);
+        self.viewport.forget_geometry();
+    }
+
+    /// Removes the window from the screen and taskbar while idle. Elsewhere parking it
+    /// off-screen has to do.
+    fn hide_to_tray(&self) {
+        #[cfg(target_os = "windows")]
+        if let Some(hwnd) = self.hwnd {
+            utils::hide_window_to_tray(hwnd);
+        }
+    }
+
+    /// Brings the window back from `hide_to_tray` before it is used again.
+    fn show_from_tray(&self) {
+        #[cfg(target_os = "windows")]
+        if let Some(hwnd) = self.hwnd {
+            utils::show_window_from_tray(hwnd);
+        }
+    }
+
+    /// Keeps the tray tooltip in line with the hotkey, auto-save and the last capture time.
+    fn update_tray_tooltip(&mut self, ctx: &egui::Context) {
+        let Some(tray) = &self.tray else {
+            return;
+        };
+        let mut tooltip = format!(
+            "Crab Grab — {} — Auto-save {}",
+            utils::format_hotkey(&self.config.snap_hotkey),
+            if self.config.auto_save { "ON" } else { "OFF" },
+        );
+        if let Some(profile) = self.config.active_profile.and_then(|index| self.config.profiles.get(index)) {
+            tooltip.push_str(&format!(" — profile {}", profile.name));
+        }
+        if let Some(captured_at) = self.last_capture_at {
+            tooltip.push_str(&format!(" — last capture {}", captured_at.format("%H:%M")));
+        }
+        if let Some(interval) = &self.interval_capture {
+            tooltip.push_str(&format!(" — interval capture: {} shots", interval.count));
+        }
+        if let Some(count) = self.montage_captures {
+            tooltip.push_str(&format!(" — montage: {} captures", count));
+        }
+        if tooltip == self.tray_tooltip {
+            return;
+        }
+
+        let since_last = self.tray_tooltip_sent_at.elapsed();
+        if since_last < TRAY_TOOLTIP_INTERVAL {
+            // The app may go idle in between, make sure we come back for it
+            ctx.request_repaint_after(TRAY_TOOLTIP_INTERVAL - since_last);
+            return;
+        }
+        tray.send(TrayCommand::SetTooltip(tooltip.clone()));
+        self.tray_tooltip = tooltip;
+        self.tray_tooltip_sent_at = Instant::now();
+    }
+
+    /// Shows the error icon for a while after a failed capture, then the interval icon while
+    /// an interval capture runs, otherwise the normal one.
+    fn update_tray_icon(&mut self, ctx: &egui::Context) {
+        let Some(tray) = &self.tray else {
+            return;
+        };
+        let now = Instant::now();
+        let kind = match self.tray_error_until {
+            Some(until) if now < until => {
+                ctx.request_repaint_after(until - now);
+                TrayIconKind::Error
+            }
+            _ if self.interval_capture.is_some() => TrayIconKind::Interval,
+            _ => TrayIconKind::Normal,
+        };
+        if self.tray_error_until.is_some_and(|until| now >= until) {
+            self.tray_error_until = None;
+        }
+        if kind != self.tray_icon_kind {
+            tray.send(TrayCommand::SetIcon(kind));
+            self.tray_icon_kind = kind;
+        }
+    }
+
+    /// Tells the user a capture failed, so the hotkey doesn't just seem dead.
+    /// After a few failures in a row the settings open with a diagnostic banner instead of another dialog.
+    fn report_capture_failure(&mut self, ctx: &egui::Context, error: &CaptureError) {
+        log::error!("Capture failed: {}", error);
+        self.capture_failures += 1;
+        self.play_error_sound();
+
+        // Nothing works until the permission is granted, take the user right there
+        #[cfg(target_os = "macos")]
+        if matches!(error, CaptureError::PermissionDenied) && self.capture_failures == 1 {
+            utils::open_screen_recording_settings();
+        }
+
+        // Picked up by update_tray_icon
+        self.tray_error_until = Some(Instant::now() + TRAY_ERROR_DURATION);
+        ctx.request_repaint();
+
+        let message = utils::describe_capture_error(error);
+        if self.capture_failures >= FAILURES_BEFORE_SETTINGS {
+            self.capture_failure_banner = Some(format!("The last {} captures failed. {}", self.capture_failures, message));
+            if self.machine.state() != AppState::Config {
+                self.handle_open_settings(ctx);
+            }
+        } else {
+            utils::show_error_dialog("Capture failed", &utils::with_log_hint(&message));
+        }
     }
 
     /// Helper to handle system tray events (Right click menu, Left click toggle)
     fn handle_tray_events(&mut self, ctx: &egui::Context) {
         // 1. Drain Menu Events
         // (Menus don't usually spam, but it's good practice to limit them too)
-        while let Ok(event) = MenuEvent::receiver().try_recv() {
+        while let Ok((received_at, event)) = self.menu_events.try_recv() {
             log::debug!("MENU CLICK: {:?}", event.id);
             match event.id {
-                _ if event.id == self.quit_id => {
-                    ctx.send_viewport_cmd(egui::ViewportCommand::Close);
-                    self.config.save();
+                _ if event.id == self.menu_ids.quit => self.quit(ctx),
+                _ if event.id == self.menu_ids.settings => self.handle_open_settings(ctx),
+                _ if event.id == self.menu_ids.capture => self.handle_begin_capture(ctx, received_at),
+                _ if event.id == self.menu_ids.capture_everything => self.handle_capture_everything(ctx),
+                _ if event.id == self.menu_ids.interval_start => self.handle_open_interval_dialog(ctx),
+                _ if event.id == self.menu_ids.interval_stop => {
+                    match &self.interval_capture {
+                        Some(interval) => interval.stop(),
+                        None => log::debug!("No interval capture running."),
+                    }
+                }
+                _ if event.id == self.menu_ids.montage_start => self.start_montage(),
+                _ if event.id == self.menu_ids.montage_finish => self.finish_montage(),
+                _ if event.id == self.menu_ids.capture_edit => {
+                    if self.machine.can_begin_capture() {
+                        self.external_edit_requested = true;
+                        self.handle_begin_capture(ctx, received_at);
+                    }
+                }
+                _ => match (TrayMenuIds::profile_index(&event.id), TrayMenuIds::save_directory_index(&event.id)) {
+                    (Some(index), _) => self.switch_profile(index),
+                    (_, Some(index)) => self.switch_save_directory(index),
+                    _ => log::warn!("Warning: Unhandled Menu ID: {:?}", event.id),
                 },
-                _ if event.id == self.settings_id => self.handle_open_settings(ctx),
-                _ if event.id == self.capture_id => self.handle_begin_capture(ctx),
-                _ => log::warn!("Warning: Unhandled Menu ID: {:?}", event.id),
             }
         }
     }
 
-    fn handle_begin_capture(&mut self, ctx: &egui::Context) {
-        // 1. Save where we came from
-        self.previous_state = self.state;
+    /// Tray "Start Montage": captures from now on are also stacked into one image.
+    fn start_montage(&mut self) {
+        if self.montage_captures.is_some() {
+            log::debug!("A montage is already running.");
+            return;
+        }
+        self.pipeline.start_montage(MontageSettings {
+            gap: self.config.montage_gap_px,
+            background: self.config.montage_background,
+            align: self.config.montage_align,
+            save_directory: self.config.save_directory.clone(),
+            long_paths: self.config.long_path_strategy,
+            png_filter: self.config.png_filter,
+        });
+        self.montage_captures = Some(0);
+    }
 
-        // 2. If coming from Config, save the window position/size
-        if self.state == AppState::Config {
-            // We grab the current outer rectangle of the window from egui context
-            if let Some(rect) = ctx.input(|i| i.viewport().outer_rect) {
-                self.restore_rect = Some(rect);
-            }
+    /// Tray "Finish & Save Montage", also done when quitting.
+    fn finish_montage(&mut self) {
+        if self.montage_captures.take().is_some() {
+            self.pipeline.finish_montage();
+        }
+    }
+
+    /// Lets captures still in the pipeline finish saving before the process goes away.
+    fn flush_pipeline(&mut self) {
+        self.finish_montage();
+        if !self.pipeline.wait_until_idle(EXIT_FLUSH_TIMEOUT) {
+            log::warn!("Captures were still being processed after {:?}, exiting anyway.", EXIT_FLUSH_TIMEOUT);
+        }
+    }
+
+    /// Applies a capture profile's settings and makes it the active one.
+    fn switch_profile(&mut self, index: usize) {
+        let Some(profile) = self.config.profiles.get(index).cloned() else {
+            log::warn!("No profile at index {}.", index);
+            return;
+        };
+        profile.apply_to(&mut self.config);
+        if let Some(hotkey) = profile.snap_hotkey()
+            && hotkey.id() != self.config.snap_hotkey.id()
+        {
+            self.update_hotkey(HotkeyTarget::Snap, hotkey);
+        }
+        self.config.active_profile = Some(index);
+        self.save_config();
+        self.send_tray_menu();
+        log::info!("Switched to profile \"{}\".", profile.name);
+    }
+
+    /// The profile after the active one, wrapping around.
+    fn switch_to_next_profile(&mut self) {
+        if self.config.profiles.is_empty() {
+            return;
+        }
+        let next = self.config.active_profile.map_or(0, |index| (index + 1) % self.config.profiles.len());
+        self.switch_profile(next);
+    }
+
+    /// Rebuilds the tray's "Profiles" and "Save Folder" submenus after profiles were added,
+    /// renamed or switched, or the save directory changed.
+    fn send_tray_menu(&self) {
+        if let Some(tray) = &self.tray {
+            tray.send(TrayCommand::RebuildMenu(MenuEntries::from_config(&self.config)));
         }
+    }
+
+    /// Tray "Save Folder": saves go to one of the recently picked directories from now on.
+    fn switch_save_directory(&mut self, index: usize) {
+        let Some(directory) = self.config.recent_save_directories.get(index).cloned() else {
+            log::warn!("No recent save directory at index {}.", index);
+            return;
+        };
+        self.config.use_save_directory(directory);
+        self.save_config();
+        self.send_tray_menu();
+        log::info!("Saving captures to {} now.", self.config.save_directory);
+    }
+
+    /// `triggered_at` is when the hotkey or click that started the capture came in.
+    fn handle_begin_capture(&mut self, ctx: &egui::Context, triggered_at: Instant) {
+        // Its keys (Escape among them) must not stay registered while the overlay is up
+        self.disarm_chord();
 
-        log::debug!("Starting Capture from state: {:?}", self.previous_state);
-        // 3. Prepare Window Style (Transparent Overlay)
-        ctx.send_viewport_cmd(egui::ViewportCommand::Decorations(false));
-        ctx.send_viewport_cmd(egui::ViewportCommand::Transparent(true));
+        log::debug!("Starting Capture from state: {:?}", self.machine.state());
 
-        if self.config.play_sound {
-            self.sound_engine.play_activation();
+        // On the main thread on purpose: whatever it hides has to be gone before the screens are read
+        if let Some(command) = &self.config.pre_capture_command {
+            log::debug!("Running pre-capture command: {}", command);
+            utils::run_command_with_timeout(command, PRE_CAPTURE_COMMAND_TIMEOUT);
         }
 
-        match crate::capture::capture_all_screens() {
+        // Remembers the settings window before the overlay moves it
+        let effects = self.machine.begin_capture();
+        self.show_from_tray();
+        self.apply(ctx, effects);
+
+        log::debug!("Timing: trigger -> capture start took {:?}", triggered_at.elapsed());
+
+        match self.capture_for_overlay() {
             Ok(data) => {
-                self.raw_image = Some(data.full_image);
-                self.virtual_origin = (0.0, 0.0);
+                log::debug!("Timing: trigger -> capture done took {:?}", triggered_at.elapsed());
+                self.capture_failures = 0;
+                self.capture_failure_banner = None;
+                self.raw_image = data.full_image;
+                self.physical_size = (data.physical_width, data.physical_height);
+                self.topmost_windows = crate::capture::topmost_windows();
+                log::debug!("Always-on-top windows: {:?}", self.topmost_windows);
+
+                if self.origin_changed(data.logical_origin, data.origin_scale_factor) {
+                    log::info!("Corrected stale monitor origin ({}, {}) @ {} -> ({}, {}) @ {}",
+                        self.virtual_origin.0, self.virtual_origin.1, self.origin_scale,
+                        data.logical_origin.0, data.logical_origin.1, data.origin_scale_factor);
+                }
+                self.virtual_origin = data.logical_origin;
+                self.origin_scale = data.origin_scale_factor;
+                self.layout_checked_at = Instant::now();
 
                 // CHANGED: Do NOT use ctx.pixels_per_point() here.
                 // It is stale because the window hasn't moved yet.
@@ -238,260 +930,2098 @@ impl CrabGrabApp {
                 log::debug!("Using Predicted PPI: {}", predicted_ppi);
 
                 // 1. VISUALS: Pass Predicted PPI
-                let tiles = utils::load_screens_as_tiles(
-                    ctx,
+                let tiles = utils::decode_screen_tiles(
                     &data.monitors,
                     data.physical_origin,
-                    predicted_ppi // <--- Use the value from capture data
+                    predicted_ppi, // <--- Use the value from capture data
+                    self.config.compensate_sdr_white_level,
+                    self.config.gpu_tile_budget_mb,
                 );
-                self.tiles = Some(tiles);
+                self.queue_tiles(tiles);
 
                 // 2. HITBOXES: Pass Predicted PPI
-                self.monitor_layout = data.monitors.iter().map(|m| {
-                    let phys_offset_x = (m.x - data.physical_origin.0) as f32;
-                    let phys_offset_y = (m.y - data.physical_origin.1) as f32;
-
-                    // Divide by the predicted PPI
-                    let egui_x = phys_offset_x / predicted_ppi;
-                    let egui_y = phys_offset_y / predicted_ppi;
-
-                    let egui_w = m.width as f32 / predicted_ppi;
-                    let egui_h = m.height as f32 / predicted_ppi;
-
-                    egui::Rect::from_min_size(
-                        egui::pos2(egui_x, egui_y),
-                        egui::vec2(egui_w, egui_h)
-                    )
-                }).collect();
+                self.monitor_layout = Self::layout_monitors(&data.monitors, data.physical_origin, predicted_ppi);
 
                 // Save predicted PPI and monitor data so we can re-build later if needed
                 self.predicted_ppi = predicted_ppi;
+                self.ppi_checked = false;
                 self.last_monitors = Some(data.monitors);
                 self.physical_origin = data.physical_origin;
 
-                // ... Window positioning code remains the same ...
-                ctx.send_viewport_cmd(egui::ViewportCommand::OuterPosition(
-                    egui::pos2(data.logical_origin.0, data.logical_origin.1)
-                ));
+                // Hide the window while it moves over the desktop; it is shown again once
+                // a frame with the tiles has been rendered (see the end of the Snapping branch).
+                self.viewport.set_visible(ctx, false);
+                self.hidden_overlay_frames = Some(1);
 
-                ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(
-                    egui::vec2(data.logical_width, data.logical_height)
-                ));
+                // Otherwise showing the window switches back to the desktop it was last shown on
+                #[cfg(target_os = "windows")]
+                if let Some(hwnd) = self.hwnd {
+                    utils::move_to_current_virtual_desktop(hwnd);
+                }
+
+                // 3. Window Style (Transparent Overlay)
+                self.show_overlay(ctx, data.logical_origin, egui::vec2(data.logical_width, data.logical_height));
 
-                self.state = AppState::Snapping;
-                ctx.send_viewport_cmd(egui::ViewportCommand::Visible(true));
-                ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+                self.machine.overlay_shown();
+                self.capture_triggered_at = Some(triggered_at);
+
+                if self.config.hide_cursor_during_capture {
+                    self.hidden_cursor = Some(HiddenCursor::hide());
+                }
+            }
+            Err(e) => {
+                // Capturing again from the overlay: the old one is already hidden
+                if self.machine.state() == AppState::Snapping {
+                    self.cancel_capture(ctx);
+                }
+                self.alignment_test = false;
+                self.report_capture_failure(ctx, &e);
             }
-            Err(e) => log::error!("Capture failed: {}", e),
         }
     }
 
-    fn handle_hotkey_events(&mut self, ctx: &egui::Context) {
-        let receiver = GlobalHotKeyEvent::receiver();
+    /// The desktop for the overlay. The overlay crops in physical px, so it always gets the
+    /// desktop at native scale.
+    fn capture_for_overlay(&self) -> Result<crate::capture::CaptureData, CaptureError> {
+        if self.alignment_test {
+            return crate::capture::capture_test_pattern();
+        }
 
-        while let Ok(event) = receiver.try_recv() {
-            if event.state == HotKeyState::Pressed {
-                match event.id {
-                    _ if event.id == self.config.snap_hotkey.id() => {
-                        if matches!(self.state, AppState::Idle | AppState::Config) {
-                            self.handle_begin_capture(ctx);
-                        }
-                    }
-                    _ if event.id == self.cancel_hotkey.id() => {
-                        if matches!(self.state, AppState::Snapping) {
-                            self.state = AppState::Idle;
-                            self.start_pos = None;
-                            self.current_pos = None;
-                            self.raw_image = None;
-                            self.tiles = None;
-                            ctx.send_viewport_cmd(egui::ViewportCommand::OuterPosition(egui::pos2(10000.0, 10000.0)));
-                            ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(vec2(0.0, 0.0)));
-                        }
-                    }
-                    _ if event.id == self.settings_hotkey.id() => {
-                        if !matches!(self.state, AppState::Config) {
-                            self.handle_open_settings(ctx);
-                        } else {
-                            self.handle_close_settings(ctx);
-                        }
-                    }
-                    _ => {}
-                }
+        #[cfg(target_os = "windows")]
+        {
+            // A virtual desktop switch during the capture leaves the old desktop's content in it
+            let desktop = utils::current_virtual_desktop();
+            let capture = crate::capture::capture_all_screens(None)?;
+            let desktop_after = utils::current_virtual_desktop();
+            log::info!("Region capture on virtual desktop {:?}.", desktop_after);
+            if desktop.is_some() && desktop_after.is_some() && desktop != desktop_after {
+                log::info!("Virtual desktop switched during the capture ({:?} -> {:?}), capturing again.", desktop, desktop_after);
+                return crate::capture::capture_all_screens(None);
             }
+            Ok(capture)
         }
+        #[cfg(not(target_os = "windows"))]
+        crate::capture::capture_all_screens(None)
     }
 
-    fn handle_capture_finish(&mut self, ctx: &egui::Context, rect: egui::Rect, window_size: egui::Vec2) {
-        if rect.width() <= 1.0 || rect.height() <= 1.0 {
-            return;
+    fn play_error_sound(&mut self) {
+        if self.config.play_sound && self.config.play_error_sound {
+            self.sound_engine.play_error();
         }
+    }
 
-        // 1. CROP (Must be done on Main Thread to access self.raw_image)
-        // We clone the cropped buffer so the background thread can own it.
-        let cropped_buffer = if let Some(image) = &self.raw_image {
-            let scale_x = image.width() as f32 / window_size.x;
-            let scale_y = image.height() as f32 / window_size.y;
-
-            let x = (rect.min.x * scale_x) as u32;
-            let y = (rect.min.y * scale_y) as u32;
-            let width = (rect.width() * scale_x) as u32;
-            let height = (rect.height() * scale_y) as u32;
-
-            image::imageops::crop_imm(
-                image,
-                x.min(image.width() - 1),
-                y.min(image.height() - 1),
-                width.min(image.width() - x),
-                height.min(image.height() - y)
-            ).to_image()
-        } else {
+    fn play_shutter_sound(&self) {
+        if !self.config.play_sound {
             return;
-        };
-
-        if self.config.play_sound {
-            self.sound_engine.play_shutter();
         }
-
-        // 2. PREPARE DATA FOR BACKGROUND THREAD
-        // We need to clone small config strings to move them into the thread.
-        let save_path = self.config.save_directory.clone();
-        let auto_save = self.config.auto_save;
-
-        // 3. SPAWN BACKGROUND TASK (Fire and Forget)
-        // Rayon uses a thread pool, so this is very efficient.
-        rayon::spawn(move || {
-            // A. Save to Disk (The Slow Part)
-            if auto_save {
-                utils::save_image_to_disk(&cropped_buffer, &save_path);
-            }
-
-            // B. Copy to Clipboard
-            // Converting to raw bytes takes a little time too, so we do it here.
-            let width = cropped_buffer.width();
-            let height = cropped_buffer.height();
-            let pixels = cropped_buffer.into_raw();
-
-            let image_data = ImageData {
-                width: width as usize,
-                height: height as usize,
-                bytes: Cow::Owned(pixels),
-            };
-
-            if let Ok(mut clipboard) = Clipboard::new() {
-                if let Err(e) = clipboard.set_image(image_data) {
-                    log::error!("Failed to copy to clipboard: {}", e);
-                } else {
-                    log::debug!("Copied to clipboard successfully.");
+        #[cfg(target_os = "windows")]
+        match self.config.shutter_with_other_audio {
+            ShutterWithOtherAudio::Play => {}
+            ShutterWithOtherAudio::Duck => crate::audio::other_audio::duck(0.2, SHUTTER_DUCK_DURATION),
+            ShutterWithOtherAudio::Skip => {
+                if crate::audio::other_audio::is_playing() {
+                    log::debug!("Other audio is playing, skipping the shutter sound.");
+                    return;
                 }
             }
-        });
-
-        // 4. INSTANT UI RESTORE
-        // We don't wait for the save/clipboard. We hide the window immediately.
-        log::debug!("Capture Finished. Restoring to: {:?}", self.previous_state);
+        }
+        self.sound_engine.play_shutter();
+    }
 
-        match self.previous_state {
-            AppState::Config => {
-                self.state = AppState::Config;
-                ctx.send_viewport_cmd(egui::ViewportCommand::Decorations(true));
-                ctx.send_viewport_cmd(egui::ViewportCommand::Transparent(false));
+    /// Captures the whole virtual desktop without showing the overlay.
+    fn handle_capture_everything(&mut self, ctx: &egui::Context) {
+        if !self.machine.can_begin_capture() {
+            log::debug!("Ignoring Capture Everything while a capture is in progress.");
+            return;
+        }
+        self.disarm_chord();
 
-                if let Some(saved_rect) = self.restore_rect {
-                    ctx.send_viewport_cmd(egui::ViewportCommand::OuterPosition(saved_rect.min));
-                    ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(saved_rect.size()));
+        utils::log_virtual_desktop("Capture Everything");
+        match crate::capture::capture_all_screens(self.config.normalize_capture_scale) {
+            Ok(data) => {
+                // Huge desktops are not stitched up front, try once more now that it was asked for
+                let full_image = data.full_image.or_else(|| crate::capture::crop_from_monitors(
+                    &data.monitors, data.physical_origin, 0, 0, data.physical_width, data.physical_height,
+                ));
+                let Some(full_image) = full_image else {
+                    log::error!("Not enough memory for the {}x{} desktop.", data.physical_width, data.physical_height);
+                    utils::show_error_dialog(
+                        "Capture Everything failed",
+                        &format!("The virtual desktop ({}x{}) is too large to capture as one image.", data.physical_width, data.physical_height),
+                    );
+                    return;
+                };
+                self.capture_failures = 0;
+                self.capture_failure_banner = None;
+                self.play_shutter_sound();
+                log::debug!("Captured everything ({}x{}).", full_image.width(), full_image.height());
+                // A normalized desktop no longer lines up with the monitors, its colors stay as captured
+                self.pending_color_parts = if self.config.color_management != ColorManagement::Off
+                    && self.config.normalize_capture_scale.is_none()
+                {
+                    color::parts_for_crop(
+                        &data.monitors, data.physical_origin, (0, 0, data.physical_width, data.physical_height),
+                        &crate::capture::monitor_icc_profiles(),
+                    )
                 } else {
-                    ctx.send_viewport_cmd(egui::ViewportCommand::OuterPosition(egui::pos2(100.0, 100.0)));
-                    ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(egui::vec2(600.0, 400.0)));
-                }
-            },
-            _ => {
-                self.state = AppState::Idle;
-                ctx.send_viewport_cmd(egui::ViewportCommand::Decorations(false));
-                ctx.send_viewport_cmd(egui::ViewportCommand::Transparent(true));
-                ctx.send_viewport_cmd(egui::ViewportCommand::OuterPosition(egui::pos2(10000.0, 10000.0)));
-                ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(egui::vec2(0.0, 0.0)));
+                    Vec::new()
+                };
+                self.spawn_capture_pipeline(full_image, true, self.config.auto_save, String::new(), CaptureMode::Everything, None);
             }
+            Err(e) => self.report_capture_failure(ctx, &e),
         }
-
-        // --- CLEANUP ---
-        self.raw_image = None;
-        self.tiles = None;
-        self.restore_rect = None;
-        self.start_pos = None;
-        self.current_pos = None;
-        self.last_monitors = None;
     }
 
-    fn update_hotkey(&mut self, new_hotkey: HotKey) {
-        log::debug!("Updating hotkey to: {:?}", new_hotkey);
+    /// Registers the chord's follow-up keys and, from the tray, shows which key does what
+    /// next to the pointer.
+    fn arm_chord(&mut self, ctx: &egui::Context) {
+        self.disarm_chord();
+        let actions = self.config.chord_actions.iter().map(|&action| (HotKey::new(None, action.key()), Some(action)));
+        let mut followers = Vec::new();
+        for (hotkey, action) in actions.chain([(self.cancel_hotkey, None)]) {
+            match self.hotkey_manager.register(hotkey) {
+                Ok(()) => followers.push((hotkey, action)),
+                Err(e) => log::warn!("Chord key {:?} is not available: {:?}", hotkey, e),
+            }
+        }
+        log::debug!("Chord armed, waiting for one of {} keys.", followers.len());
+        self.chord_armed = Some(ChordArmed { started: Instant::now(), followers });
 
-        // 1. Unregister the OLD hotkey (self.config.snap_hotkey)
-        let result = self.hotkey_manager.unregister(self.config.snap_hotkey);
-        // Hint: self.hotkey_manager.unregister(self.config.snap_hotkey)
+        // The settings window is in the way there, only the hidden window is put to use
+        if self.machine.state() == AppState::Idle {
+            let ppi = ctx.pixels_per_point();
+            let pointer = utils::cursor_position().map_or(egui::pos2(40.0, 40.0), |(x, y)| egui::pos2(x as f32, y as f32) / ppi);
+            let width = self.chord_hint_text().chars().count() as f32 * 7.5 + 24.0;
+            self.show_from_tray();
+            self.viewport.set_position(ctx, pointer + vec2(16.0, 16.0));
+            self.viewport.set_size(ctx, vec2(width, 30.0));
+        }
+        ctx.request_repaint_after(CHORD_TIMEOUT);
+    }
 
-        if let Err(e) = result {
-            log::error!("Failed to unregister old hotkey {:?}: {:?}", self.config.snap_hotkey, e);
+    fn disarm_chord(&mut self) {
+        let Some(chord) = self.chord_armed.take() else {
             return;
+        };
+        for (hotkey, _) in chord.followers {
+            if let Err(e) = self.hotkey_manager.unregister(hotkey) {
+                log::error!("Failed to unregister chord key {:?}: {:?}", hotkey, e);
+            }
+        }
+        // The idle state parks the window again
+        if self.machine.state() == AppState::Idle {
+            self.hide_to_tray();
         }
+    }
 
-        // 2. Register the NEW hotkey
-        // Hint: self.hotkey_manager.register(new_hotkey)
-        let result = self.hotkey_manager.register(new_hotkey);
-        if let Err(e) = result {
-            log::error!("Failed to register new hotkey {:?}: {:?}", new_hotkey, e);
-            // Attempt to restore the previous hotkey; log any failure but don't panic.
-            if let Err(e2) = self.hotkey_manager.register(self.config.snap_hotkey) {
-                log::error!("Failed to restore previous hotkey {:?}: {:?}", self.config.snap_hotkey, e2);
-            }
-            return;
+    /// The second key of the chord was pressed.
+    fn handle_chord_key(&mut self, ctx: &egui::Context, id: u32, triggered_at: Instant) {
+        let action = self.chord_armed.as_ref()
+            .and_then(|chord| chord.followers.iter().find(|(hotkey, _)| hotkey.id() == id))
+            .and_then(|(_, action)| *action);
+        self.disarm_chord();
+        match action {
+            Some(ChordAction::Region) => self.handle_begin_capture(ctx, triggered_at),
+            Some(ChordAction::Everything) => self.handle_capture_everything(ctx),
+            Some(ChordAction::LastRegion) => self.handle_capture_last_region(),
+            None => log::debug!("Chord cancelled."),
         }
+    }
 
-        // 4. Update the config state
-        self.config.snap_hotkey = new_hotkey;
+    fn check_chord_timeout(&mut self) {
+        if self.chord_armed.as_ref().is_some_and(|chord| chord.started.elapsed() >= CHORD_TIMEOUT) {
+            log::debug!("Chord timed out.");
+            self.disarm_chord();
+        }
     }
 
-    fn open_file_picker(&mut self) {
-        log::debug!("Spawning file picker thread...");
-        // TASK: Spawn a thread to pick a folder.
-        // 1. Create a channel (tx, rx).
-        let (tx, rx) = channel();
-        // 2. Store 'rx' in self.file_picker_receiver.
-        self.file_picker_receiver = Some(rx);
-        // 3. Spawn a std::thread.
-        std::thread::spawn(move || {
-            // 4. Inside the thread: call rfd::FileDialog::new().pick_folder().
-            if let Some(path_buf) = rfd::FileDialog::new().pick_folder() {
-                // 5. If a path is found, convert to String and send it via 'tx'.
-                if let Some(path_str) = path_buf.to_str() {
-                    let _ = tx.send(path_str.to_string());
-                }
-            }
+    /// "R: Select a region · F: ..." for the enabled chord actions.
+    fn chord_hint_text(&self) -> String {
+        self.config.chord_actions.iter()
+            .map(|action| format!("{}: {}", utils::format_hotkey(&HotKey::new(None, action.key())), action.label()))
+            .chain(["Esc: cancel".to_string()])
+            .collect::<Vec<_>>()
+            .join("  ·  ")
+    }
+
+    fn show_chord_hint(&self, ctx: &egui::Context) {
+        egui::CentralPanel::default().frame(egui::Frame::NONE).show(ctx, |ui| {
+            let rect = ui.max_rect();
+            ui.painter().rect_filled(rect, 6.0, egui::Color32::from_black_alpha(200));
+            ui.painter().text(
+                rect.center(),
+                egui::Align2::CENTER_CENTER,
+                self.chord_hint_text(),
+                egui::FontId::proportional(13.0),
+                egui::Color32::from_gray(230),
+            );
         });
     }
 
-    fn check_file_picker_result(&mut self) {
-        if let Some(rx) = &self.file_picker_receiver {
-            match rx.try_recv() {
-                Ok(new_path) => {
-                    log::debug!("File picker returned path: {}", new_path);
-                    self.config.save_directory = new_path;
-                    self.file_picker_receiver = None;
-                }
-                Err(std::sync::mpsc::TryRecvError::Empty) => {}
-                Err(e) => {
-                    log::error!("File picker channel error: {:?}", e);
-                    self.file_picker_receiver = None;
-                }
+    /// Captures the region of the last selection again, without the overlay.
+    fn handle_capture_last_region(&mut self) {
+        let Some(region) = self.last_region.clone() else {
+            log::info!("No region selected yet, nothing to capture again.");
+            return;
+        };
+        utils::log_virtual_desktop("Last region");
+        match crate::capture::capture_monitor_region(&region.monitor, region.monitor_position, region.x, region.y, region.width, region.height) {
+            Ok(image) => {
+                self.play_shutter_sound();
+                self.spawn_capture_pipeline(image, true, self.config.auto_save, String::new(), CaptureMode::Region, None);
+            }
+            Err(e) => {
+                log::error!("Capturing the last region again failed: {}", e);
+                utils::show_error_dialog("Capture failed", &utils::describe_capture_error(&e));
             }
         }
     }
 
-    fn handle_hotkey_activation(&mut self) {
-        if self.state == AppState::Snapping {
-            if !self.cancel_registered {
-                 match self.hotkey_manager.register(self.cancel_hotkey) {
-                     Err(err) => log::error!("Failed to register cancel hotkey: {:?}", err),
-                     Ok(_) => self.cancel_registered = true,
+    /// Captures a saved region preset straight away, without the overlay.
+    fn handle_capture_preset(&mut self, index: usize) {
+        let Some(preset) = self.config.region_presets.get(index).cloned() else {
+            return;
+        };
+        log::debug!("Capturing region preset '{}'", preset.name);
+
+        utils::log_virtual_desktop("Preset");
+        match crate::capture::capture_monitor_region(&preset.monitor, preset.monitor_position, preset.x, preset.y, preset.width, preset.height) {
+            Ok(image) => {
+                self.play_shutter_sound();
+                self.spawn_capture_pipeline(image, true, self.config.auto_save, String::new(), CaptureMode::Preset, None);
+            }
+            Err(e) => {
+                log::error!("Region preset '{}' failed: {}", preset.name, e);
+                utils::show_error_dialog("Region preset failed", &format!("Capturing '{}' failed: {}", preset.name, utils::describe_capture_error(&e)));
+            }
+        }
+    }
+
+    fn handle_hotkey_events(&mut self, ctx: &egui::Context) {
+        while let Ok((received_at, event)) = self.hotkey_events.try_recv() {
+            if event.state == HotKeyState::Pressed {
+                match event.id {
+                    // Checked first: it has to work whatever state the app thinks it is in
+                    _ if event.id == self.config.panic_hotkey.id() => self.force_reset_to_idle(ctx),
+                    // Before the cancel hotkey, Escape is one of the chord's follow-up keys
+                    _ if self.machine.can_begin_capture()
+                        && self.chord_armed.as_ref().is_some_and(|chord| chord.followers.iter().any(|(hk, _)| hk.id() == event.id)) => {
+                        self.handle_chord_key(ctx, event.id, received_at);
+                    }
+                    _ if self.config.chord_hotkey().is_some_and(|hk| hk.id() == event.id) => {
+                        if self.machine.can_begin_capture() {
+                            self.arm_chord(ctx);
+                        }
+                    }
+                    _ if event.id == self.config.snap_hotkey.id() => {
+                        if self.machine.can_begin_capture() {
+                            self.handle_begin_capture(ctx, received_at);
+                        } else if self.machine.state() == AppState::Snapping {
+                            self.handle_snap_while_snapping(ctx, received_at);
+                        }
+                    }
+                    _ if event.id == self.config.annotate_hotkey.id() => {
+                        if self.machine.can_begin_capture() {
+                            self.annotate_requested = true;
+                            self.handle_begin_capture(ctx, received_at);
+                        }
+                    }
+                    _ if event.id == self.config.external_edit_hotkey.id() => {
+                        if self.machine.can_begin_capture() {
+                            self.external_edit_requested = true;
+                            self.handle_begin_capture(ctx, received_at);
+                        }
+                    }
+                    _ if event.id == self.cancel_hotkey.id() => {
+                        if matches!(self.machine.state(), AppState::Snapping) {
+                            self.cancel_capture(ctx);
+                        } else if matches!(self.machine.state(), AppState::ActionBar) {
+                            self.commit_pending_capture(ctx, false, false);
+                        } else if matches!(self.machine.state(), AppState::NoteInput) {
+                            // Escape skips the note but still keeps the capture
+                            self.pending_note.clear();
+                            self.continue_pending_capture(ctx);
+                        } else if matches!(self.machine.state(), AppState::Editing | AppState::Preview) {
+                            self.commit_pending_capture(ctx, false, false);
+                        }
+                    }
+                    _ if event.id == self.settings_hotkey.id() => {
+                        if !matches!(self.machine.state(), AppState::Config) {
+                            self.handle_open_settings(ctx);
+                        } else {
+                            self.handle_close_settings(ctx);
+                        }
+                    }
+                    _ if self.config.profile_hotkey().is_some_and(|hk| hk.id() == event.id) => self.switch_to_next_profile(),
+                    _ => {
+                        let preset = self.config.region_presets.iter()
+                            .position(|preset| preset.hotkey().is_some_and(|hk| hk.id() == event.id));
+                        if let Some(index) = preset
+                            && self.machine.can_begin_capture()
+                        {
+                            self.handle_capture_preset(index);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn handle_capture_finish(&mut self, ctx: &egui::Context, rect: egui::Rect, window_size: egui::Vec2) {
+        if rect.width() <= 1.0 || rect.height() <= 1.0 {
+            return;
+        }
+
+        // The selection is done, so the cursor is needed again (e.g. for the action bar)
+        self.hidden_cursor = None;
+
+        // Defining a region preset, not a capture
+        if let Some(index) = self.picking_preset {
+            self.store_picked_region(index, rect, window_size);
+            self.restore_after_capture(ctx);
+            return;
+        }
+
+        // Nothing to capture from the test patterns
+        if self.alignment_test {
+            self.restore_after_capture(ctx);
+            return;
+        }
+
+        // Remembered for "Start Interval Capture…"
+        if let Some(region) = self.region_on_monitor(rect, window_size) {
+            self.last_region = Some(region);
+        }
+
+        // 1. CROP (Must be done on Main Thread to access self.raw_image)
+        // We clone the cropped buffer so the background thread can own it.
+        let Some((x, y, width, height)) = self.physical_crop(rect, window_size) else {
+            return;
+        };
+
+        // Huge virtual desktops have no stitched image, the selection is assembled per monitor
+        let cropped_buffer = match (&self.raw_image, &self.last_monitors) {
+            (Some(image), _) => Some(image::imageops::crop_imm(image, x, y, width, height).to_image()),
+            (None, Some(monitors)) => crate::capture::crop_from_monitors(monitors, self.physical_origin, x, y, width, height),
+            (None, None) => return,
+        };
+        let Some(mut cropped_buffer) = cropped_buffer else {
+            log::error!("Not enough memory for a {}x{} capture.", width, height);
+            utils::show_error_dialog("Capture failed", &format!("Not enough memory for a {}x{} capture.", width, height));
+            self.restore_after_capture(ctx);
+            return;
+        };
+        // Before the preview and the editor, so they already show the filtered capture
+        FilterPipeline::from_kinds(&self.config.filters).apply(&mut cropped_buffer);
+
+        self.play_shutter_sound();
+
+        self.pending_capture = Some(cropped_buffer);
+        self.pending_color_parts = match &self.last_monitors {
+            Some(monitors) if self.config.color_management != ColorManagement::Off => color::parts_for_crop(
+                monitors, self.physical_origin, (x, y, width, height), &crate::capture::monitor_icc_profiles(),
+            ),
+            _ => Vec::new(),
+        };
+        let desktop_x = self.physical_origin.0 + x as i32;
+        let desktop_y = self.physical_origin.1 + y as i32;
+        self.pending_coordinates = Some(utils::format_coordinates(&self.config.coordinates_format, (desktop_x, desktop_y, width, height)));
+        self.pending_note.clear();
+
+        // 2. EXTERNAL EDITOR: Hand the capture over and skip our own save/clipboard steps
+        if self.external_edit_requested {
+            self.external_edit_requested = false;
+            if let Some(image) = self.pending_capture.take() {
+                self.last_capture_at = Some(chrono::Local::now());
+                let editor = self.config.external_editor_path.clone();
+                rayon::spawn(move || {
+                    match utils::open_in_external_editor(&image, editor.as_deref()) {
+                        Ok(path) => {
+                            log::info!("Opened {:?} in the external editor.", path);
+                            stats::record_capture(CaptureMode::ExternalEdit, 0);
+                        }
+                        Err(e) => {
+                            log::error!("Capture & Edit failed: {}", e);
+                            utils::show_error_dialog("Capture & Edit failed", &e.to_string());
+                        }
+                    }
+                });
+            }
+            self.restore_after_capture(ctx);
+            return;
+        }
+
+        // 3. EDITOR: The annotate hotkey marks the capture up before anything else
+        if self.annotate_requested {
+            self.open_editor();
+            return;
+        }
+
+        // 4. PREVIEW: Show the cropped image on its own before anything gets saved
+        if self.config.preview_before_save {
+            self.open_preview(ctx);
+            return;
+        }
+
+        self.prompt_note_or_continue(ctx);
+    }
+
+    /// The selection in physical px of the captured desktop: x, y, width, height.
+    fn physical_crop(&self, rect: egui::Rect, window_size: egui::Vec2) -> Option<(u32, u32, u32, u32)> {
+        let (physical_width, physical_height) = self.physical_size;
+        if physical_width == 0 || physical_height == 0 {
+            return None;
+        }
+        let scale_x = physical_width as f32 / window_size.x;
+        let scale_y = physical_height as f32 / window_size.y;
+
+        let x = ((rect.min.x * scale_x) as u32).min(physical_width - 1);
+        let y = ((rect.min.y * scale_y) as u32).min(physical_height - 1);
+        let width = ((rect.width() * scale_x) as u32).min(physical_width - x);
+        let height = ((rect.height() * scale_y) as u32).min(physical_height - y);
+        Some((x, y, width, height))
+    }
+
+    /// A prompt for the released selection when it is above `confirm_captures_above_mp`.
+    /// Preset regions and the alignment test are never captured, they don't ask.
+    fn large_capture_prompt(&self, rect: egui::Rect, window_size: egui::Vec2) -> Option<LargeCapturePrompt> {
+        let limit = self.config.confirm_captures_above_mp?;
+        if self.picking_preset.is_some() || self.alignment_test {
+            return None;
+        }
+        let (_, _, width, height) = self.physical_crop(rect, window_size)?;
+        let scale = self.config.capture_scale;
+        let megapixels = width as f32 * height as f32 * scale * scale / 1_000_000.0;
+        let longest = width.max(height).max(1) as f32;
+        (megapixels > limit).then(|| LargeCapturePrompt {
+            rect,
+            window_size,
+            megapixels,
+            downscaled_scale: scale.min(self.config.downscaled_max_dimension as f32 / longest),
+        })
+    }
+
+    /// Asks before a large selection is captured, on the overlay next to the selection.
+    /// Returns the selection to capture once the user decided to.
+    fn show_large_capture_prompt(&mut self, ctx: &egui::Context) -> Option<(egui::Rect, egui::Vec2)> {
+        let prompt = self.large_capture_prompt?;
+        // (capture, downscaled) chosen by the user this frame
+        let mut choice: Option<(bool, bool)> = None;
+
+        egui::Window::new("")
+            .id(egui::Id::new("large_capture_prompt"))
+            .title_bar(false)
+            .resizable(false)
+            .movable(false)
+            .frame(egui::Frame::popup(&ctx.style()))
+            .pivot(egui::Align2::CENTER_CENTER)
+            .fixed_pos(prompt.rect.center())
+            .show(ctx, |ui| {
+                ui.label(format!("Capture {:.1} MP image?", prompt.megapixels));
+                ui.horizontal(|ui| {
+                    if ui.button("💾 Save").clicked() {
+                        choice = Some((true, false));
+                    }
+                    let downscale = prompt.downscaled_scale < self.config.capture_scale;
+                    if ui.add_enabled(downscale, egui::Button::new("🗜 Save downscaled"))
+                        .on_hover_text(format!("At most {} px on the longer side.", self.config.downscaled_max_dimension))
+                        .clicked()
+                    {
+                        choice = Some((true, true));
+                    }
+                    if ui.button("Cancel").on_hover_text("Keeps the overlay open for a new selection.").clicked() {
+                        choice = Some((false, false));
+                    }
+                });
+            });
+
+        let (capture, downscaled) = choice?;
+        self.large_capture_prompt = None;
+        if !capture {
+            log::debug!("Large capture ({:.1} MP) cancelled, back to selecting.", prompt.megapixels);
+            if self.config.hide_cursor_during_capture {
+                self.hidden_cursor = Some(HiddenCursor::hide());
+            }
+            return None;
+        }
+        if downscaled {
+            self.pending_capture_scale = Some(prompt.downscaled_scale);
+        }
+        Some((prompt.rect, prompt.window_size))
+    }
+
+    fn open_preview(&mut self, ctx: &egui::Context) {
+        if let Some(image) = &self.pending_capture {
+            log::debug!("Capture Finished. Showing preview...");
+            self.preview_texture = Some(utils::load_preview_texture(ctx, image));
+            self.checker_texture = Some(utils::load_checkerboard_texture(ctx));
+            self.machine.review(AppState::Preview);
+        }
+    }
+
+    fn close_preview(&mut self) {
+        self.preview_texture = None;
+        self.checker_texture = None;
+    }
+
+    /// Stores the selection (overlay points) as monitor-relative physical px in a region preset.
+    fn store_picked_region(&mut self, index: usize, rect: egui::Rect, window_size: egui::Vec2) {
+        let Some(region) = self.region_on_monitor(rect, window_size) else {
+            log::warn!("Picked region is not on any monitor.");
+            return;
+        };
+
+        if let Some(preset) = self.config.region_presets.get_mut(index) {
+            preset.monitor = region.monitor;
+            preset.monitor_position = region.monitor_position;
+            preset.x = region.x;
+            preset.y = region.y;
+            preset.width = region.width;
+            preset.height = region.height;
+            log::info!("Region preset '{}' set to {}x{} at ({}, {}) on '{}'",
+                preset.name, preset.width, preset.height, preset.x, preset.y, preset.monitor);
+        }
+    }
+
+    /// Converts a selection (overlay points) into monitor-relative physical px, clipped to the
+    /// monitor its top-left corner is on. None if that corner is on no monitor.
+    fn region_on_monitor(&self, rect: egui::Rect, window_size: egui::Vec2) -> Option<RegionPreset> {
+        let monitors = self.last_monitors.as_ref()?;
+        let scale_x = self.physical_size.0 as f32 / window_size.x;
+        let scale_y = self.physical_size.1 as f32 / window_size.y;
+
+        // Absolute physical desktop coordinates
+        let x = self.physical_origin.0 + (rect.min.x * scale_x) as i32;
+        let y = self.physical_origin.1 + (rect.min.y * scale_y) as i32;
+        let width = (rect.width() * scale_x) as u32;
+        let height = (rect.height() * scale_y) as u32;
+
+        let monitor = monitors.iter().find(|m| {
+            x >= m.x && y >= m.y && x < m.x + m.width as i32 && y < m.y + m.height as i32
+        })?;
+
+        let local_x = (x - monitor.x) as u32;
+        let local_y = (y - monitor.y) as u32;
+        Some(RegionPreset {
+            monitor: monitor.monitor_name.clone().unwrap_or_default(),
+            monitor_position: Some((monitor.x, monitor.y)),
+            x: local_x,
+            y: local_y,
+            // A preset is limited to one monitor
+            width: width.min(monitor.width - local_x),
+            height: height.min(monitor.height - local_y),
+            ..RegionPreset::default()
+        })
+    }
+
+    fn open_editor(&mut self) {
+        log::debug!("Opening annotation editor...");
+        self.annotate_requested = false;
+        self.editor = Some(Editor::new());
+        self.editor_filter_preview = None;
+        self.machine.review(AppState::Editing);
+    }
+
+    /// Asks for a comment before anything gets saved (once per capture), then continues.
+    fn prompt_note_or_continue(&mut self, ctx: &egui::Context) {
+        if self.config.prompt_for_note && !self.note_prompted {
+            log::debug!("Capture Finished. Waiting for note input...");
+            self.note_prompted = true;
+            self.note_timeout = Instant::now() + NOTE_INPUT_TIMEOUT;
+            self.machine.review(AppState::NoteInput);
+            return;
+        }
+
+        self.continue_pending_capture(ctx);
+    }
+
+    /// Next step for a cropped capture: the action bar if enabled, otherwise the default pipeline.
+    fn continue_pending_capture(&mut self, ctx: &egui::Context) {
+        // ACTION BAR: Keep the overlay up and let the user decide what happens
+        if self.config.show_action_bar {
+            log::debug!("Waiting for action bar choice...");
+            self.action_bar_timeout = Instant::now() + ACTION_BAR_TIMEOUT;
+            self.machine.review(AppState::ActionBar);
+            return;
+        }
+
+        self.commit_pending_capture(ctx, true, self.config.auto_save);
+    }
+
+    /// Hands the slow part of a capture (clipboard + disk) to the pipeline worker.
+    /// With `report`, the outcome is sent back instead of being counted in the stats.
+    fn spawn_capture_pipeline(
+        &mut self,
+        image: RgbaImage,
+        copy_to_clipboard: bool,
+        save_to_disk: bool,
+        note: String,
+        mode: CaptureMode,
+        report: Option<Sender<PipelineReport>>,
+    ) {
+        self.last_capture_at = Some(chrono::Local::now());
+        if let Some(count) = &mut self.montage_captures
+            && report.is_none()
+        {
+            *count += 1;
+        }
+
+        // Queued behind earlier captures, the worker thread handles them one at a time in order.
+        let seq = self.pipeline.submit(CaptureJob {
+            image,
+            copy_to_clipboard,
+            save_to_disk,
+            note,
+            mode,
+            // Only ever belongs to the capture handed over here
+            color_parts: std::mem::take(&mut self.pending_color_parts),
+            coordinates: self.pending_coordinates.take(),
+            report,
+            config: AppConfig {
+                capture_scale: self.pending_capture_scale.take().unwrap_or(self.config.capture_scale),
+                ..self.config.clone()
+            },
+        });
+        log::debug!("Capture #{} queued for the pipeline.", seq);
+    }
+
+    /// Applies the action bar choice to the pending capture and tears the overlay down.
+    fn commit_pending_capture(&mut self, ctx: &egui::Context, copy_to_clipboard: bool, save_to_disk: bool) {
+        let note = std::mem::take(&mut self.pending_note);
+        if let Some(image) = self.pending_capture.take() {
+            if copy_to_clipboard || save_to_disk {
+                let mode = if self.editor.is_some() { CaptureMode::Annotated } else { CaptureMode::Region };
+                self.spawn_capture_pipeline(image, copy_to_clipboard, save_to_disk, note, mode, None);
+            } else {
+                log::debug!("Capture discarded from action bar.");
+            }
+        }
+        self.restore_after_capture(ctx);
+    }
+
+    /// Settings "Identify Displays": covers the desktop and shows a number on every monitor
+    /// for a moment, so monitor indices can be matched to the physical screens.
+    fn handle_identify_displays(&mut self, ctx: &egui::Context) {
+        let geometry = match crate::capture::capture_geometry_only() {
+            Ok(geometry) => geometry,
+            Err(e) => {
+                log::error!("Identify Displays failed: {}", e);
+                utils::show_error_dialog("Identify Displays failed", &utils::describe_capture_error(&e));
+                return;
+            }
+        };
+        log::debug!("Identifying {} displays.", geometry.monitors.len());
+
+        // Same as a capture started from the settings: remember where the window was
+        let effects = self.machine.open_identify_displays();
+        self.apply(ctx, effects);
+        self.show_overlay(ctx, geometry.logical_origin, egui::vec2(geometry.logical_width, geometry.logical_height));
+
+        self.identify_displays = Some((geometry, Instant::now()));
+    }
+
+    fn show_identify_displays(&mut self, ctx: &egui::Context) {
+        let Some((geometry, started)) = &self.identify_displays else {
+            let effects = self.machine.close_identify_displays();
+            self.apply(ctx, effects);
+            return;
+        };
+
+        let dismissed = ctx.input(|i| i.key_pressed(egui::Key::Escape) || i.pointer.any_click());
+        let elapsed = started.elapsed();
+        if dismissed || elapsed >= IDENTIFY_DISPLAYS_DURATION {
+            self.identify_displays = None;
+            let effects = self.machine.close_identify_displays();
+            self.apply(ctx, effects);
+            self.restore_rect = None;
+            return;
+        }
+        ctx.request_repaint_after(IDENTIFY_DISPLAYS_DURATION - elapsed);
+
+        egui::CentralPanel::default().frame(egui::Frame::NONE).show(ctx, |ui| {
+            let painter = ui.painter();
+            let ppi = ctx.pixels_per_point();
+            let accent = ui.visuals().selection.bg_fill;
+
+            for (index, monitor) in geometry.monitors.iter().enumerate() {
+                let monitor_rect = egui::Rect::from_min_size(
+                    egui::pos2(
+                        (monitor.x - geometry.physical_origin.0) as f32 / ppi,
+                        (monitor.y - geometry.physical_origin.1) as f32 / ppi,
+                    ),
+                    vec2(monitor.width as f32, monitor.height as f32) / ppi,
+                );
+                painter.rect_stroke(monitor_rect.shrink(3.0), 0.0, egui::Stroke::new(6.0, accent), egui::StrokeKind::Inside);
+
+                let side = monitor_rect.width().min(monitor_rect.height()) * 0.35;
+                let panel = egui::Rect::from_center_size(monitor_rect.center(), vec2(side, side));
+                painter.rect(
+                    panel,
+                    side * 0.08,
+                    egui::Color32::from_black_alpha(200),
+                    egui::Stroke::new(2.0, accent),
+                    egui::StrokeKind::Inside,
+                );
+                painter.text(
+                    panel.center(),
+                    egui::Align2::CENTER_CENTER,
+                    (index + 1).to_string(),
+                    egui::FontId::proportional(side * 0.6),
+                    egui::Color32::WHITE,
+                );
+                painter.text(
+                    panel.center_bottom() - vec2(0.0, side * 0.06),
+                    egui::Align2::CENTER_BOTTOM,
+                    format!("Index {} · {}x{} @ {:.0}%", index, monitor.width, monitor.height, monitor.scale_factor * 100.0),
+                    egui::FontId::proportional((side * 0.07).max(12.0)),
+                    egui::Color32::LIGHT_GRAY,
+                );
+            }
+        });
+    }
+
+    /// Tray "Start Interval Capture…": opens the settings with a small dialog to pick what
+    /// to capture and how often.
+    fn handle_open_interval_dialog(&mut self, ctx: &egui::Context) {
+        if !self.machine.can_begin_capture() {
+            log::debug!("Ignoring Start Interval Capture while a capture is in progress.");
+            return;
+        }
+        if self.interval_capture.is_some() {
+            utils::show_error_dialog("Interval capture", "An interval capture is already running. Stop it from the tray first.");
+            return;
+        }
+
+        let mut regions = Vec::new();
+        if let Some(region) = &self.last_region {
+            regions.push((format!("Last region ({}x{} on '{}')", region.width, region.height, region.monitor), region.clone()));
+        }
+        for preset in &self.config.region_presets {
+            regions.push((format!("Preset: {}", preset.name), preset.clone()));
+        }
+        match crate::capture::capture_geometry_only() {
+            Ok(geometry) => {
+                for (index, monitor) in geometry.monitors.into_iter().enumerate() {
+                    let label = format!("Monitor {} ({}x{})", index + 1, monitor.width, monitor.height);
+                    regions.push((label, RegionPreset {
+                        monitor_position: Some((monitor.x, monitor.y)),
+                        monitor: monitor.name.unwrap_or_default(),
+                        x: 0,
+                        y: 0,
+                        width: monitor.width,
+                        height: monitor.height,
+                        ..RegionPreset::default()
+                    }));
+                }
+            }
+            Err(e) => log::error!("Failed to read the monitor layout: {}", e),
+        }
+
+        self.interval_region = 0;
+        self.interval_regions = Some(regions);
+        if self.machine.state() != AppState::Config {
+            self.handle_open_settings(ctx);
+        }
+    }
+
+    fn show_interval_window(&mut self, ctx: &egui::Context) {
+        let Some(regions) = &self.interval_regions else {
+            return;
+        };
+
+        let mut start = None;
+        let mut open = true;
+        egui::Window::new("Interval Capture")
+            .collapsible(false)
+            .resizable(false)
+            .open(&mut open)
+            .anchor(egui::Align2::CENTER_CENTER, vec2(0.0, 0.0))
+            .show(ctx, |ui| {
+                if regions.is_empty() {
+                    ui.label("No monitors found, there is nothing to capture.");
+                    return;
+                }
+                let selected = self.interval_region.min(regions.len() - 1);
+                egui::ComboBox::from_label("Region")
+                    .selected_text(&regions[selected].0)
+                    .show_ui(ui, |ui| {
+                        for (index, (label, _)) in regions.iter().enumerate() {
+                            ui.selectable_value(&mut self.interval_region, index, label);
+                        }
+                    });
+                ui.horizontal(|ui| {
+                    ui.label("Every");
+                    ui.add(egui::DragValue::new(&mut self.config.interval_capture_seconds).range(1..=86_400).suffix(" s"));
+                });
+                ui.horizontal(|ui| {
+                    let mut limited = self.config.interval_capture_minutes.is_some();
+                    if ui.checkbox(&mut limited, "Stop after").changed() {
+                        self.config.interval_capture_minutes = limited.then_some(30);
+                    }
+                    match &mut self.config.interval_capture_minutes {
+                        Some(minutes) => {
+                            ui.add(egui::DragValue::new(minutes).range(1..=1_440).suffix(" min"));
+                        }
+                        None => {
+                            ui.weak("(until stopped from the tray)");
+                        }
+                    }
+                });
+                ui.weak(format!("Shots are saved to {} as interval_*.png. The clipboard is left alone.", self.config.save_directory));
+                ui.add_space(4.0);
+                if ui.button("▶ Start").clicked() {
+                    start = Some(regions[selected].1.clone());
+                }
+            });
+
+        if let Some(region) = start {
+            self.interval_regions = None;
+            self.start_interval_capture(region);
+            // Out of the way, so the settings don't end up in the shots
+            self.handle_close_settings(ctx);
+        } else if !open {
+            self.interval_regions = None;
+        }
+    }
+
+    fn start_interval_capture(&mut self, region: RegionPreset) {
+        let plan = IntervalPlan {
+            region,
+            interval: Duration::from_secs(self.config.interval_capture_seconds.max(1) as u64),
+            duration: self.config.interval_capture_minutes.map(|minutes| Duration::from_secs(minutes as u64 * 60)),
+            save_directory: self.config.save_directory.clone(),
+            long_paths: self.config.long_path_strategy,
+            png_filter: self.config.png_filter,
+        };
+        self.interval_capture = Some(IntervalCapture::start(plan));
+    }
+
+    /// Picks up the shots of a running interval capture, for the tooltip and the tray icon.
+    fn poll_interval_capture(&mut self, ctx: &egui::Context) {
+        let Some(interval) = &mut self.interval_capture else {
+            return;
+        };
+        let mut finished = false;
+        for event in interval.poll() {
+            match event {
+                IntervalEvent::Captured(_) => self.last_capture_at = Some(chrono::Local::now()),
+                IntervalEvent::Failed(message) => utils::show_error_dialog("Interval capture stopped", &utils::with_log_hint(&message)),
+                IntervalEvent::Finished(count) => {
+                    log::info!("Interval capture done, {} shots saved.", count);
+                    finished = true;
+                }
+            }
+        }
+        if finished {
+            self.interval_capture = None;
+        } else {
+            ctx.request_repaint_after(Duration::from_secs(1));
+        }
+    }
+
+    /// Preview of what "Import from other tools" found; nothing changes until one is confirmed.
+    fn show_import_window(&mut self, ctx: &egui::Context) {
+        let Some(candidates) = &self.import_candidates else {
+            return;
+        };
+
+        let mut chosen = None;
+        let mut open = true;
+        egui::Window::new("Import from other tools")
+            .collapsible(false)
+            .resizable(false)
+            .open(&mut open)
+            .anchor(egui::Align2::CENTER_CENTER, vec2(0.0, 0.0))
+            .show(ctx, |ui| {
+                if candidates.is_empty() {
+                    ui.label("No ShareX or Greenshot settings were found in their default locations.");
+                }
+                for (index, candidate) in candidates.iter().enumerate() {
+                    ui.group(|ui| {
+                        ui.strong(candidate.source.label());
+                        ui.small(candidate.config_path.to_string_lossy());
+                        if let Some(dir) = &candidate.save_directory {
+                            ui.label(format!("Save Location: {}", dir));
+                        }
+                        if let Some(hotkey) = &candidate.snap_hotkey {
+                            ui.label(format!("Capture hotkey: {}", utils::format_hotkey(hotkey)));
+                        }
+                        for note in &candidate.notes {
+                            ui.weak(note);
+                        }
+                        if ui.add_enabled(!candidate.is_empty(), egui::Button::new("Import")).clicked() {
+                            chosen = Some(index);
+                        }
+                    });
+                }
+            });
+
+        if let Some(index) = chosen {
+            let candidate = self.import_candidates.take().unwrap().swap_remove(index);
+            log::info!("Importing settings from {} ({:?})", candidate.source.label(), candidate.config_path);
+            if let Some(dir) = candidate.save_directory {
+                self.config.use_save_directory(dir);
+                self.send_tray_menu();
+            }
+            if let Some(hotkey) = candidate.snap_hotkey
+                && hotkey != self.config.snap_hotkey
+            {
+                self.update_hotkey(HotkeyTarget::Snap, hotkey);
+            }
+        } else if !open {
+            self.import_candidates = None;
+        }
+    }
+
+    /// Confirmation for "Clear all app data", listing what will be deleted. Quits afterwards,
+    /// without writing the config again.
+    fn show_clear_data_window(&mut self, ctx: &egui::Context) {
+        let Some(paths) = &self.clear_data_paths else {
+            return;
+        };
+
+        let mut confirmed = false;
+        let mut cancelled = false;
+        egui::Window::new("Clear all app data")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, vec2(0.0, 0.0))
+            .show(ctx, |ui| {
+                ui.label("This deletes the settings, statistics, logs and temporary files, and removes CrabGrab from autostart. Saved captures are kept.");
+                for path in paths {
+                    ui.small(path.to_string_lossy());
+                }
+                ui.label("CrabGrab quits afterwards and starts with the default settings next time.");
+                ui.horizontal(|ui| {
+                    confirmed = ui.button("🗑 Delete and Quit").clicked();
+                    cancelled = ui.button("Cancel").clicked();
+                });
+            });
+
+        if cancelled {
+            self.clear_data_paths = None;
+        } else if confirmed && let Some(paths) = self.clear_data_paths.take() {
+            // Pending saves would write the stats again
+            self.flush_pipeline();
+            utils::set_autostart(false);
+            let failed = utils::remove_app_data(&paths);
+            if !failed.is_empty() {
+                utils::show_error_dialog("Some app data could not be deleted", &failed.join("\n"));
+            }
+            log::info!("App data cleared, quitting.");
+            self.quitting = true;
+            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+        }
+    }
+
+    /// Shows the settings window again where it was before an overlay took the window over.
+    fn restore_settings_window(&mut self, ctx: &egui::Context) {
+        // Only reuse the saved rect if it is still mostly on a monitor we just captured, and
+        // keep it on that one (a window across monitors with different scales gets resized).
+        let saved_rect = self.restore_rect.and_then(|rect| match self.last_monitors.as_deref() {
+            Some(monitors) => utils::fit_onto_monitor(rect, monitors),
+            None => Some(rect),
+        });
+
+        if let Some(saved_rect) = saved_rect {
+            // Convert back from physical px using the scale the window has *now*
+            // (the overlay's), since egui-winit multiplies by it when applying.
+            let ppi = ctx.pixels_per_point();
+            self.show_settings(ctx, (saved_rect.min.to_vec2() / ppi).to_pos2(), saved_rect.size() / ppi);
+        } else {
+            if self.restore_rect.is_some() {
+                log::warn!("Saved settings position is off-screen, using the default position.");
+            }
+            let position = egui::pos2(self.config.settings_last_x, self.config.settings_last_y);
+            let size = egui::vec2(self.config.settings_last_w, self.config.settings_last_h);
+            self.show_settings(ctx, position, size);
+        }
+    }
+
+    /// Capture hotkey pressed again while selecting.
+    fn handle_snap_while_snapping(&mut self, ctx: &egui::Context, triggered_at: Instant) {
+        match self.config.recapture_hotkey_behavior {
+            RecaptureHotkeyBehavior::Ignore => {}
+            RecaptureHotkeyBehavior::Cancel => self.cancel_capture(ctx),
+            RecaptureHotkeyBehavior::Recapture => {
+                log::debug!("Capture hotkey while selecting, capturing again.");
+                // The overlay must be gone before the screens are read, so they are read a
+                // little later (see the Snapping branch)
+                self.viewport.set_visible(ctx, false);
+                self.capture_flash = None;
+                self.large_capture_prompt = None;
+                self.pending_capture_scale = None;
+                self.recapture_at = Some(triggered_at + RECAPTURE_DELAY);
+                ctx.request_repaint_after(RECAPTURE_DELAY);
+            }
+        }
+    }
+
+    fn cancel_capture(&mut self, ctx: &egui::Context) {
+        let effects = self.machine.cancel(self.return_to_settings());
+        self.apply(ctx, effects);
+    }
+
+    /// Picking a preset region or the alignment test always goes back to settings.
+    fn return_to_settings(&self) -> bool {
+        self.config.after_capture_from_settings == AfterCaptureFromSettings::ReturnToSettings
+            || self.picking_preset.is_some()
+            || self.alignment_test
+    }
+
+    /// Hides the overlay and returns to wherever the capture was started from.
+    fn restore_after_capture(&mut self, ctx: &egui::Context) {
+        // INSTANT UI RESTORE
+        // We don't wait for the save/clipboard. We hide the window immediately.
+        log::debug!("Capture Finished. Restoring to: {:?}", self.machine.previous_state());
+        let effects = self.machine.finish_capture(self.return_to_settings());
+        self.apply(ctx, effects);
+    }
+
+    /// Panic button for an overlay that failed to hide and blocks the screen: drops whatever
+    /// is in progress and hides the window, no matter which state the app is in.
+    fn force_reset_to_idle(&mut self, ctx: &egui::Context) {
+        log::warn!("Panic hotkey: forcing reset to idle from {:?}.", self.machine.state());
+        self.identify_displays = None;
+        self.import_candidates = None;
+        self.interval_regions = None;
+        self.recording_hotkey = None;
+        self.viewport.set_visible(ctx, false);
+        let effects = self.machine.force_reset();
+        self.apply(ctx, effects);
+    }
+
+    /// Energy saver after a long idle: stops the periodic frames (hotkeys and the tray still wake
+    /// the app through their event handlers) and lets go of the audio device and buffers.
+    /// The GPU surface stays, eframe has no way to release it while the window exists.
+    fn enter_deep_sleep(&mut self) {
+        log::info!("Idle for {:?}, entering deep sleep.", self.idle_since.elapsed());
+        self.deep_sleep = true;
+        self.clear_capture_state();
+        self.pending_tiles.shrink_to_fit();
+        self.identify_displays = None;
+        self.sound_engine.suspend();
+    }
+
+    /// Called on the first frame after deep sleep, i.e. when a hotkey or tray click woke us up.
+    fn wake_from_deep_sleep(&mut self) {
+        let started = Instant::now();
+        self.deep_sleep = false;
+        self.idle_since = Instant::now();
+        self.sound_engine.resume();
+        log::info!("Woke up from deep sleep, resuming took {:?}", started.elapsed());
+    }
+
+    /// Drops everything a capture in progress holds on to.
+    fn clear_capture_state(&mut self) {
+        self.raw_image = None;
+        self.tiles = None;
+        self.pending_tiles.clear();
+        self.restore_rect = None;
+        self.capture_flash = None;
+        self.large_capture_prompt = None;
+        self.pending_capture_scale = None;
+        self.last_monitors = None;
+        self.pending_capture = None;
+        self.pending_color_parts.clear();
+        self.pending_coordinates = None;
+        self.pending_note.clear();
+        self.annotate_requested = false;
+        self.external_edit_requested = false;
+        self.note_prompted = false;
+        self.editor = None;
+        self.editor_filter_preview = None;
+        self.picking_preset = None;
+        self.alignment_test = false;
+        self.close_preview();
+        self.hidden_overlay_frames = None;
+        self.hidden_cursor = None;
+        self.recapture_at = None;
+    }
+
+    /// Grid spacing in overlay points, None with the grid off.
+    fn grid_step(&self, ctx: &egui::Context) -> Option<f32> {
+        (self.config.snap_to_grid && self.config.grid_size_px > 0)
+            .then(|| self.config.grid_size_px as f32 / ctx.pixels_per_point())
+    }
+
+    /// Moves a selection corner to the nearest grid point.
+    fn snap_to_grid(&self, ctx: &egui::Context, pos: egui::Pos2) -> egui::Pos2 {
+        match self.grid_step(ctx) {
+            Some(step) => egui::pos2((pos.x / step).round() * step, (pos.y / step).round() * step),
+            None => pos,
+        }
+    }
+
+    fn draw_grid(&self, ctx: &egui::Context, ui: &egui::Ui) {
+        let Some(step) = self.grid_step(ctx) else {
+            return;
+        };
+        let rect = ui.max_rect();
+        // Denser than this is just a gray haze (and a lot of lines on big desktops)
+        if step < 4.0 {
+            return;
+        }
+        let stroke = egui::Stroke::new(1.0, egui::Color32::from_rgba_unmultiplied(255, 255, 255, 20));
+        let mut x = 0.0;
+        while x <= rect.max.x {
+            ui.painter().vline(x, rect.y_range(), stroke);
+            x += step;
+        }
+        let mut y = 0.0;
+        while y <= rect.max.y {
+            ui.painter().hline(rect.x_range(), y, stroke);
+            y += step;
+        }
+    }
+
+    /// Small toolbar at the bottom of the monitor under the pointer, hidden while dragging.
+    fn show_snapping_tools(&mut self, ctx: &egui::Context) {
+        if self.machine.selection.is_active() || self.hidden_overlay_frames.is_some() {
+            self.snapping_tools_rect = None;
+            return;
+        }
+        let Some(monitor) = self.monitor_under_pointer(ctx) else {
+            return;
+        };
+
+        let response = egui::Area::new(egui::Id::new("snapping_tools"))
+            .pivot(egui::Align2::CENTER_BOTTOM)
+            .fixed_pos(monitor.center_bottom() - vec2(0.0, 16.0))
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        let toggle = ui.toggle_value(&mut self.config.snap_to_grid, "# Grid")
+                            .on_hover_text(format!("Snap the selection to a {} px grid", self.config.grid_size_px));
+                        if toggle.changed() {
+                            self.save_config();
+                        }
+                    });
+                });
+            }).response;
+        self.snapping_tools_rect = Some(response.rect);
+    }
+
+    /// The monitor the pointer is on (overlay points), the first one if it is on none.
+    fn monitor_under_pointer(&self, ctx: &egui::Context) -> Option<egui::Rect> {
+        let pointer = ctx.input(|i| i.pointer.latest_pos());
+        pointer
+            .and_then(|pos| self.monitor_layout.iter().find(|rect| rect.contains(pos)))
+            .or(self.monitor_layout.first())
+            .copied()
+    }
+
+    /// The shortcuts that do something right now, for the hints bar.
+    fn snapping_hints(&self) -> Vec<String> {
+        if self.machine.selection.is_active() {
+            let release = if self.picking_preset.is_some() {
+                "Release: set the preset region"
+            } else if self.alignment_test {
+                "Release: close the alignment test"
+            } else if self.annotate_requested {
+                "Release: annotate"
+            } else if self.external_edit_requested {
+                "Release: open in the editor"
+            } else {
+                "Release: capture"
+            };
+            return vec![release.to_string(), "Alt: grow from the center".to_string(), "Esc: cancel".to_string()];
+        }
+        vec![
+            "Drag: select".to_string(),
+            "Alt + drag: from the center".to_string(),
+            format!("G: guide ({})", self.config.alignment_guide.label()),
+            "Esc: cancel".to_string(),
+        ]
+    }
+
+    /// Dismissible bar above the snapping tools listing the overlay's shortcuts.
+    fn show_hints(&mut self, ctx: &egui::Context) {
+        if !self.config.show_hints || self.hidden_overlay_frames.is_some() || self.capture_flash.is_some() {
+            self.hints_rect = None;
+            return;
+        }
+        let Some(monitor) = self.monitor_under_pointer(ctx) else {
+            return;
+        };
+        let bottom = match self.snapping_tools_rect {
+            Some(tools) => tools.top() - 8.0,
+            None => monitor.bottom() - 16.0,
+        };
+        let text = self.snapping_hints().join("  ·  ");
+
+        let mut dismissed = false;
+        let response = egui::Area::new(egui::Id::new("snapping_hints"))
+            .pivot(egui::Align2::CENTER_BOTTOM)
+            .fixed_pos(egui::pos2(monitor.center().x, bottom))
+            .show(ctx, |ui| {
+                let text_color = egui::Color32::from_gray(230);
+                let galley = ui.painter().layout_no_wrap(text, egui::FontId::proportional(13.0), text_color);
+                let padding = vec2(10.0, 6.0);
+                let close_size = 16.0;
+                let size = vec2(
+                    galley.size().x + close_size + padding.x * 3.0,
+                    galley.size().y.max(close_size) + padding.y * 2.0,
+                );
+                let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+                let close_rect = egui::Rect::from_center_size(
+                    egui::pos2(rect.right() - padding.x - close_size / 2.0, rect.center().y),
+                    vec2(close_size, close_size),
+                );
+                let close = ui.interact(close_rect, egui::Id::new("snapping_hints_close"), egui::Sense::click())
+                    .on_hover_text("Hide the hints. They can be turned back on in the settings.");
+
+                let painter = ui.painter();
+                painter.rect_filled(rect, 6.0, egui::Color32::from_black_alpha(180));
+                painter.galley(egui::pos2(rect.left() + padding.x, rect.center().y - galley.size().y / 2.0), galley, text_color);
+                let close_color = if close.hovered() { egui::Color32::WHITE } else { egui::Color32::from_gray(160) };
+                painter.text(close_rect.center(), egui::Align2::CENTER_CENTER, "✖", egui::FontId::proportional(12.0), close_color);
+                dismissed = close.clicked();
+            }).response;
+        self.hints_rect = Some(response.rect);
+
+        if dismissed {
+            self.config.show_hints = false;
+            self.hints_rect = None;
+            self.save_config();
+        }
+    }
+
+    /// Monitor hitboxes in overlay points, for the window's pixels_per_point.
+    fn layout_monitors(monitors: &[MonitorData], physical_origin: (i32, i32), ppi: f32) -> Vec<egui::Rect> {
+        monitors.iter().map(|m| {
+            let phys_offset_x = (m.x - physical_origin.0) as f32;
+            let phys_offset_y = (m.y - physical_origin.1) as f32;
+            egui::Rect::from_min_size(
+                egui::pos2(phys_offset_x / ppi, phys_offset_y / ppi),
+                egui::vec2(m.width as f32 / ppi, m.height as f32 / ppi),
+            )
+        }).collect()
+    }
+
+    /// Replaces the overlay tiles; they are uploaded over the next frames (upload_pending_tiles).
+    fn queue_tiles(&mut self, tiles: Vec<(egui::Rect, egui::ColorImage)>) {
+        self.pending_tiles_total = tiles.len();
+        self.pending_tiles = tiles.into();
+        self.tiles = Some(Vec::with_capacity(self.pending_tiles_total));
+    }
+
+    /// Uploads at most `tiles_per_frame` queued tiles, so slow GPUs don't stall a whole frame.
+    fn upload_pending_tiles(&mut self, ctx: &egui::Context) {
+        if self.pending_tiles.is_empty() {
+            return;
+        }
+        let tiles = self.tiles.get_or_insert_with(Vec::new);
+        for _ in 0..self.config.tiles_per_frame.max(1) {
+            let Some((rect, pixels)) = self.pending_tiles.pop_front() else {
+                break;
+            };
+            tiles.push(utils::upload_tile(ctx, rect, pixels));
+        }
+
+        if self.pending_tiles.is_empty() {
+            log::debug!("All {} overlay tiles uploaded.", self.pending_tiles_total);
+        } else {
+            ctx.request_repaint();
+        }
+    }
+
+    fn draw_tiles(&self, painter: &egui::Painter, tint: egui::Color32) {
+        if let Some(tiles) = &self.tiles {
+            for (rect, texture) in tiles {
+                painter.image(
+                    texture.id(),
+                    *rect, // Rect is already in local physical coords (0,0 based)
+                    egui::Rect::from_min_max(egui::Pos2::ZERO, egui::Pos2::new(1.0, 1.0)),
+                    tint,
+                );
+
+                // Makes stitching seams visible on unusual monitor setups
+                if self.config.debug_overlay && self.config.show_tile_borders {
+                    painter.rect_stroke(
+                        *rect,
+                        0.0,
+                        egui::Stroke::new(1.0, egui::Color32::RED),
+                        eframe::epaint::StrokeKind::Inside,
+                    );
+                }
+            }
+        }
+    }
+
+    /// Debug overlay: index, name and physical geometry of every captured monitor.
+    fn draw_monitor_labels(&self, painter: &egui::Painter) {
+        let Some(monitors) = &self.last_monitors else {
+            return;
+        };
+
+        for (i, (rect, monitor)) in self.monitor_layout.iter().zip(monitors).enumerate() {
+            let label = format!(
+                "#{} {}\n{}x{} at ({}, {}) @ {}x",
+                i,
+                monitor.monitor_name.as_deref().unwrap_or("unnamed"),
+                monitor.width,
+                monitor.height,
+                monitor.x,
+                monitor.y,
+                monitor.scale_factor,
+            );
+            painter.debug_text(rect.min + egui::vec2(8.0, 8.0), egui::Align2::LEFT_TOP, egui::Color32::YELLOW, label);
+        }
+    }
+
+    /// Warning icon on the selection border when always-on-top windows of other programs
+    /// overlap it; they are in the capture even if they are easy to overlook.
+    fn draw_topmost_warning(&self, ui: &egui::Ui, selection_rect: egui::Rect) {
+        let ppi = self.predicted_ppi;
+        let covering: Vec<&str> = self.topmost_windows.iter()
+            .filter(|window| {
+                let rect = egui::Rect::from_min_size(
+                    egui::pos2(
+                        (window.x - self.physical_origin.0) as f32 / ppi,
+                        (window.y - self.physical_origin.1) as f32 / ppi,
+                    ),
+                    egui::vec2(window.width as f32 / ppi, window.height as f32 / ppi),
+                );
+                rect.intersects(selection_rect)
+            })
+            .map(|window| window.title.as_str())
+            .collect();
+        if covering.is_empty() {
+            return;
+        }
+
+        // Just outside the top-right corner, but kept on screen
+        let size = egui::vec2(22.0, 22.0);
+        let min = (selection_rect.right_top() + egui::vec2(4.0, -size.y - 4.0))
+            .clamp(ui.max_rect().min, ui.max_rect().max - size);
+        let icon_rect = egui::Rect::from_min_size(min, size);
+
+        let painter = ui.painter();
+        painter.rect_filled(icon_rect, 4.0, egui::Color32::from_black_alpha(200));
+        painter.text(icon_rect.center(), egui::Align2::CENTER_CENTER, "⚠", egui::FontId::proportional(16.0), egui::Color32::YELLOW);
+        ui.interact(icon_rect, egui::Id::new("topmost_warning"), egui::Sense::hover())
+            .on_hover_text(format!("Always-on-top windows in the selection:\n{}", covering.join("\n")));
+    }
+
+    /// "[CENTER]" under the selection while Alt keeps its center in place.
+    fn draw_center_anchor_label(&self, ui: &egui::Ui, selection_rect: egui::Rect) {
+        let painter = ui.painter();
+        let font = egui::FontId::monospace(12.0);
+        let galley = painter.layout_no_wrap("[CENTER]".to_string(), font, egui::Color32::WHITE);
+        let size = galley.size() + egui::vec2(8.0, 4.0);
+        // Below the bottom-left corner, but kept on screen
+        let min = (selection_rect.left_bottom() + egui::vec2(0.0, 4.0))
+            .clamp(ui.max_rect().min, ui.max_rect().max - size);
+        let label_rect = egui::Rect::from_min_size(min, size);
+        painter.rect_filled(label_rect, 3.0, egui::Color32::from_black_alpha(200));
+        painter.galley(label_rect.min + egui::vec2(4.0, 2.0), galley, egui::Color32::WHITE);
+    }
+
+    /// Draws the frozen screenshot with the current selection highlighted.
+    fn draw_capture_overlay(&self, ctx: &egui::Context, ui: &egui::Ui) {
+        // 1. Background (Dark)
+        self.draw_tiles(ui.painter(), egui::Color32::from_gray(120));
+        if self.config.debug_overlay || self.alignment_test {
+            self.draw_monitor_labels(ui.painter());
+        }
+
+        // Monitor frames, beneath the selection so they never hide its edge
+        if let (AppState::Snapping, Some([r, g, b, a])) = (self.machine.state(), self.config.monitor_frame) {
+            let stroke = egui::Stroke::new(3.0, egui::Color32::from_rgba_unmultiplied(r, g, b, a));
+            for rect in &self.monitor_layout {
+                ui.painter().rect_stroke(*rect, 0.0, stroke, egui::StrokeKind::Inside);
+            }
+        }
+
+        if self.machine.state() == AppState::Snapping && self.config.snap_to_grid {
+            self.draw_grid(ctx, ui);
+        }
+        if self.machine.state() == AppState::Snapping && self.config.show_viewfinder {
+            utils::draw_viewfinder(ui.painter(), ui.max_rect().shrink(self.config.viewfinder_inset));
+        }
+
+        // 2. Foreground (Bright)
+        if let Some(selection_rect) = self.machine.selection.rect() {
+            let clip_painter = ui.painter().with_clip_rect(selection_rect);
+
+            // Draw the tiles inside the selection with FULL brightness (No tint)
+            self.draw_tiles(&clip_painter, egui::Color32::WHITE);
+            // The editor's filters, they stay visible after it closed like the annotations
+            if let Some((_, texture)) = &self.editor_filter_preview {
+                let uv = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+                clip_painter.image(texture.id(), selection_rect, uv, egui::Color32::WHITE);
+            }
+
+            // Optional colored tint, only while selecting (it is not part of the capture)
+            if let (AppState::Snapping, Some([r, g, b, a])) = (self.machine.state(), self.config.selection_fill) {
+                ui.painter().rect_filled(selection_rect, 0.0, egui::Color32::from_rgba_unmultiplied(r, g, b, a));
+            }
+            if self.machine.state() == AppState::Snapping {
+                utils::draw_alignment_guide(ui.painter(), selection_rect, self.config.alignment_guide, self.config.guide_opacity);
+                if self.machine.selection.center_anchored {
+                    self.draw_center_anchor_label(ui, selection_rect);
+                }
+            }
+
+            match self.config.selection_style {
+                SelectionStyle::Classic => {
+                    // BLACK stroke so it stands out against the bright selection
+                    ui.painter().rect_stroke(
+                        selection_rect,
+                        0.0,
+                        egui::Stroke::new(2.0, egui::Color32::BLACK),
+                        eframe::epaint::StrokeKind::Middle,
+                    );
+
+                    // Inner white line for contrast
+                    ui.painter().rect_stroke(
+                        selection_rect,
+                        0.0,
+                        egui::Stroke::new(1.0, egui::Color32::WHITE),
+                        eframe::epaint::StrokeKind::Inside,
+                    );
+                }
+                SelectionStyle::MarchingAnts if self.config.reduce_motion => {
+                    utils::draw_marching_ants(ui.painter(), selection_rect, 0.0);
+                }
+                SelectionStyle::MarchingAnts => {
+                    utils::draw_marching_ants(ui.painter(), selection_rect, ui.input(|i| i.time));
+                    // Only animate while a selection exists, so the idle overlay stays cheap.
+                    ctx.request_repaint_after(Duration::from_millis(33));
+                }
+            }
+
+            self.draw_topmost_warning(ui, selection_rect);
+
+            // Annotations stay visible after the editor is closed (note prompt, action bar)
+            if let (Some(editor), Some(image)) = (&self.editor, &self.pending_capture) {
+                let image_size = egui::vec2(image.width() as f32, image.height() as f32);
+                editor.paint(&clip_painter, selection_rect, image_size);
+            }
+        }
+    }
+
+    /// Small prompt shown over the selection asking for an optional note to embed in the PNG.
+    fn show_note_input(&mut self, ctx: &egui::Context) {
+        let remaining = self.note_timeout.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            log::debug!("Note input timed out. Saving without a note.");
+            self.pending_note.clear();
+            self.continue_pending_capture(ctx);
+            return;
+        }
+
+        let anchor = self.machine.selection.rect().map_or(ctx.content_rect().center(), |rect| rect.center());
+
+        let mut confirmed = false;
+
+        egui::Window::new("Add a Note")
+            .id(egui::Id::new("note_input"))
+            .collapsible(false)
+            .resizable(false)
+            .movable(false)
+            .pivot(egui::Align2::CENTER_CENTER)
+            .fixed_pos(anchor)
+            .show(ctx, |ui| {
+                let response = ui.add(
+                    egui::TextEdit::singleline(&mut self.pending_note)
+                        .hint_text("What is this capture about?")
+                        .desired_width(300.0),
+                );
+                if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                    confirmed = true;
+                } else {
+                    response.request_focus();
+                }
+
+                // The timeout only counts idle time
+                if response.changed() {
+                    self.note_timeout = Instant::now() + NOTE_INPUT_TIMEOUT;
+                }
+
+                ui.horizontal(|ui| {
+                    if ui.button("Save with Note").clicked() {
+                        confirmed = true;
+                    }
+                    if ui.button("Skip").clicked() {
+                        self.pending_note.clear();
+                        confirmed = true;
+                    }
+                });
+                ui.weak(format!("Saving without a note in {}s", remaining.as_secs() + 1));
+            });
+
+        if confirmed {
+            self.continue_pending_capture(ctx);
+        } else {
+            // Keep the countdown ticking even without input
+            ctx.request_repaint_after(Duration::from_millis(250));
+        }
+    }
+
+    /// Floating toolbar shown over the selection after release (when enabled).
+    fn show_action_bar(&mut self, ctx: &egui::Context) {
+        let remaining = self.action_bar_timeout.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            log::debug!("Action bar timed out. Committing default action (Copy + Save).");
+            self.commit_pending_capture(ctx, true, true);
+            return;
+        }
+
+        let anchor = self.machine.selection.rect().map_or(ctx.content_rect().center(), |rect| rect.center());
+
+        // (copy, save) chosen by the user this frame
+        let mut choice: Option<(bool, bool)> = None;
+        let mut annotate = false;
+
+        egui::Window::new("")
+            .id(egui::Id::new("action_bar"))
+            .title_bar(false)
+            .resizable(false)
+            .movable(false)
+            .frame(egui::Frame::popup(&ctx.style()))
+            .pivot(egui::Align2::CENTER_CENTER)
+            .fixed_pos(anchor)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if ui.button("📋 Copy").clicked() {
+                        choice = Some((true, false));
+                    }
+                    if ui.button("💾 Save").clicked() {
+                        choice = Some((false, true));
+                    }
+                    if ui.button("✏ Annotate").clicked() {
+                        annotate = true;
+                    }
+                    ui.add_enabled(false, egui::Button::new("☁ Upload"))
+                        .on_disabled_hover_text("Uploading is not available yet.");
+                    if ui.button("🗑 Discard").clicked() {
+                        choice = Some((false, false));
+                    }
+                });
+                ui.weak(format!("Copy + Save in {}s", remaining.as_secs() + 1));
+            });
+
+        if let Some((copy, save)) = choice {
+            self.commit_pending_capture(ctx, copy, save);
+        } else if annotate {
+            self.open_editor();
+        } else {
+            // Keep the countdown ticking even without input
+            ctx.request_repaint_after(Duration::from_millis(250));
+        }
+    }
+
+    /// Full-screen review of the cropped capture (on the monitor of the selection) with Accept / Discard.
+    fn show_preview(&mut self, ctx: &egui::Context) {
+        let (Some(image), Some(preview), Some(checker)) = (&self.pending_capture, &self.preview_texture, &self.checker_texture) else {
+            return;
+        };
+
+        let selection_center = self.machine.selection.rect().map_or(ctx.content_rect().center(), |rect| rect.center());
+        let screen = self.monitor_layout.iter()
+            .find(|m| m.contains(selection_center))
+            .copied()
+            .unwrap_or_else(|| ctx.content_rect());
+
+        // Show the capture 1:1 in physical pixels, shrunk to fit above the buttons
+        let area = screen.shrink(48.0).with_max_y(screen.max.y - 96.0);
+        let image_size = egui::vec2(image.width() as f32, image.height() as f32) / ctx.pixels_per_point();
+        let fit = (area.width() / image_size.x).min(area.height() / image_size.y).min(1.0);
+        let image_rect = egui::Rect::from_center_size(area.center(), image_size * fit);
+
+        egui::CentralPanel::default().frame(egui::Frame::NONE).show(ctx, |ui| {
+            self.draw_tiles(ui.painter(), egui::Color32::from_gray(120));
+
+            let painter = ui.painter();
+            painter.rect_filled(screen, 0.0, egui::Color32::from_black_alpha(220));
+            // The checker texture repeats: one texel per 2 points, so 8 point cells
+            let checker_uv = egui::Rect::from_min_size(egui::Pos2::ZERO, image_rect.size() / 16.0);
+            painter.image(checker.id(), image_rect, checker_uv, egui::Color32::WHITE);
+            painter.image(
+                preview.id(),
+                image_rect,
+                egui::Rect::from_min_max(egui::Pos2::ZERO, egui::pos2(1.0, 1.0)),
+                egui::Color32::WHITE,
+            );
+        });
+
+        // true = accept, false = discard
+        let mut choice: Option<bool> = None;
+
+        egui::Window::new("")
+            .id(egui::Id::new("preview_buttons"))
+            .title_bar(false)
+            .resizable(false)
+            .movable(false)
+            .frame(egui::Frame::popup(&ctx.style()))
+            .pivot(egui::Align2::CENTER_TOP)
+            .fixed_pos(image_rect.center_bottom() + egui::vec2(0.0, 16.0))
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{} x {} px", image.width(), image.height()));
+                    ui.separator();
+                    if ui.button("✔ Accept").clicked() {
+                        choice = Some(true);
+                    }
+                    if ui.button("🗑 Discard").clicked() {
+                        choice = Some(false);
+                    }
+                });
+            });
+
+        if ctx.input(|i| i.key_pressed(egui::Key::Enter)) {
+            choice = Some(true);
+        }
+
+        match choice {
+            Some(true) => {
+                self.close_preview();
+                self.prompt_note_or_continue(ctx);
+            }
+            Some(false) => self.commit_pending_capture(ctx, false, false),
+            None => {}
+        }
+    }
+
+    /// Re-renders the filter preview when the editor's filters changed since the last frame.
+    fn update_editor_filter_preview(&mut self, ctx: &egui::Context) {
+        let (Some(editor), Some(image)) = (&self.editor, &self.pending_capture) else {
+            return;
+        };
+        if editor.filters().is_empty() {
+            self.editor_filter_preview = None;
+            return;
+        }
+        if self.editor_filter_preview.as_ref().is_some_and(|(filters, _)| filters == editor.filters()) {
+            return;
+        }
+
+        let mut preview = utils::make_thumbnail(image, EDITOR_PREVIEW_SIZE);
+        editor.apply_filters(&mut preview);
+        let texture = utils::load_preview_texture(ctx, &preview);
+        self.editor_filter_preview = Some((editor.filters().to_vec(), texture));
+    }
+
+    /// Annotation editor: drawing on the selection plus a small toolbar below it.
+    fn show_editor(&mut self, ctx: &egui::Context) {
+        let Some(selection_rect) = self.machine.selection.rect() else {
+            return;
+        };
+        let image_size = match &self.pending_capture {
+            Some(image) => egui::vec2(image.width() as f32, image.height() as f32),
+            None => return,
+        };
+
+        self.update_editor_filter_preview(ctx);
+
+        egui::CentralPanel::default().frame(egui::Frame::NONE).show(ctx, |ui| {
+            self.draw_capture_overlay(ctx, ui);
+
+            let response = ui.interact(selection_rect, egui::Id::new("editor_canvas"), egui::Sense::click_and_drag());
+            if let Some(editor) = &mut self.editor {
+                editor.handle_canvas_input(&response, selection_rect, image_size);
+
+                // Keyboard starts on the canvas; Tab cycles on into the toolbar
+                if response.clicked_by(egui::PointerButton::Primary) || response.drag_started() || ctx.memory(|m| m.focused().is_none()) {
+                    response.request_focus();
+                }
+                if response.has_focus() {
+                    // Arrow keys move the selected annotation instead of the focus
+                    ctx.memory_mut(|m| m.set_focus_lock_filter(response.id, egui::EventFilter {
+                        horizontal_arrows: true,
+                        vertical_arrows: true,
+                        ..Default::default()
+                    }));
+                    ctx.input(|i| {
+                        let step = if i.modifiers.shift { 10.0 } else { 1.0 };
+                        for (key, direction) in [
+                            (egui::Key::ArrowLeft, egui::Vec2::LEFT),
+                            (egui::Key::ArrowRight, egui::Vec2::RIGHT),
+                            (egui::Key::ArrowUp, egui::Vec2::UP),
+                            (egui::Key::ArrowDown, egui::Vec2::DOWN),
+                        ] {
+                            if i.key_pressed(key) {
+                                editor.nudge_selected(direction * step);
+                            }
+                        }
+                        if i.key_pressed(egui::Key::Space) {
+                            editor.select_next();
+                        }
+                    });
+                }
+            }
+            if response.hovered() {
+                ctx.set_cursor_icon(egui::CursorIcon::Crosshair);
+            }
+        });
+
+        let Some(editor) = &mut self.editor else {
+            return;
+        };
+
+        // Ctrl+Z / Ctrl+Y (Cmd on macOS)
+        ctx.input_mut(|i| {
+            if i.consume_shortcut(&egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Z)) {
+                editor.undo();
+            }
+            if i.consume_shortcut(&egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Y)) {
+                editor.redo();
+            }
+        });
+
+        // true = commit, false = discard
+        let mut finished: Option<bool> = None;
+
+        egui::Window::new("")
+            .id(egui::Id::new("editor_toolbar"))
+            .title_bar(false)
+            .resizable(false)
+            .movable(false)
+            .frame(egui::Frame::popup(&ctx.style()))
+            .pivot(egui::Align2::CENTER_TOP)
+            .fixed_pos(selection_rect.center_bottom() + egui::vec2(0.0, 8.0))
+            .show(ctx, |ui| {
+                // Tab walks these in order; Enter/Space activates the focused one (egui's click)
+                ui.horizontal(|ui| {
+                    for tool in Tool::ALL {
+                        focus_ring(ui.selectable_value(&mut editor.tool, tool, tool.label()));
+                    }
+                    ui.separator();
+                    focus_ring(ui.color_edit_button_srgba(&mut editor.color));
+                    focus_ring(ui.add(egui::Slider::new(&mut editor.thickness, 1.0..=32.0).suffix(" px")));
+                    ui.separator();
+                    let undo = ui.add_enabled(editor.can_undo(), egui::Button::new("↶")).on_hover_text("Undo (Ctrl+Z)");
+                    if focus_ring(undo).clicked() {
+                        editor.undo();
+                    }
+                    let redo = ui.add_enabled(editor.can_redo(), egui::Button::new("↷")).on_hover_text("Redo (Ctrl+Y)");
+                    if focus_ring(redo).clicked() {
+                        editor.redo();
+                    }
+                    ui.separator();
+                    for (label, hover, filter) in QUICK_FILTERS {
+                        if focus_ring(ui.button(label).on_hover_text(hover)).clicked() {
+                            editor.add_filter(filter);
+                        }
+                    }
+                    ui.separator();
+                    if focus_ring(ui.button("✔ Done")).clicked() {
+                        finished = Some(true);
+                    }
+                    if focus_ring(ui.button("🗑 Discard")).clicked() {
+                        finished = Some(false);
+                    }
+                });
+                ui.weak("Tab: toolbar · Space: select annotation · Arrows: move it (Shift: 10 px)");
+            });
+
+        match finished {
+            Some(true) => {
+                if let (Some(editor), Some(image)) = (&self.editor, &mut self.pending_capture) {
+                    editor.render_onto(image);
+                }
+                self.prompt_note_or_continue(ctx);
+            }
+            Some(false) => self.commit_pending_capture(ctx, false, false),
+            None => {}
+        }
+    }
+
+    fn hotkey_for(&self, target: HotkeyTarget) -> Option<HotKey> {
+        match target {
+            HotkeyTarget::Snap => Some(self.config.snap_hotkey),
+            HotkeyTarget::Annotate => Some(self.config.annotate_hotkey),
+            HotkeyTarget::ExternalEdit => Some(self.config.external_edit_hotkey),
+            HotkeyTarget::Panic => Some(self.config.panic_hotkey),
+            HotkeyTarget::Preset(index) => self.config.region_presets.get(index).and_then(RegionPreset::hotkey),
+            HotkeyTarget::NextProfile => self.config.profile_hotkey(),
+            HotkeyTarget::Chord => self.config.chord_hotkey(),
+        }
+    }
+
+    fn update_hotkey(&mut self, target: HotkeyTarget, new_hotkey: HotKey) {
+        log::debug!("Updating {:?} hotkey to: {:?}", target, new_hotkey);
+
+        let old_hotkey = self.hotkey_for(target);
+
+        // 1. Unregister the OLD hotkey (region presets may not have one yet)
+        if let Some(old_hotkey) = old_hotkey
+            && let Err(e) = self.hotkey_manager.unregister(old_hotkey)
+        {
+            log::error!("Failed to unregister old hotkey {:?}: {:?}", old_hotkey, e);
+            return;
+        }
+
+        // 2. Register the NEW hotkey
+        let result = self.hotkey_manager.register(new_hotkey);
+        if let Err(e) = result {
+            log::error!("Failed to register new hotkey {:?}: {:?}", new_hotkey, e);
+            // Attempt to restore the previous hotkey; log any failure but don't panic.
+            if let Some(old_hotkey) = old_hotkey
+                && let Err(e2) = self.hotkey_manager.register(old_hotkey)
+            {
+                log::error!("Failed to restore previous hotkey {:?}: {:?}", old_hotkey, e2);
+            }
+            return;
+        }
+
+        // 4. Update the config state
+        match target {
+            HotkeyTarget::Snap => self.config.snap_hotkey = new_hotkey,
+            HotkeyTarget::Annotate => self.config.annotate_hotkey = new_hotkey,
+            HotkeyTarget::ExternalEdit => self.config.external_edit_hotkey = new_hotkey,
+            HotkeyTarget::Panic => self.config.panic_hotkey = new_hotkey,
+            HotkeyTarget::Preset(index) => {
+                if let Some(preset) = self.config.region_presets.get_mut(index) {
+                    preset.set_hotkey(Some(new_hotkey));
+                }
+            }
+            HotkeyTarget::NextProfile => self.config.set_profile_hotkey(Some(new_hotkey)),
+            HotkeyTarget::Chord => self.config.set_chord_hotkey(Some(new_hotkey)),
+        }
+    }
+
+    /// Unregisters a region preset's hotkey and removes it from the preset.
+    fn clear_preset_hotkey(&mut self, index: usize) {
+        if let Some(hotkey) = self.hotkey_for(HotkeyTarget::Preset(index)) {
+            if let Err(e) = self.hotkey_manager.unregister(hotkey) {
+                log::error!("Failed to unregister preset hotkey {:?}: {:?}", hotkey, e);
+            }
+            self.config.region_presets[index].set_hotkey(None);
+        }
+    }
+
+    /// Unregisters the "next profile" hotkey and removes it from the config.
+    fn clear_profile_hotkey(&mut self) {
+        if let Some(hotkey) = self.config.profile_hotkey() {
+            if let Err(e) = self.hotkey_manager.unregister(hotkey) {
+                log::error!("Failed to unregister profile hotkey {:?}: {:?}", hotkey, e);
+            }
+            self.config.set_profile_hotkey(None);
+        }
+    }
+
+    /// Unregisters the chord hotkey and removes it from the config.
+    fn clear_chord_hotkey(&mut self) {
+        if let Some(hotkey) = self.config.chord_hotkey() {
+            if let Err(e) = self.hotkey_manager.unregister(hotkey) {
+                log::error!("Failed to unregister chord hotkey {:?}: {:?}", hotkey, e);
+            }
+            self.config.set_chord_hotkey(None);
+        }
+    }
+
+    /// Button showing a hotkey; clicking it records the next key combination.
+    fn show_hotkey_recorder(&mut self, ctx: &egui::Context, ui: &mut egui::Ui, target: HotkeyTarget) {
+        let recording = self.recording_hotkey == Some(target);
+        let btn_text = if recording {
+            // Show the modifiers held so far, so the user sees e.g. the Win key was picked up
+            let held = ctx.input(|i| utils::hotkey_modifiers(i.modifiers, utils::win_key_down()));
+            if held.is_empty() {
+                "Press any key... (Esc to cancel)".to_string()
+            } else {
+                format!("{}...", utils::format_modifiers(held))
+            }
+        } else {
+            self.hotkey_for(target).map_or("None".to_string(), |hotkey| utils::format_hotkey(&hotkey))
+        };
+
+        let btn = ui.button(btn_text).on_hover_text(
+            "Hotkeys follow the key's position, not its letter: they keep working after switching keyboard layouts. \
+             The label shows what the key types on the current layout."
+        );
+        if btn.clicked() {
+            self.recording_hotkey = Some(target);
+        }
+
+        if recording {
+            // Modifiers alone don't produce input events, keep polling so the label follows them
+            ctx.request_repaint_after(Duration::from_millis(50));
+            ui.memory_mut(|m| m.request_focus(btn.id));
+            let input = ctx.input(|i| i.clone());
+
+            if input.key_pressed(egui::Key::Escape) {
+                self.recording_hotkey = None;
+            }
+
+            // Hotkeys are physical keys, so record where the key is rather than what it types
+            let pressed = input.events.iter().filter_map(|event| match event {
+                egui::Event::Key { key, physical_key, pressed: true, .. } => Some(physical_key.unwrap_or(*key)),
+                _ => None,
+            });
+            for key in pressed {
+                if let Some(new_hotkey) = utils::convert_egui_to_hotkey(key, input.modifiers) {
+                    self.update_hotkey(target, new_hotkey);
+                    self.recording_hotkey = None;
+                    break;
+                }
+            }
+        }
+    }
+
+    /// True while one of the settings' folder dialogs is open. Only one is shown at a time.
+    fn folder_picker_open(&self) -> bool {
+        self.file_picker_receiver.is_some() || self.log_picker_receiver.is_some()
+    }
+
+    fn open_file_picker(&mut self) {
+        if self.folder_picker_open() {
+            return;
+        }
+        log::debug!("Spawning file picker thread...");
+        self.file_picker_receiver = Some(spawn_folder_picker());
+    }
+
+    fn open_log_location_picker(&mut self) {
+        if self.folder_picker_open() {
+            return;
+        }
+        log::debug!("Spawning log location picker thread...");
+        self.log_picker_receiver = Some(spawn_folder_picker());
+    }
+
+    fn check_file_picker_result(&mut self) {
+        // A dialog still open when the settings closed: its folder would land in a config that was already saved
+        let settings_open = self.machine.state() == AppState::Config;
+        if let Some(picked) = poll_folder_picker(&mut self.file_picker_receiver, "File picker") {
+            match picked {
+                Some(new_path) if settings_open => {
+                    log::debug!("File picker returned path: {}", new_path);
+                    self.config.use_save_directory(new_path);
+                    self.send_tray_menu();
+                }
+                Some(_) => log::debug!("Settings were closed, ignoring the picked save folder."),
+                None => log::debug!("File picker cancelled."),
+            }
+        }
+        if let Some(picked) = poll_folder_picker(&mut self.log_picker_receiver, "Log location picker") {
+            match picked {
+                Some(new_dir) if settings_open => {
+                    let log_file = std::path::Path::new(&new_dir).join("crab-grab.log");
+                    log::info!("Log file will move to {:?} after a restart.", log_file);
+                    self.config.log_file_path = Some(log_file.to_string_lossy().into_owned());
+                }
+                Some(_) => log::debug!("Settings were closed, ignoring the picked log location."),
+                None => log::debug!("Log location picker cancelled."),
+            }
+        }
+    }
+
+    /// Settings "Test Capture": grabs a small region of the monitor the settings are on and
+    /// runs it through the normal pipeline (script, save, clipboard), so the result reflects
+    /// the current settings.
+    fn run_test_capture(&mut self, ctx: &egui::Context) {
+        let window_center = ctx.input(|i| i.viewport().outer_rect.map(|rect| rect.center() * i.pixels_per_point()));
+        let point = window_center.map_or((0, 0), |center| (center.x as i32, center.y as i32));
+
+        let started = Instant::now();
+        match crate::capture::capture_test_region(point, TEST_CAPTURE_SIZE.0, TEST_CAPTURE_SIZE.1) {
+            Ok(image) => {
+                let took = started.elapsed();
+                self.play_shutter_sound();
+                let (tx, rx) = channel();
+                self.test_capture_receiver = Some((rx, took));
+                self.test_capture_result = None;
+                self.spawn_capture_pipeline(image, true, true, String::new(), CaptureMode::Everything, Some(tx));
+            }
+            Err(e) => {
+                log::error!("Test capture failed: {}", e);
+                self.test_capture_result = Some(Err(format!("Capture failed: {}", e)));
+            }
+        }
+    }
+
+    fn check_test_capture_result(&mut self) {
+        if let Some((rx, took)) = &self.test_capture_receiver {
+            match rx.try_recv() {
+                Ok(mut report) => {
+                    report.stages.insert(0, ("Capture", *took));
+                    self.test_capture_result = Some(Ok(report));
+                    self.test_capture_receiver = None;
+                }
+                Err(std::sync::mpsc::TryRecvError::Empty) => {}
+                Err(e) => {
+                    log::error!("Test capture channel error: {:?}", e);
+                    self.test_capture_result = Some(Err("The capture pipeline stopped, see the log.".to_string()));
+                    self.test_capture_receiver = None;
+                }
+            }
+        }
+    }
+
+    /// What the last test capture did: where it went, how big it is and which steps took how long.
+    fn show_test_capture_result(&self, ui: &mut egui::Ui) {
+        let Some(result) = &self.test_capture_result else {
+            return;
+        };
+        let report = match result {
+            Ok(report) => report,
+            Err(message) => {
+                ui.colored_label(ui.visuals().error_fg_color, message);
+                return;
+            }
+        };
+
+        egui::Frame::group(ui.style()).show(ui, |ui| {
+            for error in &report.errors {
+                ui.colored_label(ui.visuals().error_fg_color, format!("⚠ {}", error));
+            }
+            egui::Grid::new("test_capture_result").num_columns(2).striped(true).show(ui, |ui| {
+                match &report.saved_path {
+                    Some(path) => {
+                        let file_name = path.file_name().map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+                        ui.label("File name");
+                        ui.label(file_name);
+                        ui.end_row();
+                        ui.label("Saved to");
+                        ui.label(path.display().to_string());
+                        ui.end_row();
+                        ui.label("Size");
+                        ui.label(stats::format_bytes(report.bytes_saved));
+                        ui.end_row();
+                    }
+                    None => {
+                        ui.label("Saved to");
+                        ui.label("Nothing saved (handled by the Lua script or failed, see the log)");
+                        ui.end_row();
+                    }
+                }
+                for (stage, took) in &report.stages {
+                    ui.label(*stage);
+                    ui.label(format!("{:.1} ms", took.as_secs_f64() * 1000.0));
+                    ui.end_row();
+                }
+                let total: Duration = report.stages.iter().map(|(_, took)| *took).sum();
+                ui.strong("Total");
+                ui.strong(format!("{:.1} ms", total.as_secs_f64() * 1000.0));
+                ui.end_row();
+            });
+        });
+    }
+
+    /// Reacts to captures the pipeline finished with. Features that need to know when a
+    /// capture was saved or copied hook in here rather than passing their own channel along.
+    fn poll_capture_results(&mut self, ctx: &egui::Context) {
+        for result in self.pipeline.poll_results() {
+            log::debug!("Capture #{} done: {}x{}, saved to {:?}, clipboard ok: {}, save ok: {}",
+                result.seq, result.dimensions.0, result.dimensions.1, result.path, result.clipboard_ok, result.save_ok);
+            if !result.clipboard_ok || !result.save_ok {
+                // The capture itself worked, so no dialog; the tray icon hints at the log
+                self.tray_error_until = Some(Instant::now() + TRAY_ERROR_DURATION);
+                self.play_error_sound();
+                ctx.request_repaint();
+            }
+        }
+    }
+
+    fn handle_hotkey_activation(&mut self) {
+        if matches!(self.machine.state(), AppState::Snapping | AppState::ActionBar | AppState::NoteInput | AppState::Editing | AppState::Preview) {
+            if !self.cancel_registered {
+                 match self.hotkey_manager.register(self.cancel_hotkey) {
+                     Err(err) => log::error!("Failed to register cancel hotkey: {:?}", err),
+                     Ok(_) => self.cancel_registered = true,
                  }
             }
         } else{
@@ -506,143 +3036,160 @@ impl CrabGrabApp {
 }
 
 impl eframe::App for CrabGrabApp {
+    fn clear_color(&self, visuals: &egui::Visuals) -> [f32; 4] {
+        // The overlay must never clear to an opaque color, otherwise the first frame after
+        // showing the window flashes before the tiles are drawn.
+        if self.machine.state() == AppState::Config {
+            visuals.panel_fill.to_normalized_gamma_f32()
+        } else {
+            egui::Rgba::TRANSPARENT.to_array()
+        }
+    }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        // Quitting from the tray already waited, closing the window any other way didn't
+        self.flush_pipeline();
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        if self.deep_sleep {
+            self.wake_from_deep_sleep();
+        }
+        if self.machine.state() != AppState::Idle {
+            self.idle_since = Instant::now();
+        }
         self.handle_tray_events(ctx);
         self.handle_hotkey_events(ctx);
+        self.check_chord_timeout();
         self.check_file_picker_result();
+        self.check_test_capture_result();
+        self.poll_capture_results(ctx);
+        self.update_tray_tooltip(ctx);
+        self.update_tray_icon(ctx);
+        self.poll_interval_capture(ctx);
+        self.upload_pending_tiles(ctx);
         self.handle_hotkey_activation();
 
         // --- Drawing Logic ---
-        match self.state {
+        match self.machine.state() {
             AppState::Idle => {
-                ctx.send_viewport_cmd(egui::ViewportCommand::OuterPosition(egui::pos2(10000.0, 10000.0)));
-                ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(vec2(0.0, 0.0)));
-                ctx.request_repaint_after(Duration::from_millis(100));
+                let sleep_after = self.config.idle_sleep_minutes.map(|minutes| Duration::from_secs(minutes as u64 * 60));
+                if sleep_after.is_some_and(|after| self.idle_since.elapsed() >= after)
+                    && self.interval_capture.is_none()
+                {
+                    // No repaint requested: from here on only the event handlers wake us up
+                    self.enter_deep_sleep();
+                } else if self.chord_armed.is_some() {
+                    self.show_chord_hint(ctx);
+                    ctx.request_repaint_after(Duration::from_millis(100));
+                } else {
+                    // Skip parking the window for one frame when a handshake was just sent,
+                    // otherwise the move to the origin monitor would be overridden right away.
+                    if !self.check_monitor_layout(ctx) {
+                        self.park_window(ctx);
+                    }
+                    ctx.request_repaint_after(Duration::from_millis(100));
+                }
             }
             AppState::Snapping => {
+                if let Some(at) = self.recapture_at {
+                    if Instant::now() >= at {
+                        self.recapture_at = None;
+                        // Timed from the hotkey press
+                        self.handle_begin_capture(ctx, at - RECAPTURE_DELAY);
+                    } else {
+                        ctx.request_repaint_after(at - Instant::now());
+                    }
+                    return;
+                }
+
                 // Check whether the window's actual pixels_per_point has been negotiated.
-                // If it differs from our predicted PPI, rebuild tiles and hitboxes.
+                // If it differs from our predicted PPI, rebuild tiles and hitboxes. This also
+                // catches a scale factor changed mid-session: the capture reports the new
+                // scale while the window may still be on the old one for a while.
                 let actual_ppi = ctx.pixels_per_point();
+                if !self.ppi_checked {
+                    log::debug!("Overlay PPI: predicted {}, actual {}", self.predicted_ppi, actual_ppi);
+                    self.ppi_checked = true;
+                }
                 if (actual_ppi - self.predicted_ppi).abs() > 0.001 {
                     if let Some(monitors) = &self.last_monitors {
                         log::debug!("Detected actual PPI {} differs from predicted {}. Rebuilding tiles.", actual_ppi, self.predicted_ppi);
                         // Rebuild tiles using the actual PPI
-                        let tiles = utils::load_screens_as_tiles(
-                            ctx,
+                        let tiles = utils::decode_screen_tiles(
                             monitors,
                             self.physical_origin,
                             actual_ppi,
+                            self.config.compensate_sdr_white_level,
+                            self.config.gpu_tile_budget_mb,
                         );
-                        self.tiles = Some(tiles);
 
                         // Rebuild monitor_layout hitboxes
-                        self.monitor_layout = monitors.iter().map(|m| {
-                            let phys_offset_x = (m.x - self.physical_origin.0) as f32;
-                            let phys_offset_y = (m.y - self.physical_origin.1) as f32;
-
-                            let egui_x = phys_offset_x / actual_ppi;
-                            let egui_y = phys_offset_y / actual_ppi;
-
-                            let egui_w = m.width as f32 / actual_ppi;
-                            let egui_h = m.height as f32 / actual_ppi;
-
-                            egui::Rect::from_min_size(
-                                egui::pos2(egui_x, egui_y),
-                                egui::vec2(egui_w, egui_h)
-                            )
-                        }).collect();
+                        self.monitor_layout = Self::layout_monitors(monitors, self.physical_origin, actual_ppi);
 
                         // Update predicted_ppi so we don't rebuild repeatedly
                         self.predicted_ppi = actual_ppi;
+                        self.queue_tiles(tiles);
                     }
                 }
 
+                if ctx.input(|i| i.key_pressed(egui::Key::G)) {
+                    self.config.alignment_guide = self.config.alignment_guide.next();
+                    log::debug!("Alignment guide: {:?}", self.config.alignment_guide);
+                    self.save_config();
+                }
+
                 let mut finish_capture: Option<(egui::Rect, egui::Vec2)> = None;
 
                 egui::CentralPanel::default().frame(egui::Frame::NONE).show(ctx, |ui| {
-                    let draw_tiles = |painter: &egui::Painter, tint: egui::Color32| {
-                        if let Some(tiles) = &self.tiles {
-                            for (rect, texture) in tiles {
-                                painter.image(
-                                    texture.id(),
-                                    *rect, // Rect is already in local physical coords (0,0 based)
-                                    egui::Rect::from_min_max(egui::Pos2::ZERO, egui::Pos2::new(1.0, 1.0)),
-                                    tint,
-                                );
-                            }
-                        }
-                    };
-
-                    // 1. Background (Dark)
-                    draw_tiles(ui.painter(), egui::Color32::from_gray(120));
-
                     let input = ctx.input(|i| i.clone());
-                    if input.pointer.any_pressed() {
-                        if let Some(pos) = input.pointer.interact_pos() {
-                            self.start_pos = Some(pos);
-                            self.current_pos = Some(pos);
+                    let on_tools = |pos: egui::Pos2| {
+                        [self.snapping_tools_rect, self.hints_rect].into_iter().flatten().any(|rect| rect.contains(pos))
+                    };
+                    if self.capture_flash.is_some() || self.large_capture_prompt.is_some() {
+                        // The selection is final, the flash or the prompt only has to run out
+                    } else if input.pointer.any_pressed() {
+                        if let Some(pos) = input.pointer.interact_pos()
+                            && !on_tools(pos)
+                        {
+                            let pos = self.snap_to_grid(ctx, pos);
+                            self.machine.selection.press(pos);
                         }
                     } else if input.pointer.any_down() {
                         if let Some(pos) = input.pointer.interact_pos() {
-                            self.current_pos = Some(pos);
+                            let pos = self.snap_to_grid(ctx, pos);
+                            // Alt grows the selection symmetrically around where the drag began,
+                            // kept on screen since the crop can't reach past the desktop's edge
+                            self.machine.selection.drag(pos, input.modifiers.alt, ui.max_rect());
                         }
                     }  else if input.pointer.any_released() {
-                        if let (Some(start), Some(end)) = (self.start_pos, self.current_pos) {
-                            let rect = egui::Rect::from_two_pos(start, end);
-                            finish_capture = Some((rect, ui.max_rect().size()));
+                        if let Some(rect) = self.machine.selection.rect() {
+                            let window_size = ui.max_rect().size();
+                            // Picking a preset region isn't a capture, nothing to flash for
+                            let flash = self.config.capture_flash && !self.config.reduce_motion && self.picking_preset.is_none();
+                            if let Some(prompt) = self.large_capture_prompt(rect, window_size) {
+                                // The prompt's buttons have to be clicked
+                                self.hidden_cursor = None;
+                                self.large_capture_prompt = Some(prompt);
+                            } else if flash {
+                                self.capture_flash = Some(CaptureFlash { started: Instant::now(), rect, window_size });
+                            } else {
+                                finish_capture = Some((rect, window_size));
+                            }
                         }
                     }
 
-                    if let (Some(start), Some(current)) = (self.start_pos, self.current_pos) {
-                        let selection_rect = egui::Rect::from_two_pos(start, current);
-                        let clip_painter = ui.painter().with_clip_rect(selection_rect);
-
-                        // Draw the tiles inside the selection with FULL brightness (No tint)
-                        draw_tiles(&clip_painter, egui::Color32::WHITE);
-
-                        // CHANGE: Use BLACK stroke so it stands out against the white background
-                        ui.painter().rect_stroke(
-                            selection_rect,
-                            0.0,
-                            egui::Stroke::new(2.0, egui::Color32::BLACK),
-                            eframe::epaint::StrokeKind::Middle,
-                        );
-
-                        // Optional: Inner white line for "marching ants" contrast
-                        ui.painter().rect_stroke(
-                            selection_rect,
-                            0.0,
-                            egui::Stroke::new(1.0, egui::Color32::WHITE),
-                            eframe::epaint::StrokeKind::Inside,
-                        );
-                    }
-
-                    // 2. Foreground (Bright)
-                    if let (Some(start), Some(current)) = (self.start_pos, self.current_pos) {
-                        let selection_rect = egui::Rect::from_two_pos(start, current);
-
-                        let clip_painter = ui.painter().with_clip_rect(selection_rect);
-
-                        // We use 'draw_tiles' again here.
-                        // This is why we couldn't mutate self earlier!
-                        draw_tiles(&clip_painter, egui::Color32::WHITE);
-
-                        ui.painter().rect_stroke(
-                            selection_rect,
-                            0.0,
-                            egui::Stroke::new(1.0, egui::Color32::WHITE),
-                            eframe::epaint::StrokeKind::Middle,
-                        );
-
-                        ui.painter().rect_stroke(
-                            selection_rect,
-                            0.0,
-                            egui::Stroke::new(1.0, egui::Color32::from_black_alpha(100)),
-                            eframe::epaint::StrokeKind::Inside,
-                        );
+                    self.draw_capture_overlay(ctx, ui);
+                    if let Some(flash) = self.capture_flash {
+                        // The overlay shows the frozen screenshot, the flash never ends up in the capture
+                        let fade = 1.0 - flash.started.elapsed().as_secs_f32() / CAPTURE_FLASH_DURATION.as_secs_f32();
+                        ui.painter().rect_filled(flash.rect, 0.0, egui::Color32::WHITE.gamma_multiply(fade.clamp(0.0, 1.0)));
+                        ctx.request_repaint();
                     }
 
-                    if self.config.custom_cursor {
+                    if self.config.hide_cursor_during_capture && self.large_capture_prompt.is_none() {
+                        ctx.set_cursor_icon(egui::CursorIcon::None);
+                    } else if self.config.custom_cursor {
                         if let Some(texture) = &self.cursor_texture {
                             ctx.set_cursor_icon(egui::CursorIcon::None);
                             utils::draw_custom_cursor(ui, texture);
@@ -655,94 +3202,873 @@ impl eframe::App for CrabGrabApp {
                     }
                 });
 
-                if let Some((rect, window_size)) = finish_capture {
-                    self.handle_capture_finish(ctx, rect, window_size);
+                self.show_snapping_tools(ctx);
+                self.show_hints(ctx);
+                if let Some(selection) = self.show_large_capture_prompt(ctx) {
+                    finish_capture = Some(selection);
+                }
+
+                if !self.pending_tiles.is_empty() {
+                    let uploaded = self.pending_tiles_total - self.pending_tiles.len();
+                    egui::Area::new(egui::Id::new("tile_upload_progress"))
+                        .anchor(egui::Align2::CENTER_TOP, vec2(0.0, 16.0))
+                        .show(ctx, |ui| {
+                            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                                ui.add(egui::ProgressBar::new(uploaded as f32 / self.pending_tiles_total as f32)
+                                    .desired_width(240.0)
+                                    .text(format!("Loading screens {}/{}", uploaded, self.pending_tiles_total)));
+                            });
+                        });
+                }
+
+                match self.hidden_overlay_frames {
+                    Some(0) => {
+                        // The previous frame already contains the tiles, so it is safe to show now.
+                        self.hidden_overlay_frames = None;
+                        self.viewport.set_visible(ctx, true);
+                        ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+                        if let Some(triggered_at) = self.capture_triggered_at.take() {
+                            log::debug!("Timing: trigger -> first presented frame took {:?}", triggered_at.elapsed());
+                        }
+                    }
+                    Some(frames) => {
+                        self.hidden_overlay_frames = Some(frames - 1);
+                        ctx.request_repaint();
+                    }
+                    None => {}
                 }
 
+                if let Some(flash) = self.capture_flash
+                    && flash.started.elapsed() >= CAPTURE_FLASH_DURATION
+                {
+                    self.capture_flash = None;
+                    finish_capture = Some((flash.rect, flash.window_size));
+                }
 
+                if let Some((rect, window_size)) = finish_capture {
+                    self.handle_capture_finish(ctx, rect, window_size);
+                }
             }
+            AppState::ActionBar => {
+                egui::CentralPanel::default().frame(egui::Frame::NONE).show(ctx, |ui| {
+                    self.draw_capture_overlay(ctx, ui);
+                });
+                self.show_action_bar(ctx);
+            }
+            AppState::NoteInput => {
+                egui::CentralPanel::default().frame(egui::Frame::NONE).show(ctx, |ui| {
+                    self.draw_capture_overlay(ctx, ui);
+                });
+                self.show_note_input(ctx);
+            }
+            AppState::Editing => self.show_editor(ctx),
+            AppState::Preview => self.show_preview(ctx),
+            AppState::IdentifyDisplays => self.show_identify_displays(ctx),
             AppState::Config => {
                 // 1. Handle "X" Button (Close Request)
                 // If user clicked X on the window title bar:
-                if ctx.input(|i| i.viewport().close_requested()) {
-                    // A. Cancel the actual kill command
-                    ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
-                    self.handle_close_settings(ctx);
+                if ctx.input(|i| i.viewport().close_requested()) && !self.quitting {
+                    match self.config.close_button_action {
+                        CloseButtonAction::Tray => {
+                            // A. Cancel the actual kill command
+                            ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+                            self.handle_close_settings(ctx);
+                        }
+                        CloseButtonAction::Quit => {
+                            // Still remembers where the window was
+                            self.handle_close_settings(ctx);
+                            self.quit(ctx);
+                        }
+                    }
                 }
 
+                // Bottom Action Bar (outside the scroll area, so it is always reachable)
+                egui::TopBottomPanel::bottom("settings_bottom_bar").show(ctx, |ui| {
+                    ui.vertical_centered(|ui| {
+                        if ui.button("Close Settings").clicked() {
+                            self.handle_close_settings(ctx);
+                        }
+                    });
+                });
+
+                self.show_import_window(ctx);
+                self.show_clear_data_window(ctx);
+                self.show_interval_window(ctx);
+
                 egui::CentralPanel::default().show(ctx, |ui| {
                     ui.heading("CrabGrab Settings");
                     ui.separator();
 
-                    // 1. Storage & Saving
-                    ui.heading("Storage");
-                    ui.horizontal(|ui| {
-                        ui.label("Save Location:");
-                        // Display the path in a monospace font so it looks like code
-                        ui.code(&self.config.save_directory);
+                    let mut dismiss_banner = false;
+                    if let Some(banner) = self.capture_failure_banner.clone() {
+                        egui::Frame::group(ui.style())
+                            .fill(ui.visuals().extreme_bg_color)
+                            .stroke(egui::Stroke::new(1.0, ui.visuals().error_fg_color))
+                            .show(ui, |ui| {
+                                ui.colored_label(ui.visuals().error_fg_color, format!("⚠ {}", banner));
+                                match utils::current_log_file_path() {
+                                    Some(path) => ui.label(format!("The log may tell more: {}", path.display())),
+                                    None => ui.label("The log is unavailable."),
+                                };
+                                ui.horizontal(|ui| {
+                                    if ui.button("📸 Test Capture").clicked() {
+                                        self.run_test_capture(ctx);
+                                    }
+                                    dismiss_banner = ui.button("Dismiss").clicked();
+                                });
+                            });
+                        ui.separator();
+                    }
+                    if dismiss_banner {
+                        self.capture_failure_banner = None;
+                    }
+
+                    egui::ScrollArea::vertical().auto_shrink(false).show(ui, |ui| {
+                        // 1. Storage & Saving
+                        ui.heading("Storage");
+                        ui.horizontal(|ui| {
+                            ui.label("Save Location:");
+                            // Display the path in a monospace font so it looks like code
+                            ui.code(&self.config.save_directory);
+
+                            if ui.add_enabled(!self.folder_picker_open(), egui::Button::new("📂 Browse...")).clicked() {
+                                self.open_file_picker();
+                            }
+                        });
 
-                        if ui.button("📂 Browse...").clicked() {
-                            self.open_file_picker();
+                        ui.checkbox(&mut self.config.auto_save, "Auto-save screenshots to file");
+                        ui.horizontal(|ui| {
+                            let mut limit = self.config.max_saved_files.is_some();
+                            if ui.checkbox(&mut limit, "Keep only the newest").changed() {
+                                self.config.max_saved_files = limit.then_some(100);
+                            }
+                            if let Some(max_files) = &mut self.config.max_saved_files {
+                                ui.add(egui::DragValue::new(max_files).range(1..=100_000).suffix(" captures"));
+                                ui.checkbox(&mut self.config.delete_to_trash, "Move old ones to the trash");
+                            }
+                        }).response.on_hover_text(
+                            "After each save, the oldest screenshot_*.png files in the save location are deleted."
+                        );
+                        if cfg!(target_os = "windows") {
+                            ui.horizontal(|ui| {
+                                ui.label("Paths over 250 characters:");
+                                egui::ComboBox::from_id_salt("long_path_strategy")
+                                    .selected_text(match self.config.long_path_strategy {
+                                        LongPathStrategy::ShortenFileName => "Shorten file name",
+                                        LongPathStrategy::ExtendedPrefix => "Use long path",
+                                    })
+                                    .show_ui(ui, |ui| {
+                                        ui.selectable_value(&mut self.config.long_path_strategy, LongPathStrategy::ShortenFileName, "Shorten file name");
+                                        ui.selectable_value(&mut self.config.long_path_strategy, LongPathStrategy::ExtendedPrefix, "Use long path");
+                                    });
+                            }).response.on_hover_text(
+                                "Long paths keep the full name, but some programs (including Explorer) cannot open them."
+                            );
                         }
-                    });
+                        ui.horizontal(|ui| {
+                            ui.label("If the file name is taken:");
+                            egui::ComboBox::from_id_salt("collision_policy")
+                                .selected_text(self.config.collision_policy.label())
+                                .show_ui(ui, |ui| {
+                                    for policy in CollisionPolicy::ALL {
+                                        ui.selectable_value(&mut self.config.collision_policy, policy, policy.label())
+                                            .on_hover_text(policy.description());
+                                    }
+                                });
+                        }).response.on_hover_text(self.config.collision_policy.description());
+                        ui.horizontal(|ui| {
+                            ui.label("PNG filter:");
+                            egui::ComboBox::from_id_salt("png_filter")
+                                .selected_text(self.config.png_filter.label())
+                                .show_ui(ui, |ui| {
+                                    for filter in PngFilter::ALL {
+                                        ui.selectable_value(&mut self.config.png_filter, filter, filter.label())
+                                            .on_hover_text(filter.description());
+                                    }
+                                });
+                        }).response.on_hover_text(self.config.png_filter.description());
+                        ui.horizontal(|ui| {
+                            ui.label("Color profile:");
+                            egui::ComboBox::from_id_salt("color_management")
+                                .selected_text(self.config.color_management.label())
+                                .show_ui(ui, |ui| {
+                                    for mode in ColorManagement::ALL {
+                                        ui.selectable_value(&mut self.config.color_management, mode, mode.label())
+                                            .on_hover_text(mode.description());
+                                    }
+                                });
+                        }).response.on_hover_text("For wide-gamut or calibrated monitors. Monitor profiles are read on Windows only.");
+                        ui.checkbox(&mut self.config.prompt_for_note, "Ask for a note before saving")
+                            .on_hover_text("The note is embedded as a comment in the saved PNG.");
+                        ui.checkbox(&mut self.config.preview_before_save, "Preview capture before saving")
+                            .on_hover_text("Review the cropped image full-screen and Accept or Discard it.");
+                        ui.checkbox(&mut self.config.show_action_bar, "Show action bar after selecting")
+                            .on_hover_text("Choose Copy / Save / Discard after each capture. Copy + Save is applied after 10 seconds.");
 
-                    ui.checkbox(&mut self.config.auto_save, "Auto-save screenshots to file");
+                        ui.horizontal(|ui| {
+                            ui.label("After capturing from Settings:");
+                            egui::ComboBox::from_id_salt("after_capture_from_settings")
+                                .selected_text(match self.config.after_capture_from_settings {
+                                    AfterCaptureFromSettings::ReturnToSettings => "Return to Settings",
+                                    AfterCaptureFromSettings::CloseSettings => "Close Settings",
+                                })
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(&mut self.config.after_capture_from_settings, AfterCaptureFromSettings::ReturnToSettings, "Return to Settings");
+                                    ui.selectable_value(&mut self.config.after_capture_from_settings, AfterCaptureFromSettings::CloseSettings, "Close Settings");
+                                });
+                        });
 
-                    ui.separator();
+                        ui.horizontal(|ui| {
+                            ui.label("Capture hotkey while selecting:");
+                            egui::ComboBox::from_id_salt("recapture_hotkey_behavior")
+                                .selected_text(match self.config.recapture_hotkey_behavior {
+                                    RecaptureHotkeyBehavior::Ignore => "Ignore",
+                                    RecaptureHotkeyBehavior::Recapture => "Capture again",
+                                    RecaptureHotkeyBehavior::Cancel => "Cancel",
+                                })
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(&mut self.config.recapture_hotkey_behavior, RecaptureHotkeyBehavior::Ignore, "Ignore");
+                                    ui.selectable_value(&mut self.config.recapture_hotkey_behavior, RecaptureHotkeyBehavior::Recapture, "Capture again");
+                                    ui.selectable_value(&mut self.config.recapture_hotkey_behavior, RecaptureHotkeyBehavior::Cancel, "Cancel");
+                                });
+                        }).response.on_hover_text("Capture again picks up screen content that changed since the overlay opened.");
 
-                    // 2. Visuals & Audio
-                    ui.heading("Experience");
-                    ui.checkbox(&mut self.config.custom_cursor, "Use CrabGrab Cursor");
-                    ui.checkbox(&mut self.config.play_sound, "Play Camera Shutter Sound");
+                        ui.horizontal(|ui| {
+                            ui.label("Window close button:");
+                            egui::ComboBox::from_id_salt("close_button_action")
+                                .selected_text(match self.config.close_button_action {
+                                    CloseButtonAction::Tray => "Keep running in the tray",
+                                    CloseButtonAction::Quit => "Quit CrabGrab",
+                                })
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(&mut self.config.close_button_action, CloseButtonAction::Tray, "Keep running in the tray");
+                                    ui.selectable_value(&mut self.config.close_button_action, CloseButtonAction::Quit, "Quit CrabGrab");
+                                });
+                        }).response.on_hover_text("What the X in the settings' title bar does. \"Quit\" in the tray menu always exits.");
 
-                    if ui.checkbox(&mut self.config.run_on_startup, "Run on Startup").changed() {
-                        utils::set_autostart(self.config.run_on_startup);
-                        self.config.save();
-                    }
+                        ui.horizontal(|ui| {
+                            ui.label("Clipboard:");
+                            for target in ClipboardTarget::ALL {
+                                let mut enabled = self.config.clipboard_targets.contains(&target);
+                                if ui.checkbox(&mut enabled, target.label()).changed() {
+                                    if enabled {
+                                        self.config.clipboard_targets.insert(target);
+                                    } else {
+                                        self.config.clipboard_targets.remove(&target);
+                                    }
+                                }
+                            }
+                        }).response.on_hover_text(
+                            "The clipboard holds one kind of content: Image wins over File, which wins over text. \
+                             On Windows the coordinates are added to the image. \
+                             File and File Path need auto-save."
+                        );
+                        if self.config.clipboard_targets.contains(&ClipboardTarget::Coordinates) {
+                            ui.horizontal(|ui| {
+                                ui.label("Coordinates format:");
+                                ui.text_edit_singleline(&mut self.config.coordinates_format);
+                            }).response.on_hover_text(
+                                "{x} {y} = top-left corner of the selection on the desktop, {w} {h} = its size. Physical pixels."
+                            );
+                        }
 
-                    ui.separator();
+                        ui.checkbox(&mut self.config.secure_delete_temp_files, "Overwrite temp files before deleting")
+                            .on_hover_text(
+                                "Temp files of script runs and \"Capture & Edit\" are filled with zeros before removal. \
+                                 Slower, and on SSDs the old data may survive anyway."
+                            );
 
-                    // 3. Shortcuts
-                    ui.heading("Shortcuts");
-                    ui.horizontal(|ui| {
-                        ui.label("Capture Screen:");
+                        ui.checkbox(&mut self.config.keep_clipboard_when_auto_saving, "Don't overwrite the clipboard when auto-saving")
+                            .on_hover_text("With auto-save on, captures only go to disk and whatever you copied before stays on the clipboard.");
 
-                        let btn_text = if self.is_recording_hotkey {
-                            "Press any key... (Esc to cancel)".to_string()
-                        } else {
-                            // FIX: Use the new utility function
-                            utils::format_hotkey(&self.config.snap_hotkey)
-                        };
+                        ui.horizontal(|ui| {
+                            let mut restore = self.config.restore_clipboard_after_secs.is_some();
+                            if ui.checkbox(&mut restore, "Restore the previous clipboard after").changed() {
+                                self.config.restore_clipboard_after_secs = restore.then_some(30);
+                            }
+                            if let Some(seconds) = &mut self.config.restore_clipboard_after_secs {
+                                ui.add(egui::DragValue::new(seconds).range(1..=3_600).suffix(" s"));
+                            }
+                        }).response.on_hover_text(
+                            "Puts the text or image you had copied back once the capture was pasted. Skipped if you copied something else in the meantime."
+                        );
 
-                        let btn = ui.button(btn_text);
-                        if btn.clicked() {
-                            self.is_recording_hotkey = true;
+                        if cfg!(target_os = "windows") {
+                            ui.horizontal(|ui| {
+                                let mut compress = self.config.clipboard_compress_above_kb.is_some();
+                                if ui.checkbox(&mut compress, "Compress clipboard image above").changed() {
+                                    self.config.clipboard_compress_above_kb = compress.then_some(1024);
+                                }
+                                if let Some(limit_kb) = &mut self.config.clipboard_compress_above_kb {
+                                    ui.add(egui::DragValue::new(limit_kb).range(64..=1_048_576).suffix(" KB"));
+                                    ui.label("JPEG quality:");
+                                    ui.add(egui::Slider::new(&mut self.config.clipboard_jpeg_quality, 10..=100));
+                                }
+                            }).response.on_hover_text(
+                                "Larger captures go on the clipboard as a JPEG, for destinations that reject large pastes. \
+                                 Programs that read JPEG from the clipboard (\"JFIF\") get the small file, others a bitmap without alpha. \
+                                 Saved files stay lossless."
+                            );
+                        }
+
+                        ui.checkbox(&mut self.config.clipboard_premultiply_alpha, "Premultiply clipboard alpha")
+                            .on_hover_text(
+                                "Only matters for captures with transparent edges. \
+                                 Turn this on if pasting them shows dark or white fringes, \
+                                 some programs expect premultiplied colors on the clipboard."
+                            );
+
+                        #[cfg(all(target_os = "windows", feature = "clipboard-metadata"))]
+                        ui.checkbox(&mut self.config.clipboard_metadata, "Label clipboard images for clipboard managers")
+                            .on_hover_text(
+                                "Adds the capture's file name as an extra clipboard format. \
+                                 Clipboard managers that show all formats of an entry can use it as a title."
+                            );
+
+                        ui.horizontal(|ui| {
+                            ui.label("Montage:");
+                            ui.add(egui::DragValue::new(&mut self.config.montage_gap_px).range(0..=500).suffix(" px gap"));
+                            ui.color_edit_button_srgba_unmultiplied(&mut self.config.montage_background);
+                            egui::ComboBox::from_id_salt("montage_align")
+                                .selected_text(self.config.montage_align.label())
+                                .show_ui(ui, |ui| {
+                                    for align in MontageAlign::ALL {
+                                        ui.selectable_value(&mut self.config.montage_align, align, align.label());
+                                    }
+                                });
+                        }).response.on_hover_text(
+                            "\"Start Montage\" in the tray stacks every following capture below the previous ones into one image, \
+                             saved to the save folder every few captures and on \"Finish & Save Montage\"."
+                        );
+
+                        ui.horizontal(|ui| {
+                            let running = self.test_capture_receiver.is_some();
+                            if ui.add_enabled(!running, egui::Button::new("📸 Test Capture")).on_hover_text(
+                                "Captures a small region of this monitor and saves + copies it with the settings above, then shows what happened."
+                            ).clicked() {
+                                self.run_test_capture(ctx);
+                            }
+                            if running {
+                                ui.spinner();
+                                ctx.request_repaint_after(Duration::from_millis(100));
+                            }
+                        });
+                        self.show_test_capture_result(ui);
+
+                        ui.separator();
+
+                        // 2. Visuals & Audio
+                        ui.heading("Experience");
+                        ui.checkbox(&mut self.config.custom_cursor, "Use CrabGrab Cursor");
+                        ui.checkbox(&mut self.config.hide_cursor_during_capture, "Hide cursor while selecting");
+                        ui.checkbox(&mut self.config.compensate_sdr_white_level, "Match overlay brightness on HDR displays")
+                            .on_hover_text("Applies the Windows \"SDR content brightness\" to the frozen overlay only. Saved captures keep the original pixels.");
+                        ui.checkbox(&mut self.config.play_sound, "Play Camera Shutter Sound");
+                        #[cfg(target_os = "windows")]
+                        if self.config.play_sound {
+                            ui.indent("shutter_with_other_audio", |ui| {
+                                ui.horizontal(|ui| {
+                                    ui.label("While other audio plays:");
+                                    let label = |mode| match mode {
+                                        ShutterWithOtherAudio::Play => "Play normally",
+                                        ShutterWithOtherAudio::Duck => "Turn other audio down",
+                                        ShutterWithOtherAudio::Skip => "Skip the sound",
+                                    };
+                                    egui::ComboBox::from_id_salt("shutter_with_other_audio")
+                                        .selected_text(label(self.config.shutter_with_other_audio))
+                                        .show_ui(ui, |ui| {
+                                            for mode in [ShutterWithOtherAudio::Play, ShutterWithOtherAudio::Duck, ShutterWithOtherAudio::Skip] {
+                                                ui.selectable_value(&mut self.config.shutter_with_other_audio, mode, label(mode));
+                                            }
+                                        });
+                                }).response.on_hover_text("Useful during calls: the shutter neither gets lost nor startles anyone.");
+                            });
                         }
+                        ui.add_enabled(
+                            self.config.play_sound,
+                            egui::Checkbox::new(&mut self.config.play_error_sound, "Play an error sound when a capture fails"),
+                        ).on_hover_text("Also when saving or copying to the clipboard fails. Failures in a row play it once.");
+
+                        ui.horizontal(|ui| {
+                            ui.label("Selection Border:");
+                            egui::ComboBox::from_id_salt("selection_style")
+                                .selected_text(match self.config.selection_style {
+                                    SelectionStyle::Classic => "Classic",
+                                    SelectionStyle::MarchingAnts => "Marching Ants",
+                                })
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(&mut self.config.selection_style, SelectionStyle::Classic, "Classic");
+                                    ui.selectable_value(&mut self.config.selection_style, SelectionStyle::MarchingAnts, "Marching Ants");
+                                });
+                        });
+
+                        ui.horizontal(|ui| {
+                            let mut tint = self.config.selection_fill.is_some();
+                            if ui.checkbox(&mut tint, "Tint the selection").changed() {
+                                self.config.selection_fill = tint.then_some([0, 120, 215, 40]);
+                            }
+                            if let Some(fill) = &mut self.config.selection_fill {
+                                ui.color_edit_button_srgba_unmultiplied(fill);
+                            }
+                        }).response.on_hover_text("A translucent color over the selected area while selecting. Not part of the capture.");
 
-                        if self.is_recording_hotkey {
-                            ui.memory_mut(|m| m.request_focus(btn.id));
-                            let input = ctx.input(|i| i.clone());
+                        ui.horizontal(|ui| {
+                            let mut frame = self.config.monitor_frame.is_some();
+                            if ui.checkbox(&mut frame, "Frame every monitor while selecting").changed() {
+                                self.config.monitor_frame = frame.then_some([255, 140, 0, 255]);
+                            }
+                            if let Some(color) = &mut self.config.monitor_frame {
+                                ui.color_edit_button_srgba_unmultiplied(color);
+                            }
+                        }).response.on_hover_text("Makes it obvious that every screen is in capture mode, even a dark one.");
+
+                        ui.add_enabled(
+                            !self.config.reduce_motion,
+                            egui::Checkbox::new(&mut self.config.capture_flash, "Flash the selection when it is captured"),
+                        ).on_hover_text("The selected area flashes white for a moment, so you see what was grabbed.");
+                        ui.checkbox(&mut self.config.reduce_motion, "Reduce motion")
+                            .on_hover_text("No capture flash and no moving selection border.");
+
+                        ui.horizontal(|ui| {
+                            let mut normalize = self.config.normalize_capture_scale.is_some();
+                            if ui.checkbox(&mut normalize, "Uniform scale for Capture Everything").changed() {
+                                self.config.normalize_capture_scale = normalize.then_some(1.0);
+                            }
+                            if let Some(scale) = &mut self.config.normalize_capture_scale {
+                                ui.add(egui::DragValue::new(scale).range(0.5..=4.0).speed(0.05).fixed_decimals(2).suffix("x"));
+                            }
+                        }).response.on_hover_text(
+                            "Resamples every monitor to this scale factor (1.00x = 100%), so content from monitors with different DPI has the same size in the screenshot."
+                        );
+
+                        ui.horizontal(|ui| {
+                            ui.label("Scale captures by:");
+                            ui.add(egui::DragValue::new(&mut self.config.capture_scale).range(CAPTURE_SCALE_RANGE).speed(0.05).fixed_decimals(2).suffix("x"));
+                        }).response.on_hover_text(
+                            "Resamples every capture before it is copied or saved, e.g. 2.00x for print documents that need a certain size. \
+                             Captures are already taken at the monitor's full resolution; enlarging them doesn't add detail."
+                        );
 
-                            if input.key_pressed(egui::Key::Escape) {
-                                self.is_recording_hotkey = false;
+                        ui.horizontal(|ui| {
+                            let mut confirm = self.config.confirm_captures_above_mp.is_some();
+                            if ui.checkbox(&mut confirm, "Ask before capturing more than").changed() {
+                                self.config.confirm_captures_above_mp = confirm.then_some(20.0);
+                            }
+                            if let Some(megapixels) = &mut self.config.confirm_captures_above_mp {
+                                ui.add(egui::DragValue::new(megapixels).range(1.0..=500.0).speed(0.5).fixed_decimals(1).suffix(" MP"));
+                                ui.label("or downscale to");
+                                ui.add(egui::DragValue::new(&mut self.config.downscaled_max_dimension).range(256..=16_384).suffix(" px"));
                             }
+                        }).response.on_hover_text(
+                            "A huge selection (e.g. the whole desktop by accident) can take seconds to save and copy. \
+                             Asks whether to save it as is, downscaled to the given size on its longer side, or to select again."
+                        );
+
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut self.config.snap_to_grid, "Snap selection to a grid of");
+                            ui.add_enabled(
+                                self.config.snap_to_grid,
+                                egui::DragValue::new(&mut self.config.grid_size_px).range(2..=256).suffix(" px"),
+                            );
+                        }).response.on_hover_text("Shows a faint grid while selecting. Can also be toggled from the overlay.");
+
+                        ui.horizontal(|ui| {
+                            ui.label("Alignment guide:");
+                            egui::ComboBox::from_id_salt("alignment_guide")
+                                .selected_text(self.config.alignment_guide.label())
+                                .show_ui(ui, |ui| {
+                                    for guide in AlignmentGuide::ALL {
+                                        ui.selectable_value(&mut self.config.alignment_guide, guide, guide.label());
+                                    }
+                                });
+                            ui.add_enabled(
+                                self.config.alignment_guide != AlignmentGuide::None,
+                                egui::Slider::new(&mut self.config.guide_opacity, 10..=255).text("opacity"),
+                            );
+                        }).response.on_hover_text("Composition lines inside the selection while selecting. Press G in the overlay to switch.");
+
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut self.config.show_viewfinder, "Viewfinder guides, inset by");
+                            ui.add_enabled(
+                                self.config.show_viewfinder,
+                                egui::DragValue::new(&mut self.config.viewfinder_inset).range(0.0..=400.0).suffix(" pt"),
+                            );
+                        }).response.on_hover_text("Corner brackets and a center dot while selecting, to frame repeatable screenshots. Never captured.");
+
+                        ui.checkbox(&mut self.config.show_hints, "Show shortcut hints while selecting")
+                            .on_hover_text("A bar at the bottom of the screen with the keys the overlay understands.");
+
+                        if ui.button("🖥 Identify Displays").on_hover_text(
+                            "Shows each monitor's number for a moment. Monitor indices in the settings and the log start at 0."
+                        ).clicked() {
+                            self.handle_identify_displays(ctx);
+                        }
+
+                        if ui.checkbox(&mut self.config.run_on_startup, "Run on Startup").changed() {
+                            utils::set_autostart(self.config.run_on_startup);
+                            self.save_config();
+                        }
+
+                        ui.separator();
 
-                            for key in input.keys_down {
-                                if let Some(new_hotkey) = utils::convert_egui_to_hotkey(key, input.modifiers) {
-                                    self.update_hotkey(new_hotkey);
-                                    self.is_recording_hotkey = false;
-                                    break;
+                        // 3. Shortcuts
+                        ui.heading("Shortcuts");
+                        for (target, label) in [
+                            (HotkeyTarget::Snap, "Capture Screen:"),
+                            (HotkeyTarget::Annotate, "Capture & Annotate:"),
+                            (HotkeyTarget::ExternalEdit, "Capture & Edit:"),
+                            (HotkeyTarget::Panic, "Reset stuck overlay:"),
+                        ] {
+                            ui.horizontal(|ui| {
+                                ui.label(label);
+                                self.show_hotkey_recorder(ctx, ui, target);
+                            });
+                        }
+                        ui.horizontal(|ui| {
+                            ui.label("Chord:");
+                            self.show_hotkey_recorder(ctx, ui, HotkeyTarget::Chord);
+                            if self.config.chord_hotkey().is_some()
+                                && ui.button("✖").on_hover_text("Remove hotkey").clicked()
+                            {
+                                self.clear_chord_hotkey();
+                            }
+                        }).response.on_hover_text("Two-step shortcut: press this, then within 1.5 seconds one of the keys below. Esc cancels.");
+                        if self.config.chord_hotkey().is_some() {
+                            ui.indent("chord_actions", |ui| {
+                                for action in ChordAction::ALL {
+                                    let key = utils::format_hotkey(&HotKey::new(None, action.key()));
+                                    let mut enabled = self.config.chord_actions.contains(&action);
+                                    if ui.checkbox(&mut enabled, format!("then {}: {}", key, action.label())).changed() {
+                                        if enabled {
+                                            self.config.chord_actions.insert(action);
+                                        } else {
+                                            self.config.chord_actions.remove(&action);
+                                        }
+                                    }
                                 }
+                            });
+                        }
+                        ui.horizontal(|ui| {
+                            ui.label("External editor:");
+                            let mut editor = self.config.external_editor_path.clone().unwrap_or_default();
+                            let response = ui.add(egui::TextEdit::singleline(&mut editor).hint_text("System default"))
+                                .on_hover_text("Program that \"Capture & Edit\" opens the capture with, e.g. the path to gimp or photoshop.");
+                            if response.changed() {
+                                self.config.external_editor_path = (!editor.trim().is_empty()).then_some(editor);
                             }
+                        });
+
+                        ui.separator();
+
+                        // Post-processing filters
+                        ui.heading("Filters");
+                        ui.weak("Applied from top to bottom to every selection, before the preview, the editor and the save.");
+                        let mut remove: Option<usize> = None;
+                        let mut move_up: Option<usize> = None;
+                        let filter_count = self.config.filters.len();
+                        for (index, filter) in self.config.filters.iter_mut().enumerate() {
+                            ui.horizontal(|ui| {
+                                if ui.add_enabled(index > 0, egui::Button::new("⏶")).on_hover_text("Run earlier").clicked() {
+                                    move_up = Some(index);
+                                }
+                                if ui.add_enabled(index + 1 < filter_count, egui::Button::new("⏷")).on_hover_text("Run later").clicked() {
+                                    move_up = Some(index + 1);
+                                }
+                                ui.label(filter.label());
+                                if let FilterKind::BrightnessContrast { brightness, contrast } = filter {
+                                    ui.label("Brightness:");
+                                    ui.add(egui::DragValue::new(brightness).range(-255..=255));
+                                    ui.label("Contrast:");
+                                    ui.add(egui::DragValue::new(contrast).range(-100.0..=100.0).suffix(" %"));
+                                }
+                                if ui.button("🗑").on_hover_text("Remove filter").clicked() {
+                                    remove = Some(index);
+                                }
+                            });
+                        }
+                        ui.menu_button("➕ Add Filter", |ui| {
+                            for kind in FilterKind::ALL {
+                                if ui.button(kind.label()).clicked() {
+                                    self.config.filters.push(kind);
+                                    ui.close();
+                                }
+                            }
+                        });
+                        if let Some(index) = move_up {
+                            self.config.filters.swap(index - 1, index);
+                        }
+                        if let Some(index) = remove {
+                            self.config.filters.remove(index);
                         }
-                    });
 
-                    ui.add_space(20.0);
+                        ui.separator();
 
-                    // Bottom Action Bar
-                    ui.with_layout(egui::Layout::bottom_up(egui::Align::Center), |ui| {
-                        if ui.button("Close Settings").clicked() {
-                            self.handle_close_settings(ctx);
+                        // 4. Region Presets
+                        ui.heading("Region Presets");
+                        let mut remove: Option<usize> = None;
+                        let mut pick: Option<usize> = None;
+                        for index in 0..self.config.region_presets.len() {
+                            ui.group(|ui| {
+                                let preset = &mut self.config.region_presets[index];
+                                ui.horizontal(|ui| {
+                                    ui.add(egui::TextEdit::singleline(&mut preset.name).desired_width(160.0));
+                                    ui.weak(if preset.monitor.is_empty() { "(monitor not picked)" } else { preset.monitor.as_str() });
+                                    if ui.button("🗑").on_hover_text("Remove preset").clicked() {
+                                        remove = Some(index);
+                                    }
+                                });
+                                ui.horizontal(|ui| {
+                                    ui.label("X:");
+                                    ui.add(egui::DragValue::new(&mut preset.x));
+                                    ui.label("Y:");
+                                    ui.add(egui::DragValue::new(&mut preset.y));
+                                    ui.label("W:");
+                                    ui.add(egui::DragValue::new(&mut preset.width).range(1..=u32::MAX));
+                                    ui.label("H:");
+                                    ui.add(egui::DragValue::new(&mut preset.height).range(1..=u32::MAX));
+                                    if ui.button("🎯 Pick Region").on_hover_text("Select the region on screen").clicked() {
+                                        pick = Some(index);
+                                    }
+                                });
+                                ui.horizontal(|ui| {
+                                    ui.label("Hotkey:");
+                                    self.show_hotkey_recorder(ctx, ui, HotkeyTarget::Preset(index));
+                                    if self.hotkey_for(HotkeyTarget::Preset(index)).is_some()
+                                        && ui.button("✖").on_hover_text("Remove hotkey").clicked()
+                                    {
+                                        self.clear_preset_hotkey(index);
+                                    }
+                                });
+                            });
+                        }
+                        if ui.button("➕ Add Preset").clicked() {
+                            self.config.region_presets.push(RegionPreset::default());
+                        }
+                        if let Some(index) = remove {
+                            self.clear_preset_hotkey(index);
+                            self.config.region_presets.remove(index);
+                            // Indices of the rows below shift
+                            self.recording_hotkey = None;
+                        }
+                        if let Some(index) = pick {
+                            self.picking_preset = Some(index);
+                            self.handle_begin_capture(ctx, Instant::now());
+                        }
+
+                        ui.separator();
+
+                        // Capture Profiles
+                        ui.heading("Profiles");
+                        ui.weak("A profile remembers auto-save, the save folder, clipboard and PNG options, the sound and the capture hotkey.");
+                        let mut remove: Option<usize> = None;
+                        let mut activate: Option<usize> = None;
+                        let mut menu_changed = false;
+                        for index in 0..self.config.profiles.len() {
+                            let active = self.config.active_profile == Some(index);
+                            let hotkey_label = self.config.profiles[index].snap_hotkey().map(|hk| utils::format_hotkey(&hk));
+                            let current = Profile::from_config("", &self.config);
+                            let profile = &mut self.config.profiles[index];
+                            ui.horizontal(|ui| {
+                                if ui.radio(active, "").on_hover_text("Switch to this profile").clicked() && !active {
+                                    activate = Some(index);
+                                }
+                                if ui.add(egui::TextEdit::singleline(&mut profile.name).desired_width(140.0)).lost_focus() {
+                                    menu_changed = true;
+                                }
+                                ui.weak(format!(
+                                    "Auto-save {}, {}",
+                                    if profile.auto_save { "ON" } else { "OFF" },
+                                    hotkey_label.as_deref().unwrap_or("current hotkey"),
+                                ));
+                                if ui.button("💾").on_hover_text("Store the current settings in this profile").clicked() {
+                                    *profile = Profile { name: profile.name.clone(), ..current };
+                                }
+                                if ui.button("🗑").on_hover_text("Remove profile").clicked() {
+                                    remove = Some(index);
+                                }
+                            });
+                        }
+                        if ui.button("➕ Add Profile from current settings").clicked() {
+                            let name = format!("Profile {}", self.config.profiles.len() + 1);
+                            self.config.profiles.push(Profile::from_config(&name, &self.config));
+                            menu_changed = true;
+                        }
+                        if let Some(index) = remove {
+                            self.config.profiles.remove(index);
+                            self.config.active_profile = match self.config.active_profile {
+                                Some(active) if active == index => None,
+                                Some(active) if active > index => Some(active - 1),
+                                active => active,
+                            };
+                            menu_changed = true;
+                        }
+                        if let Some(index) = activate {
+                            // Sends the menu itself
+                            self.switch_profile(index);
+                        } else if menu_changed {
+                            self.send_tray_menu();
+                        }
+                        ui.horizontal(|ui| {
+                            ui.label("Next profile:");
+                            self.show_hotkey_recorder(ctx, ui, HotkeyTarget::NextProfile);
+                            if self.config.profile_hotkey().is_some()
+                                && ui.button("✖").on_hover_text("Remove hotkey").clicked()
+                            {
+                                self.clear_profile_hotkey();
+                            }
+                        });
+
+                        ui.separator();
+
+                        // 5. Stats
+                        ui.heading("Stats");
+                        ui.label(format!("Captures: {}", self.stats.total_captures));
+                        ui.label(format!("Saved to disk: {}", stats::format_bytes(self.stats.total_bytes_saved)));
+                        ui.label(format!(
+                            "Most used: {}",
+                            self.stats.most_used_mode().map_or("-", |mode| mode.label())
+                        ));
+                        if ui.button("Reset Stats").clicked() {
+                            stats::reset();
+                            self.stats = CaptureStats::default();
+                        }
+
+                        ui.separator();
+
+                        // 6. Advanced
+                        ui.heading("Advanced");
+                        if ui.button("🗑 Clear all app data…").on_hover_text(
+                            "Deletes the settings, statistics, logs and temporary files for a clean uninstall. Asks first."
+                        ).clicked() {
+                            self.clear_data_paths = Some(utils::app_data_paths());
+                        }
+                        if ui.button("📥 Import from other tools…").on_hover_text(
+                            "Takes over the save folder and capture hotkey from ShareX or Greenshot."
+                        ).clicked() {
+                            self.import_candidates = Some(import::find_imports());
+                        }
+                        if ui.button("📐 Show alignment test").on_hover_text(
+                            "Opens the overlay with a grid on every monitor, labeled with its size, scale and origin. \
+                             If frames or grids don't line up with the screen edges, take a photo of it for the bug report."
+                        ).clicked() {
+                            self.alignment_test = true;
+                            self.handle_begin_capture(ctx, Instant::now());
+                        }
+
+                        ui.horizontal(|ui| {
+                            ui.label("Post-capture Lua script:");
+                            let mut script = self.config.lua_post_script.clone().unwrap_or_default();
+                            let response = ui.add(egui::TextEdit::singleline(&mut script).hint_text("path/to/script.lua"))
+                                .on_hover_text("Runs after each capture. Use crab.save/resize/rotate/get_path, crab.skip_save() replaces the normal save.");
+                            if response.changed() {
+                                self.config.lua_post_script = (!script.trim().is_empty()).then_some(script);
+                            }
+                        });
+
+                        ui.horizontal(|ui| {
+                            ui.label("Pre-capture command:");
+                            let mut command = self.config.pre_capture_command.clone().unwrap_or_default();
+                            let response = ui.add(egui::TextEdit::singleline(&mut command).hint_text("e.g. a script that hides the dock"))
+                                .on_hover_text("Shell command run before each capture. The capture waits up to 0.5 s for it to finish.");
+                            if response.changed() {
+                                self.config.pre_capture_command = (!command.trim().is_empty()).then_some(command);
+                            }
+                        });
+
+                        ui.horizontal(|ui| {
+                            ui.label("Post-capture command:");
+                            let mut command = self.config.post_capture_command.clone().unwrap_or_default();
+                            let response = ui.add(egui::TextEdit::singleline(&mut command).hint_text("e.g. optipng {path}"))
+                                .on_hover_text("Shell command started after each save, {path} is replaced with the saved file, quoted for the shell.");
+                            if response.changed() {
+                                self.config.post_capture_command = (!command.trim().is_empty()).then_some(command);
+                            }
+                        });
+
+                        ui.horizontal(|ui| {
+                            ui.label("Log file:");
+                            match &self.config.log_file_path {
+                                Some(path) => ui.code(path),
+                                None => match utils::default_log_file_path() {
+                                    Some(path) => ui.code(path.to_string_lossy()),
+                                    None => ui.weak("Unavailable, logging to the console only"),
+                                },
+                            };
+                            if ui.add_enabled(!self.folder_picker_open(), egui::Button::new("📂 Browse Log Location...")).clicked() {
+                                self.open_log_location_picker();
+                            }
+                            if self.config.log_file_path.is_some() && ui.button("Reset").clicked() {
+                                self.config.log_file_path = None;
+                            }
+                        }).response.on_hover_text("Takes effect after a restart.");
+
+                        ui.horizontal(|ui| {
+                            ui.label("GPU budget for the overlay:");
+                            ui.add(egui::DragValue::new(&mut self.config.gpu_tile_budget_mb).range(64..=8192).suffix(" MB"));
+                        }).response.on_hover_text(
+                            "Larger desktops are shown slightly downscaled while selecting. Captures keep full resolution."
+                        );
+
+                        ui.horizontal(|ui| {
+                            ui.label("Overlay tiles uploaded per frame:");
+                            ui.add(egui::DragValue::new(&mut self.config.tiles_per_frame).range(1..=64));
+                        }).response.on_hover_text("Lower it if the screen freezes when capturing starts (slow or integrated GPUs).");
+
+                        ui.horizontal(|ui| {
+                            let mut sleep = self.config.idle_sleep_minutes.is_some();
+                            if ui.checkbox(&mut sleep, "Deep sleep after idling for").changed() {
+                                self.config.idle_sleep_minutes = sleep.then_some(10);
+                            }
+                            if let Some(minutes) = &mut self.config.idle_sleep_minutes {
+                                ui.add(egui::DragValue::new(minutes).range(1..=1_440).suffix(" min"));
+                            }
+                        }).response.on_hover_text(
+                            "Stops the background frames and closes the audio device until the next hotkey or tray click. Saves battery on laptops."
+                        );
+
+                        ui.checkbox(&mut self.config.dpi_warmup, "Warm up the overlay's DPI in the background")
+                            .on_hover_text("Moves the hidden window onto the main monitor at startup and after display changes, so the first capture lines up. Turn off if the window flashes or steals focus.");
+
+                        ui.horizontal(|ui| {
+                            ui.label("GPU:");
+                            egui::ComboBox::from_id_salt("gpu_power_preference")
+                                .selected_text(match self.config.gpu_power_preference {
+                                    GpuPowerPreference::HighPerformance => "High performance",
+                                    GpuPowerPreference::LowPower => "Power saving",
+                                })
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(&mut self.config.gpu_power_preference, GpuPowerPreference::HighPerformance, "High performance");
+                                    ui.selectable_value(&mut self.config.gpu_power_preference, GpuPowerPreference::LowPower, "Power saving");
+                                });
+                        }).response.on_hover_text(
+                            "Power saving prefers the integrated GPU, which is easier on laptop batteries. Takes effect after a restart."
+                        );
+
+                        ui.horizontal(|ui| {
+                            ui.label("Window transparency:");
+                            egui::ComboBox::from_id_salt("window_transparency")
+                                .selected_text(match self.config.window_transparency {
+                                    WindowTransparency::Auto => "Auto",
+                                    WindowTransparency::On => "On",
+                                    WindowTransparency::Off => "Off",
+                                })
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(&mut self.config.window_transparency, WindowTransparency::Auto, "Auto");
+                                    ui.selectable_value(&mut self.config.window_transparency, WindowTransparency::On, "On");
+                                    ui.selectable_value(&mut self.config.window_transparency, WindowTransparency::Off, "Off");
+                                });
+                        }).response.on_hover_text(
+                            "Turn this off if the overlay shows up black (window managers without a compositor). \
+                             Auto checks for a compositor at startup. Takes effect after a restart."
+                        );
+
+                        ui.checkbox(&mut self.config.debug_overlay, "Debug overlay")
+                            .on_hover_text("Labels every monitor with its name and geometry while snapping.");
+                        if self.config.debug_overlay {
+                            ui.indent("debug_overlay_options", |ui| {
+                                ui.checkbox(&mut self.config.show_tile_borders, "Show tile borders")
+                                    .on_hover_text("Outlines every texture tile of the overlay in red.");
+                            });
                         }
                     });
                 });
@@ -751,3 +4077,62 @@ impl eframe::App for CrabGrabApp {
     }
 }
 
+/// Outlines a keyboard-focused toolbar widget; egui's own focus look is hard to see over a capture.
+fn focus_ring(response: egui::Response) -> egui::Response {
+    if response.has_focus() {
+        response.ctx.layer_painter(response.layer_id).rect_stroke(
+            response.rect.expand(2.0),
+            3.0,
+            egui::Stroke::new(2.0, response.ctx.style().visuals.selection.bg_fill),
+            egui::StrokeKind::Outside,
+        );
+    }
+    response
+}
+
+/// Lets the user pick a folder on a helper thread; the path arrives on the returned channel,
+/// or None when the dialog was cancelled.
+fn spawn_folder_picker() -> Receiver<Option<String>> {
+    let (tx, rx) = channel();
+    std::thread::spawn(move || {
+        let picked = rfd::FileDialog::new().pick_folder().and_then(|path_buf| match path_buf.to_str() {
+            Some(path_str) => Some(path_str.to_string()),
+            None => {
+                log::warn!("The picked folder {:?} is not valid UTF-8, ignoring it.", path_buf);
+                None
+            }
+        });
+        let _ = tx.send(picked);
+    });
+    rx
+}
+
+/// What a folder dialog returned, once it closed. Clears `receiver` then, so the dialog can
+/// be opened again.
+fn poll_folder_picker(receiver: &mut Option<Receiver<Option<String>>>, name: &str) -> Option<Option<String>> {
+    let rx = receiver.as_ref()?;
+    let picked = match rx.try_recv() {
+        Ok(picked) => picked,
+        Err(std::sync::mpsc::TryRecvError::Empty) => return None,
+        Err(e) => {
+            log::error!("{} channel error: {:?}", name, e);
+            None
+        }
+    };
+    *receiver = None;
+    Some(picked)
+}
+
+/// The Win32 handle of the eframe window.
+#[cfg(target_os = "windows")]
+fn native_window_handle(cc: &eframe::CreationContext) -> Option<isize> {
+    use eframe::wgpu::rwh::{HasWindowHandle, RawWindowHandle};
+
+    match cc.window_handle().map(|handle| handle.as_raw()) {
+        Ok(RawWindowHandle::Win32(handle)) => Some(handle.hwnd.get()),
+        other => {
+            log::warn!("No Win32 window handle ({:?}), the window is parked off-screen instead of hidden.", other.err());
+            None
+        }
+    }
+}
@@ -1,12 +1,19 @@
 use std::io::Cursor;
+use std::time::{Duration, Instant};
 use rodio::{Decoder, OutputStream, OutputStreamBuilder, Sink};
 
+/// Failures in a row (a full disk, a broken save folder) play the error sound only once per this.
+const ERROR_SOUND_COOLDOWN: Duration = Duration::from_secs(3);
+
 pub struct SoundEngine {
-    _stream: OutputStream,
+    // None while suspended (deep sleep), the device is reopened on resume
+    stream: Option<OutputStream>,
 
     // Store two sounds now
     shutter_data: Vec<u8>,
     activate_data: Vec<u8>,
+    error_data: Vec<u8>,
+    last_error_at: Option<Instant>,
 }
 
 impl SoundEngine {
@@ -20,18 +27,39 @@ impl SoundEngine {
         // Use a dummy empty vec if you don't have the file yet to prevent compile error:
         // let activate_data = vec![];
         let activate_data = include_bytes!("assets/activate.wav").to_vec();
+        let error_data = include_bytes!("assets/error.wav").to_vec();
 
         Self {
-            _stream: stream,
+            stream: Some(stream),
             shutter_data,
             activate_data,
+            error_data,
+            last_error_at: None,
+        }
+    }
+
+    /// Closes the output device. Sounds are skipped until `resume`.
+    pub fn suspend(&mut self) {
+        self.stream = None;
+    }
+
+    pub fn resume(&mut self) {
+        if self.stream.is_some() {
+            return;
+        }
+        match OutputStreamBuilder::open_default_stream() {
+            Ok(stream) => self.stream = Some(stream),
+            Err(e) => log::error!("Failed to reopen the audio output: {}", e),
         }
     }
 
     /// Helper to play raw data
     fn play(&self, data: &[u8]) {
+        let Some(stream) = &self.stream else {
+            return;
+        };
         // Create a Sink connected to the stream's mixer
-        let sink = Sink::connect_new(&self._stream.mixer());
+        let sink = Sink::connect_new(stream.mixer());
         let cursor = Cursor::new(data.to_vec()); // Clone the data for playback
         if let Ok(source) = Decoder::try_from(cursor) {
             sink.append(source);
@@ -46,4 +74,119 @@ impl SoundEngine {
     pub fn play_activation(&self) {
         self.play(&self.activate_data);
     }
-}
\ No newline at end of file
+
+    /// Skipped while the previous error sound is less than `ERROR_SOUND_COOLDOWN` ago.
+    pub fn play_error(&mut self) {
+        if self.last_error_at.is_some_and(|at| at.elapsed() < ERROR_SOUND_COOLDOWN) {
+            return;
+        }
+        self.last_error_at = Some(Instant::now());
+        self.play(&self.error_data);
+    }
+}
+
+/// Access to the audio sessions of other programs on the default output device (WASAPI).
+/// Each call runs on its own thread, so COM is set up the way we need it there.
+#[cfg(target_os = "windows")]
+pub mod other_audio {
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant};
+    use windows::core::Interface;
+    use windows::Win32::Media::Audio::{
+        eConsole, eRender, AudioSessionStateActive, IAudioSessionControl2, IAudioSessionManager2,
+        IMMDeviceEnumerator, ISimpleAudioVolume, MMDeviceEnumerator,
+    };
+    use windows::Win32::System::Com::{CoCreateInstance, CLSCTX_ALL};
+
+    use crate::utils::ComGuard;
+
+    /// When the running duck ends, None while nothing is ducked. Ducking again while one runs
+    /// only moves this, a second duck would take the lowered levels for the user's.
+    static DUCKED_UNTIL: Mutex<Option<Instant>> = Mutex::new(None);
+
+    /// True if another program is playing something right now.
+    pub fn is_playing() -> bool {
+        std::thread::spawn(|| {
+            let _com = ComGuard::init();
+            active_sessions().map(|sessions| !sessions.is_empty()).unwrap_or_else(|e| {
+                log::warn!("Could not list audio sessions: {}", e);
+                false
+            })
+        }).join().unwrap_or(false)
+    }
+
+    /// Scales the volume of everything else that is playing by `factor` and restores it after
+    /// `duration`. Returns right away. While a duck is running it is only extended.
+    pub fn duck(factor: f32, duration: Duration) {
+        {
+            let mut until = DUCKED_UNTIL.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(until) = until.as_mut() {
+                *until = (*until).max(Instant::now() + duration);
+                return;
+            }
+            *until = Some(Instant::now() + duration);
+        }
+
+        std::thread::spawn(move || {
+            let _com = ComGuard::init();
+            let sessions = match active_sessions() {
+                Ok(sessions) => sessions,
+                Err(e) => {
+                    log::warn!("Could not list audio sessions, not ducking: {}", e);
+                    *DUCKED_UNTIL.lock().unwrap_or_else(|e| e.into_inner()) = None;
+                    return;
+                }
+            };
+
+            // Remember each level, the sessions may already be turned down by the user
+            let ducked: Vec<(ISimpleAudioVolume, f32)> = sessions.iter().filter_map(|session| unsafe {
+                let volume: ISimpleAudioVolume = session.cast().ok()?;
+                let level = volume.GetMasterVolume().ok()?;
+                volume.SetMasterVolume(level * factor, std::ptr::null()).ok()?;
+                Some((volume, level))
+            }).collect();
+            log::debug!("Ducked {} audio session(s).", ducked.len());
+
+            loop {
+                let mut until = DUCKED_UNTIL.lock().unwrap_or_else(|e| e.into_inner());
+                let remaining = until.map_or(Duration::ZERO, |until| until.saturating_duration_since(Instant::now()));
+                if remaining.is_zero() {
+                    // Restored before the lock is released, so a new duck sees the user's levels
+                    for (volume, level) in &ducked {
+                        unsafe {
+                            if let Err(e) = volume.SetMasterVolume(*level, std::ptr::null()) {
+                                log::warn!("Failed to restore an audio session volume: {}", e);
+                            }
+                        }
+                    }
+                    *until = None;
+                    break;
+                }
+                drop(until);
+                std::thread::sleep(remaining);
+            }
+        });
+    }
+
+    /// Sessions of other processes that are currently playing.
+    fn active_sessions() -> windows::core::Result<Vec<IAudioSessionControl2>> {
+        unsafe {
+            let enumerator: IMMDeviceEnumerator = CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)?;
+            let device = enumerator.GetDefaultAudioEndpoint(eRender, eConsole)?;
+            let manager: IAudioSessionManager2 = device.Activate(CLSCTX_ALL, None)?;
+            let sessions = manager.GetSessionEnumerator()?;
+
+            let mut active = Vec::new();
+            for i in 0..sessions.GetCount()? {
+                let session: IAudioSessionControl2 = sessions.GetSession(i)?.cast()?;
+                if session.GetProcessId().is_ok_and(|pid| pid == std::process::id()) {
+                    continue;
+                }
+                if session.GetState()? == AudioSessionStateActive {
+                    active.push(session);
+                }
+            }
+            Ok(active)
+        }
+    }
+}
@@ -1,6 +1,50 @@
-use xcap::Monitor;
-use image::RgbaImage;
+use xcap::{Monitor, XCapError};
+use image::{Rgba, RgbaImage};
 use std::error::Error;
+use std::fmt;
+use std::time::Instant;
+
+/// Why a capture failed, so the UI can tell the user what to do about it.
+#[derive(Debug)]
+pub enum CaptureError {
+    NoMonitors,
+    /// The OS doesn't let us read the screen (macOS Screen Recording permission, a declined
+    /// Wayland portal request).
+    PermissionDenied,
+    /// Reading the monitor with this index (in `Monitor::all` order) failed, e.g. it went to
+    /// sleep or was unplugged mid-capture.
+    MonitorCaptureFailed(usize),
+    /// A saved region doesn't fit the current monitor layout anymore.
+    RegionUnavailable(String),
+    Backend(String),
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptureError::NoMonitors => write!(f, "No monitors found"),
+            CaptureError::PermissionDenied => write!(f, "Not allowed to record the screen"),
+            // Numbered like Identify Displays
+            CaptureError::MonitorCaptureFailed(index) => write!(f, "Capturing monitor {} failed", index + 1),
+            CaptureError::RegionUnavailable(reason) | CaptureError::Backend(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl Error for CaptureError {}
+
+impl From<XCapError> for CaptureError {
+    fn from(error: XCapError) -> Self {
+        // The backends report a missing permission only as text
+        let text = error.to_string();
+        let lower = text.to_lowercase();
+        if lower.contains("permission") || lower.contains("denied") {
+            CaptureError::PermissionDenied
+        } else {
+            CaptureError::Backend(text)
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct MonitorData {
@@ -9,12 +53,18 @@ pub struct MonitorData {
     pub width: u32,  // Physical Width
     pub height: u32, // Physical Height
     pub scale_factor: f32,
+    // Name as reported by the OS, None if unknown
+    pub monitor_name: Option<String>,
+    // SDR white level as a multiple of the 80 nit sRGB reference (1.0 = no HDR boost)
+    pub sdr_white_level: f32,
     pub image: RgbaImage,
 }
 
 pub struct CaptureData {
     pub monitors: Vec<MonitorData>,
-    pub full_image: RgbaImage,
+    // All monitors stitched together. None for huge virtual desktops, crop with
+    // `crop_from_monitors` instead.
+    pub full_image: Option<RgbaImage>,
 
     // We need both Origins.
     // 1. Logical: To tell the OS where to put the Window.
@@ -30,37 +80,441 @@ pub struct CaptureData {
     pub physical_height: u32,
 }
 
-pub fn capture_all_screens() -> Result<CaptureData, Box<dyn Error>> {
+/// Where the overlay window has to go to cover all monitors.
+pub struct CaptureGeometry {
+    // Physical placement of every monitor, in the order monitor indices refer to
+    pub monitors: Vec<MonitorRect>,
+    pub logical_origin: (f32, f32),
+    pub logical_width: f32,
+    pub logical_height: f32,
+    pub origin_scale_factor: f32,
+    pub physical_origin: (i32, i32),
+    pub physical_width: u32,
+    pub physical_height: u32,
+}
+
+/// Above this the stitched desktop is not built (1 GiB of RGBA); crops come from the monitors.
+const MAX_STITCHED_PIXELS: u64 = 256 * 1024 * 1024;
+
+/// Captures every monitor and stitches them into `full_image`.
+///
+/// With `normalize_to`, every monitor is resampled to that scale factor before stitching, so
+/// the combined image has the same density everywhere. `full_image` then no longer lines up
+/// with the physical coordinates, so the overlay (which crops by them) always passes None.
+pub fn capture_all_screens(normalize_to: Option<f32>) -> Result<CaptureData, CaptureError> {
+    let started = Instant::now();
     let monitors = Monitor::all()?;
-    if monitors.is_empty() { return Err("No monitors found".into()); }
+    if monitors.is_empty() { return Err(CaptureError::NoMonitors); }
 
     log::debug!("--- CAPTURE DEBUG START ---");
 
+    let white_levels = sdr_white_levels();
+
     let captures: Vec<MonitorData> = monitors.into_iter().enumerate().map(|(i, monitor)| {
         let scale = monitor.scale_factor().unwrap_or(1.0);
         let phys_x = monitor.x()?;
         let phys_y = monitor.y()?;
         let phys_w = monitor.width()?;
         let phys_h = monitor.height()?;
+        let monitor_name = monitor.name().ok().filter(|name| !name.is_empty());
+
+        let sdr_white_level = white_levels.iter()
+            .find(|(position, _)| *position == (phys_x, phys_y))
+            .map_or(1.0, |(_, level)| *level);
 
-        log::debug!("Monitor #{}: PhysRect=[x:{}, y:{}, w:{}, h:{}], Scale={}",
-            i, phys_x, phys_y, phys_w, phys_h, scale);
+        log::debug!("Monitor #{} ({}): PhysRect=[x:{}, y:{}, w:{}, h:{}], Scale={}, SDR White={}",
+            i, monitor_name.as_deref().unwrap_or("unnamed"), phys_x, phys_y, phys_w, phys_h, scale, sdr_white_level);
 
-        let image = monitor.capture_image()?;
+        let capture_started = Instant::now();
+        let image = monitor.capture_image().map_err(|e| {
+            log::error!("Capturing monitor #{} failed: {}", i, e);
+            match CaptureError::from(e) {
+                CaptureError::PermissionDenied => CaptureError::PermissionDenied,
+                _ => CaptureError::MonitorCaptureFailed(i),
+            }
+        })?;
+        log::debug!("Timing: capture of monitor #{} took {:?}", i, capture_started.elapsed());
 
         Ok(MonitorData {
             x: phys_x, y: phys_y, width: phys_w, height: phys_h,
-            scale_factor: scale, image
+            scale_factor: scale, monitor_name, sdr_white_level, image
         })
-    }).collect::<Result<Vec<MonitorData>, Box<dyn Error>>>()?;
+    }).collect::<Result<Vec<MonitorData>, CaptureError>>()?;
+
+    let data = assemble(captures, normalize_to);
+    log::debug!("Timing: capture_all_screens took {:?}", started.elapsed());
+    Ok(data)
+}
+
+/// Alignment test: like `capture_all_screens`, but every monitor shows `test_pattern` instead
+/// of its content, so it can be seen whether the overlay lines up with the physical screens.
+pub fn capture_test_pattern() -> Result<CaptureData, CaptureError> {
+    let rects = monitor_rects()?;
+    Ok(assemble(synthetic_monitors(&rects), None))
+}
+
+/// Monitors with generated content (`test_pattern`) in the given layout. Used by the alignment
+/// test and as test input for the geometry code.
+pub fn synthetic_monitors(rects: &[MonitorRect]) -> Vec<MonitorData> {
+    rects.iter().enumerate().map(|(index, rect)| MonitorData {
+        x: rect.x,
+        y: rect.y,
+        width: rect.width,
+        height: rect.height,
+        scale_factor: rect.scale_factor,
+        monitor_name: rect.name.clone(),
+        sdr_white_level: 1.0,
+        image: test_pattern(rect, index),
+    }).collect()
+}
+
+/// Frame color of the test pattern on the monitor with this index.
+const TEST_PATTERN_COLORS: [[u8; 4]; 6] = [
+    [230, 60, 60, 255], [60, 200, 90, 255], [70, 130, 240, 255],
+    [240, 200, 40, 255], [210, 80, 210, 255], [40, 200, 210, 255],
+];
+/// Grid cell size of the test pattern (physical px), every fifth line is brighter.
+const TEST_PATTERN_CELL: u32 = 100;
+const TEST_PATTERN_FRAME: u32 = 4;
+
+/// A grid with a colored frame along the monitor's edges and its number, size, scale factor
+/// and origin written in the middle, big enough to read on a photo of the screen.
+fn test_pattern(rect: &MonitorRect, index: usize) -> RgbaImage {
+    let accent = Rgba(TEST_PATTERN_COLORS[index % TEST_PATTERN_COLORS.len()]);
+    let mut image = RgbaImage::from_fn(rect.width, rect.height, |x, y| {
+        let frame = TEST_PATTERN_FRAME;
+        if x < frame || y < frame || x >= rect.width.saturating_sub(frame) || y >= rect.height.saturating_sub(frame) {
+            accent
+        } else if x % (TEST_PATTERN_CELL * 5) == 0 || y % (TEST_PATTERN_CELL * 5) == 0 {
+            Rgba([170, 170, 170, 255])
+        } else if x % TEST_PATTERN_CELL == 0 || y % TEST_PATTERN_CELL == 0 {
+            Rgba([90, 90, 90, 255])
+        } else {
+            Rgba([30, 30, 30, 255])
+        }
+    });
+
+    let lines = [
+        format!("#{}", index + 1),
+        format!("{}x{}  {}x", rect.width, rect.height, rect.scale_factor),
+        format!("{},{}", rect.x, rect.y),
+    ];
+    let dot = ((8.0 * rect.scale_factor).round() as u32).max(2);
+    let line_height = 7 * dot;
+    let top = (rect.height / 2).saturating_sub(line_height * lines.len() as u32 / 2);
+    for (row, line) in lines.iter().enumerate() {
+        let width = line.chars().count() as u32 * 4 * dot;
+        let left = (rect.width / 2).saturating_sub(width / 2);
+        draw_pattern_text(&mut image, line, left, top + row as u32 * line_height, dot, accent);
+    }
+    image
+}
+
+/// 3x5 glyphs (one row per byte, high bit left) for the characters `test_pattern` writes.
+fn pattern_glyph(character: char) -> [u8; 5] {
+    match character {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'x' => [0b000, 0b101, 0b010, 0b101, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '#' => [0b101, 0b111, 0b101, 0b111, 0b101],
+        _ => [0; 5],
+    }
+}
+
+/// Writes `text` with its top left at (x, y), every glyph dot `dot` px big.
+fn draw_pattern_text(image: &mut RgbaImage, text: &str, x: u32, y: u32, dot: u32, color: Rgba<u8>) {
+    for (index, character) in text.chars().enumerate() {
+        let glyph_x = x + index as u32 * 4 * dot;
+        for (row, bits) in pattern_glyph(character).iter().enumerate() {
+            for column in 0..3 {
+                if bits & (0b100 >> column) == 0 {
+                    continue;
+                }
+                let left = glyph_x + column * dot;
+                let top = y + row as u32 * dot;
+                for py in top..(top + dot).min(image.height()) {
+                    for px in left..(left + dot).min(image.width()) {
+                        image.put_pixel(px, py, color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Lays the monitors out on the virtual desktop and stitches them into `full_image`, see
+/// `capture_all_screens`.
+fn assemble(captures: Vec<MonitorData>, normalize_to: Option<f32>) -> CaptureData {
+    let rects: Vec<MonitorRect> = captures.iter().map(|m| MonitorRect {
+        x: m.x, y: m.y, width: m.width, height: m.height, scale_factor: m.scale_factor,
+        name: m.monitor_name.clone(),
+    }).collect();
+    let geometry = compute_geometry(&rects);
+    let (min_phys_x, min_phys_y) = geometry.physical_origin;
+
+    // --- 3. STITCH FULL IMAGE ---
+    let stitch_started = Instant::now();
+    let pixels = geometry.physical_width as u64 * geometry.physical_height as u64;
+    let full_image = if let Some(reference) = normalize_to {
+        let stitched = stitch_normalized(&captures, reference);
+        if stitched.is_none() {
+            log::warn!("Could not allocate the desktop normalized to {}x scale.", reference);
+        }
+        stitched
+    } else if pixels > MAX_STITCHED_PIXELS {
+        log::warn!("Virtual desktop is {}x{}, not stitching it; selections are cropped per monitor.",
+            geometry.physical_width, geometry.physical_height);
+        None
+    } else {
+        match try_new_image(geometry.physical_width, geometry.physical_height) {
+            Some(mut full_image) => {
+                for mon in &captures {
+                    // Normalize: Screen X - Leftmost X = Local X
+                    let local_x = (mon.x - min_phys_x) as i64;
+                    let local_y = (mon.y - min_phys_y) as i64;
+
+                    image::imageops::overlay(
+                        &mut full_image,
+                        &mon.image,
+                        local_x,
+                        local_y
+                    );
+                }
+                Some(full_image)
+            }
+            None => {
+                log::warn!("Could not allocate the {}x{} stitched desktop; selections are cropped per monitor.",
+                    geometry.physical_width, geometry.physical_height);
+                None
+            }
+        }
+    };
+
+    log::debug!("Timing: stitching took {:?}", stitch_started.elapsed());
+
+    CaptureData {
+        monitors: captures,
+        full_image,
+        logical_origin: geometry.logical_origin,
+        logical_width: geometry.logical_width,
+        logical_height: geometry.logical_height,
+        origin_scale_factor: geometry.origin_scale_factor,
+        physical_origin: geometry.physical_origin,
+        physical_width: geometry.physical_width,
+        physical_height: geometry.physical_height,
+    }
+}
+
+/// Stitches the monitors after resampling each one from its own scale factor to `reference`.
+///
+/// Monitors that touched before still touch afterwards; the others keep their offset from the
+/// desktop origin, scaled with the monitor.
+fn stitch_normalized(monitors: &[MonitorData], reference: f32) -> Option<RgbaImage> {
+    let factors: Vec<f32> = monitors.iter().map(|m| reference / m.scale_factor.max(0.1)).collect();
+    let sizes: Vec<(u32, u32)> = monitors.iter().zip(&factors).map(|(m, factor)| {
+        (((m.width as f32 * factor).round() as u32).max(1), ((m.height as f32 * factor).round() as u32).max(1))
+    }).collect();
+    let min_x = monitors.iter().map(|m| m.x).min()?;
+    let min_y = monitors.iter().map(|m| m.y).min()?;
+
+    // One pass per axis, in edge order, so a neighbour is always placed before the monitor touching it
+    let place = |position: fn(&MonitorData) -> (i32, u32), size: fn((u32, u32)) -> u32, min: i32| {
+        let mut order: Vec<usize> = (0..monitors.len()).collect();
+        order.sort_by_key(|&i| position(&monitors[i]).0);
+        let mut placed: Vec<Option<i64>> = vec![None; monitors.len()];
+        for &i in &order {
+            let (start, _) = position(&monitors[i]);
+            let neighbour = (0..monitors.len()).find(|&j| {
+                let (other_start, other_len) = position(&monitors[j]);
+                placed[j].is_some() && other_start + other_len as i32 == start
+            });
+            placed[i] = Some(match neighbour {
+                Some(j) => placed[j].unwrap_or_default() + size(sizes[j]) as i64,
+                None => ((start - min) as f32 * factors[i]).round() as i64,
+            });
+        }
+        placed.into_iter().map(Option::unwrap_or_default).collect::<Vec<i64>>()
+    };
+    let xs = place(|m| (m.x, m.width), |(width, _)| width, min_x);
+    let ys = place(|m| (m.y, m.height), |(_, height)| height, min_y);
+
+    let width = xs.iter().zip(&sizes).map(|(x, (w, _))| x + *w as i64).max()?;
+    let height = ys.iter().zip(&sizes).map(|(y, (_, h))| y + *h as i64).max()?;
+    if width as u64 * height as u64 > MAX_STITCHED_PIXELS {
+        return None;
+    }
+
+    let mut full_image = try_new_image(width as u32, height as u32)?;
+    for (i, mon) in monitors.iter().enumerate() {
+        let (w, h) = sizes[i];
+        if (w, h) == (mon.width, mon.height) {
+            image::imageops::overlay(&mut full_image, &mon.image, xs[i], ys[i]);
+        } else {
+            let resized = image::imageops::resize(&mon.image, w, h, image::imageops::FilterType::CatmullRom);
+            image::imageops::overlay(&mut full_image, &resized, xs[i], ys[i]);
+        }
+    }
+    Some(full_image)
+}
+
+/// Allocates a blank image, returning None instead of aborting when memory is short.
+pub fn try_new_image(width: u32, height: u32) -> Option<RgbaImage> {
+    let len = (width as usize).checked_mul(height as usize)?.checked_mul(4)?;
+    let mut buffer = Vec::new();
+    buffer.try_reserve_exact(len).ok()?;
+    buffer.resize(len, 0);
+    RgbaImage::from_raw(width, height, buffer)
+}
+
+/// Builds the given part of the virtual desktop (physical px, relative to `physical_origin`)
+/// from the individual monitor captures. Gaps between monitors stay transparent.
+pub fn crop_from_monitors(monitors: &[MonitorData], physical_origin: (i32, i32), x: u32, y: u32, width: u32, height: u32) -> Option<RgbaImage> {
+    let mut cropped = try_new_image(width, height)?;
+    for mon in monitors {
+        // Monitor position inside the crop, may be negative
+        let local_x = (mon.x - physical_origin.0) as i64 - x as i64;
+        let local_y = (mon.y - physical_origin.1) as i64 - y as i64;
+        image::imageops::overlay(&mut cropped, &mon.image, local_x, local_y);
+    }
+    Some(cropped)
+}
+
+/// Captures part of one monitor (monitor-relative physical px), checked against the current
+/// layout. Only that monitor is read. `monitor_position` is where the monitor was when the
+/// region was picked, see `find_region_monitor`.
+pub fn capture_monitor_region(monitor_name: &str, monitor_position: Option<(i32, i32)>, x: u32, y: u32, width: u32, height: u32) -> Result<RgbaImage, CaptureError> {
+    let started = Instant::now();
+    let monitors = Monitor::all()?;
+
+    // Validate against the layout we have *now*, monitors come and go
+    let rects = monitors.iter().map(monitor_rect).collect::<Result<Vec<_>, _>>()?;
+    let index = find_region_monitor(&rects, monitor_name, monitor_position)?;
+    let rect = &rects[index];
+    if !region_fits(rect, x, y, width, height) {
+        return Err(CaptureError::RegionUnavailable(format!(
+            "The region does not fit on monitor '{}' ({}x{}). Pick the region again.",
+            monitor_name, rect.width, rect.height
+        )));
+    }
+
+    let image = monitors[index].capture_region(x, y, width, height).map_err(|e| {
+        log::error!("Capturing a region of monitor #{} failed: {}", index, e);
+        match CaptureError::from(e) {
+            CaptureError::PermissionDenied => CaptureError::PermissionDenied,
+            _ => CaptureError::MonitorCaptureFailed(index),
+        }
+    })?;
+    log::debug!("Timing: capture_monitor_region took {:?}", started.elapsed());
+    Ok(image)
+}
+
+/// The monitor a region was picked on: the one with the same name at the same position, or
+/// the only one with that name (it was moved since). Names alone can't tell identical or
+/// unnamed monitors apart.
+fn find_region_monitor(monitors: &[MonitorRect], name: &str, position: Option<(i32, i32)>) -> Result<usize, CaptureError> {
+    let named = |rect: &MonitorRect| rect.name.as_deref().unwrap_or("") == name;
+    if let Some(position) = position
+        && let Some(index) = monitors.iter().position(|rect| named(rect) && (rect.x, rect.y) == position)
+    {
+        return Ok(index);
+    }
+
+    let mut candidates = monitors.iter().enumerate().filter(|(_, rect)| named(rect));
+    match (candidates.next(), candidates.next()) {
+        (Some((index, _)), None) => Ok(index),
+        (None, _) => Err(CaptureError::RegionUnavailable(format!("The monitor '{}' is not connected.", name))),
+        (Some(_), Some(_)) => Err(CaptureError::RegionUnavailable(format!(
+            "Several monitors are called '{}' and none is where the region was picked. Pick the region again.", name
+        ))),
+    }
+}
+
+/// Whether the region (monitor-relative) is non-empty and lies on the monitor.
+fn region_fits(monitor: &MonitorRect, x: u32, y: u32, width: u32, height: u32) -> bool {
+    width > 0
+        && height > 0
+        && x.checked_add(width).is_some_and(|right| right <= monitor.width)
+        && y.checked_add(height).is_some_and(|bottom| bottom <= monitor.height)
+}
+
+/// Captures a `width` x `height` region from the middle of the monitor at `point` (physical
+/// desktop px), or of the primary monitor when no monitor is there. Smaller monitors give a
+/// smaller region.
+pub fn capture_test_region(point: (i32, i32), width: u32, height: u32) -> Result<RgbaImage, CaptureError> {
+    let monitor = match Monitor::from_point(point.0, point.1) {
+        Ok(monitor) => monitor,
+        Err(_) => {
+            let monitors = Monitor::all()?;
+            let primary = monitors.iter().position(|monitor| monitor.is_primary().unwrap_or(false)).unwrap_or(0);
+            monitors.into_iter().nth(primary).ok_or(CaptureError::NoMonitors)?
+        }
+    };
+    let width = width.min(monitor.width()?);
+    let height = height.min(monitor.height()?);
+    let x = (monitor.width()? - width) / 2;
+    let y = (monitor.height()? - height) / 2;
+    Ok(monitor.capture_region(x, y, width, height)?)
+}
+
+/// Reads only the monitor layout (no pixels). Cheap enough for startup, where we just
+/// need the origin and its scale factor to do the DPI handshake.
+pub fn capture_geometry_only() -> Result<CaptureGeometry, CaptureError> {
+    let started = Instant::now();
+    let rects = monitor_rects()?;
+    let geometry = compute_geometry(&rects);
+    log::debug!("Timing: capture_geometry_only took {:?}", started.elapsed());
+    Ok(geometry)
+}
+
+/// The current monitor layout, without reading any pixels.
+fn monitor_rects() -> Result<Vec<MonitorRect>, CaptureError> {
+    let monitors = Monitor::all()?;
+    if monitors.is_empty() { return Err(CaptureError::NoMonitors); }
 
+    monitors.iter().map(monitor_rect).collect()
+}
+
+fn monitor_rect(monitor: &Monitor) -> Result<MonitorRect, CaptureError> {
+    Ok(MonitorRect {
+        x: monitor.x()?,
+        y: monitor.y()?,
+        width: monitor.width()?,
+        height: monitor.height()?,
+        scale_factor: monitor.scale_factor().unwrap_or(1.0),
+        name: monitor.name().ok().filter(|name| !name.is_empty()),
+    })
+}
+
+/// Physical placement and scale of one monitor.
+#[derive(Clone, Debug)]
+pub struct MonitorRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub scale_factor: f32,
+    pub name: Option<String>,
+}
+
+fn compute_geometry(monitors: &[MonitorRect]) -> CaptureGeometry {
     // --- 1. CALCULATE PHYSICAL BOUNDS (For internal drawing) ---
     let mut min_phys_x = i32::MAX;
     let mut min_phys_y = i32::MAX;
     let mut max_phys_x = i32::MIN;
     let mut max_phys_y = i32::MIN;
 
-    for mon in &captures {
+    for mon in monitors {
         min_phys_x = min_phys_x.min(mon.x);
         min_phys_y = min_phys_y.min(mon.y);
         max_phys_x = max_phys_x.max(mon.x + mon.width as i32);
@@ -82,7 +536,7 @@ pub fn capture_all_screens() -> Result<CaptureData, Box<dyn Error>> {
     let mut max_log_x = f32::MIN;
     let mut max_log_y = f32::MIN;
 
-    for (i, mon) in captures.iter().enumerate() {
+    for (i, mon) in monitors.iter().enumerate() {
         let log_x = mon.x as f32 / mon.scale_factor;
         let log_y = mon.y as f32 / mon.scale_factor;
         let log_w = mon.width as f32 / mon.scale_factor;
@@ -107,7 +561,7 @@ pub fn capture_all_screens() -> Result<CaptureData, Box<dyn Error>> {
     let mut origin_scale_factor = 1.0_f32;
     let epsilon = 0.001_f32;
     let mut found = false;
-    for mon in &captures {
+    for mon in monitors {
         let mon_log_x = mon.x as f32 / mon.scale_factor;
         let mon_log_y = mon.y as f32 / mon.scale_factor;
         if (mon_log_x - min_log_x).abs() < epsilon && (mon_log_y - min_log_y).abs() < epsilon {
@@ -119,7 +573,7 @@ pub fn capture_all_screens() -> Result<CaptureData, Box<dyn Error>> {
 
     if !found {
         // Fallback to previous physical-based selection
-        for mon in &captures {
+        for mon in monitors {
             if mon.x == min_phys_x && mon.y == min_phys_y {
                 origin_scale_factor = mon.scale_factor;
                 break;
@@ -137,24 +591,8 @@ pub fn capture_all_screens() -> Result<CaptureData, Box<dyn Error>> {
     log::debug!("Bounds Logical (final): Origin=({}, {}), Size={}x{} (using PPI={})",
         logical_origin_x, logical_origin_y, logical_w, logical_h, origin_scale_factor);
 
-    // --- 3. STITCH FULL IMAGE ---
-    let mut full_image = RgbaImage::new(total_phys_w, total_phys_h);
-    for mon in &captures {
-        // Normalize: Screen X - Leftmost X = Local X
-        let local_x = (mon.x - min_phys_x) as i64;
-        let local_y = (mon.y - min_phys_y) as i64;
-
-        image::imageops::overlay(
-            &mut full_image,
-            &mon.image,
-            local_x,
-            local_y
-        );
-    }
-
-    Ok(CaptureData {
-        monitors: captures,
-        full_image,
+    CaptureGeometry {
+        monitors: monitors.to_vec(),
         logical_origin: (logical_origin_x, logical_origin_y),
         logical_width: logical_w,
         logical_height: logical_h,
@@ -162,5 +600,304 @@ pub fn capture_all_screens() -> Result<CaptureData, Box<dyn Error>> {
         physical_origin: (min_phys_x, min_phys_y),
         physical_width: total_phys_w,
         physical_height: total_phys_h,
-    })
-}
\ No newline at end of file
+    }
+}
+/// SDR white level per active display, keyed by its physical desktop position.
+/// With HDR enabled, Windows shows SDR content brighter than the 80 nit reference
+/// ("SDR content brightness"), the level is reported as a multiple of that reference.
+#[cfg(target_os = "windows")]
+fn sdr_white_levels() -> Vec<((i32, i32), f32)> {
+    use windows::Win32::Devices::Display::{
+        DisplayConfigGetDeviceInfo, GetDisplayConfigBufferSizes, QueryDisplayConfig,
+        DISPLAYCONFIG_DEVICE_INFO_GET_SDR_WHITE_LEVEL, DISPLAYCONFIG_DEVICE_INFO_HEADER,
+        DISPLAYCONFIG_MODE_INFO, DISPLAYCONFIG_MODE_INFO_TYPE_SOURCE, DISPLAYCONFIG_PATH_INFO,
+        DISPLAYCONFIG_SDR_WHITE_LEVEL, QDC_ONLY_ACTIVE_PATHS,
+    };
+    use windows::Win32::Foundation::ERROR_SUCCESS;
+
+    let mut levels = Vec::new();
+
+    unsafe {
+        let mut path_count = 0;
+        let mut mode_count = 0;
+        if GetDisplayConfigBufferSizes(QDC_ONLY_ACTIVE_PATHS, &mut path_count, &mut mode_count) != ERROR_SUCCESS {
+            log::warn!("Could not query the display configuration, SDR white levels unknown.");
+            return levels;
+        }
+
+        let mut paths = vec![DISPLAYCONFIG_PATH_INFO::default(); path_count as usize];
+        let mut modes = vec![DISPLAYCONFIG_MODE_INFO::default(); mode_count as usize];
+        if QueryDisplayConfig(
+            QDC_ONLY_ACTIVE_PATHS,
+            &mut path_count,
+            paths.as_mut_ptr(),
+            &mut mode_count,
+            modes.as_mut_ptr(),
+            None,
+        ) != ERROR_SUCCESS {
+            log::warn!("Could not query the display configuration, SDR white levels unknown.");
+            return levels;
+        }
+
+        for path in paths.iter().take(path_count as usize) {
+            // The source mode holds the desktop position, which is how xcap identifies monitors too
+            let Some(mode) = modes.get(path.sourceInfo.Anonymous.modeInfoIdx as usize) else {
+                continue;
+            };
+            if mode.infoType != DISPLAYCONFIG_MODE_INFO_TYPE_SOURCE {
+                continue;
+            }
+            let position = mode.Anonymous.sourceMode.position;
+
+            let mut white_level = DISPLAYCONFIG_SDR_WHITE_LEVEL {
+                header: DISPLAYCONFIG_DEVICE_INFO_HEADER {
+                    r#type: DISPLAYCONFIG_DEVICE_INFO_GET_SDR_WHITE_LEVEL,
+                    size: size_of::<DISPLAYCONFIG_SDR_WHITE_LEVEL>() as u32,
+                    adapterId: path.targetInfo.adapterId,
+                    id: path.targetInfo.id,
+                },
+                SDRWhiteLevel: 0,
+            };
+            if DisplayConfigGetDeviceInfo(&mut white_level.header) == 0 && white_level.SDRWhiteLevel > 0 {
+                // 1000 = 80 nits
+                levels.push(((position.x, position.y), white_level.SDRWhiteLevel as f32 / 1000.0));
+            }
+        }
+    }
+
+    levels
+}
+
+#[cfg(not(target_os = "windows"))]
+fn sdr_white_levels() -> Vec<((i32, i32), f32)> {
+    Vec::new()
+}
+
+/// ICC profile contents keyed by physical monitor position.
+pub type MonitorProfiles = Vec<((i32, i32), std::sync::Arc<Vec<u8>>)>;
+
+/// ICC profile contents per monitor that has one assigned, keyed by its physical desktop position
+/// (like the SDR white levels). Monitors without a profile are left out.
+#[cfg(target_os = "windows")]
+pub fn monitor_icc_profiles() -> MonitorProfiles {
+    use windows::core::{BOOL, PCWSTR, PWSTR, w};
+    use windows::Win32::Foundation::{LPARAM, RECT};
+    use windows::Win32::Graphics::Gdi::{
+        CreateDCW, DeleteDC, EnumDisplayMonitors, GetMonitorInfoW, HDC, HMONITOR, MONITORINFO, MONITORINFOEXW,
+    };
+    use windows::Win32::UI::ColorSystem::GetICMProfileW;
+
+    unsafe extern "system" fn collect(monitor: HMONITOR, _: HDC, _: *mut RECT, data: LPARAM) -> BOOL {
+        let monitors = unsafe { &mut *(data.0 as *mut Vec<HMONITOR>) };
+        monitors.push(monitor);
+        BOOL(1)
+    }
+
+    let mut handles: Vec<HMONITOR> = Vec::new();
+    let mut profiles = Vec::new();
+    unsafe {
+        let _ = EnumDisplayMonitors(None, None, Some(collect), LPARAM(&mut handles as *mut _ as isize));
+
+        for handle in handles {
+            let mut info = MONITORINFOEXW::default();
+            info.monitorInfo.cbSize = size_of::<MONITORINFOEXW>() as u32;
+            if !GetMonitorInfoW(handle, &mut info as *mut MONITORINFOEXW as *mut MONITORINFO).as_bool() {
+                continue;
+            }
+
+            let dc = CreateDCW(w!("DISPLAY"), PCWSTR(info.szDevice.as_ptr()), PCWSTR::null(), None);
+            if dc.is_invalid() {
+                continue;
+            }
+            let mut path = [0u16; 260];
+            let mut len = path.len() as u32;
+            let found = GetICMProfileW(dc, &mut len, Some(PWSTR(path.as_mut_ptr()))).as_bool();
+            let _ = DeleteDC(dc);
+            if !found {
+                continue;
+            }
+
+            let end = path.iter().position(|&c| c == 0).unwrap_or(path.len());
+            let path = String::from_utf16_lossy(&path[..end]);
+            match std::fs::read(&path) {
+                Ok(profile) => {
+                    let origin = info.monitorInfo.rcMonitor;
+                    log::debug!("Monitor at ({}, {}) uses color profile {}", origin.left, origin.top, path);
+                    profiles.push(((origin.left, origin.top), std::sync::Arc::new(profile)));
+                }
+                Err(e) => log::warn!("Could not read the color profile {}: {}", path, e),
+            }
+        }
+    }
+
+    profiles
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn monitor_icc_profiles() -> MonitorProfiles {
+    Vec::new()
+}
+
+/// An always-on-top window of another program, in physical screen pixels.
+#[derive(Clone, Debug)]
+pub struct TopmostWindow {
+    pub title: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Visible always-on-top windows of other programs (volume OSD, picture-in-picture players, ...).
+/// They end up in the frozen screenshot, so the overlay points them out.
+#[cfg(target_os = "windows")]
+pub fn topmost_windows() -> Vec<TopmostWindow> {
+    use windows::core::BOOL;
+    use windows::Win32::Foundation::{HWND, LPARAM, RECT};
+    use windows::Win32::Graphics::Dwm::{DwmGetWindowAttribute, DWMWA_CLOAKED};
+    use windows::Win32::UI::WindowsAndMessaging::{
+        EnumWindows, GetClassNameW, GetWindowLongW, GetWindowRect, GetWindowTextW,
+        GetWindowThreadProcessId, IsIconic, IsWindowVisible, GWL_EXSTYLE, WS_EX_TOPMOST,
+    };
+
+    // The taskbars are always on top and would flag every selection touching them
+    const IGNORED_CLASSES: [&str; 2] = ["Shell_TrayWnd", "Shell_SecondaryTrayWnd"];
+
+    unsafe extern "system" fn collect(hwnd: HWND, lparam: LPARAM) -> BOOL {
+        let found = unsafe { &mut *(lparam.0 as *mut Vec<TopmostWindow>) };
+        unsafe {
+            if !IsWindowVisible(hwnd).as_bool() || IsIconic(hwnd).as_bool() {
+                return true.into();
+            }
+            if GetWindowLongW(hwnd, GWL_EXSTYLE) as u32 & WS_EX_TOPMOST.0 == 0 {
+                return true.into();
+            }
+            let mut process_id = 0;
+            GetWindowThreadProcessId(hwnd, Some(&mut process_id));
+            if process_id == std::process::id() {
+                return true.into();
+            }
+            // "Visible" UWP windows may still be cloaked (not drawn at all)
+            let mut cloaked = 0u32;
+            if DwmGetWindowAttribute(hwnd, DWMWA_CLOAKED, &mut cloaked as *mut u32 as *mut _, size_of::<u32>() as u32).is_ok()
+                && cloaked != 0
+            {
+                return true.into();
+            }
+
+            let mut class = [0u16; 256];
+            let class_len = GetClassNameW(hwnd, &mut class).max(0) as usize;
+            let class = String::from_utf16_lossy(&class[..class_len]);
+            if IGNORED_CLASSES.contains(&class.as_str()) {
+                return true.into();
+            }
+
+            let mut rect = RECT::default();
+            if GetWindowRect(hwnd, &mut rect).is_err() || rect.right <= rect.left || rect.bottom <= rect.top {
+                return true.into();
+            }
+
+            let mut title = [0u16; 256];
+            let title_len = GetWindowTextW(hwnd, &mut title).max(0) as usize;
+            let title = String::from_utf16_lossy(&title[..title_len]);
+            found.push(TopmostWindow {
+                title: if title.is_empty() { class } else { title },
+                x: rect.left,
+                y: rect.top,
+                width: (rect.right - rect.left) as u32,
+                height: (rect.bottom - rect.top) as u32,
+            });
+        }
+        true.into()
+    }
+
+    let mut found: Vec<TopmostWindow> = Vec::new();
+    unsafe {
+        if let Err(e) = EnumWindows(Some(collect), LPARAM(&mut found as *mut Vec<TopmostWindow> as isize)) {
+            log::warn!("Could not enumerate windows: {}", e);
+        }
+    }
+    found
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn topmost_windows() -> Vec<TopmostWindow> {
+    Vec::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 1.5x laptop panel left of a 1x monitor, their top edges aligned.
+    fn mixed_dpi_layout() -> Vec<MonitorRect> {
+        vec![
+            MonitorRect { x: -2400, y: 0, width: 2400, height: 1600, scale_factor: 1.5, name: Some("laptop".into()) },
+            MonitorRect { x: 0, y: 0, width: 1920, height: 1080, scale_factor: 1.0, name: Some("external".into()) },
+        ]
+    }
+
+    fn frame_color(index: usize) -> Rgba<u8> {
+        Rgba(TEST_PATTERN_COLORS[index])
+    }
+
+    #[test]
+    fn geometry_spans_all_monitors_in_physical_px() {
+        let geometry = compute_geometry(&mixed_dpi_layout());
+        assert_eq!(geometry.physical_origin, (-2400, 0));
+        assert_eq!((geometry.physical_width, geometry.physical_height), (2400 + 1920, 1600));
+        // The leftmost monitor also has the smallest logical origin, the window uses its scale
+        assert_eq!(geometry.origin_scale_factor, 1.5);
+        assert_eq!(geometry.logical_origin, (-1600.0, 0.0));
+    }
+
+    #[test]
+    fn stitching_places_every_monitor_at_its_offset() {
+        let data = assemble(synthetic_monitors(&mixed_dpi_layout()), None);
+        let full_image = data.full_image.unwrap();
+        assert_eq!(full_image.dimensions(), (4320, 1600));
+
+        assert_eq!(*full_image.get_pixel(0, 0), frame_color(0));
+        assert_eq!(*full_image.get_pixel(2399, 1599), frame_color(0));
+        assert_eq!(*full_image.get_pixel(2400, 0), frame_color(1));
+        assert_eq!(*full_image.get_pixel(4319, 1079), frame_color(1));
+        // Below the shorter monitor nothing is drawn
+        assert_eq!(full_image.get_pixel(4000, 1200)[3], 0);
+    }
+
+    #[test]
+    fn crops_across_a_monitor_boundary_match_the_stitched_image() {
+        let data = assemble(synthetic_monitors(&mixed_dpi_layout()), None);
+        let cropped = crop_from_monitors(&data.monitors, data.physical_origin, 2300, 1000, 200, 200).unwrap();
+        let expected = image::imageops::crop_imm(data.full_image.as_ref().unwrap(), 2300, 1000, 200, 200).to_image();
+        assert_eq!(cropped, expected);
+        assert_eq!(*cropped.get_pixel(99, 0), frame_color(0));
+        assert_eq!(*cropped.get_pixel(100, 0), frame_color(1));
+    }
+
+    #[test]
+    fn region_monitor_is_matched_by_name_and_position() {
+        let mut monitors = mixed_dpi_layout();
+        monitors.push(MonitorRect { x: 1920, y: 0, width: 1920, height: 1080, scale_factor: 1.0, name: Some("external".into()) });
+
+        assert_eq!(find_region_monitor(&monitors, "external", Some((1920, 0))).unwrap(), 2);
+        assert_eq!(find_region_monitor(&monitors, "external", Some((0, 0))).unwrap(), 1);
+        // Two monitors with that name and neither is where the region was picked
+        assert!(find_region_monitor(&monitors, "external", Some((5000, 0))).is_err());
+        assert!(find_region_monitor(&monitors, "external", None).is_err());
+        // A unique name still matches after the monitor was moved
+        assert_eq!(find_region_monitor(&monitors, "laptop", Some((-1920, 0))).unwrap(), 0);
+        assert!(find_region_monitor(&monitors, "projector", None).is_err());
+    }
+
+    #[test]
+    fn region_must_lie_on_the_monitor() {
+        let monitor = &mixed_dpi_layout()[1];
+        assert!(region_fits(monitor, 0, 0, 1920, 1080));
+        assert!(!region_fits(monitor, 1, 0, 1920, 1080));
+        assert!(!region_fits(monitor, 0, 0, 0, 10));
+        // Would overflow u32 instead of failing the check
+        assert!(!region_fits(monitor, u32::MAX, 0, 2, 2));
+        assert!(!region_fits(monitor, 0, 10, 2, u32::MAX));
+    }
+}
@@ -0,0 +1,136 @@
+//! Command line subcommands (e.g. `crab-grab convert ...`), handled before the tray app starts.
+
+use std::path::PathBuf;
+use image::ImageFormat;
+use image::imageops::FilterType;
+use crate::utils;
+
+const CONVERT_USAGE: &str = "Usage: crab-grab convert <input> [--format png|jpeg|webp|tiff|bmp|avif] \
+    [--quality 1-100] [--resize WxH] [-o <output>]";
+
+const SUPPORTED_FORMATS: [ImageFormat; 6] = [
+    ImageFormat::Png,
+    ImageFormat::Jpeg,
+    ImageFormat::WebP,
+    ImageFormat::Tiff,
+    ImageFormat::Bmp,
+    ImageFormat::Avif,
+];
+
+/// Runs the subcommand named in `args` (as from `std::env::args()`), if any.
+/// Returns the process exit code, or None when the app should start normally.
+pub fn run(args: &[String]) -> Option<i32> {
+    let command = args.get(1)?;
+    match command.as_str() {
+        "convert" => {
+            attach_console();
+            Some(match convert(&args[2..]) {
+                Ok(output) => {
+                    println!("Wrote {}", output.display());
+                    0
+                }
+                Err(e) => {
+                    eprintln!("{}\n{}", e, CONVERT_USAGE);
+                    1
+                }
+            })
+        }
+        _ => None,
+    }
+}
+
+struct ConvertArgs {
+    input: PathBuf,
+    output: Option<PathBuf>,
+    format: Option<ImageFormat>,
+    quality: u8,
+    resize: Option<(u32, u32)>,
+}
+
+fn convert(args: &[String]) -> Result<PathBuf, String> {
+    let args = parse_convert_args(args)?;
+
+    // An explicit --format wins, otherwise the output extension decides
+    let format = match (args.format, &args.output) {
+        (Some(format), _) => format,
+        (None, Some(output)) => ImageFormat::from_path(output)
+            .map_err(|_| format!("Cannot tell the format from {}, use --format", output.display()))?,
+        (None, None) => return Err("Missing --format (or an output file with a known extension)".to_string()),
+    };
+    if !SUPPORTED_FORMATS.contains(&format) {
+        return Err(format!("Unsupported output format: {:?}", format));
+    }
+
+    let output = args.output.unwrap_or_else(|| args.input.with_extension(format.extensions_str()[0]));
+    if output == args.input {
+        return Err("The output would overwrite the input, use -o".to_string());
+    }
+
+    let mut image = image::open(&args.input)
+        .map_err(|e| format!("Failed to open {}: {}", args.input.display(), e))?;
+    if let Some((width, height)) = args.resize {
+        image = image.resize_exact(width, height, FilterType::Lanczos3);
+    }
+
+    utils::encode_image_to_file(&image, &output, format, args.quality)
+        .map_err(|e| format!("Failed to write {}: {}", output.display(), e))?;
+    log::info!("Converted {:?} to {:?} ({:?})", args.input, output, format);
+    Ok(output)
+}
+
+fn parse_convert_args(args: &[String]) -> Result<ConvertArgs, String> {
+    let mut input = None;
+    let mut output = None;
+    let mut format = None;
+    let mut quality = 80;
+    let mut resize = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "-f" | "--format" => {
+                let name = value()?;
+                format = Some(ImageFormat::from_extension(name).ok_or_else(|| format!("Unknown format: {}", name))?);
+            }
+            "-q" | "--quality" => {
+                let text = value()?;
+                quality = text.parse::<u8>().ok()
+                    .filter(|q| (1..=100).contains(q))
+                    .ok_or_else(|| format!("Quality must be 1-100, got {}", text))?;
+            }
+            "--resize" => {
+                let text = value()?;
+                resize = Some(parse_size(text).ok_or_else(|| format!("Resize must look like 1920x1080, got {}", text))?);
+            }
+            "-o" | "--output" => output = Some(PathBuf::from(value()?)),
+            other if other.starts_with('-') => return Err(format!("Unknown option: {}", other)),
+            other if input.is_none() => input = Some(PathBuf::from(other)),
+            other => return Err(format!("Unexpected argument: {}", other)),
+        }
+    }
+
+    Ok(ConvertArgs {
+        input: input.ok_or("Missing input file")?,
+        output,
+        format,
+        quality,
+        resize,
+    })
+}
+
+fn parse_size(text: &str) -> Option<(u32, u32)> {
+    let (width, height) = text.split_once(['x', 'X'])?;
+    let size = (width.trim().parse().ok()?, height.trim().parse().ok()?);
+    (size.0 > 0 && size.1 > 0).then_some(size)
+}
+
+/// The release build uses the Windows GUI subsystem, so there is no console unless we
+/// borrow the one of the shell that started us.
+fn attach_console() {
+    #[cfg(target_os = "windows")]
+    unsafe {
+        use windows::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+        let _ = AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
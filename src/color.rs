@@ -0,0 +1,103 @@
+//! Color management for captures from monitors with an ICC profile (wide-gamut or calibrated).
+//! The framebuffer holds the monitor's own color values, which look oversaturated elsewhere
+//! unless they are tagged with the profile or converted to sRGB.
+
+use std::sync::Arc;
+use image::RgbaImage;
+
+use crate::capture::{MonitorData, MonitorProfiles};
+use crate::config::ColorManagement;
+
+/// The part of a capture that came from one monitor (capture px), with that monitor's profile.
+#[derive(Clone)]
+pub struct ColorPart {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub icc_profile: Arc<Vec<u8>>,
+}
+
+/// Splits a crop of the virtual desktop (physical px, relative to `physical_origin`) into the
+/// parts the monitors contributed. Monitors without a profile are left out.
+pub fn parts_for_crop(
+    monitors: &[MonitorData],
+    physical_origin: (i32, i32),
+    crop: (u32, u32, u32, u32),
+    profiles: &MonitorProfiles,
+) -> Vec<ColorPart> {
+    let (crop_x, crop_y, crop_width, crop_height) = crop;
+    monitors.iter().filter_map(|monitor| {
+        let (_, icc_profile) = profiles.iter().find(|(position, _)| *position == (monitor.x, monitor.y))?;
+
+        // Monitor rect in crop coordinates, clipped to the crop
+        let left = (monitor.x - physical_origin.0) as i64 - crop_x as i64;
+        let top = (monitor.y - physical_origin.1) as i64 - crop_y as i64;
+        let x0 = left.max(0);
+        let y0 = top.max(0);
+        let x1 = (left + monitor.width as i64).min(crop_width as i64);
+        let y1 = (top + monitor.height as i64).min(crop_height as i64);
+        (x1 > x0 && y1 > y0).then(|| ColorPart {
+            x: x0 as u32,
+            y: y0 as u32,
+            width: (x1 - x0) as u32,
+            height: (y1 - y0) as u32,
+            icc_profile: icc_profile.clone(),
+        })
+    }).collect()
+}
+
+/// Applies the color management setting to a capture before it is saved or copied.
+/// Returns the profile to embed into the saved PNG, if any.
+pub fn prepare_capture(image: &mut RgbaImage, mode: ColorManagement, parts: &[ColorPart]) -> Option<Arc<Vec<u8>>> {
+    match mode {
+        ColorManagement::Off => None,
+        ColorManagement::EmbedProfile => {
+            // A PNG holds one profile: the monitor with the largest share of the capture wins
+            let largest = parts.iter().max_by_key(|part| part.width as u64 * part.height as u64)?;
+            if parts.iter().any(|part| part.icc_profile != largest.icc_profile) {
+                log::warn!("Capture spans monitors with different color profiles, embedding the one covering most of it.");
+            }
+            Some(largest.icc_profile.clone())
+        }
+        ColorManagement::ConvertToSrgb => {
+            // Each monitor's part separately, they may have different profiles
+            for part in parts {
+                if let Err(e) = convert_to_srgb(image, part) {
+                    log::error!("Could not convert a capture part to sRGB, keeping its original colors: {}", e);
+                }
+            }
+            None
+        }
+    }
+}
+
+#[cfg(feature = "color-management")]
+fn convert_to_srgb(image: &mut RgbaImage, part: &ColorPart) -> Result<(), Box<dyn std::error::Error>> {
+    use moxcms::{ColorProfile, Layout, TransformOptions};
+
+    // The parts describe the selection, not whatever the image became since
+    if part.x + part.width > image.width() || part.y + part.height > image.height() {
+        return Err("the part is outside the image".into());
+    }
+
+    let source = ColorProfile::new_from_slice(&part.icc_profile).map_err(|e| format!("{:?}", e))?;
+    let transform = source
+        .create_transform_8bit(Layout::Rgba, &ColorProfile::new_srgb(), Layout::Rgba, TransformOptions::default())
+        .map_err(|e| format!("{:?}", e))?;
+
+    let stride = image.width() as usize * 4;
+    let start = part.x as usize * 4;
+    let end = start + part.width as usize * 4;
+    let mut converted = vec![0u8; end - start];
+    for row in image.chunks_exact_mut(stride).skip(part.y as usize).take(part.height as usize) {
+        transform.transform(&row[start..end], &mut converted).map_err(|e| format!("{:?}", e))?;
+        row[start..end].copy_from_slice(&converted);
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "color-management"))]
+fn convert_to_srgb(_image: &mut RgbaImage, _part: &ColorPart) -> Result<(), Box<dyn std::error::Error>> {
+    Err("built without the color-management feature".into())
+}
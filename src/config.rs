@@ -1,4 +1,8 @@
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use global_hotkey::hotkey::{Code, HotKey, Modifiers};
 use crate::utils;
@@ -15,27 +19,643 @@ fn default_snap_key() -> HotKey {
     HotKey::new(Some(Modifiers::CONTROL | Modifiers::SHIFT), Code::KeyG)
 }
 
+fn default_annotate_key() -> HotKey {
+    HotKey::new(Some(Modifiers::CONTROL | Modifiers::SHIFT), Code::KeyA)
+}
+
+fn default_external_edit_key() -> HotKey {
+    HotKey::new(Some(Modifiers::CONTROL | Modifiers::SHIFT), Code::KeyE)
+}
+
+fn default_panic_key() -> HotKey {
+    HotKey::new(Some(Modifiers::CONTROL | Modifiers::ALT), Code::Escape)
+}
+
 fn hotkey_to_savable(hotkey: &HotKey) -> (String, u32) {
     (hotkey.key.to_string(), hotkey.mods.bits())
 }
 
-fn savable_to_hotkey(code: &str, modifiers: u32) -> HotKey {
+fn savable_to_hotkey(code: &str, modifiers: u32, fallback: HotKey) -> HotKey {
     let mods = Modifiers::from_bits(modifiers);
     if let Ok(key) = Code::from_str(code) {
         HotKey::new(mods, key)
     } else {
         // Fallback to default if parsing fails
-        default_snap_key()
+        fallback
+    }
+}
+
+/// How the border of the active selection is drawn while snapping.
+#[derive(Clone, Copy, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub enum SelectionStyle {
+    /// Static black outline with a thin white inner line.
+    #[default]
+    Classic,
+    /// Animated dashed border ("marching ants").
+    MarchingAnts,
+}
+
+/// What happens to the settings window after a capture that was started while it was open.
+#[derive(Clone, Copy, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub enum AfterCaptureFromSettings {
+    /// Bring the settings window back where it was.
+    #[default]
+    ReturnToSettings,
+    /// Close settings and go back to the tray.
+    CloseSettings,
+}
+
+/// What the capture hotkey does while a selection is already on screen.
+#[derive(Clone, Copy, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub enum RecaptureHotkeyBehavior {
+    /// Nothing, keep selecting.
+    #[default]
+    Ignore,
+    /// Throw the overlay away and capture the screens again, e.g. after their content changed.
+    Recapture,
+    /// Same as Escape.
+    Cancel,
+}
+
+/// What the settings window's close button (X) does.
+#[derive(Clone, Copy, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub enum CloseButtonAction {
+    /// Close settings, CrabGrab keeps running in the tray.
+    #[default]
+    Tray,
+    /// Quit CrabGrab.
+    Quit,
+}
+
+/// What the shutter sound does while other programs are playing audio (Windows only).
+#[cfg(target_os = "windows")]
+#[derive(Clone, Copy, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub enum ShutterWithOtherAudio {
+    /// Play it like always.
+    #[default]
+    Play,
+    /// Turn the other programs down while it plays.
+    Duck,
+    /// Stay silent.
+    Skip,
+}
+
+/// What happens when a save path is longer than the classic Windows path limit.
+#[derive(Clone, Copy, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub enum LongPathStrategy {
+    /// Cut the file name (keeping timestamp and extension) until the path fits.
+    #[default]
+    ShortenFileName,
+    /// Keep the name and write through the `\\?\` extended-length prefix.
+    ExtendedPrefix,
+}
+
+/// What happens when a capture's file name is already taken (two captures in the same second).
+#[derive(Clone, Copy, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub enum CollisionPolicy {
+    /// Replace the existing file.
+    Overwrite,
+    /// Add `_1`, `_2`, ... to the name.
+    #[default]
+    Increment,
+    /// Add microseconds to every timestamp, so names practically never collide.
+    Timestamp,
+}
+
+impl CollisionPolicy {
+    pub const ALL: [CollisionPolicy; 3] = [
+        CollisionPolicy::Overwrite,
+        CollisionPolicy::Increment,
+        CollisionPolicy::Timestamp,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            CollisionPolicy::Overwrite => "Overwrite",
+            CollisionPolicy::Increment => "Add a number",
+            CollisionPolicy::Timestamp => "Add microseconds",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            CollisionPolicy::Overwrite => "The newer capture replaces the older one.",
+            CollisionPolicy::Increment => "screenshot_<time>_1.png, _2, ... only when the name is taken.",
+            CollisionPolicy::Timestamp => "Every file name gets microseconds, e.g. screenshot_<time>_123456.png.",
+        }
+    }
+}
+
+/// PNG filter applied to each row before compression. Trades encode speed for file size.
+#[derive(Clone, Copy, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub enum PngFilter {
+    None,
+    Sub,
+    Up,
+    Average,
+    Paeth,
+    /// Picks the best of the others per row (the encoder's default).
+    #[default]
+    Adaptive,
+}
+
+impl PngFilter {
+    pub const ALL: [PngFilter; 6] = [
+        PngFilter::None,
+        PngFilter::Sub,
+        PngFilter::Up,
+        PngFilter::Average,
+        PngFilter::Paeth,
+        PngFilter::Adaptive,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PngFilter::None => "None",
+            PngFilter::Sub => "Sub",
+            PngFilter::Up => "Up",
+            PngFilter::Average => "Average",
+            PngFilter::Paeth => "Paeth",
+            PngFilter::Adaptive => "Adaptive",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            PngFilter::None => "Fastest to encode, but the largest files except for flat UI screenshots.",
+            PngFilter::Sub => "Cheap and good on horizontal gradients and rows of similar pixels.",
+            PngFilter::Up => "Cheap and good when rows repeat, like text and window chrome.",
+            PngFilter::Average => "Slower than Sub/Up, helps on smooth photos and soft gradients.",
+            PngFilter::Paeth => "The slowest single filter, usually the smallest files for photos and video frames.",
+            PngFilter::Adaptive => "Tries every filter per row: the smallest files overall at the highest encode cost.",
+        }
+    }
+}
+
+/// Composition lines drawn inside the selection while snapping.
+#[derive(Clone, Copy, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub enum AlignmentGuide {
+    #[default]
+    None,
+    RuleOfThirds,
+    GoldenRatio,
+    DiagonalMethod,
+}
+
+impl AlignmentGuide {
+    pub const ALL: [AlignmentGuide; 4] = [
+        AlignmentGuide::None,
+        AlignmentGuide::RuleOfThirds,
+        AlignmentGuide::GoldenRatio,
+        AlignmentGuide::DiagonalMethod,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            AlignmentGuide::None => "None",
+            AlignmentGuide::RuleOfThirds => "Rule of thirds",
+            AlignmentGuide::GoldenRatio => "Golden ratio",
+            AlignmentGuide::DiagonalMethod => "Diagonals",
+        }
+    }
+
+    /// The next guide in `ALL`, wrapping around (the G key while snapping).
+    pub fn next(&self) -> AlignmentGuide {
+        let index = Self::ALL.iter().position(|guide| guide == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+/// Where captures narrower than the montage sit on it.
+#[derive(Clone, Copy, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub enum MontageAlign {
+    Left,
+    #[default]
+    Center,
+}
+
+impl MontageAlign {
+    pub const ALL: [MontageAlign; 2] = [MontageAlign::Left, MontageAlign::Center];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            MontageAlign::Left => "Left",
+            MontageAlign::Center => "Centered",
+        }
+    }
+}
+
+/// A built-in post-processing step, see `filters::FilterPipeline`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum FilterKind {
+    Grayscale,
+    Invert,
+    /// Brightness is added to every channel (-255..=255), contrast is in percent (-100..=100).
+    BrightnessContrast { brightness: i32, contrast: f32 },
+}
+
+impl FilterKind {
+    pub const ALL: [FilterKind; 3] = [
+        FilterKind::Grayscale,
+        FilterKind::Invert,
+        FilterKind::BrightnessContrast { brightness: 0, contrast: 0.0 },
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            FilterKind::Grayscale => "Grayscale",
+            FilterKind::Invert => "Invert",
+            FilterKind::BrightnessContrast { .. } => "Brightness / Contrast",
+        }
+    }
+}
+
+/// What happens to captures from monitors with a color profile (wide-gamut or calibrated).
+#[derive(Clone, Copy, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub enum ColorManagement {
+    /// Keep the raw framebuffer values, untagged.
+    #[default]
+    Off,
+    /// Tag the saved PNG with the monitor's profile (iCCP), pixels stay untouched.
+    EmbedProfile,
+    /// Convert the pixels to sRGB, per monitor.
+    ConvertToSrgb,
+}
+
+impl ColorManagement {
+    pub const ALL: [ColorManagement; 3] = [
+        ColorManagement::Off,
+        ColorManagement::EmbedProfile,
+        ColorManagement::ConvertToSrgb,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ColorManagement::Off => "Off",
+            ColorManagement::EmbedProfile => "Embed monitor profile",
+            ColorManagement::ConvertToSrgb => "Convert to sRGB",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            ColorManagement::Off => "Save the pixels as the monitor shows them, without a profile.",
+            ColorManagement::EmbedProfile => "Color-managed viewers show the capture as it looked. The clipboard gets the raw pixels.",
+            ColorManagement::ConvertToSrgb => "Looks right everywhere, including the clipboard. Colors outside sRGB get clipped.",
+        }
+    }
+}
+
+/// Which GPU the renderer asks for. Read before the window exists, see `RendererConfig`.
+#[derive(Clone, Copy, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub enum GpuPowerPreference {
+    /// The discrete GPU, if there is one.
+    #[default]
+    HighPerformance,
+    /// The integrated GPU, easier on laptop batteries.
+    LowPower,
+}
+
+/// Whether the overlay window is made transparent. Read before the window exists, see `RendererConfig`.
+#[derive(Clone, Copy, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub enum WindowTransparency {
+    /// Transparent unless no compositor is running (some X11 window managers).
+    #[default]
+    Auto,
+    On,
+    /// The overlay is drawn opaque from the captured screen, for setups where a transparent
+    /// window shows up black.
+    Off,
+}
+
+impl WindowTransparency {
+    pub fn enabled(self) -> bool {
+        match self {
+            WindowTransparency::Auto => utils::window_transparency_supported(),
+            WindowTransparency::On => true,
+            WindowTransparency::Off => false,
+        }
+    }
+}
+
+/// What the key pressed after the chord hotkey does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum ChordAction {
+    Region,
+    Everything,
+    LastRegion,
+}
+
+impl ChordAction {
+    pub const ALL: [ChordAction; 3] = [ChordAction::Region, ChordAction::Everything, ChordAction::LastRegion];
+
+    /// The key that picks the action once the chord is armed (no modifiers).
+    pub fn key(&self) -> Code {
+        match self {
+            ChordAction::Region => Code::KeyR,
+            ChordAction::Everything => Code::KeyF,
+            ChordAction::LastRegion => Code::KeyL,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ChordAction::Region => "Select a region",
+            ChordAction::Everything => "Capture everything",
+            ChordAction::LastRegion => "Last region again",
+        }
+    }
+}
+
+/// A representation of the capture that can be placed on the clipboard.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum ClipboardTarget {
+    /// The bitmap itself (arboard also adds a PNG format where the platform supports it).
+    Image,
+    /// The saved file, as a file list (paste into Explorer / file managers).
+    File,
+    /// The saved file path as text.
+    Path,
+    /// A `data:image/png;base64,...` URL as text.
+    Base64,
+    /// The selected region's physical desktop coordinates as text, see `coordinates_format`.
+    /// On Windows they go along with the image, elsewhere only when Image is off.
+    Coordinates,
+}
+
+impl ClipboardTarget {
+    pub const ALL: [ClipboardTarget; 5] = [
+        ClipboardTarget::Image,
+        ClipboardTarget::File,
+        ClipboardTarget::Path,
+        ClipboardTarget::Base64,
+        ClipboardTarget::Coordinates,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ClipboardTarget::Image => "Image",
+            ClipboardTarget::File => "File",
+            ClipboardTarget::Path => "File Path (text)",
+            ClipboardTarget::Base64 => "Base64 Data URL (text)",
+            ClipboardTarget::Coordinates => "Coordinates (text)",
+        }
+    }
+}
+
+/// A fixed region that is captured without the overlay, optionally bound to its own hotkey.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RegionPreset {
+    pub name: String,
+    /// Monitor name as reported by the OS (see `MonitorData::monitor_name`).
+    pub monitor: String,
+    /// That monitor's top-left corner on the desktop (physical px) when the region was picked,
+    /// tells monitors with the same name apart. None in presets from older versions.
+    pub monitor_position: Option<(i32, i32)>,
+    /// Position relative to the monitor's top-left corner, physical px.
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// Empty = no hotkey.
+    pub hotkey_code: String,
+    pub hotkey_mods: u32,
+}
+
+impl Default for RegionPreset {
+    fn default() -> Self {
+        Self {
+            name: "New Preset".to_string(),
+            monitor: String::new(),
+            monitor_position: None,
+            x: 0,
+            y: 0,
+            width: 800,
+            height: 600,
+            hotkey_code: String::new(),
+            hotkey_mods: 0,
+        }
+    }
+}
+
+impl RegionPreset {
+    pub fn hotkey(&self) -> Option<HotKey> {
+        let key = Code::from_str(&self.hotkey_code).ok()?;
+        Some(HotKey::new(Modifiers::from_bits(self.hotkey_mods), key))
+    }
+
+    pub fn set_hotkey(&mut self, hotkey: Option<HotKey>) {
+        (self.hotkey_code, self.hotkey_mods) = hotkey.as_ref().map(hotkey_to_savable).unwrap_or_default();
+    }
+}
+
+/// A named set of output settings (where captures go, how, and with which hotkey) that can be
+/// switched to from the tray or with the profile hotkey.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    pub name: String,
+    pub auto_save: bool,
+    pub save_directory: String,
+    pub png_filter: PngFilter,
+    pub clipboard_targets: BTreeSet<ClipboardTarget>,
+    pub clipboard_compress_above_kb: Option<u32>,
+    pub clipboard_jpeg_quality: u8,
+    pub play_sound: bool,
+    /// Capture hotkey while the profile is active. Empty = keep whatever is set.
+    pub snap_hotkey_code: String,
+    pub snap_hotkey_mods: u32,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile::from_config("New Profile", &AppConfig::default())
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+impl Profile {
+    /// A profile holding the current settings.
+    pub fn from_config(name: &str, config: &AppConfig) -> Self {
+        let (snap_hotkey_code, snap_hotkey_mods) = hotkey_to_savable(&config.snap_hotkey);
+        Self {
+            name: name.to_string(),
+            auto_save: config.auto_save,
+            save_directory: config.save_directory.clone(),
+            png_filter: config.png_filter,
+            clipboard_targets: config.clipboard_targets.clone(),
+            clipboard_compress_above_kb: config.clipboard_compress_above_kb,
+            clipboard_jpeg_quality: config.clipboard_jpeg_quality,
+            play_sound: config.play_sound,
+            snap_hotkey_code,
+            snap_hotkey_mods,
+        }
+    }
+
+    pub fn snap_hotkey(&self) -> Option<HotKey> {
+        let key = Code::from_str(&self.snap_hotkey_code).ok()?;
+        Some(HotKey::new(Modifiers::from_bits(self.snap_hotkey_mods), key))
+    }
+
+    /// Copies the profile's settings into the config. The hotkey is left to the caller, it has
+    /// to be re-registered.
+    pub fn apply_to(&self, config: &mut AppConfig) {
+        config.auto_save = self.auto_save;
+        config.save_directory = self.save_directory.clone();
+        config.png_filter = self.png_filter;
+        config.clipboard_targets = self.clipboard_targets.clone();
+        config.clipboard_compress_above_kb = self.clipboard_compress_above_kb;
+        config.clipboard_jpeg_quality = self.clipboard_jpeg_quality;
+        config.play_sound = self.play_sound;
+    }
+
+    /// Whether the config still has the profile's settings.
+    pub fn matches(&self, config: &AppConfig) -> bool {
+        self.auto_save == config.auto_save
+            && self.save_directory == config.save_directory
+            && self.png_filter == config.png_filter
+            && self.clipboard_targets == config.clipboard_targets
+            && self.clipboard_compress_above_kb == config.clipboard_compress_above_kb
+            && self.clipboard_jpeg_quality == config.clipboard_jpeg_quality
+            && self.play_sound == config.play_sound
+            && self.snap_hotkey().is_none_or(|hotkey| hotkey.id() == config.snap_hotkey.id())
+    }
+}
+
+fn default_clipboard_targets() -> BTreeSet<ClipboardTarget> {
+    BTreeSet::from([ClipboardTarget::Image])
+}
+
+/// Length of `recent_save_directories`.
+const MAX_RECENT_SAVE_DIRECTORIES: usize = 8;
+/// Values `capture_scale` may have, the settings offer the same range.
+pub const CAPTURE_SCALE_RANGE: std::ops::RangeInclusive<f32> = 0.25..=4.0;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct AppConfig {
     pub save_directory: String,
+    // Save directories picked before, most recent first, for the tray's "Save Folder" menu
+    pub recent_save_directories: Vec<String>,
     pub auto_save: bool,
+    pub long_path_strategy: LongPathStrategy,
+    pub collision_policy: CollisionPolicy,
+    pub png_filter: PngFilter,
+    // Monitor profiles are only read on Windows, elsewhere this stays a no-op
+    pub color_management: ColorManagement,
+    // None = keep all saved captures
+    pub max_saved_files: Option<usize>,
+    pub delete_to_trash: bool,
     pub play_sound: bool,
+    #[cfg(target_os = "windows")]
+    pub shutter_with_other_audio: ShutterWithOtherAudio,
+    // Capture, save or clipboard failures; only with play_sound on
+    pub play_error_sound: bool,
     pub custom_cursor: bool,
     pub run_on_startup: bool,
+    pub selection_style: SelectionStyle,
+    // Translucent tint over the selection (unmultiplied RGBA), None = no tint
+    pub selection_fill: Option<[u8; 4]>,
+    // Border around every monitor while snapping (RGBA), None = off
+    pub monitor_frame: Option<[u8; 4]>,
+    // Grid over the overlay that the selection corners snap to, in physical px
+    pub snap_to_grid: bool,
+    pub grid_size_px: u32,
+    // Corner brackets and a center dot over the overlay, inset in points. Never part of the capture
+    pub show_viewfinder: bool,
+    pub viewfinder_inset: f32,
+    // Bar listing the overlay's shortcuts while selecting, until the user dismisses it
+    pub show_hints: bool,
+    // White flash over the selection right when it is captured
+    pub capture_flash: bool,
+    // Accessibility: no flash, the marching ants stand still
+    pub reduce_motion: bool,
+    pub alignment_guide: AlignmentGuide,
+    pub guide_opacity: u8,
+    pub show_action_bar: bool,
+    pub prompt_for_note: bool,
+    pub preview_before_save: bool,
+    pub lua_post_script: Option<String>,
+    // Shell command run (and waited for briefly) before each capture, e.g. to hide a dock
+    pub pre_capture_command: Option<String>,
+    // Shell command started (not waited for) after each save, `{path}` = the saved file
+    pub post_capture_command: Option<String>,
+    // None = crab-grab.log next to the config. Mirrored into log_config.json, see LogConfig
+    pub log_file_path: Option<String>,
+    pub clipboard_targets: BTreeSet<ClipboardTarget>,
+    // Text for the Coordinates clipboard target, {x} {y} {w} {h} = the selection in physical px
+    pub coordinates_format: String,
+    pub hide_cursor_during_capture: bool,
+    // Match the overlay to the desktop on HDR displays (Windows "SDR content brightness")
+    pub compensate_sdr_white_level: bool,
+    // Capture Everything resamples every monitor to this scale factor (mixed-DPI setups)
+    pub normalize_capture_scale: Option<f32>,
+    // Captures are resampled by this factor before they are copied or saved (1.0 = as captured)
+    pub capture_scale: f32,
+    // Selections larger than this (megapixels, as saved) ask before they are captured, None = never
+    pub confirm_captures_above_mp: Option<f32>,
+    // "Save downscaled" in that prompt fits the capture into this many px on its longer side
+    pub downscaled_max_dimension: u32,
+    // Clipboard images larger than this (raw RGBA) go on the clipboard as JPEG (Windows),
+    // None = always lossless
+    pub clipboard_compress_above_kb: Option<u32>,
+    pub clipboard_jpeg_quality: u8,
+    // Some paste targets read the clipboard bitmap as premultiplied and show dark/white fringes
+    pub clipboard_premultiply_alpha: bool,
+    // With auto-save on, only save and leave the clipboard alone
+    pub keep_clipboard_when_auto_saving: bool,
+    // Put the previous clipboard text/image back after this many seconds, None = don't
+    pub restore_clipboard_after_secs: Option<u32>,
+    // Overwrite temp files holding screen content with zeros before deleting them
+    pub secure_delete_temp_files: bool,
+    // Label the clipboard image with its file name for clipboard managers
+    #[cfg(all(target_os = "windows", feature = "clipboard-metadata"))]
+    pub clipboard_metadata: bool,
+    pub after_capture_from_settings: AfterCaptureFromSettings,
+    pub close_button_action: CloseButtonAction,
+    pub recapture_hotkey_behavior: RecaptureHotkeyBehavior,
+    pub region_presets: Vec<RegionPreset>,
+    pub profiles: Vec<Profile>,
+    // Index into `profiles`, None = settings were changed by hand since the last switch
+    // (checked whenever the config is saved)
+    pub active_profile: Option<usize>,
+    // Switches to the next profile, empty = no hotkey
+    pub profile_hotkey_code: String,
+    pub profile_hotkey_mods: u32,
+    // Two-step shortcut: this hotkey, then a plain key for the action. Empty = off
+    pub chord_hotkey_code: String,
+    pub chord_hotkey_mods: u32,
+    pub chord_actions: BTreeSet<ChordAction>,
+    // Montage mode (started from the tray): captures stacked into one tall image
+    pub montage_gap_px: u32,
+    pub montage_background: [u8; 4],
+    pub montage_align: MontageAlign,
+    // Applied in this order to every selection before it is previewed, saved or copied
+    pub filters: Vec<FilterKind>,
+    // Interval capture (time-lapse) started from the tray
+    pub interval_capture_seconds: u32,
+    // None = runs until stopped from the tray
+    pub interval_capture_minutes: Option<u32>,
+    // Where the settings window was last closed (logical points)
+    pub settings_last_x: f32,
+    pub settings_last_y: f32,
+    pub settings_last_w: f32,
+    pub settings_last_h: f32,
+    // Development aids, drawn on top of the overlay
+    pub debug_overlay: bool,
+    pub show_tile_borders: bool,
+    // VRAM the overlay textures may use before they are downscaled
+    pub gpu_tile_budget_mb: u32,
+    // Overlay tiles uploaded per frame, more = faster but choppier on weak GPUs
+    pub tiles_per_frame: u8,
+    // Minutes in idle after which the app stops polling and frees what it can, None = never
+    pub idle_sleep_minutes: Option<u32>,
+    // Move the hidden window onto the origin monitor at startup (and after layout changes), so the
+    // DPI is negotiated before the first capture. Some Linux WMs flash or steal focus on that move
+    pub dpi_warmup: bool,
+    // Read by main before the renderer starts, see RendererConfig
+    pub gpu_power_preference: GpuPowerPreference,
+    pub window_transparency: WindowTransparency,
 
     // 2. The Runtime Hotkey (Skipped by Serde)
     // We tell Serde: "If this is missing, call default_snap_key() to make one"
@@ -46,62 +666,201 @@ pub struct AppConfig {
     // We will sync these with the 'snap_hotkey' before saving/after loading
     pub snap_hotkey_mods: u32,
     pub snap_hotkey_code: String,
+
+    // Same as above, for the "capture and annotate" hotkey
+    #[serde(skip, default = "default_annotate_key")]
+    pub annotate_hotkey: HotKey,
+    pub annotate_hotkey_mods: u32,
+    pub annotate_hotkey_code: String,
+
+    // And for "capture and open in an external editor"
+    #[serde(skip, default = "default_external_edit_key")]
+    pub external_edit_hotkey: HotKey,
+    pub external_edit_hotkey_mods: u32,
+    pub external_edit_hotkey_code: String,
+    // None = the system's default app for PNG files
+    pub external_editor_path: Option<String>,
+
+    // Always registered: forces the app back to idle if the overlay gets stuck
+    #[serde(skip, default = "default_panic_key")]
+    pub panic_hotkey: HotKey,
+    pub panic_hotkey_mods: u32,
+    pub panic_hotkey_code: String,
 }
 
 impl Default for AppConfig {
     fn default() -> Self {
         Self {
             save_directory: dirs::picture_dir().unwrap().to_string_lossy().to_string(),
+            recent_save_directories: Vec::new(),
             auto_save: false,
+            long_path_strategy: LongPathStrategy::ShortenFileName,
+            collision_policy: CollisionPolicy::Increment,
+            png_filter: PngFilter::Adaptive,
+            color_management: ColorManagement::Off,
+            max_saved_files: None,
+            delete_to_trash: true,
             play_sound: true,
+            #[cfg(target_os = "windows")]
+            shutter_with_other_audio: ShutterWithOtherAudio::Play,
+            play_error_sound: true,
             custom_cursor: true,
             run_on_startup: false,
+            selection_style: SelectionStyle::Classic,
+            selection_fill: None,
+            monitor_frame: None,
+            snap_to_grid: false,
+            grid_size_px: 8,
+            show_viewfinder: false,
+            viewfinder_inset: 48.0,
+            show_hints: true,
+            capture_flash: false,
+            reduce_motion: false,
+            alignment_guide: AlignmentGuide::None,
+            guide_opacity: 80,
+            show_action_bar: false,
+            prompt_for_note: false,
+            preview_before_save: false,
+            lua_post_script: None,
+            pre_capture_command: None,
+            post_capture_command: None,
+            log_file_path: None,
+            clipboard_targets: default_clipboard_targets(),
+            coordinates_format: "x={x} y={y} w={w} h={h}".to_string(),
+            hide_cursor_during_capture: false,
+            compensate_sdr_white_level: true,
+            normalize_capture_scale: None,
+            capture_scale: 1.0,
+            confirm_captures_above_mp: Some(20.0),
+            downscaled_max_dimension: 3840,
+            clipboard_compress_above_kb: None,
+            clipboard_jpeg_quality: 80,
+            clipboard_premultiply_alpha: false,
+            keep_clipboard_when_auto_saving: false,
+            restore_clipboard_after_secs: None,
+            secure_delete_temp_files: false,
+            #[cfg(all(target_os = "windows", feature = "clipboard-metadata"))]
+            clipboard_metadata: false,
+            after_capture_from_settings: AfterCaptureFromSettings::ReturnToSettings,
+            close_button_action: CloseButtonAction::Tray,
+            recapture_hotkey_behavior: RecaptureHotkeyBehavior::Ignore,
+            region_presets: Vec::new(),
+            profiles: Vec::new(),
+            active_profile: None,
+            profile_hotkey_code: String::new(),
+            profile_hotkey_mods: 0,
+            chord_hotkey_code: String::new(),
+            chord_hotkey_mods: 0,
+            chord_actions: ChordAction::ALL.into(),
+            montage_gap_px: 16,
+            montage_background: [255, 255, 255, 255],
+            montage_align: MontageAlign::Center,
+            filters: Vec::new(),
+            interval_capture_seconds: 10,
+            interval_capture_minutes: Some(30),
+            settings_last_x: 100.0,
+            settings_last_y: 100.0,
+            settings_last_w: 600.0,
+            settings_last_h: 400.0,
+            debug_overlay: false,
+            show_tile_borders: false,
+            gpu_tile_budget_mb: 512,
+            tiles_per_frame: 4,
+            idle_sleep_minutes: Some(10),
+            dpi_warmup: true,
+            gpu_power_preference: GpuPowerPreference::HighPerformance,
+            window_transparency: WindowTransparency::Auto,
             snap_hotkey: default_snap_key(),
             // Sync the raw numbers with the default key
             snap_hotkey_mods: (Modifiers::CONTROL | Modifiers::SHIFT).bits(),
             snap_hotkey_code: Code::KeyG.to_string(),
+            annotate_hotkey: default_annotate_key(),
+            annotate_hotkey_mods: (Modifiers::CONTROL | Modifiers::SHIFT).bits(),
+            annotate_hotkey_code: Code::KeyA.to_string(),
+            external_edit_hotkey: default_external_edit_key(),
+            external_edit_hotkey_mods: (Modifiers::CONTROL | Modifiers::SHIFT).bits(),
+            external_edit_hotkey_code: Code::KeyE.to_string(),
+            external_editor_path: None,
+            panic_hotkey: default_panic_key(),
+            panic_hotkey_mods: (Modifiers::CONTROL | Modifiers::ALT).bits(),
+            panic_hotkey_code: Code::Escape.to_string(),
         }
     }
 }
 
 impl AppConfig {
+    /// Switches to `directory` and moves it to the front of the recent save directories. The
+    /// one it replaces is kept in the list too, so switching back is one click.
+    pub fn use_save_directory(&mut self, directory: String) {
+        let previous = std::mem::replace(&mut self.save_directory, directory.clone());
+        for entry in [previous, directory] {
+            self.recent_save_directories.retain(|recent| *recent != entry);
+            self.recent_save_directories.insert(0, entry);
+        }
+        self.recent_save_directories.truncate(MAX_RECENT_SAVE_DIRECTORIES);
+    }
+
+    pub fn profile_hotkey(&self) -> Option<HotKey> {
+        let key = Code::from_str(&self.profile_hotkey_code).ok()?;
+        Some(HotKey::new(Modifiers::from_bits(self.profile_hotkey_mods), key))
+    }
+
+    pub fn set_profile_hotkey(&mut self, hotkey: Option<HotKey>) {
+        (self.profile_hotkey_code, self.profile_hotkey_mods) = hotkey.as_ref().map(hotkey_to_savable).unwrap_or_default();
+    }
+
+    pub fn chord_hotkey(&self) -> Option<HotKey> {
+        let key = Code::from_str(&self.chord_hotkey_code).ok()?;
+        Some(HotKey::new(Modifiers::from_bits(self.chord_hotkey_mods), key))
+    }
+
+    pub fn set_chord_hotkey(&mut self, hotkey: Option<HotKey>) {
+        (self.chord_hotkey_code, self.chord_hotkey_mods) = hotkey.as_ref().map(hotkey_to_savable).unwrap_or_default();
+    }
+
     pub fn load() -> Self {
-        if let Some(config_dir) = dirs::config_dir() {
-            let config_path = config_dir.join("crab-grab").join("crab_config.json");
-            return if let Ok(data) = std::fs::read_to_string(config_path) {
-                if let Ok(mut config) = serde_json::from_str::<AppConfig>(&data) {
-                    let snap_hotkey = savable_to_hotkey(&config.snap_hotkey_code, config.snap_hotkey_mods);
-                    config.snap_hotkey = snap_hotkey;
-                    utils::set_autostart(config.run_on_startup); // Ensure autostart is set on load
-                    config
-                } else {
-                    log::error!("Failed to parse config file, using default config.");
-                    AppConfig::default()
-                }
-            } else {
-                log::error!("Config file not found, using default config.");
-                AppConfig::default()
-            }
-        } else {
+        let Some(config_path) = config_path() else {
             log::error!("Could not determine config directory, using default config.");
-        }
-        AppConfig::default()
+            return AppConfig::default();
+        };
+        let Some(mut config) = read_config_file::<AppConfig>(&config_path) else {
+            return AppConfig::default();
+        };
+        let snap_hotkey = savable_to_hotkey(&config.snap_hotkey_code, config.snap_hotkey_mods, default_snap_key());
+        config.snap_hotkey = snap_hotkey;
+        config.annotate_hotkey = savable_to_hotkey(&config.annotate_hotkey_code, config.annotate_hotkey_mods, default_annotate_key());
+        config.external_edit_hotkey = savable_to_hotkey(
+            &config.external_edit_hotkey_code,
+            config.external_edit_hotkey_mods,
+            default_external_edit_key(),
+        );
+        config.panic_hotkey = savable_to_hotkey(&config.panic_hotkey_code, config.panic_hotkey_mods, default_panic_key());
+        config.capture_scale = valid_capture_scale(config.capture_scale);
+        utils::set_autostart(config.run_on_startup); // Ensure autostart is set on load
+        config
     }
 
     pub fn save(&mut self) {
-        if let Some(config_dir) = dirs::config_dir() {
-            let config_path = config_dir.join("crab-grab").join("crab_config.json");
+        if let Some(config_path) = config_path() {
             let (code_str, mods_bits) = hotkey_to_savable(&self.snap_hotkey);
             self.snap_hotkey_code = code_str;
             self.snap_hotkey_mods = mods_bits;
+            let (code_str, mods_bits) = hotkey_to_savable(&self.annotate_hotkey);
+            self.annotate_hotkey_code = code_str;
+            self.annotate_hotkey_mods = mods_bits;
+            let (code_str, mods_bits) = hotkey_to_savable(&self.external_edit_hotkey);
+            self.external_edit_hotkey_code = code_str;
+            self.external_edit_hotkey_mods = mods_bits;
+
+            let (code_str, mods_bits) = hotkey_to_savable(&self.panic_hotkey);
+            self.panic_hotkey_code = code_str;
+            self.panic_hotkey_mods = mods_bits;
             if let Ok(json) = serde_json::to_string_pretty(&self) {
-                if let Err(e) = std::fs::create_dir_all(&config_dir) {
-                    log::error!("Failed to create config directory: {}", e);
-                    return;
-                }
-                if let Err(e) = std::fs::write(config_path, json) {
+                if let Err(e) = write_config_file(&config_path, &json) {
                     log::error!("Failed to write config file: {}", e);
+                    return;
                 }
+                LogConfig { log_file_path: self.log_file_path.clone() }.save();
             } else {
                 log::error!("Failed to serialize config.");
             }
@@ -111,3 +870,240 @@ impl AppConfig {
     }
 }
 
+/// Keeps a hand-edited `capture_scale` from blowing captures up or shrinking them to nothing.
+/// NaN becomes 1.0 (as captured).
+fn valid_capture_scale(scale: f32) -> f32 {
+    if scale.is_nan() {
+        log::warn!("capture_scale is not a number, using 1.0.");
+        return 1.0;
+    }
+    let valid = scale.clamp(*CAPTURE_SCALE_RANGE.start(), *CAPTURE_SCALE_RANGE.end());
+    if valid != scale {
+        log::warn!("capture_scale {} is out of range, using {}.", scale, valid);
+    }
+    valid
+}
+
+/// The app's folder in the OS config directory: config, stats and, unless moved, the logs.
+/// Everything the app writes is listed in `utils::app_data_paths`.
+pub fn app_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("crab-grab"))
+}
+
+fn config_path() -> Option<PathBuf> {
+    app_dir().map(|dir| dir.join("crab_config.json"))
+}
+
+/// `crab_config.json.bak`: the config as it was before the last save.
+fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".bak");
+    PathBuf::from(name)
+}
+
+/// Parses the config file, or its backup when the file is missing or broken (e.g. cut short
+/// by a crash). None when neither can be used.
+fn read_config_file<T: DeserializeOwned>(path: &Path) -> Option<T> {
+    let parse = |path: &Path| -> Result<T, String> {
+        let data = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        serde_json::from_str(&data).map_err(|e| e.to_string())
+    };
+    match parse(path) {
+        Ok(config) => return Some(config),
+        Err(_) if !path.exists() => log::error!("Config file not found."),
+        Err(e) => log::error!("Failed to parse config file: {}", e),
+    }
+
+    let backup = backup_path(path);
+    if !backup.exists() {
+        log::error!("No backup config either, using default config.");
+        return None;
+    }
+    match parse(&backup) {
+        Ok(config) => {
+            log::warn!("Loaded the backup config {:?} instead.", backup);
+            Some(config)
+        }
+        Err(e) => {
+            log::error!("Failed to parse the backup config too ({}), using default config.", e);
+            None
+        }
+    }
+}
+
+/// Writes the config so that a crash at any point leaves a complete file behind: the new
+/// content goes to a temporary file first, the previous config is kept as the backup.
+fn write_config_file(path: &Path, json: &str) -> std::io::Result<()> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    std::fs::create_dir_all(dir)?;
+
+    let temp_path = path.with_extension("json.tmp");
+    let mut file = std::fs::File::create(&temp_path)?;
+    file.write_all(json.as_bytes())?;
+    file.sync_all()?;
+    drop(file);
+
+    // A broken config (the one a backup was loaded for) must not replace a good backup
+    let current_is_valid = std::fs::read_to_string(path).ok()
+        .is_some_and(|data| serde_json::from_str::<serde_json::Value>(&data).is_ok());
+    if current_is_valid {
+        std::fs::rename(path, backup_path(path))?;
+    }
+    std::fs::rename(&temp_path, path)?;
+
+    // Makes the renames themselves durable; Windows can't open directories for this
+    #[cfg(unix)]
+    std::fs::File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+/// The part of the config needed to set up logging. Logging starts before `AppConfig::load`
+/// (which logs itself), so this lives in its own tiny file that `AppConfig::save` keeps in sync.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    pub log_file_path: Option<String>,
+}
+
+impl LogConfig {
+    pub fn load() -> Self {
+        // Nothing to log to yet, a missing or broken file just means the default path
+        log_config_path()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self) {
+        let Some(path) = log_config_path() else {
+            return;
+        };
+        match serde_json::to_string_pretty(self) {
+            Ok(json) => {
+                if let Err(e) = std::fs::write(&path, json) {
+                    log::error!("Failed to write log config file: {}", e);
+                }
+            }
+            Err(e) => log::error!("Failed to serialize log config: {}", e),
+        }
+    }
+}
+
+/// The part of the config the renderer is built with. The window (and with it the GPU device)
+/// exists before `AppConfig::load` runs, so main reads just these fields from the config file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct RendererConfig {
+    pub gpu_power_preference: GpuPowerPreference,
+    pub window_transparency: WindowTransparency,
+}
+
+impl RendererConfig {
+    pub fn load() -> Self {
+        // Other fields of the file are ignored; a missing or broken file means the defaults
+        config_path()
+            .and_then(|path| read_config_file(&path))
+            .unwrap_or_default()
+    }
+}
+
+fn log_config_path() -> Option<std::path::PathBuf> {
+    app_dir().map(|dir| dir.join("log_config.json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn temp_config(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("crab-grab-config-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir.join("crab_config.json")
+    }
+
+    #[test]
+    fn saving_keeps_the_previous_config_as_backup() {
+        let path = temp_config("rotate");
+        write_config_file(&path, &json!({ "save": 1 }).to_string()).unwrap();
+        write_config_file(&path, &json!({ "save": 2 }).to_string()).unwrap();
+
+        assert_eq!(read_config_file::<Value>(&path), Some(json!({ "save": 2 })));
+        assert_eq!(read_config_file::<Value>(&backup_path(&path)), Some(json!({ "save": 1 })));
+        assert!(!path.with_extension("json.tmp").exists());
+    }
+
+    #[test]
+    fn truncated_config_recovers_from_the_backup() {
+        let path = temp_config("truncated");
+        write_config_file(&path, &json!({ "save": 1 }).to_string()).unwrap();
+        write_config_file(&path, &json!({ "save": 2 }).to_string()).unwrap();
+
+        // A write cut short by a crash
+        std::fs::write(&path, r#"{ "sa"#).unwrap();
+        assert_eq!(read_config_file::<Value>(&path), Some(json!({ "save": 1 })));
+    }
+
+    #[test]
+    fn a_broken_config_does_not_replace_the_backup() {
+        let path = temp_config("broken");
+        write_config_file(&path, &json!({ "save": 1 }).to_string()).unwrap();
+        write_config_file(&path, &json!({ "save": 2 }).to_string()).unwrap();
+        std::fs::write(&path, "").unwrap();
+
+        write_config_file(&path, &json!({ "save": 3 }).to_string()).unwrap();
+        assert_eq!(read_config_file::<Value>(&path), Some(json!({ "save": 3 })));
+        assert_eq!(read_config_file::<Value>(&backup_path(&path)), Some(json!({ "save": 1 })));
+    }
+
+    #[test]
+    fn recent_save_directories_put_the_newest_first() {
+        let mut config = AppConfig { save_directory: "a".into(), ..AppConfig::default() };
+        config.use_save_directory("b".into());
+        assert_eq!(config.save_directory, "b");
+        assert_eq!(config.recent_save_directories, ["b", "a"]);
+
+        config.use_save_directory("c".into());
+        config.use_save_directory("a".into());
+        assert_eq!(config.recent_save_directories, ["a", "c", "b"]);
+
+        for index in 0..20 {
+            config.use_save_directory(index.to_string());
+        }
+        assert_eq!(config.recent_save_directories.len(), MAX_RECENT_SAVE_DIRECTORIES);
+        assert_eq!(config.recent_save_directories[0], "19");
+    }
+
+    #[test]
+    fn capture_scale_is_kept_in_range() {
+        assert_eq!(valid_capture_scale(1.5), 1.5);
+        assert_eq!(valid_capture_scale(100.0), 4.0);
+        assert_eq!(valid_capture_scale(0.0), 0.25);
+        assert_eq!(valid_capture_scale(f32::NEG_INFINITY), 0.25);
+        assert_eq!(valid_capture_scale(f32::NAN), 1.0);
+    }
+
+    #[test]
+    fn profiles_match_until_one_of_their_settings_changes() {
+        let mut config = AppConfig::default();
+        let mut profile = Profile::from_config("Work", &config);
+        assert!(profile.matches(&config));
+
+        // Settings outside the profile don't matter
+        config.show_action_bar = !config.show_action_bar;
+        assert!(profile.matches(&config));
+
+        config.play_sound = !config.play_sound;
+        assert!(!profile.matches(&config));
+
+        // A profile without a hotkey keeps whatever is set
+        profile = Profile { snap_hotkey_code: String::new(), ..Profile::from_config("Work", &config) };
+        config.snap_hotkey = HotKey::new(Some(Modifiers::ALT), Code::KeyQ);
+        assert!(profile.matches(&config));
+    }
+
+    #[test]
+    fn missing_files_mean_defaults() {
+        assert_eq!(read_config_file::<Value>(&temp_config("missing")), None);
+    }
+}
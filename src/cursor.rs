@@ -0,0 +1,87 @@
+//! Hiding the system mouse cursor while snapping (for cursor-free screenshots).
+
+#[cfg(target_os = "windows")]
+use windows::Win32::UI::WindowsAndMessaging::ShowCursor;
+
+/// Hides the system cursor for as long as it is alive.
+/// Dropping it (also while unwinding from a panic) shows the cursor again.
+pub struct HiddenCursor {
+    #[cfg(all(unix, not(target_os = "macos")))]
+    x11: Option<X11Cursor>,
+}
+
+impl HiddenCursor {
+    pub fn hide() -> Self {
+        log::debug!("Hiding system cursor");
+
+        #[cfg(target_os = "windows")]
+        unsafe {
+            ShowCursor(false);
+        }
+
+        Self {
+            #[cfg(all(unix, not(target_os = "macos")))]
+            x11: X11Cursor::hide(),
+        }
+    }
+}
+
+impl Drop for HiddenCursor {
+    fn drop(&mut self) {
+        log::debug!("Restoring system cursor");
+
+        #[cfg(target_os = "windows")]
+        unsafe {
+            ShowCursor(true);
+        }
+
+        #[cfg(all(unix, not(target_os = "macos")))]
+        if let Some(x11) = self.x11.take() {
+            x11.show();
+        }
+    }
+}
+
+/// XFixes ties the hidden state to the display connection, so we keep it open while hidden.
+#[cfg(all(unix, not(target_os = "macos")))]
+struct X11Cursor {
+    xlib: x11_dl::xlib::Xlib,
+    xfixes: x11_dl::xfixes::Xlib,
+    display: *mut x11_dl::xlib::Display,
+    root: x11_dl::xlib::Window,
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+impl X11Cursor {
+    fn hide() -> Option<Self> {
+        // Not available on pure Wayland sessions, in which case the cursor stays visible.
+        let xlib = x11_dl::xlib::Xlib::open().ok()?;
+        let xfixes = match x11_dl::xfixes::Xlib::open() {
+            Ok(xfixes) => xfixes,
+            Err(e) => {
+                log::warn!("XFixes not available, cannot hide cursor: {}", e);
+                return None;
+            }
+        };
+
+        unsafe {
+            let display = (xlib.XOpenDisplay)(std::ptr::null());
+            if display.is_null() {
+                log::warn!("Could not open X display, cannot hide cursor");
+                return None;
+            }
+            let root = (xlib.XDefaultRootWindow)(display);
+            (xfixes.XFixesHideCursor)(display, root);
+            (xlib.XFlush)(display);
+            Some(Self { xlib, xfixes, display, root })
+        }
+    }
+
+    fn show(self) {
+        unsafe {
+            (self.xfixes.XFixesShowCursor)(self.display, self.root);
+            (self.xlib.XFlush)(self.display);
+            (self.xlib.XCloseDisplay)(self.display);
+        }
+    }
+}
@@ -0,0 +1,375 @@
+use eframe::egui;
+use image::{Rgba, RgbaImage};
+
+use crate::config::FilterKind;
+use crate::filters::FilterPipeline;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tool {
+    Pen,
+    Rectangle,
+    Arrow,
+}
+
+impl Tool {
+    pub const ALL: [Tool; 3] = [Tool::Pen, Tool::Rectangle, Tool::Arrow];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Tool::Pen => "✏ Pen",
+            Tool::Rectangle => "⬜ Rectangle",
+            Tool::Arrow => "➡ Arrow",
+        }
+    }
+}
+
+/// The toolbar's quick filters: button label, tooltip and the step a click adds.
+pub const QUICK_FILTERS: [(&str, &str, FilterKind); 6] = [
+    ("Gray", "Grayscale", FilterKind::Grayscale),
+    ("Invert", "Invert the colors", FilterKind::Invert),
+    ("Bright +", "Brighter", FilterKind::BrightnessContrast { brightness: 20, contrast: 0.0 }),
+    ("Bright -", "Darker", FilterKind::BrightnessContrast { brightness: -20, contrast: 0.0 }),
+    ("Contrast +", "More contrast", FilterKind::BrightnessContrast { brightness: 0, contrast: 20.0 }),
+    ("Contrast -", "Less contrast", FilterKind::BrightnessContrast { brightness: 0, contrast: -20.0 }),
+];
+
+/// A single mark-up item. All coordinates are in IMAGE pixels (not egui points),
+/// so the annotations can be rasterized 1:1 onto the cropped capture.
+#[derive(Clone, Debug)]
+pub enum Annotation {
+    Stroke { points: Vec<egui::Pos2>, color: egui::Color32, width: f32 },
+    Rectangle { rect: egui::Rect, color: egui::Color32, width: f32 },
+    Arrow { from: egui::Pos2, to: egui::Pos2, color: egui::Color32, width: f32 },
+}
+
+impl Annotation {
+    /// Bounding box in image pixels, including the line width.
+    fn bounds(&self) -> egui::Rect {
+        let (rect, width) = match self {
+            Annotation::Stroke { points, width, .. } => (egui::Rect::from_points(points), *width),
+            Annotation::Rectangle { rect, width, .. } => (*rect, *width),
+            Annotation::Arrow { from, to, width, .. } => (egui::Rect::from_two_pos(*from, *to), *width),
+        };
+        rect.expand(width / 2.0)
+    }
+
+    /// Points shown as handles while the annotation is selected.
+    fn handles(&self) -> Vec<egui::Pos2> {
+        match self {
+            Annotation::Stroke { points, .. } => points.first().into_iter().chain(points.last()).copied().collect(),
+            Annotation::Rectangle { rect, .. } => vec![rect.left_top(), rect.right_top(), rect.right_bottom(), rect.left_bottom()],
+            Annotation::Arrow { from, to, .. } => vec![*from, *to],
+        }
+    }
+
+    fn translate(&mut self, delta: egui::Vec2) {
+        match self {
+            Annotation::Stroke { points, .. } => points.iter_mut().for_each(|p| *p += delta),
+            Annotation::Rectangle { rect, .. } => *rect = rect.translate(delta),
+            Annotation::Arrow { from, to, .. } => {
+                *from += delta;
+                *to += delta;
+            }
+        }
+    }
+}
+
+/// Something undo can take back.
+enum Edit {
+    Annotation(Annotation),
+    Filter(FilterKind),
+}
+
+#[derive(Clone, Copy)]
+enum EditKind {
+    Annotation,
+    Filter,
+}
+
+/// State of the annotation editor for one capture.
+pub struct Editor {
+    pub tool: Tool,
+    pub color: egui::Color32,
+    pub thickness: f32, // Image pixels
+
+    annotations: Vec<Annotation>,
+    // Applied to the capture in this order, beneath the annotations
+    filters: Vec<FilterKind>,
+    // Which list each change went to, newest last, so undo goes back in order across both
+    history: Vec<EditKind>,
+    redo_stack: Vec<Edit>,
+    in_progress: Option<Annotation>,
+    selected: Option<usize>, // Index into `annotations`, moved with the arrow keys
+}
+
+impl Editor {
+    pub fn new() -> Self {
+        Self {
+            tool: Tool::Rectangle,
+            color: egui::Color32::RED,
+            thickness: 4.0,
+            annotations: Vec::new(),
+            filters: Vec::new(),
+            history: Vec::new(),
+            redo_stack: Vec::new(),
+            in_progress: None,
+            selected: None,
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.history.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    pub fn undo(&mut self) {
+        let edit = match self.history.pop() {
+            Some(EditKind::Annotation) => self.annotations.pop().map(Edit::Annotation),
+            Some(EditKind::Filter) => self.filters.pop().map(Edit::Filter),
+            None => None,
+        };
+        self.redo_stack.extend(edit);
+        self.selected = self.selected.filter(|&index| index < self.annotations.len());
+    }
+
+    pub fn redo(&mut self) {
+        match self.redo_stack.pop() {
+            Some(Edit::Annotation(annotation)) => {
+                self.annotations.push(annotation);
+                self.history.push(EditKind::Annotation);
+            }
+            Some(Edit::Filter(filter)) => {
+                self.filters.push(filter);
+                self.history.push(EditKind::Filter);
+            }
+            None => {}
+        }
+    }
+
+    /// Adds a filter step on top of the ones applied so far.
+    pub fn add_filter(&mut self, filter: FilterKind) {
+        self.filters.push(filter);
+        self.history.push(EditKind::Filter);
+        self.redo_stack.clear();
+    }
+
+    pub fn filters(&self) -> &[FilterKind] {
+        &self.filters
+    }
+
+    /// Applies the filters to `image`, e.g. a downscaled copy for the live preview.
+    pub fn apply_filters(&self, image: &mut RgbaImage) {
+        FilterPipeline::from_kinds(&self.filters).apply(image);
+    }
+
+    /// Selects the next annotation (wrapping around), for keyboard-only use.
+    pub fn select_next(&mut self) {
+        if self.annotations.is_empty() {
+            return;
+        }
+        self.selected = Some(self.selected.map_or(0, |index| (index + 1) % self.annotations.len()));
+    }
+
+    /// Moves the selected annotation by `delta` image pixels.
+    pub fn nudge_selected(&mut self, delta: egui::Vec2) {
+        if let Some(annotation) = self.selected.and_then(|index| self.annotations.get_mut(index)) {
+            annotation.translate(delta);
+        }
+    }
+
+    /// Selects the topmost annotation under `pos` (image pixels), or nothing.
+    fn select_at(&mut self, pos: egui::Pos2, tolerance: f32) {
+        self.selected = self.annotations.iter()
+            .rposition(|annotation| annotation.bounds().expand(tolerance).contains(pos));
+    }
+
+    /// Feeds pointer interaction on the canvas into the active tool. A click without a drag
+    /// selects an annotation instead. `canvas` is where the image is shown (egui points),
+    /// `image_size` its size in pixels.
+    pub fn handle_canvas_input(&mut self, response: &egui::Response, canvas: egui::Rect, image_size: egui::Vec2) {
+        let to_image = |pos: egui::Pos2| {
+            let clamped = canvas.clamp(pos);
+            egui::pos2(
+                (clamped.x - canvas.min.x) * image_size.x / canvas.width(),
+                (clamped.y - canvas.min.y) * image_size.y / canvas.height(),
+            )
+        };
+
+        // clicked_by skips the fake click egui sends for Space/Enter, which cycles the selection instead
+        if response.clicked_by(egui::PointerButton::Primary)
+            && let Some(pos) = response.interact_pointer_pos()
+        {
+            // A few screen points of slack, so thin lines are still easy to hit
+            self.select_at(to_image(pos), 4.0 * image_size.x / canvas.width());
+        }
+
+        if response.drag_started() {
+            if let Some(pos) = response.interact_pointer_pos() {
+                let pos = to_image(pos);
+                self.in_progress = Some(match self.tool {
+                    Tool::Pen => Annotation::Stroke { points: vec![pos], color: self.color, width: self.thickness },
+                    Tool::Rectangle => Annotation::Rectangle {
+                        rect: egui::Rect::from_two_pos(pos, pos),
+                        color: self.color,
+                        width: self.thickness,
+                    },
+                    Tool::Arrow => Annotation::Arrow { from: pos, to: pos, color: self.color, width: self.thickness },
+                });
+            }
+        } else if response.dragged()
+            && let (Some(pos), Some(annotation)) = (response.interact_pointer_pos(), &mut self.in_progress)
+        {
+            let pos = to_image(pos);
+            match annotation {
+                Annotation::Stroke { points, .. } => points.push(pos),
+                Annotation::Rectangle { rect, .. } => *rect = egui::Rect::from_two_pos(rect.min, pos),
+                Annotation::Arrow { to, .. } => *to = pos,
+            }
+        }
+
+        if response.drag_stopped()
+            && let Some(annotation) = self.in_progress.take()
+        {
+            self.annotations.push(annotation);
+            self.history.push(EditKind::Annotation);
+            self.redo_stack.clear();
+            self.selected = Some(self.annotations.len() - 1);
+        }
+    }
+
+    /// Draws all annotations on top of the canvas.
+    pub fn paint(&self, painter: &egui::Painter, canvas: egui::Rect, image_size: egui::Vec2) {
+        let scale = canvas.width() / image_size.x;
+        let to_screen = |pos: egui::Pos2| canvas.min + pos.to_vec2() * scale;
+
+        for annotation in self.annotations.iter().chain(self.in_progress.iter()) {
+            match annotation {
+                Annotation::Stroke { points, color, width } => {
+                    let points = points.iter().map(|p| to_screen(*p)).collect();
+                    painter.add(egui::Shape::line(points, egui::Stroke::new(width * scale, *color)));
+                }
+                Annotation::Rectangle { rect, color, width } => {
+                    painter.rect_stroke(
+                        egui::Rect::from_min_max(to_screen(rect.min), to_screen(rect.max)),
+                        0.0,
+                        egui::Stroke::new(width * scale, *color),
+                        egui::StrokeKind::Middle,
+                    );
+                }
+                Annotation::Arrow { from, to, color, width } => {
+                    let stroke = egui::Stroke::new(width * scale, *color);
+                    for (start, end) in arrow_segments(*from, *to, *width) {
+                        painter.line_segment([to_screen(start), to_screen(end)], stroke);
+                    }
+                }
+            }
+        }
+
+        if let Some(annotation) = self.selected.and_then(|index| self.annotations.get(index)) {
+            let visuals = &painter.ctx().style().visuals;
+            for handle in annotation.handles() {
+                let rect = egui::Rect::from_center_size(to_screen(handle), egui::vec2(8.0, 8.0));
+                painter.rect_filled(rect, 1.0, visuals.selection.bg_fill);
+                painter.rect_stroke(rect, 1.0, visuals.selection.stroke, egui::StrokeKind::Outside);
+            }
+        }
+    }
+
+    /// Burns the filters, then the annotations into the captured image (full resolution).
+    pub fn render_onto(&self, image: &mut RgbaImage) {
+        self.apply_filters(image);
+        for annotation in &self.annotations {
+            match annotation {
+                Annotation::Stroke { points, color, width } => {
+                    for pair in points.windows(2) {
+                        draw_thick_line(image, pair[0], pair[1], *width, *color);
+                    }
+                    if let [single] = points.as_slice() {
+                        draw_thick_line(image, *single, *single, *width, *color);
+                    }
+                }
+                Annotation::Rectangle { rect, color, width } => {
+                    let corners = [rect.left_top(), rect.right_top(), rect.right_bottom(), rect.left_bottom()];
+                    for i in 0..4 {
+                        draw_thick_line(image, corners[i], corners[(i + 1) % 4], *width, *color);
+                    }
+                }
+                Annotation::Arrow { from, to, color, width } => {
+                    for (start, end) in arrow_segments(*from, *to, *width) {
+                        draw_thick_line(image, start, end, *width, *color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The shaft plus the two head lines of an arrow.
+fn arrow_segments(from: egui::Pos2, to: egui::Pos2, width: f32) -> Vec<(egui::Pos2, egui::Pos2)> {
+    let dir = to - from;
+    let length = dir.length();
+    if length < 1.0 {
+        return vec![(from, to)];
+    }
+    let head_length = (width * 4.0).max(10.0).min(length * 0.5);
+    let back = -dir.normalized() * head_length;
+    let rotation = egui::emath::Rot2::from_angle(std::f32::consts::FRAC_PI_6);
+    vec![
+        (from, to),
+        (to, to + rotation * back),
+        (to, to + rotation.inverse() * back),
+    ]
+}
+
+/// Rasterizes a line of the given width by stamping filled circles along it.
+fn draw_thick_line(image: &mut RgbaImage, from: egui::Pos2, to: egui::Pos2, width: f32, color: egui::Color32) {
+    let radius = (width / 2.0).max(0.5);
+    let step = (radius / 2.0).max(0.5);
+    let steps = ((to - from).length() / step).ceil().max(1.0) as usize;
+    let rgba = Rgba(color.to_srgba_unmultiplied());
+
+    for i in 0..=steps {
+        let center = from.lerp(to, i as f32 / steps as f32);
+        let min_x = (center.x - radius).floor().max(0.0) as u32;
+        let min_y = (center.y - radius).floor().max(0.0) as u32;
+        let max_x = ((center.x + radius).ceil() as u32).min(image.width());
+        let max_y = ((center.y + radius).ceil() as u32).min(image.height());
+
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let dx = x as f32 + 0.5 - center.x;
+                let dy = y as f32 + 0.5 - center.y;
+                if dx * dx + dy * dy <= radius * radius {
+                    image.put_pixel(x, y, rgba);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_stack_and_undo_in_order() {
+        let mut editor = Editor::new();
+        editor.add_filter(FilterKind::Grayscale);
+        editor.add_filter(FilterKind::Invert);
+        assert_eq!(editor.filters(), [FilterKind::Grayscale, FilterKind::Invert]);
+
+        editor.undo();
+        assert_eq!(editor.filters(), [FilterKind::Grayscale]);
+        editor.redo();
+        assert_eq!(editor.filters(), [FilterKind::Grayscale, FilterKind::Invert]);
+
+        editor.undo();
+        editor.undo();
+        assert!(!editor.can_undo());
+        editor.add_filter(FilterKind::Invert);
+        assert!(!editor.can_redo());
+    }
+}
@@ -0,0 +1,98 @@
+//! Post-processing filters applied to a selection right after it is cropped, before the
+//! preview, the editor, the clipboard and the save see it. The built-in ones are configured in
+//! the settings (`AppConfig::filters`); anything else that should touch every capture
+//! (watermarks, blurring, downscaling, ...) registers itself as another step.
+
+use image::RgbaImage;
+
+use crate::config::FilterKind;
+
+/// One step of the pipeline. Gets the whole capture and changes it in place.
+pub type Filter = Box<dyn Fn(&mut RgbaImage) + Send>;
+
+/// Filters run one after the other, in the order they were registered.
+#[derive(Default)]
+pub struct FilterPipeline {
+    steps: Vec<(String, Filter)>,
+}
+
+impl FilterPipeline {
+    /// The built-in filters, in the configured order.
+    pub fn from_kinds(kinds: &[FilterKind]) -> Self {
+        let mut pipeline = Self::default();
+        for &kind in kinds {
+            pipeline.register(kind.label(), move |image| apply_built_in(kind, image));
+        }
+        pipeline
+    }
+
+    /// Adds a step that runs after the ones registered so far.
+    pub fn register(&mut self, name: impl Into<String>, filter: impl Fn(&mut RgbaImage) + Send + 'static) {
+        self.steps.push((name.into(), Box::new(filter)));
+    }
+
+    pub fn apply(&self, image: &mut RgbaImage) {
+        for (name, filter) in &self.steps {
+            log::debug!("Applying filter \"{}\".", name);
+            filter(image);
+        }
+    }
+}
+
+fn apply_built_in(kind: FilterKind, image: &mut RgbaImage) {
+    match kind {
+        FilterKind::Grayscale => {
+            for pixel in image.pixels_mut() {
+                let [r, g, b, _] = pixel.0;
+                // Rec. 709 luma, the weights sRGB primaries are defined with
+                let luma = (0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32).round() as u8;
+                pixel.0[..3].fill(luma);
+            }
+        }
+        // Leaves alpha alone
+        FilterKind::Invert => image::imageops::invert(image),
+        FilterKind::BrightnessContrast { brightness, contrast } => {
+            if brightness != 0 {
+                image::imageops::colorops::brighten_in_place(image, brightness);
+            }
+            if contrast != 0.0 {
+                image::imageops::colorops::contrast_in_place(image, contrast);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn filters_run_in_order_and_keep_alpha() {
+        let mut image = RgbaImage::from_pixel(2, 2, Rgba([200, 100, 0, 128]));
+        FilterPipeline::from_kinds(&[FilterKind::Invert, FilterKind::Grayscale]).apply(&mut image);
+
+        // Inverted to (55, 155, 255) first, then turned gray
+        let luma = (0.2126f32 * 55.0 + 0.7152 * 155.0 + 0.0722 * 255.0).round() as u8;
+        assert_eq!(*image.get_pixel(1, 1), Rgba([luma, luma, luma, 128]));
+    }
+
+    #[test]
+    fn registered_steps_run_after_the_built_in_ones() {
+        let mut pipeline = FilterPipeline::from_kinds(&[FilterKind::Invert]);
+        pipeline.register("clear top left", |image| image.put_pixel(0, 0, Rgba([0, 0, 0, 0])));
+
+        let mut image = RgbaImage::from_pixel(2, 1, Rgba([0, 0, 0, 255]));
+        pipeline.apply(&mut image);
+        assert_eq!(*image.get_pixel(0, 0), Rgba([0, 0, 0, 0]));
+        assert_eq!(*image.get_pixel(1, 0), Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn neutral_brightness_and_contrast_change_nothing() {
+        let original = RgbaImage::from_pixel(3, 3, Rgba([10, 20, 30, 255]));
+        let mut image = original.clone();
+        FilterPipeline::from_kinds(&[FilterKind::BrightnessContrast { brightness: 0, contrast: 0.0 }]).apply(&mut image);
+        assert_eq!(image, original);
+    }
+}
@@ -0,0 +1,297 @@
+//! Reading the settings of other screenshot tools (ShareX, Greenshot), so switching to
+//! Crab Grab keeps the save folder and capture hotkey. Only the few fields we have an
+//! equivalent for are read; everything else is reported as skipped.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use global_hotkey::hotkey::{Code, HotKey, Modifiers};
+use serde_json::Value;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImportSource {
+    ShareX,
+    Greenshot,
+}
+
+impl ImportSource {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ImportSource::ShareX => "ShareX",
+            ImportSource::Greenshot => "Greenshot",
+        }
+    }
+}
+
+/// What could be taken over from one tool.
+#[derive(Debug)]
+pub struct ImportedSettings {
+    pub source: ImportSource,
+    pub config_path: PathBuf,
+    pub save_directory: Option<String>,
+    pub snap_hotkey: Option<HotKey>,
+    /// Values that were found but not imported, with the reason.
+    pub notes: Vec<String>,
+}
+
+impl ImportedSettings {
+    fn new(source: ImportSource, config_path: &Path) -> Self {
+        Self {
+            source,
+            config_path: config_path.to_path_buf(),
+            save_directory: None,
+            snap_hotkey: None,
+            notes: Vec::new(),
+        }
+    }
+
+    /// True when there is nothing to apply.
+    pub fn is_empty(&self) -> bool {
+        self.save_directory.is_none() && self.snap_hotkey.is_none()
+    }
+}
+
+/// Looks for ShareX and Greenshot settings in their default locations.
+pub fn find_imports() -> Vec<ImportedSettings> {
+    let mut found = Vec::new();
+
+    if let Some(sharex_dir) = dirs::document_dir().map(|dir| dir.join("ShareX")) {
+        let app_config = sharex_dir.join("ApplicationConfig.json");
+        if let Ok(text) = std::fs::read_to_string(&app_config) {
+            let hotkeys = std::fs::read_to_string(sharex_dir.join("HotkeysConfig.json")).ok();
+            found.push(parse_sharex(&text, hotkeys.as_deref(), &app_config));
+        }
+    }
+
+    if let Some(ini_path) = dirs::config_dir().map(|dir| dir.join("Greenshot").join("Greenshot.ini"))
+        && let Ok(text) = std::fs::read_to_string(&ini_path)
+    {
+        found.push(parse_greenshot(&text, &ini_path));
+    }
+
+    log::info!("Import: found settings of {:?}", found.iter().map(|s| s.source).collect::<Vec<_>>());
+    found
+}
+
+/// Parses ShareX's `ApplicationConfig.json` and, if present, the `HotkeysConfig.json` next to it.
+pub fn parse_sharex(app_config: &str, hotkeys: Option<&str>, config_path: &Path) -> ImportedSettings {
+    let mut settings = ImportedSettings::new(ImportSource::ShareX, config_path);
+
+    match serde_json::from_str::<Value>(app_config) {
+        Ok(json) => {
+            let custom_path = json["CustomScreenshotsPath"].as_str().filter(|path| !path.trim().is_empty());
+            if json["UseCustomScreenshotsPath"].as_bool() == Some(true) && let Some(path) = custom_path {
+                settings.save_directory = Some(path.to_string());
+            } else if let Some(sharex_dir) = config_path.parent() {
+                // Without a custom path ShareX saves into its personal folder
+                settings.save_directory = Some(sharex_dir.join("Screenshots").to_string_lossy().into_owned());
+            }
+
+            if let Some(pattern) = json["SaveImageSubFolderPattern"].as_str().filter(|p| !p.is_empty()) {
+                settings.notes.push(format!("Sub folder pattern \"{}\" skipped: Crab Grab saves straight into the folder.", pattern));
+            }
+            if let Some(pattern) = json["DefaultTaskSettings"]["UploadSettings"]["NameFormatPattern"].as_str() {
+                settings.notes.push(file_name_pattern_note(pattern));
+            }
+        }
+        Err(e) => settings.notes.push(format!("ApplicationConfig.json could not be read: {}", e)),
+    }
+
+    if let Some(hotkeys) = hotkeys {
+        match serde_json::from_str::<Value>(hotkeys) {
+            Ok(json) => settings.snap_hotkey = sharex_region_hotkey(&json, &mut settings.notes),
+            Err(e) => settings.notes.push(format!("HotkeysConfig.json could not be read: {}", e)),
+        }
+    }
+
+    settings
+}
+
+/// The hotkey of ShareX's region capture, the closest thing to our capture hotkey.
+fn sharex_region_hotkey(json: &Value, notes: &mut Vec<String>) -> Option<HotKey> {
+    let entry = json["Hotkeys"].as_array()?.iter()
+        .find(|entry| entry["TaskSettings"]["Job"].as_str() == Some("RectangleRegion"));
+    let Some(entry) = entry else {
+        notes.push("No region capture hotkey found in ShareX.".to_string());
+        return None;
+    };
+
+    let text = entry["HotkeyInfo"]["Hotkey"].as_str().unwrap_or("None");
+    if text == "None" {
+        notes.push("ShareX's region capture has no hotkey.".to_string());
+        return None;
+    }
+
+    // .NET Keys flags: "D4, Shift, Control"; the Windows key is a separate flag
+    let mut mods = Modifiers::empty();
+    if entry["HotkeyInfo"]["Win"].as_bool() == Some(true) {
+        mods |= Modifiers::META;
+    }
+    let mut key = None;
+    for part in text.split(',').map(str::trim) {
+        match part {
+            "Control" => mods |= Modifiers::CONTROL,
+            "Shift" => mods |= Modifiers::SHIFT,
+            "Alt" => mods |= Modifiers::ALT,
+            name => key = dotnet_key_code(name),
+        }
+    }
+    build_hotkey(mods, key, text, notes)
+}
+
+/// Parses Greenshot's `Greenshot.ini`.
+pub fn parse_greenshot(ini: &str, config_path: &Path) -> ImportedSettings {
+    let mut settings = ImportedSettings::new(ImportSource::Greenshot, config_path);
+    let values = parse_ini(ini);
+    let core = |key: &str| values.get(&("core".to_string(), key.to_lowercase())).map(String::as_str);
+
+    match core("OutputFilePath") {
+        // Greenshot may store placeholders like ${MyPictures} here, we can't expand those
+        Some(path) if path.contains("${") => {
+            settings.notes.push(format!("Output folder \"{}\" skipped: it contains Greenshot placeholders.", path));
+        }
+        Some(path) if !path.is_empty() => settings.save_directory = Some(path.to_string()),
+        _ => settings.notes.push("Greenshot has no output folder set.".to_string()),
+    }
+
+    if let Some(pattern) = core("OutputFileFilenamePattern") {
+        settings.notes.push(file_name_pattern_note(pattern));
+    }
+
+    match core("RegionHotkey") {
+        Some(text) if !text.is_empty() && text != "None" => {
+            // "Ctrl + Shift + PrintScreen"
+            let mut mods = Modifiers::empty();
+            let mut key = None;
+            for part in text.split('+').map(str::trim) {
+                match part {
+                    "Ctrl" | "Control" => mods |= Modifiers::CONTROL,
+                    "Shift" => mods |= Modifiers::SHIFT,
+                    "Alt" => mods |= Modifiers::ALT,
+                    "Win" => mods |= Modifiers::META,
+                    name => key = dotnet_key_code(name),
+                }
+            }
+            settings.snap_hotkey = build_hotkey(mods, key, text, &mut settings.notes);
+        }
+        _ => settings.notes.push("Greenshot's region capture has no hotkey.".to_string()),
+    }
+
+    settings
+}
+
+fn build_hotkey(mods: Modifiers, key: Option<Code>, text: &str, notes: &mut Vec<String>) -> Option<HotKey> {
+    match key {
+        Some(key) => Some(HotKey::new(Some(mods), key)),
+        None => {
+            notes.push(format!("Hotkey \"{}\" skipped: its key is not supported.", text));
+            None
+        }
+    }
+}
+
+fn file_name_pattern_note(pattern: &str) -> String {
+    format!("File name pattern \"{}\" skipped: Crab Grab names captures screenshot_<date>_<time>.", pattern)
+}
+
+/// Maps a .NET `Keys` name (used by both tools) to our key code.
+fn dotnet_key_code(name: &str) -> Option<Code> {
+    let code_name = match name {
+        "Snapshot" => "PrintScreen".to_string(),
+        "Prior" => "PageUp".to_string(),
+        "Next" => "PageDown".to_string(),
+        "Scroll" => "ScrollLock".to_string(),
+        "Return" | "Enter" => "Enter".to_string(),
+        "Oemtilde" => "Backquote".to_string(),
+        "Up" | "Down" | "Left" | "Right" => format!("Arrow{}", name),
+        _ if name.len() == 1 && name.chars().all(|c| c.is_ascii_uppercase()) => format!("Key{}", name),
+        _ if name.len() == 2 && name.starts_with('D') && name[1..].chars().all(|c| c.is_ascii_digit()) => {
+            format!("Digit{}", &name[1..])
+        }
+        _ if name.starts_with("NumPad") => format!("Numpad{}", &name["NumPad".len()..]),
+        // F1-F24, PrintScreen, Insert, Delete, Home, End, PageUp, PageDown, Pause, Space, ...
+        _ => name.to_string(),
+    };
+    Code::from_str(&code_name).ok()
+}
+
+/// Minimal INI reader: `[section]` headers, `key=value` lines, `;`/`#` comments.
+/// Section and key names are lower-cased; lines that don't fit are ignored.
+fn parse_ini(text: &str) -> HashMap<(String, String), String> {
+    let mut values = HashMap::new();
+    let mut section = String::new();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+            section = name.trim().to_lowercase();
+        } else if let Some((key, value)) = line.split_once('=') {
+            values.insert((section.clone(), key.trim().to_lowercase()), value.trim().to_string());
+        }
+    }
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHAREX_CONFIG: &str = include_str!("../tests/fixtures/import/sharex_ApplicationConfig.json");
+    const SHAREX_HOTKEYS: &str = include_str!("../tests/fixtures/import/sharex_HotkeysConfig.json");
+    const GREENSHOT_INI: &str = include_str!("../tests/fixtures/import/greenshot.ini");
+
+    #[test]
+    fn sharex_folder_and_region_hotkey() {
+        let settings = parse_sharex(SHAREX_CONFIG, Some(SHAREX_HOTKEYS), Path::new("ShareX/ApplicationConfig.json"));
+        assert_eq!(settings.save_directory.as_deref(), Some("D:\\Pictures\\Screens"));
+        assert_eq!(settings.snap_hotkey, Some(HotKey::new(Some(Modifiers::CONTROL | Modifiers::SHIFT), Code::Digit4)));
+        // Sub folder and name pattern have no equivalent
+        assert_eq!(settings.notes.len(), 2);
+        assert!(settings.notes.iter().any(|note| note.contains("%pn_%ra{10}")));
+    }
+
+    #[test]
+    fn sharex_without_custom_path_uses_personal_folder() {
+        let config = SHAREX_CONFIG.replace("\"UseCustomScreenshotsPath\": true", "\"UseCustomScreenshotsPath\": false");
+        let settings = parse_sharex(&config, None, Path::new("ShareX/ApplicationConfig.json"));
+        assert_eq!(settings.save_directory, Some(Path::new("ShareX").join("Screenshots").to_string_lossy().into_owned()));
+        assert_eq!(settings.snap_hotkey, None);
+    }
+
+    #[test]
+    fn greenshot_folder_and_region_hotkey() {
+        let settings = parse_greenshot(GREENSHOT_INI, Path::new("Greenshot.ini"));
+        assert_eq!(settings.save_directory.as_deref(), Some("C:\\Users\\demo\\Pictures\\Greenshot"));
+        assert_eq!(settings.snap_hotkey, Some(HotKey::new(Some(Modifiers::CONTROL | Modifiers::SHIFT), Code::PrintScreen)));
+        assert_eq!(settings.notes.len(), 1);
+    }
+
+    #[test]
+    fn unmappable_values_become_notes() {
+        let ini = "[Core]\nOutputFilePath=${MyPictures}\nRegionHotkey=Ctrl + OemClear\n";
+        let settings = parse_greenshot(ini, Path::new("Greenshot.ini"));
+        assert!(settings.is_empty());
+        assert_eq!(settings.notes.len(), 2);
+    }
+
+    #[test]
+    fn broken_files_do_not_panic() {
+        let settings = parse_sharex("{ not json", Some("[]"), Path::new("ApplicationConfig.json"));
+        assert!(settings.snap_hotkey.is_none());
+        assert!(!settings.notes.is_empty());
+
+        let settings = parse_greenshot("\u{feff}garbage\n[unterminated\n=\n", Path::new("Greenshot.ini"));
+        assert!(settings.is_empty());
+    }
+
+    #[test]
+    fn dotnet_key_names() {
+        assert_eq!(dotnet_key_code("A"), Some(Code::KeyA));
+        assert_eq!(dotnet_key_code("D0"), Some(Code::Digit0));
+        assert_eq!(dotnet_key_code("F12"), Some(Code::F12));
+        assert_eq!(dotnet_key_code("Snapshot"), Some(Code::PrintScreen));
+        assert_eq!(dotnet_key_code("NumPad5"), Some(Code::Numpad5));
+        assert_eq!(dotnet_key_code("OemClear"), None);
+    }
+}
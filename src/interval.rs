@@ -0,0 +1,157 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::time::{Duration, SystemTime};
+
+use crate::config::{LongPathStrategy, PngFilter, RegionPreset};
+use crate::stats::{self, CaptureMode};
+use crate::utils;
+
+/// How often the timer thread wakes up to check for Stop while waiting for the next shot.
+const STOP_POLL: Duration = Duration::from_millis(200);
+/// Failed shots in a row after which the interval capture gives up (e.g. the monitor is gone).
+const FAILURES_BEFORE_STOP: u32 = 3;
+
+/// What to capture and where to put it.
+pub struct IntervalPlan {
+    /// Monitor-relative region, same shape as a region preset.
+    pub region: RegionPreset,
+    pub interval: Duration,
+    /// None = until stopped from the tray.
+    pub duration: Option<Duration>,
+    pub save_directory: String,
+    pub long_paths: LongPathStrategy,
+    pub png_filter: PngFilter,
+}
+
+/// Progress reported back to the app.
+pub enum IntervalEvent {
+    Captured(u32),
+    /// Gave up after several failed shots in a row, `Finished` follows.
+    Failed(String),
+    Finished(u32),
+}
+
+/// A running interval capture. Shots are taken on their own thread and only saved,
+/// the clipboard is never touched.
+pub struct IntervalCapture {
+    stop: Arc<AtomicBool>,
+    events: Receiver<IntervalEvent>,
+    pub count: u32,
+}
+
+impl IntervalCapture {
+    pub fn start(plan: IntervalPlan) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let (sender, events) = channel();
+
+        let thread_stop = stop.clone();
+        std::thread::Builder::new()
+            .name("interval-capture".to_string())
+            .spawn(move || {
+                // For the virtual desktop in the log
+                #[cfg(target_os = "windows")]
+                let _com = utils::ComGuard::init();
+                let count = run(&plan, &thread_stop, |event| {
+                    let _ = sender.send(event);
+                });
+                let _ = sender.send(IntervalEvent::Finished(count));
+            })
+            .expect("Failed to spawn the interval capture thread");
+
+        Self { stop, events, count: 0 }
+    }
+
+    /// Asks the thread to finish; it reports `Finished` once it did.
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    /// Events that arrived since the last call.
+    pub fn poll(&mut self) -> Vec<IntervalEvent> {
+        let events: Vec<IntervalEvent> = self.events.try_iter().collect();
+        for event in &events {
+            if let IntervalEvent::Captured(count) | IntervalEvent::Finished(count) = event {
+                self.count = *count;
+            }
+        }
+        events
+    }
+}
+
+/// The timer loop. Returns the number of saved shots.
+///
+/// Wall-clock time is used for the schedule, so a machine waking up from sleep notices the
+/// gap. Shots missed in the meantime are dropped instead of being taken all at once.
+fn run(plan: &IntervalPlan, stop: &AtomicBool, mut report: impl FnMut(IntervalEvent)) -> u32 {
+    let session = chrono::Local::now().format("%Y-%m-%d_%H-%M-%S").to_string();
+    let started = SystemTime::now();
+    let region = &plan.region;
+    log::info!("Interval capture started: {}x{} at ({}, {}) on '{}', every {:?}, for {:?}",
+        region.width, region.height, region.x, region.y, region.monitor, plan.interval, plan.duration);
+
+    let mut count = 0;
+    let mut failures = 0;
+    let mut next_shot = started;
+    loop {
+        // Sleep in small steps, so Stop doesn't have to wait for a long interval
+        loop {
+            if stop.load(Ordering::Relaxed) {
+                log::info!("Interval capture stopped after {} shots.", count);
+                return count;
+            }
+            let now = SystemTime::now();
+            match next_shot.duration_since(now) {
+                Ok(remaining) if !remaining.is_zero() => std::thread::sleep(remaining.min(STOP_POLL)),
+                _ => break,
+            }
+        }
+
+        let now = SystemTime::now();
+        if let Some(duration) = plan.duration
+            && now.duration_since(started).unwrap_or_default() >= duration
+        {
+            log::info!("Interval capture finished after {} shots.", count);
+            return count;
+        }
+
+        // More than one interval late: the machine slept (or the clock jumped), pick up from now
+        let late = now.duration_since(next_shot).unwrap_or_default();
+        next_shot = if late > plan.interval {
+            log::info!("Interval capture resumed {:?} late, skipping the missed shots.", late);
+            now + plan.interval
+        } else {
+            next_shot + plan.interval
+        };
+
+        if utils::session_locked() {
+            log::debug!("Interval capture: session is locked, skipping this shot.");
+            continue;
+        }
+
+        utils::log_virtual_desktop("Interval");
+        let saved = crate::capture::capture_monitor_region(&region.monitor, region.monitor_position, region.x, region.y, region.width, region.height)
+            .map_err(|e| e.to_string())
+            .and_then(|image| {
+                utils::save_interval_image(&image, &plan.save_directory, &session, count + 1, plan.long_paths, plan.png_filter)
+                    .ok_or_else(|| "Saving the capture failed, see the log.".to_string())
+            });
+        match saved {
+            Ok(path) => {
+                count += 1;
+                failures = 0;
+                let bytes_saved = std::fs::metadata(&path).map_or(0, |metadata| metadata.len());
+                stats::record_capture(CaptureMode::Interval, bytes_saved);
+                report(IntervalEvent::Captured(count));
+            }
+            Err(e) => {
+                log::error!("Interval capture shot failed: {}", e);
+                failures += 1;
+                if failures >= FAILURES_BEFORE_STOP {
+                    report(IntervalEvent::Failed(e.to_string()));
+                    return count;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,193 @@
+//! Hotkeys are stored as physical keys (`Code::KeyZ` is the key right of the left Shift on
+//! every keyboard), so they keep working when the layout changes. Only the display follows
+//! the active layout: on German QWERTZ that key is shown as "Y".
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use global_hotkey::hotkey::Code;
+
+/// Character each physical key produces on a layout (unshifted).
+pub type LayoutTable = Vec<(Code, char)>;
+
+/// How long a read layout is reused. Switching layouts shows up in the labels after this.
+const LAYOUT_REFRESH: Duration = Duration::from_secs(2);
+
+/// The keys whose character depends on the layout, with their PC scan code (set 1). On Linux
+/// the evdev code is the same number, X11 key codes are 8 higher.
+const LAYOUT_KEYS: &[(Code, u32)] = &[
+    (Code::Backquote, 0x29),
+    (Code::Digit1, 0x02), (Code::Digit2, 0x03), (Code::Digit3, 0x04), (Code::Digit4, 0x05),
+    (Code::Digit5, 0x06), (Code::Digit6, 0x07), (Code::Digit7, 0x08), (Code::Digit8, 0x09),
+    (Code::Digit9, 0x0A), (Code::Digit0, 0x0B), (Code::Minus, 0x0C), (Code::Equal, 0x0D),
+    (Code::KeyQ, 0x10), (Code::KeyW, 0x11), (Code::KeyE, 0x12), (Code::KeyR, 0x13),
+    (Code::KeyT, 0x14), (Code::KeyY, 0x15), (Code::KeyU, 0x16), (Code::KeyI, 0x17),
+    (Code::KeyO, 0x18), (Code::KeyP, 0x19), (Code::BracketLeft, 0x1A), (Code::BracketRight, 0x1B),
+    (Code::KeyA, 0x1E), (Code::KeyS, 0x1F), (Code::KeyD, 0x20), (Code::KeyF, 0x21),
+    (Code::KeyG, 0x22), (Code::KeyH, 0x23), (Code::KeyJ, 0x24), (Code::KeyK, 0x25),
+    (Code::KeyL, 0x26), (Code::Semicolon, 0x27), (Code::Quote, 0x28), (Code::Backslash, 0x2B),
+    (Code::KeyZ, 0x2C), (Code::KeyX, 0x2D), (Code::KeyC, 0x2E), (Code::KeyV, 0x2F),
+    (Code::KeyB, 0x30), (Code::KeyN, 0x31), (Code::KeyM, 0x32), (Code::Comma, 0x33),
+    (Code::Period, 0x34), (Code::Slash, 0x35),
+];
+
+static ACTIVE_LAYOUT: Mutex<Option<(Instant, LayoutTable)>> = Mutex::new(None);
+
+/// Label for a physical key on `layout`, e.g. "Z". Keys the layout has no printable character
+/// for (F-keys, arrows, ...) keep the name of the code.
+pub fn key_label(code: Code, layout: &[(Code, char)]) -> String {
+    if let Some(&(_, character)) = layout.iter().find(|(key, _)| *key == code)
+        && !character.is_control()
+        && !character.is_whitespace()
+    {
+        // "ß" would turn into "SS", keep characters without a single uppercase form as they are
+        let upper: String = character.to_uppercase().collect();
+        return if upper.chars().count() == 1 { upper } else { character.to_string() };
+    }
+
+    // Clean up the Code string (e.g. "KeyG" -> "G")
+    let key_str = format!("{:?}", code);
+    key_str.strip_prefix("Key").unwrap_or(&key_str).to_string()
+}
+
+/// The active keyboard layout, re-read every few seconds. Empty where it can't be read
+/// (macOS, Wayland without XWayland), labels then follow the US layout.
+pub fn active_layout() -> LayoutTable {
+    let mut cached = ACTIVE_LAYOUT.lock().unwrap_or_else(|e| e.into_inner());
+    match &*cached {
+        Some((read_at, layout)) if read_at.elapsed() < LAYOUT_REFRESH => layout.clone(),
+        _ => {
+            let layout = read_active_layout();
+            *cached = Some((Instant::now(), layout.clone()));
+            layout
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn read_active_layout() -> LayoutTable {
+    use windows::Win32::UI::Input::KeyboardAndMouse::{GetKeyboardLayout, MapVirtualKeyExW, ToUnicodeEx, MAPVK_VSC_TO_VK};
+
+    // Don't touch the keyboard state, so dead keys typed elsewhere aren't swallowed (Windows 10 1607+)
+    const NO_STATE_CHANGE: u32 = 0x4;
+    let no_modifiers = [0u8; 256];
+
+    unsafe {
+        let layout = GetKeyboardLayout(0);
+        LAYOUT_KEYS.iter().filter_map(|&(code, scan_code)| {
+            let virtual_key = MapVirtualKeyExW(scan_code, MAPVK_VSC_TO_VK, Some(layout));
+            if virtual_key == 0 {
+                return None;
+            }
+            let mut buffer = [0u16; 8];
+            // Negative for dead keys, the buffer still holds the accent itself
+            let written = ToUnicodeEx(virtual_key, scan_code, &no_modifiers, &mut buffer, NO_STATE_CHANGE, Some(layout));
+            if written == 0 {
+                return None;
+            }
+            char::decode_utf16(buffer[..written.unsigned_abs() as usize].iter().copied())
+                .next()?
+                .ok()
+                .map(|character| (code, character))
+        }).collect()
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn read_active_layout() -> LayoutTable {
+    use x11_dl::xlib::{Xlib, XkbStateRec};
+
+    const XKB_USE_CORE_KBD: u32 = 0x0100;
+
+    let Ok(xlib) = Xlib::open() else {
+        return Vec::new();
+    };
+    unsafe {
+        let display = (xlib.XOpenDisplay)(std::ptr::null());
+        if display.is_null() {
+            return Vec::new();
+        }
+        let mut state: XkbStateRec = std::mem::zeroed();
+        let group = if (xlib.XkbGetState)(display, XKB_USE_CORE_KBD, &mut state) == 0 { state.group as i32 } else { 0 };
+
+        let layout = LAYOUT_KEYS.iter().filter_map(|&(code, scan_code)| {
+            let keysym = (xlib.XkbKeycodeToKeysym)(display, (scan_code + 8) as u8, group, 0);
+            keysym_to_char(keysym).map(|character| (code, character))
+        }).collect();
+        (xlib.XCloseDisplay)(display);
+        layout
+    }
+}
+
+/// Latin-1 keysyms are the character itself, the others carry the code point above 0x01000000.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn keysym_to_char(keysym: std::os::raw::c_ulong) -> Option<char> {
+    match keysym {
+        0x20..=0x7E | 0xA0..=0xFF => char::from_u32(keysym as u32),
+        0x0100_0100..=0x0110_FFFF => char::from_u32((keysym - 0x0100_0000) as u32),
+        _ => None,
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn read_active_layout() -> LayoutTable {
+    Vec::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn german() -> LayoutTable {
+        vec![
+            (Code::KeyY, 'z'), (Code::KeyZ, 'y'), (Code::KeyQ, 'q'),
+            (Code::Minus, 'ß'), (Code::Semicolon, 'ö'), (Code::BracketLeft, 'ü'),
+            (Code::Digit1, '1'), (Code::Backquote, '^'),
+        ]
+    }
+
+    fn french() -> LayoutTable {
+        vec![
+            (Code::KeyQ, 'a'), (Code::KeyA, 'q'), (Code::KeyW, 'z'), (Code::KeyZ, 'w'),
+            (Code::Semicolon, 'm'), (Code::Digit1, '&'), (Code::Digit2, 'é'),
+        ]
+    }
+
+    #[test]
+    fn us_layout_keeps_the_code_names() {
+        assert_eq!(key_label(Code::KeyZ, &[]), "Z");
+        assert_eq!(key_label(Code::Digit1, &[]), "Digit1");
+        assert_eq!(key_label(Code::F5, &[]), "F5");
+    }
+
+    #[test]
+    fn german_layout_swaps_y_and_z() {
+        let layout = german();
+        assert_eq!(key_label(Code::KeyZ, &layout), "Y");
+        assert_eq!(key_label(Code::KeyY, &layout), "Z");
+        assert_eq!(key_label(Code::KeyQ, &layout), "Q");
+        assert_eq!(key_label(Code::Semicolon, &layout), "Ö");
+        assert_eq!(key_label(Code::BracketLeft, &layout), "Ü");
+        assert_eq!(key_label(Code::Backquote, &layout), "^");
+    }
+
+    #[test]
+    fn characters_without_a_single_uppercase_stay() {
+        assert_eq!(key_label(Code::Minus, &german()), "ß");
+    }
+
+    #[test]
+    fn french_layout_shows_azerty_letters_and_symbols() {
+        let layout = french();
+        assert_eq!(key_label(Code::KeyQ, &layout), "A");
+        assert_eq!(key_label(Code::KeyA, &layout), "Q");
+        assert_eq!(key_label(Code::Semicolon, &layout), "M");
+        assert_eq!(key_label(Code::Digit1, &layout), "&");
+        assert_eq!(key_label(Code::Digit2, &layout), "É");
+    }
+
+    #[test]
+    fn keys_outside_the_table_fall_back_to_the_code() {
+        assert_eq!(key_label(Code::ArrowUp, &german()), "ArrowUp");
+        assert_eq!(key_label(Code::KeyM, &french()), "M");
+    }
+}
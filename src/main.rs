@@ -3,42 +3,69 @@
 use eframe::{egui, egui_wgpu, NativeOptions, Renderer};
 use eframe::egui_wgpu::{WgpuConfiguration, WgpuSetup, WgpuSetupCreateNew, wgpu};
 use std::sync::Arc;
-use tray_icon::{TrayIcon, TrayIconBuilder, menu::{Menu, MenuItem, MenuId}};
+use tray_icon::TrayIconBuilder;
+use crate::config::{GpuPowerPreference, RendererConfig};
+use crate::tray::{MenuEntries, TrayLink, TrayMenuIds};
 
 mod app;
 mod capture;
 mod utils;
 mod config;
 mod audio;
+mod cursor;
+mod scripting;
+mod editor;
+mod stats;
+mod cli;
+mod tray;
+mod pipeline;
+mod import;
+mod interval;
+mod color;
+mod keymap;
+mod montage;
+mod filters;
+mod state;
 
 // --- WINDOWS SPECIFIC IMPORTS ---
 #[cfg(target_os = "windows")]
 use windows::Win32::UI::WindowsAndMessaging::{GetMessageW, TranslateMessage, DispatchMessageW, MSG};
+#[cfg(target_os = "windows")]
+use windows::Win32::System::Threading::GetCurrentThreadId;
 
 fn main() -> Result<(), eframe::Error> {
-    let config = utils::get_logging_config();
+    let (config, warnings) = utils::get_logging_config();
     let _handle = log4rs::init_config(config).unwrap();
+    for warning in warnings {
+        log::warn!("{}", warning);
+    }
 
     utils::setup_panic_hook();
 
+    // Subcommands (e.g. `crab-grab convert`) run instead of the tray app
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(exit_code) = cli::run(&args) {
+        std::process::exit(exit_code);
+    }
+
     log::info!("Starting Crab Grab v{} ...", env!("CARGO_PKG_VERSION"));
 
     // 1. Setup Common Menu Items
-    let quit_id = "quit".to_string();
-    let settings_id = "settings".to_string();
-    let capture_id = "capture".to_string();
+    let menu_ids = TrayMenuIds::default();
 
     // 2. Initialize Tray (Platform Dependent Logic)
-    // We get back an Option<TrayIcon>.
-    // On Windows, this is None (because the icon lives in a thread).
-    // On Mac/Linux, this is Some(icon) (because we must keep it alive in the App).
-    let _tray_handle = init_tray_platform(
-        quit_id.clone(),
-        settings_id.clone(),
-        capture_id.clone(),
-    );
+    // We get back an Option<TrayLink> to update the icon later.
+    // On Windows, it talks to the thread the icon lives in.
+    // On Mac/Linux, it holds the icon itself (because we must keep it alive in the App).
+    let tray_link = init_tray_platform(menu_ids.clone());
 
     // 3. WGPU Setup
+    let renderer_config = RendererConfig::load();
+    let power_preference = match renderer_config.gpu_power_preference {
+        GpuPowerPreference::HighPerformance => wgpu::PowerPreference::HighPerformance,
+        GpuPowerPreference::LowPower => wgpu::PowerPreference::LowPower,
+    };
+    log::info!("GPU power preference: {:?}", power_preference);
     let wgpu_options = WgpuConfiguration {
         wgpu_setup: WgpuSetup::CreateNew(WgpuSetupCreateNew {
             instance_descriptor: wgpu::InstanceDescriptor {
@@ -72,7 +99,7 @@ fn main() -> Result<(), eframe::Error> {
                     ..Default::default()
                 }
             }),
-            power_preference: wgpu::PowerPreference::HighPerformance,
+            power_preference,
             ..Default::default()
         }),
 
@@ -96,7 +123,7 @@ fn main() -> Result<(), eframe::Error> {
             .with_decorations(false)
             .with_always_on_top()
             .with_taskbar(false)
-            .with_transparent(true)
+            .with_transparent(renderer_config.window_transparency.enabled())
             .with_position(egui::pos2(10000.0, 10000.0)),
         renderer: Renderer::Wgpu,
         wgpu_options,
@@ -108,7 +135,7 @@ fn main() -> Result<(), eframe::Error> {
         native_options,
         Box::new(move |cc| {
             // We pass the handle (if it exists) into the app to keep it alive
-            Ok(Box::new(app::CrabGrabApp::new(cc, _tray_handle, MenuId::new(quit_id), MenuId::new(settings_id), MenuId::new(capture_id))))
+            Ok(Box::new(app::CrabGrabApp::new(cc, tray_link, menu_ids)))
         }),
     )
 }
@@ -119,53 +146,53 @@ fn main() -> Result<(), eframe::Error> {
 
 /// Windows: Spawns thread. Creates Items INSIDE the thread.
 #[cfg(target_os = "windows")]
-fn init_tray_platform(quit_id: String, settings_id: String, capture_id: String) -> Option<TrayIcon> {
-    // We move the Strings into the closure. This is allowed.
+fn init_tray_platform(menu_ids: TrayMenuIds) -> Option<TrayLink> {
+    let (command_tx, command_rx) = std::sync::mpsc::channel();
+    let (thread_id_tx, thread_id_rx) = std::sync::mpsc::channel();
+
+    // We move the ids into the closure. This is allowed.
     std::thread::spawn(move || {
         let icon = utils::load_tray_icon();
 
         // CREATE ITEMS HERE (Inside the thread)
-        let quit_item = MenuItem::with_id(MenuId::new(quit_id), "Quit", true, None);
-        let settings_item = MenuItem::with_id(MenuId::new(settings_id), "Settings", true, None);
-        let capture_item = MenuItem::with_id(MenuId::new(capture_id), "Capture Screen", true, None);
-
-        let tray_menu = Menu::new();
-        let _ = tray_menu.append(&capture_item);
-        let _ = tray_menu.append(&settings_item);
-        let _ = tray_menu.append(&quit_item);
+        let tray_menu = tray::build_menu(&menu_ids, &MenuEntries::default());
 
-        let _tray_icon = TrayIconBuilder::new()
+        let tray_icon = TrayIconBuilder::new()
             .with_menu(Box::new(tray_menu))
             .with_tooltip("Crab Grab")
             .with_icon(icon)
             .build()
             .unwrap();
 
+        // The icon created our message queue, so thread messages can be posted from now on
+        let _ = thread_id_tx.send(unsafe { GetCurrentThreadId() });
+
         unsafe {
             let mut msg = MSG::default();
             while GetMessageW(&mut msg, None, 0, 0).as_bool() {
+                if msg.message == tray::WM_TRAY_COMMAND {
+                    while let Ok(command) = command_rx.try_recv() {
+                        tray::apply(&tray_icon, command);
+                    }
+                    continue;
+                }
                 let _ = TranslateMessage(&msg);
                 DispatchMessageW(&msg);
             }
         }
     });
-    None
+
+    // Fails only if the tray thread died while starting
+    thread_id_rx.recv().ok().map(|thread_id| TrayLink::new(command_tx, thread_id))
 }
 
 /// Linux/macOS: Creates Items on Main Thread.
 #[cfg(not(target_os = "windows"))]
-fn init_tray_platform(quit_id: String, settings_id: String, capture_id: String) -> Option<TrayIcon> {
+fn init_tray_platform(menu_ids: TrayMenuIds) -> Option<TrayLink> {
     let icon = utils::load_tray_icon();
 
     // Create items normally
-    let quit_item = MenuItem::with_id(MenuId::new(quit_id), "Quit", true, None);
-    let settings_item = MenuItem::with_id(MenuId::new(settings_id), "Settings", true, None);
-    let capture_item = MenuItem::with_id(MenuId::new(capture_id), "Capture Screen", true, None);
-
-    let tray_menu = Menu::new();
-    let _ = tray_menu.append(&capture_item);
-    let _ = tray_menu.append(&settings_item);
-    let _ = tray_menu.append(&quit_item);
+    let tray_menu = tray::build_menu(&menu_ids, &MenuEntries::default());
 
     let tray_icon = TrayIconBuilder::new()
         .with_menu(Box::new(tray_menu))
@@ -174,5 +201,5 @@ fn init_tray_platform(quit_id: String, settings_id: String, capture_id: String)
         .build()
         .unwrap();
 
-    Some(tray_icon)
-}
\ No newline at end of file
+    Some(TrayLink::new(tray_icon))
+}
@@ -0,0 +1,169 @@
+//! Montage mode: while it runs, every capture is also stacked below the previous ones into one
+//! tall image, e.g. to document a process step by step. The canvas lives on the pipeline
+//! worker (see `PipelineWorker::start_montage`), so captures land on it in order.
+
+use std::path::PathBuf;
+use image::{Rgba, RgbaImage};
+
+use crate::config::{LongPathStrategy, MontageAlign, PngFilter};
+use crate::utils;
+
+/// The montage is written to disk every this many captures, so a crash loses little.
+const SAVE_EVERY: usize = 5;
+/// Captures that would grow the canvas beyond this are left out (RGBA, about 1 GB).
+const MAX_CANVAS_PIXELS: u64 = 256 * 1024 * 1024;
+
+/// How the montage is laid out and where it is saved.
+pub struct MontageSettings {
+    pub gap: u32,
+    pub background: [u8; 4],
+    pub align: MontageAlign,
+    pub save_directory: String,
+    pub long_paths: LongPathStrategy,
+    pub png_filter: PngFilter,
+}
+
+pub struct Montage {
+    settings: MontageSettings,
+    /// Part of the file name, the periodic saves overwrite the same file.
+    session: String,
+    /// The captures so far, only put onto one canvas when the montage is saved.
+    parts: Vec<RgbaImage>,
+    /// Size of the canvas `parts` make up.
+    size: (u32, u32),
+}
+
+impl Montage {
+    pub fn new(settings: MontageSettings) -> Self {
+        Self {
+            settings,
+            session: chrono::Local::now().format("%Y-%m-%d_%H-%M-%S").to_string(),
+            parts: Vec::new(),
+            size: (0, 0),
+        }
+    }
+
+    pub fn append(&mut self, image: &RgbaImage) {
+        let Some(size) = grown_size(self.size, self.parts.is_empty(), image, self.settings.gap) else {
+            log::error!("Montage would grow beyond {} pixels, capture left out of it.", MAX_CANVAS_PIXELS);
+            return;
+        };
+        self.size = size;
+        self.parts.push(image.clone());
+        log::debug!("Montage: appended capture #{}.", self.parts.len());
+        if self.parts.len().is_multiple_of(SAVE_EVERY) {
+            self.save();
+        }
+    }
+
+    /// Writes the montage so far. None when it is still empty or the save failed.
+    pub fn save(&self) -> Option<PathBuf> {
+        if self.parts.is_empty() {
+            return None;
+        }
+        let Some(canvas) = compose(&self.parts, self.size, &self.settings) else {
+            log::error!("Not enough memory for the {}x{} montage.", self.size.0, self.size.1);
+            return None;
+        };
+        utils::save_montage_image(&canvas, &self.settings.save_directory, &self.session, self.settings.long_paths, self.settings.png_filter)
+    }
+}
+
+/// Size of the canvas once `image` is stacked below the current one, separated by the gap.
+/// None when it would be too large.
+fn grown_size(size: (u32, u32), empty: bool, image: &RgbaImage, gap: u32) -> Option<(u32, u32)> {
+    let width = size.0.max(image.width());
+    let top = if empty { 0 } else { size.1.checked_add(gap)? };
+    let height = top.checked_add(image.height())?;
+    if width as u64 * height as u64 > MAX_CANVAS_PIXELS {
+        return None;
+    }
+    Some((width, height))
+}
+
+/// The parts below each other on a `size` canvas, separated by the gap. Narrower parts are
+/// padded with the background. None when the canvas can't be allocated.
+fn compose(parts: &[RgbaImage], size: (u32, u32), settings: &MontageSettings) -> Option<RgbaImage> {
+    let (width, height) = size;
+    let mut canvas = crate::capture::try_new_image(width, height)?;
+    canvas.pixels_mut().for_each(|pixel| *pixel = Rgba(settings.background));
+
+    let mut top = 0u32;
+    for part in parts {
+        let left = match settings.align {
+            MontageAlign::Left => 0,
+            MontageAlign::Center => (width - part.width()) / 2,
+        };
+        image::imageops::replace(&mut canvas, part, left as i64, top as i64);
+        top += part.height() + settings.gap;
+    }
+    Some(canvas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BACKGROUND: [u8; 4] = [255, 255, 255, 255];
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+
+    fn settings(align: MontageAlign) -> MontageSettings {
+        MontageSettings {
+            gap: 2,
+            background: BACKGROUND,
+            align,
+            save_directory: String::new(),
+            long_paths: LongPathStrategy::ShortenFileName,
+            png_filter: PngFilter::Adaptive,
+        }
+    }
+
+    fn stacked(parts: &[RgbaImage], align: MontageAlign) -> Option<RgbaImage> {
+        let settings = settings(align);
+        let mut size = (0, 0);
+        for (index, part) in parts.iter().enumerate() {
+            size = grown_size(size, index == 0, part, settings.gap)?;
+        }
+        compose(parts, size, &settings)
+    }
+
+    #[test]
+    fn captures_stack_below_each_other_with_a_gap() {
+        let first = RgbaImage::from_pixel(4, 3, RED);
+        let second = RgbaImage::from_pixel(4, 5, RED);
+        let stacked = stacked(&[first, second], MontageAlign::Left).unwrap();
+
+        assert_eq!(stacked.dimensions(), (4, 3 + 2 + 5));
+        assert_eq!(*stacked.get_pixel(0, 2), RED);
+        assert_eq!(*stacked.get_pixel(0, 3), Rgba(BACKGROUND));
+        assert_eq!(*stacked.get_pixel(0, 5), RED);
+    }
+
+    #[test]
+    fn narrower_captures_are_aligned_and_padded() {
+        let wide = RgbaImage::from_pixel(8, 1, RED);
+        let narrow = RgbaImage::from_pixel(4, 1, RED);
+
+        let left = stacked(&[wide.clone(), narrow.clone()], MontageAlign::Left).unwrap();
+        assert_eq!(*left.get_pixel(0, 3), RED);
+        assert_eq!(*left.get_pixel(4, 3), Rgba(BACKGROUND));
+
+        let centered = stacked(&[wide, narrow], MontageAlign::Center).unwrap();
+        assert_eq!(*centered.get_pixel(1, 3), Rgba(BACKGROUND));
+        assert_eq!(*centered.get_pixel(2, 3), RED);
+        assert_eq!(*centered.get_pixel(6, 3), Rgba(BACKGROUND));
+    }
+
+    #[test]
+    fn the_first_capture_starts_the_canvas() {
+        let first = RgbaImage::from_pixel(3, 3, RED);
+        assert_eq!(stacked(std::slice::from_ref(&first), MontageAlign::Center).unwrap(), first);
+    }
+
+    #[test]
+    fn oversized_montages_are_refused() {
+        let tall = RgbaImage::new(1, 1);
+        assert_eq!(grown_size((16 * 1024, 16 * 1024), false, &tall, 2), None);
+        assert_eq!(grown_size((1, u32::MAX), false, &tall, 0), None);
+    }
+}
@@ -0,0 +1,455 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::time::{Duration, Instant};
+use image::RgbaImage;
+
+use crate::color::{self, ColorPart};
+use crate::config::{AppConfig, ClipboardTarget};
+use crate::montage::{Montage, MontageSettings};
+use crate::scripting;
+use crate::stats::{self, CaptureMode};
+use crate::utils;
+
+/// Scaled captures larger than this keep their size (RGBA, about 1 GB).
+const MAX_SCALED_PIXELS: u64 = 256 * 1024 * 1024;
+
+/// What the capture pipeline ended up doing, sent back for the settings page's Test Capture.
+pub struct PipelineReport {
+    pub saved_path: Option<PathBuf>,
+    pub bytes_saved: u64,
+    /// How long each step took, in the order they ran.
+    pub stages: Vec<(&'static str, Duration)>,
+    /// What went wrong, for the user (details are in the log).
+    pub errors: Vec<String>,
+}
+
+/// The outcome of every capture that went through the pipeline, see `PipelineWorker::poll_results`.
+///
+/// This is where UI reactions to a finished capture (notifications, history, ...) hook in,
+/// instead of each of them passing its own channel into the worker.
+#[derive(Debug)]
+pub struct CaptureResult {
+    pub seq: u64,
+    /// None when the capture was not saved (not asked for, replaced by a script, or failed).
+    pub path: Option<PathBuf>,
+    pub dimensions: (u32, u32),
+    /// False only when copying was asked for and failed.
+    pub clipboard_ok: bool,
+    /// False only when saving was asked for and failed.
+    pub save_ok: bool,
+}
+
+/// One capture waiting for the post-capture steps (script, clipboard, save, statistics).
+pub struct CaptureJob {
+    pub image: RgbaImage,
+    pub copy_to_clipboard: bool,
+    pub save_to_disk: bool,
+    pub note: String,
+    pub mode: CaptureMode,
+    /// Which monitor (and color profile) each part of the image came from. Empty when color
+    /// management is off.
+    pub color_parts: Vec<ColorPart>,
+    /// The selection, formatted for the Coordinates clipboard target. None for captures
+    /// without one (Capture Everything, presets, interval shots).
+    pub coordinates: Option<String>,
+    /// When set, the result is sent here instead of being counted in the usage statistics.
+    pub report: Option<Sender<PipelineReport>>,
+    pub config: AppConfig,
+}
+
+/// Where the worker puts finished captures. Only the system clipboard outside of tests.
+pub trait ClipboardSink: Send + 'static {
+    /// Returns false when the copy failed.
+    fn copy(&mut self, seq: u64, image: RgbaImage, saved_path: Option<&Path>, coordinates: Option<&str>, config: &AppConfig) -> bool;
+}
+
+pub struct SystemClipboard;
+
+impl ClipboardSink for SystemClipboard {
+    fn copy(&mut self, _seq: u64, image: RgbaImage, saved_path: Option<&Path>, coordinates: Option<&str>, config: &AppConfig) -> bool {
+        utils::copy_to_clipboard(image, saved_path, coordinates, config)
+    }
+}
+
+/// What the worker thread is asked to do, in order.
+enum WorkerMessage {
+    // Boxed, a job (with its config) is far larger than the other messages
+    Capture(u64, Box<CaptureJob>),
+    /// From now on captures are also appended to a montage.
+    StartMontage(MontageSettings),
+    /// Saves the montage and ends it.
+    FinishMontage,
+}
+
+/// Runs the post-capture pipeline on a single thread, so captures finish in the order they were taken.
+///
+/// Several captures in quick succession used to race on the rayon pool, and the clipboard
+/// could end up holding an older capture than the last one.
+pub struct PipelineWorker {
+    sender: Sender<WorkerMessage>,
+    results: Receiver<CaptureResult>,
+    next_seq: u64,
+    // Queued work (captures, the final montage save) that is not done yet, so quitting can wait for it
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl PipelineWorker {
+    /// `on_result` is called on the worker thread after each result was sent (e.g. to wake the UI).
+    pub fn new(on_result: impl Fn() + Send + 'static) -> Self {
+        Self::with_clipboard(SystemClipboard, on_result)
+    }
+
+    pub fn with_clipboard(mut clipboard: impl ClipboardSink, on_result: impl Fn() + Send + 'static) -> Self {
+        let (sender, receiver) = channel::<WorkerMessage>();
+        let (result_sender, results) = channel();
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let worker_in_flight = in_flight.clone();
+        std::thread::Builder::new()
+            .name("capture-pipeline".to_string())
+            .spawn(move || {
+                let mut montage: Option<Montage> = None;
+                // Ends when the app (and with it the sender) goes away
+                while let Ok(message) = receiver.recv() {
+                    match message {
+                        WorkerMessage::Capture(seq, job) => {
+                            let result = run_job(seq, *job, &mut clipboard, montage.as_mut());
+                            worker_in_flight.fetch_sub(1, Ordering::SeqCst);
+                            if result_sender.send(result).is_ok() {
+                                on_result();
+                            }
+                        }
+                        WorkerMessage::StartMontage(settings) => {
+                            log::info!("Montage started.");
+                            montage = Some(Montage::new(settings));
+                            worker_in_flight.fetch_sub(1, Ordering::SeqCst);
+                        }
+                        WorkerMessage::FinishMontage => {
+                            match montage.take().map(|montage| montage.save()) {
+                                Some(Some(path)) => log::info!("Montage saved to {:?}.", path),
+                                Some(None) => log::warn!("Montage finished without captures or failed to save."),
+                                None => log::debug!("No montage running."),
+                            }
+                            worker_in_flight.fetch_sub(1, Ordering::SeqCst);
+                        }
+                    }
+                }
+                log::debug!("Capture pipeline worker stopped.");
+            })
+            .expect("Failed to spawn the capture pipeline thread");
+
+        Self { sender, results, next_seq: 0, in_flight }
+    }
+
+    /// Results of the captures that finished since the last call, in capture order.
+    pub fn poll_results(&self) -> Vec<CaptureResult> {
+        self.results.try_iter().collect()
+    }
+
+    /// Queues a capture behind the ones still being processed and returns its sequence number.
+    pub fn submit(&mut self, job: CaptureJob) -> u64 {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        if !self.send(WorkerMessage::Capture(seq, Box::new(job))) {
+            log::error!("Capture pipeline worker is gone, capture #{} dropped.", seq);
+        }
+        seq
+    }
+
+    /// Captures submitted after this are also stacked into a montage, until `finish_montage`.
+    pub fn start_montage(&self, settings: MontageSettings) {
+        if !self.send(WorkerMessage::StartMontage(settings)) {
+            log::error!("Capture pipeline worker is gone, montage not started.");
+        }
+    }
+
+    /// Saves the montage once the captures queued before it are on it.
+    pub fn finish_montage(&self) {
+        if !self.send(WorkerMessage::FinishMontage) {
+            log::error!("Capture pipeline worker is gone, montage not saved.");
+        }
+    }
+
+    fn send(&self, message: WorkerMessage) -> bool {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        let sent = self.sender.send(message).is_ok();
+        if !sent {
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        }
+        sent
+    }
+
+    /// Blocks until every submitted capture is finished or `timeout` passed. Returns false on
+    /// timeout. Used before exiting, so a quit right after a capture doesn't cut its save short.
+    pub fn wait_until_idle(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while self.in_flight.load(Ordering::SeqCst) > 0 {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        true
+    }
+}
+
+fn run_job(seq: u64, job: CaptureJob, clipboard: &mut impl ClipboardSink, montage: Option<&mut Montage>) -> CaptureResult {
+    let CaptureJob { mut image, copy_to_clipboard, mut save_to_disk, note, mode, color_parts, coordinates, report, config } = job;
+    log::debug!("Capture pipeline: processing capture #{}", seq);
+    let mut stages = Vec::new();
+    let mut errors = Vec::new();
+
+    // Color management first, the script and the clipboard should see the final colors
+    let icc_profile = timed(&mut stages, seq, "Color management", || {
+        color::prepare_capture(&mut image, config.color_management, &color_parts)
+    });
+
+    // Before anything that hands the image on; the color parts above are in captured px
+    if config.capture_scale != 1.0 {
+        match timed(&mut stages, seq, "Scaling", || rescale(&image, config.capture_scale)) {
+            Some(scaled) => image = scaled,
+            None => {
+                log::error!("Capture #{} is too large to scale by {}x, keeping its size.", seq, config.capture_scale);
+                errors.push(format!("Scaling by {}x was skipped, the result would be too large.", config.capture_scale));
+            }
+        }
+    }
+
+    // Test captures from the settings page stay out of the montage
+    if let Some(montage) = montage
+        && report.is_none()
+    {
+        montage.append(&image);
+    }
+
+    // A. User Script: runs before the default save, which it can skip with crab.skip_save()
+    if let Some(script) = &config.lua_post_script {
+        let temp_path = std::env::temp_dir().join(format!(
+            "crab-grab-{}.png",
+            chrono::Local::now().format("%Y%m%d_%H%M%S_%f")
+        ));
+        match timed(&mut stages, seq, "Lua script", || scripting::run_post_script(script, image.clone(), &temp_path)) {
+            Ok(output) => {
+                log::info!("Lua post-capture script finished: {}", script);
+                image = output.image;
+                if output.skip_save {
+                    log::debug!("Lua script skipped the save of capture #{}.", seq);
+                    save_to_disk = false;
+                }
+            }
+            Err(e) => {
+                log::error!("Lua post-capture script failed, using the default save:\n{}", e);
+                errors.push(format!("The Lua script failed: {}", e));
+            }
+        }
+        let _ = utils::remove_temp_file(&temp_path, config.secure_delete_temp_files);
+    }
+
+    // B. Copy to Clipboard before the slow save, so the newest capture can be pasted right away.
+    // File and Path targets need the saved file, those have to wait for the save.
+    let dimensions = image.dimensions();
+    let copy_to_clipboard = if copy_to_clipboard && save_to_disk && config.auto_save && config.keep_clipboard_when_auto_saving {
+        log::debug!("Capture #{} is auto-saved, clipboard left untouched.", seq);
+        false
+    } else {
+        copy_to_clipboard
+    };
+    let copy_after_save = copy_to_clipboard && save_to_disk && needs_saved_path(&config);
+    let mut clipboard_ok = true;
+    if copy_to_clipboard && !copy_after_save {
+        clipboard_ok = timed(&mut stages, seq, "Clipboard", || {
+            clipboard.copy(seq, image.clone(), None, coordinates.as_deref(), &config)
+        });
+    }
+
+    // C. Save to Disk (The Slow Part)
+    let saved_path = if save_to_disk {
+        let png = utils::PngOptions { filter: config.png_filter, icc_profile: icc_profile.as_deref().map(Vec::as_slice) };
+        let saved_path = timed(&mut stages, seq, "Encode & save", || {
+            utils::save_image_to_disk(&image, &config.save_directory, Some(&note), config.long_path_strategy, config.collision_policy, &png)
+        });
+        if saved_path.is_none() {
+            errors.push(format!("Saving to {} failed.", config.save_directory));
+        }
+        saved_path
+    } else {
+        None
+    };
+
+    if copy_after_save {
+        clipboard_ok = timed(&mut stages, seq, "Clipboard", || {
+            clipboard.copy(seq, image, saved_path.as_deref(), coordinates.as_deref(), &config)
+        });
+    }
+    if !clipboard_ok {
+        errors.push("Copying to the clipboard failed.".to_string());
+    }
+
+    if let (Some(path), Some(command)) = (&saved_path, &config.post_capture_command) {
+        run_post_capture_command(command, path);
+    }
+
+    // Retention: only after a successful save, so a failing disk never deletes old captures
+    if let (Some(_), Some(max_files)) = (&saved_path, config.max_saved_files) {
+        timed(&mut stages, seq, "Retention", || {
+            utils::prune_old_captures(&config.save_directory, max_files, config.delete_to_trash)
+        });
+    }
+
+    // D. Usage statistics (test captures from the settings page don't count)
+    let bytes_saved = saved_path.as_ref()
+        .and_then(|path| std::fs::metadata(path).ok())
+        .map_or(0, |metadata| metadata.len());
+    match report {
+        Some(tx) => {
+            let _ = tx.send(PipelineReport { saved_path: saved_path.clone(), bytes_saved, stages, errors });
+        }
+        None => stats::record_capture(mode, bytes_saved),
+    }
+
+    CaptureResult { seq, save_ok: !save_to_disk || saved_path.is_some(), path: saved_path, dimensions, clipboard_ok }
+}
+
+/// `image` resampled by `factor`. None when the result would be too large to allocate.
+fn rescale(image: &RgbaImage, factor: f32) -> Option<RgbaImage> {
+    let width = ((image.width() as f32 * factor).round() as u32).max(1);
+    let height = ((image.height() as f32 * factor).round() as u32).max(1);
+    if width as u64 * height as u64 > MAX_SCALED_PIXELS {
+        return None;
+    }
+    // Lanczos keeps text edges sharp when enlarging, and doesn't alias when shrinking
+    Some(image::imageops::resize(image, width, height, image::imageops::FilterType::Lanczos3))
+}
+
+/// Runs one step of a capture, logging how long it took and adding it to `stages`.
+fn timed<T>(stages: &mut Vec<(&'static str, Duration)>, seq: u64, stage: &'static str, run: impl FnOnce() -> T) -> T {
+    let started = Instant::now();
+    let result = run();
+    let elapsed = started.elapsed();
+    log::debug!("Timing: {} of capture #{} took {:?}", stage, seq, elapsed);
+    stages.push((stage, elapsed));
+    result
+}
+
+/// Starts the post-capture command with `{path}` expanded and leaves it running on its own.
+fn run_post_capture_command(command: &str, saved_path: &Path) {
+    let expanded = expand_path_placeholder(command, saved_path);
+    log::debug!("Running post-capture command: {}", expanded);
+    if let Err(e) = utils::shell_command(&expanded).spawn() {
+        log::error!("Failed to run the post-capture command {:?}: {}", expanded, e);
+    }
+}
+
+/// Replaces `{path}` with the quoted path. Quotes the user already put around it are dropped,
+/// older settings suggested `"{path}"`.
+fn expand_path_placeholder(command: &str, path: &Path) -> String {
+    let quoted = utils::shell_quote(&path.to_string_lossy());
+    command.replace("\"{path}\"", "{path}").replace("'{path}'", "{path}").replace("{path}", &quoted)
+}
+
+/// True when the first clipboard target that applies is one that points at the saved file.
+fn needs_saved_path(config: &AppConfig) -> bool {
+    let targets = &config.clipboard_targets;
+    !targets.contains(&ClipboardTarget::Image)
+        && (targets.contains(&ClipboardTarget::File) || targets.contains(&ClipboardTarget::Path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    struct RecordingClipboard(Arc<Mutex<Vec<u64>>>);
+
+    impl ClipboardSink for RecordingClipboard {
+        fn copy(&mut self, seq: u64, _image: RgbaImage, _saved_path: Option<&Path>, _coordinates: Option<&str>, _config: &AppConfig) -> bool {
+            // Uneven delays, a pool would let later jobs overtake earlier ones here
+            std::thread::sleep(Duration::from_millis(if seq.is_multiple_of(2) { 15 } else { 1 }));
+            self.0.lock().unwrap().push(seq);
+            true
+        }
+    }
+
+    fn job(report: Sender<PipelineReport>) -> CaptureJob {
+        CaptureJob {
+            image: RgbaImage::new(4, 4),
+            copy_to_clipboard: true,
+            save_to_disk: false,
+            note: String::new(),
+            mode: CaptureMode::Region,
+            color_parts: Vec::new(),
+            coordinates: None,
+            report: Some(report),
+            config: AppConfig::default(),
+        }
+    }
+
+    #[test]
+    fn clipboard_updates_follow_capture_order() {
+        let copies = Arc::new(Mutex::new(Vec::new()));
+        let mut worker = PipelineWorker::with_clipboard(RecordingClipboard(copies.clone()), || {});
+        let (tx, rx) = channel();
+
+        let submitted: Vec<u64> = (0..8).map(|_| worker.submit(job(tx.clone()))).collect();
+        for _ in &submitted {
+            rx.recv_timeout(Duration::from_secs(5)).expect("pipeline job did not finish");
+        }
+
+        let copies = copies.lock().unwrap();
+        assert_eq!(*copies, submitted);
+        assert!(copies.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn waits_for_queued_captures_before_exit() {
+        let copies = Arc::new(Mutex::new(Vec::new()));
+        let mut worker = PipelineWorker::with_clipboard(RecordingClipboard(copies.clone()), || {});
+        let (tx, _rx) = channel();
+
+        for _ in 0..4 {
+            worker.submit(job(tx.clone()));
+        }
+        assert!(worker.wait_until_idle(Duration::from_secs(5)));
+        assert_eq!(copies.lock().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn capture_scale_resizes_before_the_clipboard() {
+        let mut clipboard = RecordingClipboard(Arc::new(Mutex::new(Vec::new())));
+        let (tx, _rx) = channel();
+        let mut scaled = job(tx);
+        scaled.config.capture_scale = 2.0;
+        assert_eq!(run_job(0, scaled, &mut clipboard, None).dimensions, (8, 8));
+
+        assert!(rescale(&RgbaImage::new(4, 4), 0.1).is_some_and(|image| image.dimensions() == (1, 1)));
+        assert!(rescale(&RgbaImage::new(4, 4), 1e5).is_none());
+    }
+
+    #[test]
+    fn path_targets_wait_for_the_save() {
+        let mut config = AppConfig {
+            clipboard_targets: [ClipboardTarget::Path].into(),
+            ..AppConfig::default()
+        };
+        assert!(needs_saved_path(&config));
+
+        config.clipboard_targets = [ClipboardTarget::Image, ClipboardTarget::File].into();
+        assert!(!needs_saved_path(&config));
+
+        config.clipboard_targets = [ClipboardTarget::Base64].into();
+        assert!(!needs_saved_path(&config));
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn path_placeholder_is_quoted_for_the_shell() {
+        let path = Path::new("/tmp/it's a $(test).png");
+        let quoted = r"'/tmp/it'\''s a $(test).png'";
+        assert_eq!(expand_path_placeholder("optipng {path}", path), format!("optipng {}", quoted));
+        assert_eq!(expand_path_placeholder("optipng \"{path}\"", path), format!("optipng {}", quoted));
+
+        let output = utils::shell_command(&expand_path_placeholder("printf %s {path}", path)).output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "/tmp/it's a $(test).png");
+    }
+}
@@ -0,0 +1,147 @@
+use std::cell::{Cell, RefCell};
+use std::path::Path;
+use std::rc::Rc;
+use image::RgbaImage;
+use image::imageops::FilterType;
+use mlua::{Lua, Table, Value};
+
+/// What a post-capture script left behind.
+pub struct ScriptOutput {
+    pub image: RgbaImage,
+    /// The script called `crab.skip_save()`, it took care of the capture itself.
+    pub skip_save: bool,
+}
+
+/// Runs the user's post-capture Lua script against `image`.
+///
+/// The capture is written to `temp_path` first so scripts can hand it to other tools.
+/// Scripts get a `crab` table (`save`, `resize`, `rotate`, `get_path`, `skip_save`) and a
+/// `capture` table with metadata. Returns the (possibly modified) image on success.
+pub fn run_post_script(script_path: &str, image: RgbaImage, temp_path: &Path) -> mlua::Result<ScriptOutput> {
+    let source = std::fs::read_to_string(script_path).map_err(mlua::Error::external)?;
+    image.save(temp_path).map_err(mlua::Error::external)?;
+
+    let lua = Lua::new();
+    sandbox(&lua)?;
+
+    let temp_path_str = temp_path.to_string_lossy().to_string();
+    let capture = lua.create_table()?;
+    capture.set("path", temp_path_str.clone())?;
+    capture.set("width", image.width())?;
+    capture.set("height", image.height())?;
+    capture.set("timestamp", chrono::Local::now().to_rfc3339())?;
+    lua.globals().set("capture", capture)?;
+
+    // The Lua callbacks share the image so they can modify it in place
+    let image = Rc::new(RefCell::new(image));
+    let crab = lua.create_table()?;
+
+    let save_image = image.clone();
+    crab.set("save", lua.create_function(move |_, path: String| {
+        log::info!("Lua script saving image to: {}", path);
+        save_image.borrow().save(&path).map_err(mlua::Error::external)
+    })?)?;
+
+    let resize_image = image.clone();
+    crab.set("resize", lua.create_function(move |_, (width, height): (u32, u32)| {
+        if width == 0 || height == 0 {
+            return Err(mlua::Error::RuntimeError("crab.resize: width and height must be > 0".into()));
+        }
+        // Fail the script instead of aborting the app on a size that can't be allocated
+        if crate::capture::try_new_image(width, height).is_none() {
+            return Err(mlua::Error::RuntimeError(format!("crab.resize: {}x{} is too large", width, height)));
+        }
+        let resized = image::imageops::resize(&*resize_image.borrow(), width, height, FilterType::Lanczos3);
+        *resize_image.borrow_mut() = resized;
+        Ok(())
+    })?)?;
+
+    let rotate_image = image.clone();
+    crab.set("rotate", lua.create_function(move |_, degrees: i64| {
+        let rotated = {
+            let current = rotate_image.borrow();
+            match degrees.rem_euclid(360) {
+                0 => return Ok(()),
+                90 => image::imageops::rotate90(&*current),
+                180 => image::imageops::rotate180(&*current),
+                270 => image::imageops::rotate270(&*current),
+                _ => return Err(mlua::Error::RuntimeError(
+                    format!("crab.rotate: only multiples of 90 degrees are supported, got {}", degrees)
+                )),
+            }
+        };
+        *rotate_image.borrow_mut() = rotated;
+        Ok(())
+    })?)?;
+
+    crab.set("get_path", lua.create_function(move |_, ()| Ok(temp_path_str.clone()))?)?;
+
+    let skip_save = Rc::new(Cell::new(false));
+    let skip = skip_save.clone();
+    crab.set("skip_save", lua.create_function(move |_, ()| {
+        skip.set(true);
+        Ok(())
+    })?)?;
+    lua.globals().set("crab", crab)?;
+
+    lua.load(source).set_name(script_path).exec()?;
+
+    let result = image.borrow().clone();
+    Ok(ScriptOutput { image: result, skip_save: skip_save.get() })
+}
+
+/// Functions of the `os` library scripts keep, the others run programs, touch files or quit the app.
+const SAFE_OS_FUNCTIONS: &[&str] = &["clock", "date", "difftime", "time", "getenv"];
+
+/// Leaves scripts the pure libraries and the `crab` table: no processes, no file access besides
+/// `crab.save`, no loading other code and no way to exit the app.
+fn sandbox(lua: &Lua) -> mlua::Result<()> {
+    let globals = lua.globals();
+    for name in ["io", "package", "require", "load", "loadfile", "dofile"] {
+        globals.set(name, Value::Nil)?;
+    }
+
+    let os: Table = globals.get("os")?;
+    let safe_os = lua.create_table()?;
+    for name in SAFE_OS_FUNCTIONS {
+        safe_os.set(*name, os.get::<_, Value>(*name)?)?;
+    }
+    globals.set("os", safe_os)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sandbox_leaves_no_way_out() {
+        let lua = Lua::new();
+        sandbox(&lua).unwrap();
+        let escaped: bool = lua.load(
+            "return os.exit ~= nil or os.execute ~= nil or os.remove ~= nil or io ~= nil \
+             or load ~= nil or dofile ~= nil or require ~= nil"
+        ).eval().unwrap();
+        assert!(!escaped);
+        assert!(lua.load("return os.time()").eval::<i64>().is_ok());
+    }
+
+    #[test]
+    fn the_save_is_only_skipped_on_request() {
+        let dir = std::env::temp_dir();
+        let script = dir.join(format!("crab-grab-test-{}.lua", std::process::id()));
+        let temp = dir.join(format!("crab-grab-test-{}.png", std::process::id()));
+        let image = RgbaImage::new(4, 2);
+
+        std::fs::write(&script, "crab.rotate(90)").unwrap();
+        let output = run_post_script(script.to_str().unwrap(), image.clone(), &temp).unwrap();
+        assert_eq!(output.image.dimensions(), (2, 4));
+        assert!(!output.skip_save);
+
+        std::fs::write(&script, "crab.skip_save()").unwrap();
+        assert!(run_post_script(script.to_str().unwrap(), image, &temp).unwrap().skip_save);
+
+        let _ = std::fs::remove_file(&script);
+        let _ = std::fs::remove_file(&temp);
+    }
+}
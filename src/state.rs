@@ -0,0 +1,307 @@
+//! Which screen the app is on and how it gets from one to the next. A transition only updates
+//! the state and returns what has to happen outside of it (window, sounds, config), the app
+//! carries that out in `CrabGrabApp::apply`. So the flow can be tested without a window.
+
+use eframe::egui::{Pos2, Rect};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AppState {
+    Idle,
+    Snapping,
+    ActionBar,
+    NoteInput,
+    Editing,
+    Preview,
+    Config,
+    // Numbered panels on every monitor, started from the settings
+    IdentifyDisplays,
+}
+
+/// Side effects of a transition, in the order they have to be applied.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Effect {
+    /// Remember where the settings window is, the overlay is about to take the window over.
+    RememberSettingsRect,
+    /// Put the settings window's position and size into the config (closing the settings).
+    StoreSettingsRect,
+    /// Show the settings window where the config says it was last.
+    ShowSettings,
+    /// Show the settings window where it was before the overlay took the window over.
+    RestoreSettings,
+    /// Make the window the hidden idle window.
+    HideWindow,
+    SaveConfig,
+    PlayActivationSound,
+    /// Drop everything the capture in progress holds on to.
+    ClearCaptureState,
+}
+
+/// The selection being dragged on the overlay (overlay points).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Selection {
+    pub start: Option<Pos2>,
+    pub current: Option<Pos2>,
+    // Where the drag began. While Alt is held it is the selection's center and
+    // start is current mirrored around it
+    press: Option<Pos2>,
+    pub center_anchored: bool,
+}
+
+impl Selection {
+    pub fn press(&mut self, pos: Pos2) {
+        *self = Self { start: Some(pos), current: Some(pos), press: Some(pos), center_anchored: false };
+    }
+
+    /// Moves the dragged corner. With `center_anchored` the selection grows symmetrically around
+    /// where the drag began, the mirrored corner is kept inside `bounds`.
+    pub fn drag(&mut self, pos: Pos2, center_anchored: bool, bounds: Rect) {
+        let Some(press) = self.press else {
+            return;
+        };
+        self.center_anchored = center_anchored;
+        let mirrored = (press - (pos - press)).clamp(bounds.min, bounds.max);
+        self.start = Some(if center_anchored { mirrored } else { press });
+        self.current = Some(pos);
+    }
+
+    pub fn rect(&self) -> Option<Rect> {
+        Some(Rect::from_two_pos(self.start?, self.current?))
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.start.is_some()
+    }
+}
+
+pub struct StateMachine {
+    state: AppState,
+    // Where the current capture was started from
+    previous_state: AppState,
+    pub selection: Selection,
+}
+
+impl Default for StateMachine {
+    fn default() -> Self {
+        Self { state: AppState::Idle, previous_state: AppState::Idle, selection: Selection::default() }
+    }
+}
+
+impl StateMachine {
+    pub fn state(&self) -> AppState {
+        self.state
+    }
+
+    pub fn previous_state(&self) -> AppState {
+        self.previous_state
+    }
+
+    /// A new capture can only start from the tray or the settings.
+    pub fn can_begin_capture(&self) -> bool {
+        matches!(self.state, AppState::Idle | AppState::Config)
+    }
+
+    /// The screens are about to be read. The state stays until the overlay is up (`overlay_shown`),
+    /// a failed capture leaves the app where it was. Capturing again from the overlay keeps
+    /// where the first capture was started from.
+    pub fn begin_capture(&mut self) -> Vec<Effect> {
+        if self.state == AppState::Snapping {
+            return vec![Effect::PlayActivationSound];
+        }
+        self.previous_state = self.state;
+        let mut effects = Vec::new();
+        if self.state == AppState::Config {
+            effects.push(Effect::RememberSettingsRect);
+        }
+        effects.push(Effect::PlayActivationSound);
+        effects
+    }
+
+    pub fn overlay_shown(&mut self) {
+        self.state = AppState::Snapping;
+        self.selection = Selection::default();
+    }
+
+    /// Moves on to one of the steps after the selection: action bar, note, editor or preview.
+    pub fn review(&mut self, step: AppState) {
+        debug_assert!(matches!(step, AppState::ActionBar | AppState::NoteInput | AppState::Editing | AppState::Preview));
+        self.state = step;
+    }
+
+    /// Hides the overlay and returns to wherever the capture was started from. The settings
+    /// only come back with `return_to_settings`, otherwise they are closed (and saved).
+    pub fn finish_capture(&mut self, return_to_settings: bool) -> Vec<Effect> {
+        let mut effects = Vec::new();
+        if self.previous_state == AppState::Config && return_to_settings {
+            self.state = AppState::Config;
+            effects.push(Effect::RestoreSettings);
+        } else {
+            if self.previous_state == AppState::Config {
+                // Settings are closed instead of restored, so persist them like a normal close.
+                effects.push(Effect::SaveConfig);
+            }
+            self.state = AppState::Idle;
+            effects.push(Effect::HideWindow);
+        }
+        self.selection = Selection::default();
+        effects.push(Effect::ClearCaptureState);
+        effects
+    }
+
+    /// Escape while selecting: same as a capture that was discarded. Nothing to do elsewhere.
+    pub fn cancel(&mut self, return_to_settings: bool) -> Vec<Effect> {
+        if self.state != AppState::Snapping {
+            return Vec::new();
+        }
+        self.finish_capture(return_to_settings)
+    }
+
+    pub fn open_settings(&mut self) -> Vec<Effect> {
+        self.state = AppState::Config;
+        vec![Effect::ShowSettings]
+    }
+
+    pub fn close_settings(&mut self) -> Vec<Effect> {
+        self.state = AppState::Idle;
+        vec![Effect::StoreSettingsRect, Effect::HideWindow, Effect::SaveConfig]
+    }
+
+    pub fn open_identify_displays(&mut self) -> Vec<Effect> {
+        self.state = AppState::IdentifyDisplays;
+        vec![Effect::RememberSettingsRect]
+    }
+
+    pub fn close_identify_displays(&mut self) -> Vec<Effect> {
+        self.state = AppState::Config;
+        vec![Effect::RestoreSettings]
+    }
+
+    /// Panic hotkey: back to idle from anywhere.
+    pub fn force_reset(&mut self) -> Vec<Effect> {
+        let mut effects = Vec::new();
+        if self.state == AppState::Config {
+            effects.push(Effect::SaveConfig);
+        }
+        self.state = AppState::Idle;
+        self.previous_state = AppState::Idle;
+        self.selection = Selection::default();
+        effects.extend([Effect::ClearCaptureState, Effect::HideWindow]);
+        effects
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eframe::egui::pos2;
+
+    fn snapping_from(state: AppState) -> StateMachine {
+        let mut machine = StateMachine::default();
+        if state == AppState::Config {
+            machine.open_settings();
+        }
+        machine.begin_capture();
+        machine.overlay_shown();
+        machine
+    }
+
+    #[test]
+    fn capture_from_settings_remembers_and_restores_the_window() {
+        let mut machine = StateMachine::default();
+        machine.open_settings();
+        assert_eq!(machine.begin_capture(), vec![Effect::RememberSettingsRect, Effect::PlayActivationSound]);
+        // Nothing changes before the overlay is up, a failed capture stays in the settings
+        assert_eq!(machine.state(), AppState::Config);
+
+        machine.overlay_shown();
+        assert_eq!(machine.state(), AppState::Snapping);
+        assert!(!machine.can_begin_capture());
+
+        assert_eq!(machine.finish_capture(true), vec![Effect::RestoreSettings, Effect::ClearCaptureState]);
+        assert_eq!(machine.state(), AppState::Config);
+    }
+
+    #[test]
+    fn capturing_again_keeps_the_settings_to_return_to() {
+        let mut machine = snapping_from(AppState::Config);
+        machine.selection.press(pos2(1.0, 1.0));
+        assert_eq!(machine.begin_capture(), vec![Effect::PlayActivationSound]);
+        machine.overlay_shown();
+        assert!(!machine.selection.is_active());
+        assert_eq!(machine.previous_state(), AppState::Config);
+        assert_eq!(machine.finish_capture(true), vec![Effect::RestoreSettings, Effect::ClearCaptureState]);
+    }
+
+    #[test]
+    fn closing_the_settings_after_a_capture_saves_them() {
+        let mut machine = snapping_from(AppState::Config);
+        assert_eq!(machine.finish_capture(false), vec![Effect::SaveConfig, Effect::HideWindow, Effect::ClearCaptureState]);
+        assert_eq!(machine.state(), AppState::Idle);
+    }
+
+    #[test]
+    fn capture_from_the_tray_returns_to_idle() {
+        let mut machine = snapping_from(AppState::Idle);
+        machine.review(AppState::ActionBar);
+        assert_eq!(machine.finish_capture(true), vec![Effect::HideWindow, Effect::ClearCaptureState]);
+        assert_eq!(machine.state(), AppState::Idle);
+        assert!(machine.can_begin_capture());
+    }
+
+    #[test]
+    fn cancel_only_applies_while_selecting() {
+        let mut machine = snapping_from(AppState::Idle);
+        machine.selection.press(pos2(1.0, 1.0));
+        machine.review(AppState::Editing);
+        assert!(machine.cancel(false).is_empty());
+        assert_eq!(machine.state(), AppState::Editing);
+
+        let mut machine = snapping_from(AppState::Idle);
+        machine.selection.press(pos2(1.0, 1.0));
+        assert_eq!(machine.cancel(false), vec![Effect::HideWindow, Effect::ClearCaptureState]);
+        assert_eq!(machine.state(), AppState::Idle);
+        assert!(!machine.selection.is_active());
+    }
+
+    #[test]
+    fn settings_open_and_close() {
+        let mut machine = StateMachine::default();
+        assert_eq!(machine.open_settings(), vec![Effect::ShowSettings]);
+        assert_eq!(machine.close_settings(), vec![Effect::StoreSettingsRect, Effect::HideWindow, Effect::SaveConfig]);
+        assert_eq!(machine.state(), AppState::Idle);
+    }
+
+    #[test]
+    fn force_reset_saves_open_settings_and_forgets_the_capture() {
+        let mut machine = StateMachine::default();
+        machine.open_settings();
+        assert_eq!(machine.force_reset(), vec![Effect::SaveConfig, Effect::ClearCaptureState, Effect::HideWindow]);
+
+        let mut machine = snapping_from(AppState::Config);
+        assert_eq!(machine.force_reset(), vec![Effect::ClearCaptureState, Effect::HideWindow]);
+        assert_eq!((machine.state(), machine.previous_state()), (AppState::Idle, AppState::Idle));
+    }
+
+    #[test]
+    fn alt_mirrors_the_selection_around_the_press() {
+        let bounds = Rect::from_min_max(pos2(0.0, 0.0), pos2(100.0, 100.0));
+        let mut selection = Selection::default();
+        selection.press(pos2(50.0, 50.0));
+
+        selection.drag(pos2(60.0, 70.0), false, bounds);
+        assert_eq!(selection.rect(), Some(Rect::from_min_max(pos2(50.0, 50.0), pos2(60.0, 70.0))));
+
+        selection.drag(pos2(60.0, 70.0), true, bounds);
+        assert_eq!(selection.rect(), Some(Rect::from_min_max(pos2(40.0, 30.0), pos2(60.0, 70.0))));
+
+        // The mirrored corner stays on the desktop
+        selection.drag(pos2(90.0, 95.0), true, bounds);
+        assert_eq!(selection.rect(), Some(Rect::from_min_max(pos2(10.0, 5.0), pos2(90.0, 95.0))));
+    }
+
+    #[test]
+    fn dragging_without_a_press_does_nothing() {
+        let mut selection = Selection::default();
+        selection.drag(pos2(10.0, 10.0), false, Rect::EVERYTHING);
+        assert_eq!(selection.rect(), None);
+    }
+}
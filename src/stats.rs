@@ -0,0 +1,125 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use serde::{Deserialize, Serialize};
+
+// Captures finish on the rayon pool, so two of them may update the file at the same time.
+static STATS_FILE_LOCK: Mutex<()> = Mutex::new(());
+
+/// How a capture was taken.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum CaptureMode {
+    /// Plain region selection.
+    Region,
+    /// Region selection that went through the annotation editor.
+    Annotated,
+    /// The whole virtual desktop (tray "Capture Everything").
+    Everything,
+    /// A saved region preset, captured without the overlay.
+    Preset,
+    /// Region selection handed to an external editor.
+    ExternalEdit,
+    /// Taken by a running interval capture.
+    Interval,
+}
+
+impl CaptureMode {
+    pub fn label(&self) -> &'static str {
+        match self {
+            CaptureMode::Region => "Region",
+            CaptureMode::Annotated => "Annotated",
+            CaptureMode::Everything => "Everything",
+            CaptureMode::Preset => "Region Preset",
+            CaptureMode::ExternalEdit => "External Editor",
+            CaptureMode::Interval => "Interval",
+        }
+    }
+}
+
+/// Lifetime usage counters. Local only, stored next to the config.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CaptureStats {
+    pub total_captures: u64,
+    pub total_bytes_saved: u64,
+    pub captures_by_mode: BTreeMap<CaptureMode, u64>,
+}
+
+impl CaptureStats {
+    pub fn most_used_mode(&self) -> Option<CaptureMode> {
+        self.captures_by_mode.iter()
+            .max_by_key(|(_, count)| **count)
+            .map(|(mode, _)| *mode)
+    }
+
+    pub fn load() -> Self {
+        let Some(path) = stats_path() else {
+            return CaptureStats::default();
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(data) => serde_json::from_str(&data).unwrap_or_else(|e| {
+                log::error!("Failed to parse stats file, starting over: {}", e);
+                CaptureStats::default()
+            }),
+            // No captures yet
+            Err(_) => CaptureStats::default(),
+        }
+    }
+
+    fn save(&self) {
+        let Some(path) = stats_path() else {
+            log::error!("Could not determine config directory, stats not saved.");
+            return;
+        };
+        if let Some(dir) = path.parent()
+            && let Err(e) = std::fs::create_dir_all(dir)
+        {
+            log::error!("Failed to create config directory: {}", e);
+            return;
+        }
+        match serde_json::to_string_pretty(self) {
+            Ok(json) => {
+                if let Err(e) = std::fs::write(&path, json) {
+                    log::error!("Failed to write stats file: {}", e);
+                }
+            }
+            Err(e) => log::error!("Failed to serialize stats: {}", e),
+        }
+    }
+}
+
+/// Counts one finished capture. Blocking file IO, call it from a background thread.
+pub fn record_capture(mode: CaptureMode, bytes_saved: u64) {
+    let _guard = STATS_FILE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut stats = CaptureStats::load();
+    stats.total_captures += 1;
+    stats.total_bytes_saved += bytes_saved;
+    *stats.captures_by_mode.entry(mode).or_insert(0) += 1;
+    stats.save();
+}
+
+pub fn reset() {
+    let _guard = STATS_FILE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    CaptureStats::default().save();
+    log::info!("Capture statistics reset.");
+}
+
+fn stats_path() -> Option<PathBuf> {
+    crate::config::app_dir().map(|dir| dir.join("crab_stats.json"))
+}
+
+/// "12.3 MB" style size for the settings page.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
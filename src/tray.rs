@@ -0,0 +1,205 @@
+//! Updating the tray icon after startup. On Windows the icon lives on its own thread
+//! (see `init_tray_platform` in main.rs), elsewhere it is kept on the main thread.
+
+use tray_icon::TrayIcon;
+use tray_icon::menu::{CheckMenuItem, Menu, MenuId, MenuItem, Submenu};
+
+use crate::config::AppConfig;
+
+/// Prefix of the profile entries' ids, followed by the profile's index.
+const PROFILE_ID_PREFIX: &str = "profile:";
+/// Prefix of the "Save Folder" entries' ids, followed by the index in the recent directories.
+const SAVE_DIRECTORY_ID_PREFIX: &str = "save_directory:";
+
+/// Ids of the tray menu entries, matched in the app. Fixed strings, so the menu can be rebuilt
+/// on the tray's thread with the same ids.
+#[derive(Clone)]
+pub struct TrayMenuIds {
+    pub quit: MenuId,
+    pub settings: MenuId,
+    pub capture: MenuId,
+    pub capture_everything: MenuId,
+    pub capture_edit: MenuId,
+    pub interval_start: MenuId,
+    pub interval_stop: MenuId,
+    pub montage_start: MenuId,
+    pub montage_finish: MenuId,
+}
+
+impl Default for TrayMenuIds {
+    fn default() -> Self {
+        Self {
+            quit: MenuId::new("quit"),
+            settings: MenuId::new("settings"),
+            capture: MenuId::new("capture"),
+            capture_everything: MenuId::new("capture_everything"),
+            capture_edit: MenuId::new("capture_edit"),
+            interval_start: MenuId::new("interval_start"),
+            interval_stop: MenuId::new("interval_stop"),
+            montage_start: MenuId::new("montage_start"),
+            montage_finish: MenuId::new("montage_finish"),
+        }
+    }
+}
+
+impl TrayMenuIds {
+    /// The profile a "Profiles" entry switches to, None for other entries.
+    pub fn profile_index(id: &MenuId) -> Option<usize> {
+        id.as_ref().strip_prefix(PROFILE_ID_PREFIX)?.parse().ok()
+    }
+
+    /// The recent save directory a "Save Folder" entry switches to, None for other entries.
+    pub fn save_directory_index(id: &MenuId) -> Option<usize> {
+        id.as_ref().strip_prefix(SAVE_DIRECTORY_ID_PREFIX)?.parse().ok()
+    }
+}
+
+/// The parts of the tray menu that follow the config.
+#[derive(Default)]
+pub struct MenuEntries {
+    pub profiles: Vec<String>,
+    pub active_profile: Option<usize>,
+    pub recent_save_directories: Vec<String>,
+    pub save_directory: String,
+}
+
+impl MenuEntries {
+    pub fn from_config(config: &AppConfig) -> Self {
+        Self {
+            profiles: config.profiles.iter().map(|profile| profile.name.clone()).collect(),
+            active_profile: config.active_profile,
+            recent_save_directories: config.recent_save_directories.clone(),
+            save_directory: config.save_directory.clone(),
+        }
+    }
+}
+
+/// Which variant of the logo the tray shows.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TrayIconKind {
+    Normal,
+    /// Red dot, for a few seconds after a failed capture.
+    Error,
+    /// Blue dot, while an interval capture runs.
+    Interval,
+}
+
+/// Changes sent to the tray icon.
+pub enum TrayCommand {
+    SetTooltip(String),
+    SetIcon(TrayIconKind),
+    /// Rebuilds the menu with these profiles and save directories in their submenus.
+    RebuildMenu(MenuEntries),
+}
+
+/// Thread message telling the Windows tray thread to drain its command channel.
+#[cfg(target_os = "windows")]
+pub const WM_TRAY_COMMAND: u32 = windows::Win32::UI::WindowsAndMessaging::WM_APP + 1;
+
+/// The app's handle on the tray icon, wherever that lives.
+pub struct TrayLink {
+    #[cfg(target_os = "windows")]
+    sender: std::sync::mpsc::Sender<TrayCommand>,
+    #[cfg(target_os = "windows")]
+    thread_id: u32,
+    #[cfg(not(target_os = "windows"))]
+    icon: TrayIcon,
+}
+
+impl TrayLink {
+    #[cfg(target_os = "windows")]
+    pub fn new(sender: std::sync::mpsc::Sender<TrayCommand>, thread_id: u32) -> Self {
+        Self { sender, thread_id }
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    pub fn new(icon: TrayIcon) -> Self {
+        Self { icon }
+    }
+
+    pub fn send(&self, command: TrayCommand) {
+        #[cfg(target_os = "windows")]
+        {
+            use windows::Win32::Foundation::{LPARAM, WPARAM};
+            use windows::Win32::UI::WindowsAndMessaging::PostThreadMessageW;
+
+            if self.sender.send(command).is_err() {
+                log::warn!("Tray thread is gone, dropping tray update.");
+                return;
+            }
+            // GetMessageW blocks the tray thread, wake it up
+            if let Err(e) = unsafe { PostThreadMessageW(self.thread_id, WM_TRAY_COMMAND, WPARAM(0), LPARAM(0)) } {
+                log::warn!("Failed to notify the tray thread: {}", e);
+            }
+        }
+        #[cfg(not(target_os = "windows"))]
+        apply(&self.icon, command);
+    }
+}
+
+/// Carries out a command, on the thread that owns the icon.
+pub fn apply(icon: &TrayIcon, command: TrayCommand) {
+    match command {
+        TrayCommand::SetTooltip(text) => {
+            if let Err(e) = icon.set_tooltip(Some(text)) {
+                log::warn!("Failed to set the tray tooltip: {}", e);
+            }
+        }
+        TrayCommand::SetIcon(kind) => {
+            let icon_image = match kind {
+                TrayIconKind::Normal => crate::utils::load_tray_icon(),
+                TrayIconKind::Error => crate::utils::load_tray_error_icon(),
+                TrayIconKind::Interval => crate::utils::load_tray_interval_icon(),
+            };
+            if let Err(e) = icon.set_icon(Some(icon_image)) {
+                log::warn!("Failed to change the tray icon: {}", e);
+            }
+        }
+        TrayCommand::RebuildMenu(entries) => {
+            icon.set_menu(Some(Box::new(build_menu(&TrayMenuIds::default(), &entries))));
+        }
+    }
+}
+
+/// The tray's right-click menu. Built on the thread that owns the icon. The "Profiles" and
+/// "Save Folder" submenus are only there once profiles / recent directories exist.
+pub fn build_menu(menu_ids: &TrayMenuIds, entries: &MenuEntries) -> Menu {
+    let quit_item = MenuItem::with_id(menu_ids.quit.clone(), "Quit", true, None);
+    let settings_item = MenuItem::with_id(menu_ids.settings.clone(), "Settings", true, None);
+    let capture_item = MenuItem::with_id(menu_ids.capture.clone(), "Capture Screen", true, None);
+    let capture_everything_item = MenuItem::with_id(menu_ids.capture_everything.clone(), "Capture Everything", true, None);
+    let capture_edit_item = MenuItem::with_id(menu_ids.capture_edit.clone(), "Capture & Edit", true, None);
+    let interval_start_item = MenuItem::with_id(menu_ids.interval_start.clone(), "Start Interval Capture…", true, None);
+    let interval_stop_item = MenuItem::with_id(menu_ids.interval_stop.clone(), "Stop Interval Capture", true, None);
+    let montage_start_item = MenuItem::with_id(menu_ids.montage_start.clone(), "Start Montage", true, None);
+    let montage_finish_item = MenuItem::with_id(menu_ids.montage_finish.clone(), "Finish & Save Montage", true, None);
+
+    let tray_menu = Menu::new();
+    let _ = tray_menu.append(&capture_item);
+    let _ = tray_menu.append(&capture_everything_item);
+    let _ = tray_menu.append(&capture_edit_item);
+    let _ = tray_menu.append(&interval_start_item);
+    let _ = tray_menu.append(&interval_stop_item);
+    let _ = tray_menu.append(&montage_start_item);
+    let _ = tray_menu.append(&montage_finish_item);
+    if !entries.profiles.is_empty() {
+        let profiles_menu = Submenu::new("Profiles", true);
+        for (index, name) in entries.profiles.iter().enumerate() {
+            let id = MenuId::new(format!("{}{}", PROFILE_ID_PREFIX, index));
+            let _ = profiles_menu.append(&CheckMenuItem::with_id(id, name, true, entries.active_profile == Some(index), None));
+        }
+        let _ = tray_menu.append(&profiles_menu);
+    }
+    if !entries.recent_save_directories.is_empty() {
+        let directories_menu = Submenu::new("Save Folder", true);
+        for (index, directory) in entries.recent_save_directories.iter().enumerate() {
+            let id = MenuId::new(format!("{}{}", SAVE_DIRECTORY_ID_PREFIX, index));
+            let active = *directory == entries.save_directory;
+            let _ = directories_menu.append(&CheckMenuItem::with_id(id, directory, true, active, None));
+        }
+        let _ = tray_menu.append(&directories_menu);
+    }
+    let _ = tray_menu.append(&settings_item);
+    let _ = tray_menu.append(&quit_item);
+    tray_menu
+}
@@ -1,10 +1,22 @@
 use global_hotkey::hotkey::Code;
+use std::borrow::Cow;
 use std::env;
-use std::path::Path;
+use std::error::Error;
+use std::io::{BufWriter, Write};
+use std::ffi::OsString;
+use std::path::{Component, Path, PathBuf};
+use arboard::{Clipboard, ImageData};
+use base64::prelude::{BASE64_STANDARD, Engine};
+use std::time::{Duration, Instant};
 use eframe::egui::{Context, TextureHandle, TextureOptions};
 use egui::{vec2};
 use global_hotkey::hotkey::{HotKey, Modifiers};
-use image::RgbaImage;
+use image::codecs::avif::AvifEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::{CompressionType, PngEncoder};
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageEncoder, ImageFormat, RgbaImage};
+use rayon::prelude::*;
 use tray_icon::Icon;
 use auto_launch::{AutoLaunchBuilder, MacOSLaunchMode};
 use log4rs::append::console::ConsoleAppender;
@@ -14,63 +26,99 @@ use log4rs::append::rolling_file::policy::compound::trigger::size::SizeTrigger;
 use log4rs::append::rolling_file::RollingFileAppender;
 use log4rs::config::{Appender, Config, Root};
 use log4rs::encode::pattern::PatternEncoder;
-use crate::capture::{MonitorData};
+use crate::capture::{CaptureError, MonitorData};
+use crate::config::{self, AlignmentGuide, AppConfig, ClipboardTarget, CollisionPolicy, LogConfig, LongPathStrategy, PngFilter};
 
 const MAX_TILE_SIZE: u32 = 2048; // Safe limit for almost any GPU
 
-// Changed: Return explicit PHYSICAL offsets and sizes (px) along with the texture handle
-pub fn load_image_as_tiles(ctx: &Context, image: &RgbaImage) -> Vec<(u32, u32, u32, u32, TextureHandle)> {
+// Changed: Return explicit PHYSICAL offsets and sizes (px) along with the tile pixels.
+// CPU only, the textures are uploaded a few per frame (see upload_tile).
+fn decode_image_tiles(image: &RgbaImage) -> Vec<(u32, u32, u32, u32, egui::ColorImage)> {
     let (total_width, total_height) = image.dimensions();
-    let mut tiles = Vec::new();
 
-    let mut current_y = 0;
-    while current_y < total_height {
-        let tile_height = std::cmp::min(MAX_TILE_SIZE, total_height - current_y);
-
-        let mut current_x = 0;
-        while current_x < total_width {
+    // Grid cells as (x, y, width, height) in physical px
+    let mut cells = Vec::new();
+    for current_y in (0..total_height).step_by(MAX_TILE_SIZE as usize) {
+        for current_x in (0..total_width).step_by(MAX_TILE_SIZE as usize) {
             let tile_width = std::cmp::min(MAX_TILE_SIZE, total_width - current_x);
+            let tile_height = std::cmp::min(MAX_TILE_SIZE, total_height - current_y);
+            cells.push((current_x, current_y, tile_width, tile_height));
+        }
+    }
 
-            // Crop the specific rectangle (Grid cell)
-            let sub_image = image::imageops::crop_imm(
-                image,
-                current_x,
-                current_y,
-                tile_width,
-                tile_height
-            ).to_image();
-
-            let pixels = sub_image.as_flat_samples();
-            let color_image = egui::ColorImage::from_rgba_unmultiplied(
-                [tile_width as usize, tile_height as usize],
-                pixels.as_slice(),
-            );
+    // The conversion is the expensive part and independent per tile, so spread it over rayon.
+    cells
+        .into_par_iter()
+        .map(|(x, y, width, height)| (x, y, width, height, tile_to_color_image(image, x, y, width, height)))
+        .collect()
+}
 
-            // Unique name for caching
-            let name = format!("tile_{}_{}_{}x{}", current_x, current_y, tile_width, tile_height);
-            let handle = ctx.load_texture(&name, color_image, TextureOptions::NEAREST);
+/// Sends one decoded overlay tile to the GPU. Main thread only.
+pub fn upload_tile(ctx: &Context, rect: egui::Rect, image: egui::ColorImage) -> (egui::Rect, TextureHandle) {
+    // Unique name for caching
+    let name = format!("tile_{:.0}_{:.0}_{:.0}x{:.0}", rect.min.x, rect.min.y, rect.width(), rect.height());
+    (rect, ctx.load_texture(&name, image, TextureOptions::NEAREST))
+}
 
-            // Store physical X, Y offsets and physical tile sizes (all px)
-            tiles.push((current_x, current_y, tile_width, tile_height, handle));
+/// Copies one grid cell straight from the source rows into an egui pixel buffer
+/// (no intermediate cropped RgbaImage).
+fn tile_to_color_image(image: &RgbaImage, x: u32, y: u32, width: u32, height: u32) -> egui::ColorImage {
+    let stride = image.width() as usize * 4;
+    let row_length = width as usize * 4;
+    let raw = image.as_raw();
 
-            current_x += tile_width;
-        }
-        current_y += tile_height;
+    let mut pixels = Vec::with_capacity(width as usize * height as usize);
+    for row in y..y + height {
+        let start = row as usize * stride + x as usize * 4;
+        pixels.extend(
+            raw[start..start + row_length]
+                .chunks_exact(4)
+                .map(|p| egui::Color32::from_rgba_unmultiplied(p[0], p[1], p[2], p[3])),
+        );
     }
 
-    tiles
+    egui::ColorImage::new([width as usize, height as usize], pixels)
 }
 
-pub fn load_screens_as_tiles(
-    ctx: &Context,
+/// Cuts all monitors into overlay tiles, placed in egui points. Uploading them is left to the
+/// caller (`upload_tile`), so it can be spread over several frames.
+pub fn decode_screen_tiles(
     captures: &[MonitorData],
     physical_origin: (i32, i32), // <--- CHANGE to Physical
     current_ppi: f32,
-) -> Vec<(egui::Rect, TextureHandle)> {
+    compensate_sdr_white_level: bool,
+    gpu_tile_budget_mb: u32,
+) -> Vec<(egui::Rect, egui::ColorImage)> {
+    let started = Instant::now();
     let mut result_tiles = Vec::new();
 
+    // Uncompressed RGBA, which is what the textures take up on the GPU
+    let total_bytes: u64 = captures.iter().map(|mon| mon.width as u64 * mon.height as u64 * 4).sum();
+    let budget_bytes = gpu_tile_budget_mb as u64 * 1024 * 1024;
+    let downscale = if total_bytes > budget_bytes {
+        let factor = (budget_bytes as f64 / total_bytes as f64).sqrt() as f32;
+        log::warn!("Tiles scaled to {factor:.2}x to fit GPU budget");
+        Some(factor)
+    } else {
+        None
+    };
+
     for mon in captures {
-        let local_tiles = load_image_as_tiles(ctx, &mon.image);
+        // Only the overlay is compensated, the saved capture keeps the original pixels
+        let mut image = if compensate_sdr_white_level && mon.sdr_white_level > 1.01 {
+            Cow::Owned(compensate_white_level(&mon.image, mon.sdr_white_level))
+        } else {
+            Cow::Borrowed(&mon.image)
+        };
+        if let Some(factor) = downscale {
+            let width = ((image.width() as f32 * factor).round() as u32).max(1);
+            let height = ((image.height() as f32 * factor).round() as u32).max(1);
+            image = Cow::Owned(image::imageops::resize(&*image, width, height, FilterType::Triangle));
+        }
+        // Texture px -> monitor px, per axis since the sizes were rounded
+        let texel_x = mon.image.width() as f32 / image.width() as f32;
+        let texel_y = mon.image.height() as f32 / image.height() as f32;
+        let local_tiles = decode_image_tiles(&image);
 
         // --- THE FIX ---
         // 1. Calculate the PHYSICAL distance from the top-left of the virtual desktop
@@ -86,73 +134,1166 @@ pub fn load_screens_as_tiles(
         // 1 Physical Pixel = (1.0 / PPI) Logical Units
         let scale = 1.0 / current_ppi;
 
-        for (tile_x, tile_y, tile_w, tile_h, texture) in local_tiles {
+        for (tile_x, tile_y, tile_w, tile_h, pixels) in local_tiles {
             // Position = MonitorStart + (TileOffset * Scale)
-            let final_x = egui_offset_x + (tile_x as f32 * scale);
-            let final_y = egui_offset_y + (tile_y as f32 * scale);
+            let final_x = egui_offset_x + (tile_x as f32 * texel_x * scale);
+            let final_y = egui_offset_y + (tile_y as f32 * texel_y * scale);
 
             // Size = TileSize * Scale
-            let final_w = tile_w as f32 * scale;
-            let final_h = tile_h as f32 * scale;
+            let final_w = tile_w as f32 * texel_x * scale;
+            let final_h = tile_h as f32 * texel_y * scale;
 
             let rect = egui::Rect::from_min_size(
                 egui::pos2(final_x, final_y),
                 egui::vec2(final_w, final_h)
             );
 
-            result_tiles.push((rect, texture));
+            result_tiles.push((rect, pixels));
         }
     }
+    log::debug!("Timing: decoding {} tiles took {:?}", result_tiles.len(), started.elapsed());
     result_tiles
 }
 
+/// Darkens a capture by the monitor's SDR white level (in linear light), so it looks like the
+/// live desktop again when the overlay is shown with the same SDR brightness boost.
+fn compensate_white_level(image: &RgbaImage, white_level: f32) -> RgbaImage {
+    let lut: [u8; 256] = std::array::from_fn(|value| {
+        let srgb = value as f32 / 255.0;
+        let linear = if srgb <= 0.04045 { srgb / 12.92 } else { ((srgb + 0.055) / 1.055).powf(2.4) };
+        let scaled = linear / white_level;
+        let out = if scaled <= 0.0031308 { scaled * 12.92 } else { 1.055 * scaled.powf(1.0 / 2.4) - 0.055 };
+        (out * 255.0).round().clamp(0.0, 255.0) as u8
+    });
+
+    let mut compensated = image.clone();
+    for pixel in compensated.pixels_mut() {
+        for channel in &mut pixel.0[..3] {
+            *channel = lut[*channel as usize];
+        }
+    }
+    compensated
+}
+
+/// Moves `rect_px` (physical px) fully onto the monitor that holds most of it, shrinking it if
+/// it is larger than that monitor. None when no monitor holds at least half of it, e.g. the
+/// monitor it was on is gone.
+pub fn fit_onto_monitor(rect_px: egui::Rect, monitors: &[MonitorData]) -> Option<egui::Rect> {
+    let (monitor_rect, overlap) = monitors.iter()
+        .map(|m| {
+            let monitor_rect = egui::Rect::from_min_size(
+                egui::pos2(m.x as f32, m.y as f32),
+                egui::vec2(m.width as f32, m.height as f32),
+            );
+            let overlap = monitor_rect.intersect(rect_px);
+            (monitor_rect, if overlap.is_positive() { overlap.area() } else { 0.0 })
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))?;
+    if overlap == 0.0 || overlap < rect_px.area() / 2.0 {
+        return None;
+    }
+
+    let size = rect_px.size().min(monitor_rect.size());
+    let min = rect_px.min.clamp(monitor_rect.min, monitor_rect.max - size);
+    Some(egui::Rect::from_min_size(min, size))
+}
+
+/// Uploads a capture for the preview screen. Only a preview, so anything larger than a
+/// single tile is downscaled instead of split.
+pub fn load_preview_texture(ctx: &Context, image: &RgbaImage) -> TextureHandle {
+    let preview: Cow<RgbaImage> = if image.width() > MAX_TILE_SIZE || image.height() > MAX_TILE_SIZE {
+        Cow::Owned(make_thumbnail(image, MAX_TILE_SIZE))
+    } else {
+        Cow::Borrowed(image)
+    };
+
+    let color_image = egui::ColorImage::from_rgba_unmultiplied(
+        [preview.width() as usize, preview.height() as usize],
+        preview.as_flat_samples().as_slice(),
+    );
+    ctx.load_texture("capture_preview", color_image, TextureOptions::LINEAR)
+}
+
+/// `image` scaled down to fit into `max` x `max`, keeping its aspect ratio. Smaller images are
+/// only copied, never scaled up.
+pub fn make_thumbnail(image: &RgbaImage, max: u32) -> RgbaImage {
+    let scale = (max as f32 / image.width().max(image.height()) as f32).min(1.0);
+    let width = ((image.width() as f32 * scale) as u32).max(1);
+    let height = ((image.height() as f32 * scale) as u32).max(1);
+    image::imageops::thumbnail(image, width, height)
+}
+
+/// 8x8 checkerboard (4px cells) meant to be drawn with repeating UVs behind transparent images.
+pub fn load_checkerboard_texture(ctx: &Context) -> TextureHandle {
+    let checker = RgbaImage::from_fn(8, 8, |x, y| {
+        if (x / 4 + y / 4) % 2 == 0 {
+            image::Rgba([204, 204, 204, 255])
+        } else {
+            image::Rgba([153, 153, 153, 255])
+        }
+    });
+
+    let color_image = egui::ColorImage::from_rgba_unmultiplied([8, 8], checker.as_flat_samples().as_slice());
+    ctx.load_texture("checkerboard", color_image, TextureOptions::NEAREST_REPEAT)
+}
+
 /// Helper to load an icon from a file path or bytes.
 /// Hint: Use `image::open` or `image::load_from_memory`.
 /// Key Step: You must convert the image to RGBA8 (4 bytes per pixel).
 pub fn load_tray_icon() -> Icon {
+    icon_from_image(tray_logo())
+}
+
+/// The tray logo with a red dot in the corner, shown for a few seconds after a failed capture.
+pub fn load_tray_error_icon() -> Icon {
+    icon_from_image(tray_logo_with_badge(image::Rgba([220, 38, 38, 255])))
+}
+
+/// The tray logo with a blue dot in the corner, shown while an interval capture runs.
+pub fn load_tray_interval_icon() -> Icon {
+    icon_from_image(tray_logo_with_badge(image::Rgba([37, 99, 235, 255])))
+}
+
+fn tray_logo_with_badge(color: image::Rgba<u8>) -> RgbaImage {
+    let mut img = tray_logo();
+    let (width, height) = img.dimensions();
+    let radius = width.min(height) as f32 * 0.22;
+    let center = (width as f32 - radius - 1.0, height as f32 - radius - 1.0);
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        let distance = ((x as f32 + 0.5 - center.0).powi(2) + (y as f32 + 0.5 - center.1).powi(2)).sqrt();
+        if distance <= radius {
+            // A thin white ring keeps the dot visible on dark and colored taskbars
+            *pixel = if distance > radius * 0.8 { image::Rgba([255, 255, 255, 255]) } else { color };
+        }
+    }
+    img
+}
+
+fn tray_logo() -> RgbaImage {
     // 1. Load image (e.g., "assets/icon.png" or a generic one for now)
     let logo = include_bytes!("assets/logo.png");
     // 2. Get width, height, and raw rgba vectors.
     let img = image::load_from_memory(logo).expect("Failed to load icon image");
-    let rgba_img = img.to_rgba8();
-    let (width, height) = rgba_img.dimensions();
-    let rgba = rgba_img.into_raw();
-    // 3. Return Icon::from_rgba(rgba, width, height).unwrap()
-    Icon::from_rgba(rgba, width, height).unwrap()
+    img.to_rgba8()
+}
+
+fn icon_from_image(img: RgbaImage) -> Icon {
+    let (width, height) = img.dimensions();
+    Icon::from_rgba(img.into_raw(), width, height).unwrap()
 }
 
 pub fn format_hotkey(hotkey: &HotKey) -> String {
-    let mut text = String::new();
-    let mods = hotkey.mods;
+    let mut text = format_modifiers(hotkey.mods);
+    // The key is a physical position, shown as what it types on the active layout
+    text.push_str(&crate::keymap::key_label(hotkey.key, &crate::keymap::active_layout()));
+    text
+}
+
+/// Whether transparent windows actually show what is behind them. Without a compositor (some
+/// X11 window managers) they come out black. Checked once, the answer is cached.
+pub fn window_transparency_supported() -> bool {
+    static SUPPORTED: std::sync::OnceLock<bool> = std::sync::OnceLock::new();
+    *SUPPORTED.get_or_init(|| {
+        let supported = detect_window_transparency();
+        log::info!("Transparent windows supported: {}", supported);
+        supported
+    })
+}
+
+/// Windows (DWM) and macOS always composite.
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+fn detect_window_transparency() -> bool {
+    true
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn detect_window_transparency() -> bool {
+    use x11_dl::xlib::Xlib;
+
+    // Wayland compositors always composite
+    if env::var_os("WAYLAND_DISPLAY").is_some() {
+        return true;
+    }
+    let Ok(xlib) = Xlib::open() else {
+        return true;
+    };
+    unsafe {
+        let display = (xlib.XOpenDisplay)(std::ptr::null());
+        if display.is_null() {
+            return true;
+        }
+        // A running compositor owns the _NET_WM_CM_S<screen> selection (EWMH)
+        let screen = (xlib.XDefaultScreen)(display);
+        let name = std::ffi::CString::new(format!("_NET_WM_CM_S{}", screen)).expect("no NUL in the atom name");
+        let atom = (xlib.XInternAtom)(display, name.as_ptr(), 0);
+        let owner = (xlib.XGetSelectionOwner)(display, atom);
+        (xlib.XCloseDisplay)(display);
+        owner != 0
+    }
+}
+
+/// Where the mouse pointer is (physical desktop px), also while the window is hidden. None
+/// where it can't be read (macOS, Wayland without XWayland).
+#[cfg(target_os = "windows")]
+pub fn cursor_position() -> Option<(i32, i32)> {
+    use windows::Win32::Foundation::POINT;
+    use windows::Win32::UI::WindowsAndMessaging::GetCursorPos;
+
+    let mut point = POINT::default();
+    unsafe { GetCursorPos(&mut point) }.ok()?;
+    Some((point.x, point.y))
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn cursor_position() -> Option<(i32, i32)> {
+    use x11_dl::xlib::Xlib;
+
+    let xlib = Xlib::open().ok()?;
+    unsafe {
+        let display = (xlib.XOpenDisplay)(std::ptr::null());
+        if display.is_null() {
+            return None;
+        }
+        let root = (xlib.XDefaultRootWindow)(display);
+        let (mut root_return, mut child_return) = (0, 0);
+        let (mut x, mut y, mut window_x, mut window_y) = (0, 0, 0, 0);
+        let mut mask = 0;
+        let on_screen = (xlib.XQueryPointer)(
+            display, root, &mut root_return, &mut child_return, &mut x, &mut y, &mut window_x, &mut window_y, &mut mask,
+        );
+        (xlib.XCloseDisplay)(display);
+        (on_screen != 0).then_some((x, y))
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub fn cursor_position() -> Option<(i32, i32)> {
+    None
+}
+
+/// Takes the window off the screen and out of the taskbar. Minimizing first makes Windows
+/// drop it from the Alt+Tab order and forget its foreground state.
+#[cfg(target_os = "windows")]
+pub fn hide_window_to_tray(hwnd: isize) {
+    use windows::Win32::Foundation::HWND;
+    use windows::Win32::UI::WindowsAndMessaging::{ShowWindow, SW_HIDE, SW_MINIMIZE};
+
+    let hwnd = HWND(hwnd as *mut _);
+    unsafe {
+        let _ = ShowWindow(hwnd, SW_MINIMIZE);
+        let _ = ShowWindow(hwnd, SW_HIDE);
+    }
+}
+
+/// Undoes `hide_window_to_tray`. Must run before the window is positioned and shown again,
+/// otherwise showing it brings it back minimized.
+#[cfg(target_os = "windows")]
+pub fn show_window_from_tray(hwnd: isize) {
+    use windows::Win32::Foundation::HWND;
+    use windows::Win32::UI::WindowsAndMessaging::{ShowWindow, SW_RESTORE};
+
+    unsafe {
+        let _ = ShowWindow(HWND(hwnd as *mut _), SW_RESTORE);
+    }
+}
+
+/// The virtual desktop the user is on, i.e. the one of the foreground window. None when there
+/// is no foreground window or it isn't on a desktop (taskbar, desktop background).
+#[cfg(target_os = "windows")]
+pub fn current_virtual_desktop() -> Option<windows::core::GUID> {
+    use windows::core::GUID;
+    use windows::Win32::UI::WindowsAndMessaging::GetForegroundWindow;
+
+    let manager = virtual_desktop_manager()?;
+    unsafe {
+        let foreground = GetForegroundWindow();
+        if foreground.is_invalid() {
+            return None;
+        }
+        manager.GetWindowDesktopId(foreground).ok().filter(|id| *id != GUID::zeroed())
+    }
+}
+
+/// Moves our (hidden) window to the virtual desktop the user is on, otherwise showing it
+/// switches back to the desktop it was last shown on.
+#[cfg(target_os = "windows")]
+pub fn move_to_current_virtual_desktop(hwnd: isize) {
+    use windows::Win32::Foundation::HWND;
+
+    let hwnd = HWND(hwnd as *mut _);
+    let (Some(manager), Some(desktop)) = (virtual_desktop_manager(), current_virtual_desktop()) else {
+        return;
+    };
+    unsafe {
+        if manager.IsWindowOnCurrentVirtualDesktop(hwnd).is_ok_and(|on_it| on_it.as_bool()) {
+            return;
+        }
+        match manager.MoveWindowToDesktop(hwnd, &desktop) {
+            Ok(()) => log::debug!("Moved the window to virtual desktop {:?}", desktop),
+            Err(e) => log::warn!("Could not move the window to the current virtual desktop: {}", e),
+        }
+    }
+}
+
+/// Logs the virtual desktop a capture is taken on, to check reports of captures showing
+/// another desktop against. Only known on Windows.
+pub fn log_virtual_desktop(capture: &str) {
+    #[cfg(target_os = "windows")]
+    match current_virtual_desktop() {
+        Some(desktop) => log::info!("{} capture on virtual desktop {:?}.", capture, desktop),
+        None => log::info!("{} capture on an unknown virtual desktop.", capture),
+    }
+    #[cfg(not(target_os = "windows"))]
+    let _ = capture;
+}
 
+/// COM for threads we start ourselves, released again on drop. Keep it alive longer than any
+/// COM object of that thread. The UI thread has COM from winit.
+#[cfg(target_os = "windows")]
+pub struct ComGuard;
+
+#[cfg(target_os = "windows")]
+impl ComGuard {
+    pub fn init() -> Option<Self> {
+        use windows::Win32::System::Com::{CoInitializeEx, COINIT_MULTITHREADED};
+        unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) }.is_ok().then_some(ComGuard)
+    }
+}
+
+#[cfg(target_os = "windows")]
+impl Drop for ComGuard {
+    fn drop(&mut self) {
+        unsafe { windows::Win32::System::Com::CoUninitialize() };
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn virtual_desktop_manager() -> Option<windows::Win32::UI::Shell::IVirtualDesktopManager> {
+    use windows::Win32::System::Com::{CoCreateInstance, CLSCTX_ALL};
+    use windows::Win32::UI::Shell::VirtualDesktopManager;
+
+    // COM is already initialized on the UI thread (by winit)
+    unsafe { CoCreateInstance(&VirtualDesktopManager, None, CLSCTX_ALL) }
+        .inspect_err(|e| log::warn!("Virtual desktop manager unavailable: {}", e))
+        .ok()
+}
+
+/// Modifier prefix of a hotkey, e.g. "Ctrl + Shift + ". META is the Command key on macOS.
+pub fn format_modifiers(mods: Modifiers) -> String {
+    let mut text = String::new();
     if mods.contains(Modifiers::CONTROL) { text.push_str("Ctrl + "); }
     if mods.contains(Modifiers::SHIFT)   { text.push_str("Shift + "); }
     if mods.contains(Modifiers::ALT)     { text.push_str("Alt + "); }
-    if mods.contains(Modifiers::META)    { text.push_str("Win + "); }
+    if mods.contains(Modifiers::META)    { text.push_str(if cfg!(target_os = "macos") { "Cmd + " } else { "Win + " }); }
+    text
+}
 
-    // Clean up the Code string (e.g. "KeyG" -> "G")
-    let key_str = format!("{:?}", hotkey.key);
-    let clean_key = key_str.strip_prefix("Key").unwrap_or(&key_str);
+/// Longest save path written as is. MAX_PATH is 260 including the terminator; keep some margin.
+const MAX_SAFE_PATH_LEN: usize = 250;
 
-    text.push_str(clean_key);
-    text
+/// How saved PNGs are encoded.
+#[derive(Clone, Copy)]
+pub struct PngOptions<'a> {
+    pub filter: PngFilter,
+    /// Written as an iCCP chunk, see `color::prepare_capture`.
+    pub icc_profile: Option<&'a [u8]>,
 }
 
-pub fn save_image_to_disk(image: &RgbaImage, dir_path: &str) {
+/// Saves the image as a timestamped PNG and returns the final path on success.
+pub fn save_image_to_disk(
+    image: &RgbaImage,
+    dir_path: &str,
+    note: Option<&str>,
+    long_paths: LongPathStrategy,
+    collision: CollisionPolicy,
+    png: &PngOptions,
+) -> Option<PathBuf> {
     let time_now = chrono::Local::now();
-    let timestamp = time_now.format("%Y-%m-%d_%H-%M-%S").to_string();
-    let path = Path::new(dir_path).join(format!("screenshot_{}.png", timestamp));
-    log::info!("Saving image to: {}", dir_path);
-    if let Err(e) = std::fs::create_dir_all(dir_path) {
-        log::error!("Failed to create directory {}: {}", dir_path, e);
+    let timestamp = match collision {
+        CollisionPolicy::Timestamp => time_now.format("%Y-%m-%d_%H-%M-%S_%6f"),
+        CollisionPolicy::Overwrite | CollisionPolicy::Increment => time_now.format("%Y-%m-%d_%H-%M-%S"),
+    }.to_string();
+    let path = match collision {
+        CollisionPolicy::Increment => free_save_path(dir_path, "screenshot_", &timestamp, long_paths)?,
+        CollisionPolicy::Overwrite | CollisionPolicy::Timestamp => save_path_for(dir_path, "screenshot_", &timestamp, long_paths),
+    };
+    save_image_as(image, &path, note, png)
+}
+
+/// Saves one shot of an interval capture as `interval_<session>_<index>.png`. The own prefix
+/// keeps a time-lapse out of the "keep only the newest" retention.
+pub fn save_interval_image(image: &RgbaImage, dir_path: &str, session: &str, index: u32, long_paths: LongPathStrategy, png_filter: PngFilter) -> Option<PathBuf> {
+    let png = PngOptions { filter: png_filter, icc_profile: None };
+    let path = save_path_for(dir_path, "interval_", &format!("{}_{:04}", session, index), long_paths);
+    save_image_as(image, &path, None, &png)
+}
+
+/// Saves a montage as `montage_<session>.png`, replacing the previous save of the session.
+pub fn save_montage_image(image: &RgbaImage, dir_path: &str, session: &str, long_paths: LongPathStrategy, png_filter: PngFilter) -> Option<PathBuf> {
+    let png = PngOptions { filter: png_filter, icc_profile: None };
+    save_image_as(image, &save_path_for(dir_path, "montage_", session, long_paths), None, &png)
+}
+
+fn save_image_as(image: &RgbaImage, path: &Path, note: Option<&str>, png: &PngOptions) -> Option<PathBuf> {
+    log::debug!("Saving image to: {:?}", path);
+    if let Some(dir) = path.parent()
+        && let Err(e) = std::fs::create_dir_all(dir)
+    {
+        log::error!("Failed to create directory {:?}: {}", dir, e);
+        return None;
+    }
+    // Written under a temporary name first, an interrupted write never looks like a screenshot
+    let temp_path = path.with_extension("part");
+    let result = match note.filter(|n| !n.is_empty()) {
+        Some(note) => write_png_with_note(image, &temp_path, note, png),
+        None => write_png(image, &temp_path, png),
+    }.and_then(|_| std::fs::rename(&temp_path, path).map_err(Into::into));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    match result {
+        Ok(_) => {
+            log::info!("Image saved successfully to {:?}", path);
+            Some(path.to_path_buf())
+        }
+        Err(e) => {
+            log::error!("Failed to save image to {:?}: {}", path, e);
+            None
+        }
+    }
+}
+
+/// Attempts at a free name before `CollisionPolicy::Increment` gives up.
+const MAX_NAME_INCREMENTS: u32 = 100;
+
+/// The save path, with `_1`, `_2`, ... added to the timestamp while the name is taken.
+fn free_save_path(dir_path: &str, prefix: &str, timestamp: &str, long_paths: LongPathStrategy) -> Option<PathBuf> {
+    let path = save_path_for(dir_path, prefix, timestamp, long_paths);
+    if !path.exists() {
+        return Some(path);
+    }
+    let free = (1..=MAX_NAME_INCREMENTS)
+        .map(|n| save_path_for(dir_path, prefix, &format!("{}_{}", timestamp, n), long_paths))
+        .find(|path| !path.exists());
+    if free.is_none() {
+        log::error!("No free file name for {:?} after {} attempts, capture not saved.", path, MAX_NAME_INCREMENTS);
+    }
+    free
+}
+
+/// Builds `<dir>/<prefix><timestamp>.png`, keeping it within what Windows can open.
+fn save_path_for(dir_path: &str, prefix: &str, timestamp: &str, long_paths: LongPathStrategy) -> PathBuf {
+    const EXTENSION: &str = "png";
+    let dir = Path::new(dir_path);
+    if !cfg!(target_os = "windows") {
+        return dir.join(format!("{}{}.{}", prefix, timestamp, EXTENSION));
+    }
+
+    let dir = sanitize_directory(dir);
+    if dir.as_os_str() != dir_path {
+        log::warn!("Removed trailing dots/spaces from the save directory: {:?} -> {:?}", dir_path, dir);
+    }
+    let path = dir.join(format!("{}{}.{}", prefix, timestamp, EXTENSION));
+    if path_len(&path) <= MAX_SAFE_PATH_LEN {
+        return path;
+    }
+
+    if long_paths == LongPathStrategy::ShortenFileName {
+        match fit_file_name(&dir, prefix, timestamp, EXTENSION, MAX_SAFE_PATH_LEN) {
+            Some(name) => {
+                log::warn!("Save path is {} characters long, shortened the file name to {:?}", path_len(&path), name);
+                return dir.join(name);
+            }
+            None => log::warn!("Save directory alone is too long to shorten the file name, using an extended-length path"),
+        }
+    }
+    // The extended prefix only works on absolute paths
+    let absolute = std::path::absolute(&path).unwrap_or(path);
+    let extended = with_extended_prefix(&absolute);
+    log::warn!("Save path is {} characters long, writing to {:?}", path_len(&absolute), extended);
+    extended
+}
+
+/// Path length as Windows counts it (UTF-16 code units).
+fn path_len(path: &Path) -> usize {
+    path.to_string_lossy().encode_utf16().count()
+}
+
+/// Windows drops trailing dots and spaces from folder names, so `Shots.` would silently
+/// become `Shots` (or fail to open later). Trim them up front instead.
+fn sanitize_directory(dir: &Path) -> PathBuf {
+    dir.components()
+        .map(|component| match component {
+            Component::Normal(name) => {
+                let name = name.to_string_lossy();
+                let trimmed = name.trim_end_matches(['.', ' ']);
+                OsString::from(if trimmed.is_empty() { name.as_ref() } else { trimmed })
+            }
+            other => other.as_os_str().to_os_string(),
+        })
+        .collect()
+}
+
+/// File name `<prefix><suffix>.<extension>` that keeps `dir` joined with it within `max_len`.
+/// Only the prefix is cut, the suffix (timestamp) and extension keep the name unique and
+/// openable. None if even the bare suffix does not fit.
+fn fit_file_name(dir: &Path, prefix: &str, suffix: &str, extension: &str, max_len: usize) -> Option<String> {
+    let fixed = path_len(&dir.join(format!("{}.{}", suffix, extension)));
+    let mut budget = max_len.checked_sub(fixed)?;
+    let kept: String = prefix.chars()
+        .take_while(|c| {
+            let fits = c.len_utf16() <= budget;
+            if fits {
+                budget -= c.len_utf16();
+            }
+            fits
+        })
+        .collect();
+    Some(format!("{}{}.{}", kept, suffix, extension))
+}
+
+/// `C:\dir\file` -> `\\?\C:\dir\file`, `\\server\share` -> `\\?\UNC\server\share`.
+fn with_extended_prefix(path: &Path) -> PathBuf {
+    // No normalization happens behind the prefix, so forward slashes must go
+    let text = path.to_string_lossy().replace('/', "\\");
+    if text.starts_with(r"\\?\") {
+        PathBuf::from(text)
+    } else if let Some(unc) = text.strip_prefix(r"\\") {
+        PathBuf::from(format!(r"\\?\UNC\{}", unc))
+    } else {
+        PathBuf::from(format!(r"\\?\{}", text))
+    }
+}
+
+/// Where captures for "Capture & Edit" are written; emptied on every start.
+fn external_edit_dir() -> PathBuf {
+    env::temp_dir().join("crab-grab-edit")
+}
+
+pub fn clean_external_edit_dir(secure: bool) {
+    let dir = external_edit_dir();
+    if secure && let Ok(entries) = std::fs::read_dir(&dir) {
+        for path in entries.filter_map(Result::ok).map(|entry| entry.path()).filter(|path| path.is_file()) {
+            if let Err(e) = secure_delete(&path) {
+                log::warn!("Failed to securely delete {:?}: {}", path, e);
+            }
+        }
+    }
+    match std::fs::remove_dir_all(&dir) {
+        Ok(()) => log::debug!("Removed old external edit files in {:?}", dir),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => log::warn!("Failed to clean up {:?}: {}", dir, e),
+    }
+}
+
+/// Overwrites the file with zeros before removing it, so the screen content it held can't be
+/// recovered from the free disk space.
+pub fn secure_delete(path: &Path) -> std::io::Result<()> {
+    use std::io::{Seek, SeekFrom, Write};
+
+    let zeros = [0u8; 4096];
+    let mut file = std::fs::OpenOptions::new().write(true).open(path)?;
+    let mut remaining = file.metadata()?.len();
+    file.seek(SeekFrom::Start(0))?;
+    while remaining > 0 {
+        let chunk = remaining.min(zeros.len() as u64) as usize;
+        file.write_all(&zeros[..chunk])?;
+        remaining -= chunk as u64;
+    }
+    file.flush()?;
+    // Make sure the zeros reach the disk before the file is gone
+    file.sync_all()?;
+    drop(file);
+    std::fs::remove_file(path)
+}
+
+/// Removes a temp file that held a capture, overwriting it first if configured.
+pub fn remove_temp_file(path: &Path, secure: bool) -> std::io::Result<()> {
+    if secure { secure_delete(path) } else { std::fs::remove_file(path) }
+}
+
+/// Writes the capture to a temp file and opens it in `editor`, or the system default app
+/// for PNGs when None. Blocking file IO, call it from a background thread.
+pub fn open_in_external_editor(image: &RgbaImage, editor: Option<&str>) -> Result<PathBuf, Box<dyn Error>> {
+    let dir = external_edit_dir();
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("capture_{}.png", chrono::Local::now().format("%Y-%m-%d_%H-%M-%S_%3f")));
+    image.save(&path)?;
+
+    let mut command = match editor {
+        Some(editor) => std::process::Command::new(editor),
+        // explorer avoids the console window `cmd /C start` would flash
+        None if cfg!(target_os = "windows") => std::process::Command::new("explorer"),
+        None if cfg!(target_os = "macos") => std::process::Command::new("open"),
+        None => std::process::Command::new("xdg-open"),
+    };
+    command.arg(&path).spawn()
+        .map_err(|e| format!("Could not start {}: {}", editor.unwrap_or("the default app"), e))?;
+    Ok(path)
+}
+
+/// `sh -c <command>`, or `cmd.exe /C <command>` on Windows (without flashing a console window).
+pub fn shell_command(command: &str) -> std::process::Command {
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::process::CommandExt;
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+        let mut shell = std::process::Command::new("cmd.exe");
+        shell.arg("/C").arg(command).creation_flags(CREATE_NO_WINDOW);
+        shell
+    }
+    #[cfg(not(target_os = "windows"))]
+    {
+        let mut shell = std::process::Command::new("sh");
+        shell.arg("-c").arg(command);
+        shell
+    }
+}
+
+/// Quotes `arg` as a single argument for `shell_command`. Windows paths can't contain `"`.
+pub fn shell_quote(arg: &str) -> String {
+    if cfg!(target_os = "windows") {
+        format!("\"{}\"", arg)
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+/// Runs a user command and waits at most `timeout` for it. One that takes longer is left
+/// running in the background.
+pub fn run_command_with_timeout(command: &str, timeout: Duration) {
+    let started = Instant::now();
+    let mut child = match shell_command(command).spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            log::error!("Failed to run {:?}: {}", command, e);
+            return;
+        }
+    };
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                if !status.success() {
+                    log::warn!("{:?} exited with {}", command, status);
+                }
+                log::debug!("{:?} finished in {:?}", command, started.elapsed());
+                return;
+            }
+            Ok(None) if started.elapsed() >= timeout => {
+                log::warn!("{:?} did not finish within {:?}, continuing without it.", command, timeout);
+                return;
+            }
+            Ok(None) => std::thread::sleep(Duration::from_millis(10)),
+            Err(e) => {
+                log::error!("Failed to wait for {:?}: {}", command, e);
+                return;
+            }
+        }
+    }
+}
+
+/// Deletes the oldest saved captures (by modification time) so that at most `max_files`
+/// remain in `dir_path`. Only files following our `screenshot_*.png` naming are touched.
+pub fn prune_old_captures(dir_path: &str, max_files: usize, to_trash: bool) {
+    let entries = match std::fs::read_dir(dir_path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            log::error!("Failed to read {} for cleanup: {}", dir_path, e);
+            return;
+        }
+    };
+
+    let mut captures: Vec<(std::time::SystemTime, PathBuf)> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            name.starts_with("screenshot_") && name.ends_with(".png")
+        })
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            metadata.is_file().then_some((metadata.modified().ok()?, entry.path()))
+        })
+        .collect();
+
+    if captures.len() <= max_files {
         return;
     }
-    match image.save(&path) {
-        Ok(_) => log::info!("Image saved successfully to {:?}", path),
-        Err(e) => log::error!("Failed to save image to {:?}: {}", path, e),
+
+    captures.sort_by_key(|(modified, _)| *modified);
+    let excess = captures.len() - max_files;
+
+    for (_, path) in captures.into_iter().take(excess) {
+        let result = if to_trash {
+            trash::delete(&path).map_err(|e| e.to_string())
+        } else {
+            std::fs::remove_file(&path).map_err(|e| e.to_string())
+        };
+        match result {
+            Ok(_) => log::info!("Deleted old capture {:?}{}", path, if to_trash { " (moved to trash)" } else { "" }),
+            Err(e) => log::error!("Failed to delete old capture {:?}: {}", path, e),
+        }
     }
 }
 
+/// Fills the `{x} {y} {w} {h}` placeholders of the coordinates format with a selection.
+pub fn format_coordinates(format: &str, (x, y, width, height): (i32, i32, u32, u32)) -> String {
+    format
+        .replace("{x}", &x.to_string())
+        .replace("{y}", &y.to_string())
+        .replace("{w}", &width.to_string())
+        .replace("{h}", &height.to_string())
+}
+
+/// Puts the capture on the clipboard using the configured targets.
+///
+/// The clipboard only holds one kind of content at a time (every arboard write replaces it),
+/// so the first applicable target wins: Image, then File, then text (Path and/or Base64).
+/// The coordinates are the exception, on Windows they go into the image's entry as text.
+/// `coordinates` is the formatted selection (see `format_coordinates`), None when the capture
+/// wasn't a selection. Returns false when writing to the clipboard failed.
+pub fn copy_to_clipboard(mut image: RgbaImage, saved_path: Option<&Path>, coordinates: Option<&str>, config: &AppConfig) -> bool {
+    let targets = &config.clipboard_targets;
+    let mut clipboard = match Clipboard::new() {
+        Ok(clipboard) => clipboard,
+        Err(e) => {
+            log::error!("Failed to open clipboard: {}", e);
+            return false;
+        }
+    };
+    // Read before our write replaces it
+    let previous = config.restore_clipboard_after_secs.and_then(|_| read_clipboard(&mut clipboard));
+
+    // Ok holds the fingerprint of what was written, None if it can't be told apart later
+    let result = if targets.contains(&ClipboardTarget::Image) {
+        if config.clipboard_premultiply_alpha {
+            premultiply_alpha(&mut image);
+        }
+
+        #[cfg(all(target_os = "windows", feature = "clipboard-metadata"))]
+        let title = config.clipboard_metadata.then(|| match saved_path.and_then(Path::file_name) {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => format!("screenshot_{}.png", chrono::Local::now().format("%Y-%m-%d_%H-%M-%S")),
+        });
+        #[cfg(not(all(target_os = "windows", feature = "clipboard-metadata")))]
+        let title: Option<String> = None;
+
+        let coordinates = coordinates.filter(|_| targets.contains(&ClipboardTarget::Coordinates));
+        let jpeg_quality = clipboard_jpeg_quality(config, image.as_raw().len());
+        set_clipboard_image(&mut clipboard, image, jpeg_quality, coordinates, title.as_deref())
+    } else if let (true, Some(path)) = (targets.contains(&ClipboardTarget::File), saved_path) {
+        clipboard.set().file_list(&[path]).map(|_| None)
+    } else {
+        let mut lines = Vec::new();
+        if targets.contains(&ClipboardTarget::Path) {
+            match saved_path {
+                Some(path) => lines.push(path.display().to_string()),
+                None => log::debug!("Clipboard target Path skipped: capture was not saved."),
+            }
+        }
+        if targets.contains(&ClipboardTarget::Base64) {
+            let mut png_bytes = Vec::new();
+            match image.write_to(&mut std::io::Cursor::new(&mut png_bytes), ImageFormat::Png) {
+                Ok(_) => lines.push(format!("data:image/png;base64,{}", BASE64_STANDARD.encode(&png_bytes))),
+                Err(e) => log::error!("Failed to encode PNG for clipboard: {}", e),
+            }
+        }
+        if targets.contains(&ClipboardTarget::Coordinates) {
+            match coordinates {
+                Some(coordinates) => lines.push(coordinates.to_string()),
+                None => log::debug!("Clipboard target Coordinates skipped: capture has no selection."),
+            }
+        }
+        if lines.is_empty() {
+            log::debug!("No applicable clipboard target, clipboard left untouched.");
+            return true;
+        }
+        let text = lines.join("\n");
+        let fingerprint = text_fingerprint(&text);
+        clipboard.set_text(text).map(|_| Some(fingerprint))
+    };
+
+    match result {
+        Ok(ours) => {
+            log::debug!("Copied to clipboard successfully.");
+            if let (Some(seconds), Some(previous)) = (config.restore_clipboard_after_secs, previous) {
+                schedule_clipboard_restore(previous, ours, Duration::from_secs(seconds as u64));
+            }
+            true
+        }
+        Err(e) => {
+            log::error!("Failed to copy to clipboard: {}", e);
+            false
+        }
+    }
+}
+
+/// JPEG quality for a clipboard image of `raw_len` bytes (RGBA), None = lossless.
+fn clipboard_jpeg_quality(config: &AppConfig, raw_len: usize) -> Option<u8> {
+    config.clipboard_compress_above_kb
+        .filter(|limit_kb| raw_len as u64 > *limit_kb as u64 * 1024)
+        .map(|_| config.clipboard_jpeg_quality.clamp(1, 100))
+}
+
+/// Clipboard content that can be put back after a capture replaced it.
+enum ClipboardContent {
+    Text(String),
+    Image(ImageData<'static>),
+}
+
+fn read_clipboard(clipboard: &mut Clipboard) -> Option<ClipboardContent> {
+    if let Ok(text) = clipboard.get_text() {
+        return Some(ClipboardContent::Text(text));
+    }
+    match clipboard.get_image() {
+        Ok(image) => Some(ClipboardContent::Image(image.to_owned_img())),
+        Err(_) => {
+            log::debug!("Clipboard holds neither text nor an image, nothing to restore later.");
+            None
+        }
+    }
+}
+
+/// Hash of what the clipboard holds right now, to notice later writes by someone else.
+fn clipboard_fingerprint(clipboard: &mut Clipboard) -> Option<u64> {
+    Some(match read_clipboard(clipboard)? {
+        ClipboardContent::Text(text) => text_fingerprint(&text),
+        ClipboardContent::Image(image) => image_fingerprint(image.width, image.height, &image.bytes),
+    })
+}
+
+fn text_fingerprint(text: &str) -> u64 {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Every this many pixels go into an image's fingerprint.
+const FINGERPRINT_PIXEL_STEP: usize = 97;
+
+/// An image's size and a sample of its pixels (RGBA bytes), hashing all of a 4K+ capture
+/// would take about as long as copying it.
+fn image_fingerprint(width: usize, height: usize, bytes: &[u8]) -> u64 {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    (width, height).hash(&mut hasher);
+    for pixel in bytes.chunks_exact(4).step_by(FINGERPRINT_PIXEL_STEP) {
+        pixel.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// Puts `previous` back after `delay`, unless the clipboard no longer holds our capture.
+fn schedule_clipboard_restore(previous: ClipboardContent, ours: Option<u64>, delay: Duration) {
+    std::thread::spawn(move || {
+        std::thread::sleep(delay);
+        let mut clipboard = match Clipboard::new() {
+            Ok(clipboard) => clipboard,
+            Err(e) => {
+                log::error!("Failed to open clipboard for the restore: {}", e);
+                return;
+            }
+        };
+        if ours.is_none() || clipboard_fingerprint(&mut clipboard) != ours {
+            log::debug!("Clipboard changed since the capture, not restoring the previous content.");
+            return;
+        }
+        let result = match previous {
+            ClipboardContent::Text(text) => clipboard.set_text(text),
+            ClipboardContent::Image(image) => clipboard.set_image(image),
+        };
+        match result {
+            Ok(_) => log::debug!("Restored the previous clipboard content."),
+            Err(e) => log::error!("Failed to restore the previous clipboard content: {}", e),
+        }
+    });
+}
+
+/// Writes the clipboard through Win32, for content arboard can only put there one format at a
+/// time: everything is set in one open/empty/set session, so clipboard managers see a single
+/// complete entry and nobody can write in between.
+#[cfg(target_os = "windows")]
+mod win_clipboard {
+    use std::error::Error;
+    use std::time::Duration;
+    use image::RgbaImage;
+    use windows::core::{w, PCWSTR};
+    use windows::Win32::Foundation::HANDLE;
+    use windows::Win32::System::DataExchange::{CloseClipboard, EmptyClipboard, OpenClipboard, RegisterClipboardFormatW, SetClipboardData};
+    use windows::Win32::System::Memory::{GlobalAlloc, GlobalFree, GlobalLock, GlobalUnlock, GMEM_MOVEABLE};
+
+    const CF_UNICODETEXT: u32 = 13;
+    const CF_DIBV5: u32 = 17;
+    const BITMAPV5HEADER_SIZE: usize = 124;
+
+    /// The image file that goes on the clipboard next to the bitmap.
+    pub enum Encoded<'a> {
+        Png(&'a [u8]),
+        /// Under "JFIF", the name Office and browsers read JPEG clipboard data from.
+        Jpeg(&'a [u8]),
+    }
+
+    /// Replaces the clipboard with `image` (as a bitmap and the `encoded` file), `text` and a
+    /// title for clipboard managers that list the formats of an entry. There is no standard
+    /// format for the title, so that part is best effort.
+    pub fn set_image(image: &RgbaImage, encoded: Encoded, text: Option<&str>, title: Option<&str>) -> Result<(), Box<dyn Error>> {
+        let (file_format, file) = match encoded {
+            Encoded::Png(png) => (register_format(w!("PNG"))?, png),
+            Encoded::Jpeg(jpeg) => (register_format(w!("JFIF"))?, jpeg),
+        };
+        let title_format = title.map(|_| register_format(w!("Crab Grab Title"))).transpose()?;
+
+        // Clipboard managers open the clipboard right after someone else wrote it, wait our turn
+        let mut opened = unsafe { OpenClipboard(None) };
+        for _ in 0..5 {
+            if opened.is_ok() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+            opened = unsafe { OpenClipboard(None) };
+        }
+        opened?;
+
+        // Emptying makes us the clipboard owner, only the owner may set data
+        let result = unsafe { EmptyClipboard() }.map_err(Box::<dyn Error>::from).and_then(|_| unsafe {
+            set_data(CF_DIBV5, &dib_v5(image))?;
+            set_data(file_format, file)?;
+            if let Some(text) = text {
+                set_data(CF_UNICODETEXT, &utf16_bytes(text))?;
+            }
+            if let (Some(format), Some(title)) = (title_format, title) {
+                set_data(format, &utf16_bytes(title))?;
+            }
+            Ok(())
+        });
+        let _ = unsafe { CloseClipboard() };
+        result
+    }
+
+    /// Null-terminated UTF-16, the clipboard's text encoding.
+    fn utf16_bytes(text: &str) -> Vec<u8> {
+        text.encode_utf16().chain(std::iter::once(0)).flat_map(|unit| unit.to_le_bytes()).collect()
+    }
+
+    fn register_format(name: PCWSTR) -> Result<u32, Box<dyn Error>> {
+        match unsafe { RegisterClipboardFormatW(name) } {
+            0 => Err(windows::core::Error::from_thread().into()),
+            format => Ok(format),
+        }
+    }
+
+    /// BITMAPV5HEADER followed by the pixels, bottom-up BGRA with an alpha mask (what
+    /// Windows itself puts on the clipboard for 32-bit images).
+    fn dib_v5(image: &RgbaImage) -> Vec<u8> {
+        let (width, height) = image.dimensions();
+        let pixels_len = width as usize * height as usize * 4;
+        let mut dib = Vec::with_capacity(BITMAPV5HEADER_SIZE + pixels_len);
+        let mut put = |value: u32| dib.extend_from_slice(&value.to_le_bytes());
+        put(BITMAPV5HEADER_SIZE as u32);
+        put(width);
+        put(height); // Positive: bottom-up rows
+        put(1 | (32 << 16)); // Planes, bits per pixel
+        put(3); // BI_BITFIELDS
+        put(pixels_len as u32);
+        put(0); put(0); // Resolution
+        put(0); put(0); // Palette
+        put(0x00FF_0000); put(0x0000_FF00); put(0x0000_00FF); put(0xFF00_0000); // R, G, B, A masks
+        put(0x7352_4742); // LCS_sRGB
+        for _ in 0..12 {
+            put(0); // Endpoints and gamma, unused with sRGB
+        }
+        put(4); // LCS_GM_IMAGES
+        put(0); put(0); put(0); // Profile data and size, reserved
+
+        for row in image.rows().rev() {
+            for pixel in row {
+                let [r, g, b, a] = pixel.0;
+                dib.extend_from_slice(&[b, g, r, a]);
+            }
+        }
+        dib
+    }
+
+    /// Copies `bytes` into global memory owned by the clipboard.
+    unsafe fn set_data(format: u32, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+        unsafe {
+            let memory = GlobalAlloc(GMEM_MOVEABLE, bytes.len())?;
+            let target = GlobalLock(memory) as *mut u8;
+            if target.is_null() {
+                let _ = GlobalFree(Some(memory));
+                return Err(windows::core::Error::from_thread().into());
+            }
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), target, bytes.len());
+            let _ = GlobalUnlock(memory);
+
+            // On success the clipboard owns the memory, otherwise it is still ours to free
+            if let Err(e) = SetClipboardData(format, Some(HANDLE(memory.0))) {
+                let _ = GlobalFree(Some(memory));
+                return Err(e.into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Puts `image` on the clipboard, as a JPEG with `jpeg_quality` (Windows). On Windows `text`
+/// goes into the same entry (pasting into a text field gets it) together with a title for
+/// clipboard managers, if given.
+/// Returns the fingerprint of the entry, as `clipboard_fingerprint` will see it.
+fn set_clipboard_image(clipboard: &mut Clipboard, image: RgbaImage, jpeg_quality: Option<u8>, text: Option<&str>, title: Option<&str>) -> Result<Option<u64>, arboard::Error> {
+    let raw_kb = image.as_raw().len() / 1024;
+
+    // arboard writes a single kind of content per entry, so these entries are written by hand
+    #[cfg(target_os = "windows")]
+    if let Some(quality) = jpeg_quality {
+        match encode_clipboard_jpeg(&image, quality) {
+            Ok((jpeg, decoded)) => {
+                log::info!("Clipboard: JPEG at quality {}, {} KB instead of {} KB raw.", quality, jpeg.len() / 1024, raw_kb);
+                // The bitmap shows what the JPEG does, whichever of the two is pasted
+                let fingerprint = match text {
+                    Some(text) => text_fingerprint(text),
+                    None => image_fingerprint(decoded.width() as usize, decoded.height() as usize, decoded.as_raw()),
+                };
+                return win_clipboard::set_image(&decoded, win_clipboard::Encoded::Jpeg(&jpeg), text, title)
+                    .map(|_| Some(fingerprint))
+                    .map_err(|e| arboard::Error::Unknown { description: e.to_string() });
+            }
+            Err(e) => log::error!("Failed to compress the clipboard image, using the lossless one: {}", e),
+        }
+    }
+    log::info!("Clipboard: lossless image, {} KB raw.", raw_kb);
+
+    #[cfg(target_os = "windows")]
+    if text.is_some() || title.is_some() {
+        let mut png = Vec::new();
+        PngEncoder::new(&mut png)
+            .write_image(image.as_raw(), image.width(), image.height(), image::ExtendedColorType::Rgba8)
+            .map_err(|e| arboard::Error::Unknown { description: format!("PNG encoding failed: {}", e) })?;
+        // Reading the clipboard back finds the text first
+        let fingerprint = match text {
+            Some(text) => text_fingerprint(text),
+            None => image_fingerprint(image.width() as usize, image.height() as usize, image.as_raw()),
+        };
+        return win_clipboard::set_image(&image, win_clipboard::Encoded::Png(&png), text, title)
+            .map(|_| Some(fingerprint))
+            .map_err(|e| arboard::Error::Unknown { description: e.to_string() });
+    }
+    #[cfg(not(target_os = "windows"))]
+    {
+        let _ = title;
+        if text.is_some() {
+            log::debug!("Clipboard target Coordinates skipped: it can't share the entry with the image here.");
+        }
+        if jpeg_quality.is_some() {
+            log::debug!("Clipboard: JPEG compression is only available on Windows.");
+        }
+    }
+
+    // Converting to raw bytes takes a little time too, but we are on a worker thread.
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let fingerprint = image_fingerprint(width, height, image.as_raw());
+    clipboard.set_image(ImageData {
+        width,
+        height,
+        bytes: Cow::Owned(image.into_raw()),
+    }).map(|_| Some(fingerprint))
+}
+
+/// The image as a JPEG, and the pixels that JPEG decodes to. JPEG has no alpha channel.
+#[cfg(target_os = "windows")]
+fn encode_clipboard_jpeg(image: &RgbaImage, quality: u8) -> Result<(Vec<u8>, RgbaImage), Box<dyn Error>> {
+    let mut jpeg = Vec::new();
+    DynamicImage::ImageRgb8(image.to_rgb8()).write_with_encoder(JpegEncoder::new_with_quality(&mut jpeg, quality))?;
+    let decoded = image::load_from_memory_with_format(&jpeg, ImageFormat::Jpeg)?.to_rgba8();
+    Ok((jpeg, decoded))
+}
+
+/// Scales the color channels by alpha, for paste targets that expect premultiplied bitmaps.
+fn premultiply_alpha(image: &mut RgbaImage) {
+    for pixel in image.pixels_mut() {
+        let alpha = pixel[3] as u16;
+        if alpha == 255 {
+            continue;
+        }
+        for channel in &mut pixel.0[..3] {
+            *channel = ((*channel as u16 * alpha + 127) / 255) as u8;
+        }
+    }
+}
+
+/// Writes `image` in the given format. `quality` (1-100) applies to the lossy encoders
+/// (JPEG, AVIF); WebP is always written lossless.
+pub fn encode_image_to_file(image: &DynamicImage, path: &Path, format: ImageFormat, quality: u8) -> Result<(), Box<dyn Error>> {
+    let quality = quality.clamp(1, 100);
+    match format {
+        ImageFormat::Jpeg => {
+            // JPEG has no alpha channel
+            let writer = BufWriter::new(std::fs::File::create(path)?);
+            DynamicImage::ImageRgb8(image.to_rgb8()).write_with_encoder(JpegEncoder::new_with_quality(writer, quality))?;
+        }
+        ImageFormat::Avif => {
+            let writer = BufWriter::new(std::fs::File::create(path)?);
+            image.write_with_encoder(AvifEncoder::new_with_speed_quality(writer, 6, quality))?;
+        }
+        _ => image.save_with_format(path, format)?,
+    }
+    Ok(())
+}
+
+fn write_png(image: &RgbaImage, path: &Path, png: &PngOptions) -> Result<(), Box<dyn Error>> {
+    let filter = match png.filter {
+        PngFilter::None => image::codecs::png::FilterType::NoFilter,
+        PngFilter::Sub => image::codecs::png::FilterType::Sub,
+        PngFilter::Up => image::codecs::png::FilterType::Up,
+        PngFilter::Average => image::codecs::png::FilterType::Avg,
+        PngFilter::Paeth => image::codecs::png::FilterType::Paeth,
+        PngFilter::Adaptive => image::codecs::png::FilterType::Adaptive,
+    };
+    let mut writer = BufWriter::new(std::fs::File::create(path)?);
+    let mut encoder = PngEncoder::new_with_quality(&mut writer, CompressionType::default(), filter);
+    if let Some(profile) = png.icc_profile {
+        encoder.set_icc_profile(profile.to_vec())?;
+    }
+    image.write_with_encoder(encoder)?;
+    // Dropping the writer would swallow a failed flush (e.g. a full disk)
+    writer.into_inner()?.sync_all()?;
+    Ok(())
+}
+
+/// Writes a PNG with the user's note embedded as a "Comment" text chunk.
+/// Uses tEXt when the note fits Latin-1 (as the spec requires), iTXt otherwise.
+fn write_png_with_note(image: &RgbaImage, path: &Path, note: &str, options: &PngOptions) -> Result<(), Box<dyn Error>> {
+    let file = std::fs::File::create(path)?;
+    let mut info = png::Info::with_size(image.width(), image.height());
+    info.icc_profile = options.icc_profile.map(std::borrow::Cow::Borrowed);
+    let mut buffered = BufWriter::new(&file);
+    let mut encoder = png::Encoder::with_info(&mut buffered, info)?;
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_filter(match options.filter {
+        PngFilter::None => png::Filter::NoFilter,
+        PngFilter::Sub => png::Filter::Sub,
+        PngFilter::Up => png::Filter::Up,
+        PngFilter::Average => png::Filter::Avg,
+        PngFilter::Paeth => png::Filter::Paeth,
+        PngFilter::Adaptive => png::Filter::Adaptive,
+    });
+
+    if note.chars().all(|c| (c as u32) <= 0xFF) {
+        encoder.add_text_chunk("Comment".to_string(), note.to_string())?;
+    } else {
+        encoder.add_itxt_chunk("Comment".to_string(), note.to_string())?;
+    }
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(image.as_raw())?;
+    writer.finish()?;
+    buffered.flush()?;
+    file.sync_all()?;
+    Ok(())
+}
+
 pub fn draw_custom_cursor(ui: &mut egui::Ui, texture: &egui::TextureHandle) {
     let pointer_pos = match ui.input(|i| i.pointer.latest_pos()) {
         Some(pos) => pos,
@@ -180,6 +1321,88 @@ pub fn draw_custom_cursor(ui: &mut egui::Ui, texture: &egui::TextureHandle) {
     );
 }
 
+/// Draws an animated dashed border around `rect`. The dash offset is derived from `time`,
+/// so the caller only needs to keep requesting repaints while the animation should run.
+pub fn draw_marching_ants(painter: &egui::Painter, rect: egui::Rect, time: f64) {
+    const DASH_LENGTH: f32 = 6.0;
+    const ANTS_SPEED: f64 = 24.0; // Points per second
+
+    let path = [
+        rect.left_top(),
+        rect.right_top(),
+        rect.right_bottom(),
+        rect.left_bottom(),
+        rect.left_top(),
+    ];
+    let offset = ((time * ANTS_SPEED) % (DASH_LENGTH as f64 * 2.0)) as f32;
+
+    // Solid black base so the white dashes read on any background
+    painter.add(egui::Shape::line(path.to_vec(), egui::Stroke::new(1.0, egui::Color32::BLACK)));
+    painter.extend(egui::Shape::dashed_line_with_offset(
+        &path,
+        egui::Stroke::new(1.0, egui::Color32::WHITE),
+        &[DASH_LENGTH],
+        &[DASH_LENGTH],
+        offset,
+    ));
+}
+
+/// Draws the composition lines of `guide` inside `rect`.
+pub fn draw_alignment_guide(painter: &egui::Painter, rect: egui::Rect, guide: AlignmentGuide, opacity: u8) {
+    const GOLDEN: f32 = 0.618;
+    let stroke = egui::Stroke::new(1.0, egui::Color32::from_white_alpha(opacity));
+    let fractions: &[f32] = match guide {
+        AlignmentGuide::None => return,
+        AlignmentGuide::RuleOfThirds => &[1.0 / 3.0, 2.0 / 3.0],
+        AlignmentGuide::GoldenRatio => &[1.0 - GOLDEN, GOLDEN],
+        AlignmentGuide::DiagonalMethod => {
+            painter.line_segment([rect.left_top(), rect.right_bottom()], stroke);
+            painter.line_segment([rect.right_top(), rect.left_bottom()], stroke);
+            return;
+        }
+    };
+    for fraction in fractions {
+        painter.vline(rect.left() + rect.width() * fraction, rect.y_range(), stroke);
+        painter.hline(rect.x_range(), rect.top() + rect.height() * fraction, stroke);
+    }
+}
+
+/// Draws camera-style corner brackets around `rect` and a dot in its center.
+pub fn draw_viewfinder(painter: &egui::Painter, rect: egui::Rect) {
+    if rect.width() <= 0.0 || rect.height() <= 0.0 {
+        return;
+    }
+    let arm = (rect.width().min(rect.height()) * 0.08).clamp(12.0, 64.0);
+    let stroke = egui::Stroke::new(2.0, egui::Color32::from_white_alpha(180));
+
+    let corners = [
+        (rect.left_top(), egui::vec2(1.0, 1.0)),
+        (rect.right_top(), egui::vec2(-1.0, 1.0)),
+        (rect.right_bottom(), egui::vec2(-1.0, -1.0)),
+        (rect.left_bottom(), egui::vec2(1.0, -1.0)),
+    ];
+    for (corner, inward) in corners {
+        painter.line_segment([corner, corner + egui::vec2(arm * inward.x, 0.0)], stroke);
+        painter.line_segment([corner, corner + egui::vec2(0.0, arm * inward.y)], stroke);
+    }
+
+    // Dark ring so the dot shows on white content too
+    painter.circle(rect.center(), 3.0, egui::Color32::from_white_alpha(200), egui::Stroke::new(1.0, egui::Color32::from_black_alpha(160)));
+}
+
+/// Non-blocking error message box (the overlay window is usually hidden when this is needed).
+pub fn show_error_dialog(title: &str, message: &str) {
+    let title = title.to_string();
+    let message = message.to_string();
+    std::thread::spawn(move || {
+        rfd::MessageDialog::new()
+            .set_level(rfd::MessageLevel::Error)
+            .set_title(title)
+            .set_description(message)
+            .show();
+    });
+}
+
 pub fn set_autostart(enable: bool) {
     // Get the absolute path to the current executable
     if let Ok(current_exe) = env::current_exe() {
@@ -207,37 +1430,150 @@ pub fn set_autostart(enable: bool) {
     }
 }
 
-pub fn get_logging_config() -> Config {
-    let log_file_path = dirs::config_dir().unwrap().join("crab-grab").join("crab-grab.log");
+/// `crab-grab.log` in the app folder, None when the OS has no config directory.
+pub fn default_log_file_path() -> Option<PathBuf> {
+    config::app_dir().map(|dir| dir.join("crab-grab.log"))
+}
 
-    // Define a console appender
-    let stdout = ConsoleAppender::builder()
-        .encoder(Box::new(PatternEncoder::new("{d} - {l} - {m}\n")))
-        .build();
+/// Rolled log files are kept next to the log as `<log>.0` to `<log>.4`.
+const ROLLED_LOG_FILES: u32 = 5;
+
+/// Every file and folder the app wrote that still exists: the app folder (config, its backup,
+/// stats, default logs), a log moved elsewhere, "Capture & Edit" files and Lua script temp
+/// files. Captures in the save folder are the user's and not part of it.
+pub fn app_data_paths() -> Vec<PathBuf> {
+    let app_dir = config::app_dir();
+    let mut paths: Vec<PathBuf> = app_dir.iter().cloned().collect();
+
+    if let Some(log_file) = current_log_file_path()
+        && app_dir.as_ref().is_none_or(|dir| !log_file.starts_with(dir))
+    {
+        paths.push(log_file.clone());
+        paths.extend((0..ROLLED_LOG_FILES).map(|index| PathBuf::from(format!("{}.{}", log_file.display(), index))));
+    }
+
+    paths.push(external_edit_dir());
+    if let Ok(entries) = std::fs::read_dir(env::temp_dir()) {
+        paths.extend(entries.filter_map(Result::ok).map(|entry| entry.path()).filter(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            name.starts_with("crab-grab-") && name.ends_with(".png")
+        }));
+    }
+
+    paths.retain(|path| path.exists());
+    paths
+}
 
+/// Deletes the given files and folders. Returns what could not be deleted, for the user.
+pub fn remove_app_data(paths: &[PathBuf]) -> Vec<String> {
+    paths.iter().filter_map(|path| {
+        let result = if path.is_dir() { std::fs::remove_dir_all(path) } else { std::fs::remove_file(path) };
+        match result {
+            Ok(()) => {
+                log::info!("Deleted {:?}", path);
+                None
+            }
+            Err(e) => {
+                log::error!("Failed to delete {:?}: {}", path, e);
+                Some(format!("{}: {}", path.display(), e))
+            }
+        }
+    }).collect()
+}
+
+fn rolling_file_appender(log_file_path: &Path) -> Result<RollingFileAppender, Box<dyn Error>> {
+    // Rolled files go next to the log, not into the working directory
     let policy = CompoundPolicy::new(
         Box::new(SizeTrigger::new(10 * 1024 * 1024)),
         Box::new(FixedWindowRoller::builder()
-            .build("crab-grab.log.{}", 5)
-            .unwrap()),
+            .build(&format!("{}.{{}}", log_file_path.display()), ROLLED_LOG_FILES)?),
     );
 
-    let file = RollingFileAppender::builder()
+    Ok(RollingFileAppender::builder()
+        .encoder(Box::new(PatternEncoder::new("{d} - {l} - {m}\n")))
+        .build(log_file_path, Box::new(policy))?)
+}
+
+/// The log file this run writes to (a changed location only applies after a restart).
+/// None when there is no config directory to put it in.
+pub fn current_log_file_path() -> Option<PathBuf> {
+    LogConfig::load().log_file_path.map(PathBuf::from).or_else(default_log_file_path)
+}
+
+/// `message` and where the log is, for error dialogs.
+pub fn with_log_hint(message: &str) -> String {
+    match current_log_file_path() {
+        Some(path) => format!("{}\n\nSee the log for details:\n{}", message, path.display()),
+        None => format!("{}\n\nThe log is unavailable, there is no config folder to write it to.", message),
+    }
+}
+
+/// Turns a capture error into something a user can act on.
+pub fn describe_capture_error(error: &CaptureError) -> String {
+    match error {
+        CaptureError::NoMonitors => "No monitors were found to capture.".to_string(),
+        CaptureError::PermissionDenied if cfg!(target_os = "macos") => {
+            "Crab Grab is not allowed to record the screen. Allow it under System Settings > Privacy & Security > \
+             Screen Recording, then restart Crab Grab.".to_string()
+        }
+        CaptureError::PermissionDenied => {
+            "Crab Grab is not allowed to record the screen. Check the screen recording permissions.".to_string()
+        }
+        CaptureError::MonitorCaptureFailed(index) => format!(
+            "Monitor {} could not be captured. If it was just turned off, woke up or was unplugged, try again.",
+            index + 1
+        ),
+        CaptureError::RegionUnavailable(reason) => reason.clone(),
+        CaptureError::Backend(text) => format!("The screen could not be captured ({}).", text),
+    }
+}
+
+/// Opens the macOS Screen Recording settings, where Crab Grab has to be allowed.
+#[cfg(target_os = "macos")]
+pub fn open_screen_recording_settings() {
+    let pane = "x-apple.systempreferences:com.apple.preference.security?Privacy_ScreenCapture";
+    if let Err(e) = std::process::Command::new("open").arg(pane).spawn() {
+        log::warn!("Could not open the Screen Recording settings: {}", e);
+    }
+}
+
+/// The logging configuration, and warnings about it to log once the logger is up.
+pub fn get_logging_config() -> (Config, Vec<String>) {
+    let configured_path = LogConfig::load().log_file_path.map(PathBuf::from);
+    let mut warnings = Vec::new();
+
+    // Define a console appender
+    let stdout = ConsoleAppender::builder()
         .encoder(Box::new(PatternEncoder::new("{d} - {l} - {m}\n")))
-        .build(log_file_path, Box::new(policy))
-        .unwrap();
+        .build();
+
+    // A configured path that cannot be opened (removed drive, no permission) falls back to the
+    // default, and if that fails as well only the console is logged to
+    let file = configured_path.as_deref()
+        .and_then(|path| {
+            rolling_file_appender(path)
+                .inspect_err(|e| warnings.push(format!("Cannot log to {}: {}, using the default log file.", path.display(), e)))
+                .ok()
+        })
+        .or_else(|| {
+            let Some(path) = default_log_file_path() else {
+                warnings.push("No config folder for the log file, logging to the console only.".to_string());
+                return None;
+            };
+            rolling_file_appender(&path)
+                .inspect_err(|e| warnings.push(format!("Cannot log to {}: {}, logging to the console only.", path.display(), e)))
+                .ok()
+        });
 
     // Build the logging configuration
-    Config::builder()
-        .appender(Appender::builder().build("stdout", Box::new(stdout)))
-        .appender(Appender::builder().build("file", Box::new(file)))
-        .build(
-            Root::builder()
-                .appender("stdout")
-                .appender("file")
-                .build(log::LevelFilter::Info),
-        )
-        .unwrap()
+    let mut builder = Config::builder().appender(Appender::builder().build("stdout", Box::new(stdout)));
+    let mut root = Root::builder().appender("stdout");
+    if let Some(file) = file {
+        builder = builder.appender(Appender::builder().build("file", Box::new(file)));
+        root = root.appender("file");
+    }
+    let config = builder.build(root.build(log::LevelFilter::Info)).unwrap();
+    (config, warnings)
 }
 
 pub fn setup_panic_hook() {
@@ -255,13 +1591,57 @@ pub fn setup_panic_hook() {
     }));
 }
 
-pub fn convert_egui_to_hotkey(_egui_key: egui::Key, modifiers: egui::Modifiers) -> Option<HotKey> {
-    // 1. Convert egui::Modifiers -> global_hotkey::hotkey::Modifiers
+/// Whether the Windows key is held. egui only reports it on macOS (as `mac_cmd`), so on
+/// Windows it is asked from the OS; elsewhere it can't be recorded.
+pub fn win_key_down() -> bool {
+    #[cfg(target_os = "windows")]
+    {
+        use windows::Win32::UI::Input::KeyboardAndMouse::{GetAsyncKeyState, VK_LWIN, VK_RWIN};
+        // The high bit is set while the key is down
+        unsafe { GetAsyncKeyState(VK_LWIN.0 as i32) < 0 || GetAsyncKeyState(VK_RWIN.0 as i32) < 0 }
+    }
+    #[cfg(not(target_os = "windows"))]
+    false
+}
+
+/// Whether the session is locked (or on another secure desktop such as a UAC prompt).
+/// Captures taken then only show the lock screen. Only known on Windows.
+pub fn session_locked() -> bool {
+    #[cfg(target_os = "windows")]
+    {
+        use windows::Win32::System::StationsAndDesktops::{CloseDesktop, OpenInputDesktop, DESKTOP_CONTROL_FLAGS, DESKTOP_SWITCHDESKTOP};
+        // The input desktop can't be opened from a normal process while the lock screen has it
+        match unsafe { OpenInputDesktop(DESKTOP_CONTROL_FLAGS(0), false, DESKTOP_SWITCHDESKTOP) } {
+            Ok(desktop) => {
+                let _ = unsafe { CloseDesktop(desktop) };
+                false
+            }
+            Err(_) => true,
+        }
+    }
+    #[cfg(not(target_os = "windows"))]
+    false
+}
+
+/// Converts egui::Modifiers -> global_hotkey::hotkey::Modifiers.
+///
+/// `command` is not used: it is Ctrl everywhere but macOS, where it equals `mac_cmd`.
+/// Both Command (macOS) and the Windows key become META, which global-hotkey registers
+/// as Cmd and MOD_WIN respectively.
+pub fn hotkey_modifiers(modifiers: egui::Modifiers, win_key: bool) -> Modifiers {
     let mut gh_modifiers = Modifiers::empty();
 
     if modifiers.ctrl { gh_modifiers |= Modifiers::CONTROL; }
     if modifiers.shift { gh_modifiers |= Modifiers::SHIFT; }
     if modifiers.alt { gh_modifiers |= Modifiers::ALT; }
+    if modifiers.mac_cmd || win_key { gh_modifiers |= Modifiers::META; }
+
+    gh_modifiers
+}
+
+pub fn convert_egui_to_hotkey(_egui_key: egui::Key, modifiers: egui::Modifiers) -> Option<HotKey> {
+    // 1. Convert egui::Modifiers -> global_hotkey::hotkey::Modifiers
+    let gh_modifiers = hotkey_modifiers(modifiers, win_key_down());
 
     // 2. Convert egui::Key -> global_hotkey::hotkey::Code
     let gh_code = {
@@ -290,4 +1670,175 @@ pub fn convert_egui_to_hotkey(_egui_key: egui::Key, modifiers: egui::Modifiers)
     };
 
     Some(HotKey::new(Some(gh_modifiers), gh_code))
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMESTAMP: &str = "2024-05-01_12-30-00";
+
+    #[test]
+    fn fit_file_name_keeps_names_that_fit() {
+        let name = fit_file_name(Path::new("shots"), "screenshot_", TIMESTAMP, "png", 250);
+        assert_eq!(name.as_deref(), Some("screenshot_2024-05-01_12-30-00.png"));
+    }
+
+    #[test]
+    fn fit_file_name_cuts_only_the_prefix() {
+        let dir = Path::new("shots");
+        // "shots/" + "2024-05-01_12-30-00.png" = 29, leaves room for 4 prefix characters
+        let name = fit_file_name(dir, "screenshot_", TIMESTAMP, "png", 33).unwrap();
+        assert_eq!(name, "scre2024-05-01_12-30-00.png");
+        assert_eq!(path_len(&dir.join(&name)), 33);
+    }
+
+    #[test]
+    fn fit_file_name_drops_the_whole_prefix_at_the_limit() {
+        let name = fit_file_name(Path::new("shots"), "screenshot_", TIMESTAMP, "png", 29);
+        assert_eq!(name.as_deref(), Some("2024-05-01_12-30-00.png"));
+    }
+
+    #[test]
+    fn fit_file_name_gives_up_when_the_directory_is_too_long() {
+        let dir = PathBuf::from("d".repeat(240));
+        assert_eq!(fit_file_name(&dir, "screenshot_", TIMESTAMP, "png", 250), None);
+    }
+
+    #[test]
+    fn fit_file_name_counts_utf16_units() {
+        // '𝄞' takes two UTF-16 units, so it does not fit into a budget of one
+        let name = fit_file_name(Path::new("shots"), "a𝄞b", TIMESTAMP, "png", 30).unwrap();
+        assert_eq!(name, "a2024-05-01_12-30-00.png");
+    }
+
+    #[test]
+    fn sanitize_directory_trims_trailing_dots_and_spaces() {
+        let dir = sanitize_directory(Path::new("shots. /2024 ./keep.me"));
+        assert_eq!(dir, Path::new("shots").join("2024").join("keep.me"));
+    }
+
+    #[test]
+    fn sanitize_directory_keeps_relative_components() {
+        assert_eq!(sanitize_directory(Path::new("../shots")), Path::new("../shots"));
+    }
+
+    #[test]
+    fn extended_prefix_handles_drive_and_unc_paths() {
+        assert_eq!(with_extended_prefix(Path::new(r"C:\shots\a.png")), Path::new(r"\\?\C:\shots\a.png"));
+        assert_eq!(with_extended_prefix(Path::new(r"\\nas\share\a.png")), Path::new(r"\\?\UNC\nas\share\a.png"));
+        assert_eq!(with_extended_prefix(Path::new(r"\\?\C:\a.png")), Path::new(r"\\?\C:\a.png"));
+    }
+
+    fn egui_modifiers(ctrl: bool, shift: bool, alt: bool, mac_cmd: bool) -> egui::Modifiers {
+        egui::Modifiers { alt, ctrl, shift, mac_cmd, command: mac_cmd || ctrl }
+    }
+
+    #[test]
+    fn hotkey_modifiers_matrix() {
+        for bits in 0..16u8 {
+            let (ctrl, shift, alt, meta) = (bits & 1 != 0, bits & 2 != 0, bits & 4 != 0, bits & 8 != 0);
+            let mut expected = Modifiers::empty();
+            expected.set(Modifiers::CONTROL, ctrl);
+            expected.set(Modifiers::SHIFT, shift);
+            expected.set(Modifiers::ALT, alt);
+            expected.set(Modifiers::META, meta);
+
+            // META from the Command key (macOS) or from the Windows key, same result
+            assert_eq!(hotkey_modifiers(egui_modifiers(ctrl, shift, alt, meta), false), expected);
+            assert_eq!(hotkey_modifiers(egui_modifiers(ctrl, shift, alt, false), meta), expected);
+        }
+    }
+
+    #[test]
+    fn command_alone_is_not_meta() {
+        // Off macOS egui sets `command` together with ctrl
+        let modifiers = egui::Modifiers { ctrl: true, command: true, ..Default::default() };
+        assert_eq!(hotkey_modifiers(modifiers, false), Modifiers::CONTROL);
+    }
+
+    #[test]
+    fn recorded_hotkey_keeps_meta() {
+        let hotkey = convert_egui_to_hotkey(egui::Key::C, egui_modifiers(false, true, false, true)).unwrap();
+        assert_eq!(hotkey, HotKey::new(Some(Modifiers::META | Modifiers::SHIFT), Code::KeyC));
+        let meta = if cfg!(target_os = "macos") { "Cmd" } else { "Win" };
+        assert_eq!(format_hotkey(&hotkey), format!("Shift + {} + C", meta));
+    }
+
+    #[test]
+    fn increment_skips_taken_names() {
+        let dir = std::env::temp_dir().join(format!("crab-grab-collision-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir_path = dir.to_string_lossy();
+        let long_paths = LongPathStrategy::ShortenFileName;
+
+        let first = free_save_path(&dir_path, "screenshot_", TIMESTAMP, long_paths).unwrap();
+        assert_eq!(first.file_name().unwrap(), "screenshot_2024-05-01_12-30-00.png");
+        std::fs::write(&first, b"taken").unwrap();
+        let second = free_save_path(&dir_path, "screenshot_", TIMESTAMP, long_paths).unwrap();
+        assert_eq!(second.file_name().unwrap(), "screenshot_2024-05-01_12-30-00_1.png");
+        std::fs::write(&second, b"taken").unwrap();
+        let third = free_save_path(&dir_path, "screenshot_", TIMESTAMP, long_paths).unwrap();
+        assert_eq!(third.file_name().unwrap(), "screenshot_2024-05-01_12-30-00_2.png");
+    }
+
+    #[test]
+    fn image_fingerprints_tell_size_and_content_apart() {
+        let bytes = vec![0u8; 200 * 100 * 4];
+        let mut changed = bytes.clone();
+        changed[FINGERPRINT_PIXEL_STEP * 4] = 255;
+        assert_eq!(image_fingerprint(200, 100, &bytes), image_fingerprint(200, 100, &bytes.clone()));
+        assert_ne!(image_fingerprint(200, 100, &bytes), image_fingerprint(100, 200, &bytes));
+        assert_ne!(image_fingerprint(200, 100, &bytes), image_fingerprint(200, 100, &changed));
+    }
+
+    #[test]
+    fn only_images_above_the_limit_are_compressed_for_the_clipboard() {
+        let mut config = AppConfig { clipboard_jpeg_quality: 0, ..AppConfig::default() };
+        assert_eq!(clipboard_jpeg_quality(&config, 100 * 1024 * 1024), None);
+
+        config.clipboard_compress_above_kb = Some(1024);
+        assert_eq!(clipboard_jpeg_quality(&config, 1024 * 1024), None);
+        // Quality 0 would be rejected by the encoder
+        assert_eq!(clipboard_jpeg_quality(&config, 1024 * 1024 + 1), Some(1));
+    }
+
+    #[test]
+    fn restored_windows_are_kept_on_the_monitor_holding_most_of_them() {
+        use crate::capture::{synthetic_monitors, MonitorRect};
+        let monitors = synthetic_monitors(&[
+            MonitorRect { x: -2400, y: 0, width: 2400, height: 1600, scale_factor: 1.5, name: None },
+            MonitorRect { x: 0, y: 0, width: 1920, height: 1080, scale_factor: 1.0, name: None },
+        ]);
+        let rect = |x: f32, y: f32, w: f32, h: f32| egui::Rect::from_min_size(egui::pos2(x, y), egui::vec2(w, h));
+
+        assert_eq!(fit_onto_monitor(rect(100.0, 100.0, 800.0, 600.0), &monitors), Some(rect(100.0, 100.0, 800.0, 600.0)));
+        // Only the top-left corner is on a monitor
+        assert_eq!(fit_onto_monitor(rect(1919.0, 1079.0, 800.0, 600.0), &monitors), None);
+        // Over the right edge, mostly on the monitor: pulled back onto it
+        assert_eq!(fit_onto_monitor(rect(1500.0, 100.0, 800.0, 600.0), &monitors), Some(rect(1120.0, 100.0, 800.0, 600.0)));
+        // Across two monitors with different scales: onto the one with the larger part
+        assert_eq!(fit_onto_monitor(rect(-300.0, 100.0, 800.0, 600.0), &monitors), Some(rect(0.0, 100.0, 800.0, 600.0)));
+        // Larger than the monitor it is on
+        assert_eq!(fit_onto_monitor(rect(0.0, 0.0, 2000.0, 1000.0), &monitors), Some(rect(0.0, 0.0, 1920.0, 1000.0)));
+    }
+
+    #[test]
+    fn thumbnails_keep_the_aspect_ratio_and_never_grow() {
+        let wide = RgbaImage::new(1000, 250);
+        assert_eq!(make_thumbnail(&wide, 256).dimensions(), (256, 64));
+        let tall = RgbaImage::new(10, 4000);
+        assert_eq!(make_thumbnail(&tall, 256).dimensions(), (1, 256));
+        let small = RgbaImage::new(100, 50);
+        assert_eq!(make_thumbnail(&small, 256).dimensions(), (100, 50));
+    }
+
+    #[test]
+    fn secure_delete_removes_the_file() {
+        let path = std::env::temp_dir().join(format!("crab-grab-secure-delete-{}.bin", std::process::id()));
+        std::fs::write(&path, vec![0xAB; 10_000]).unwrap();
+        secure_delete(&path).unwrap();
+        assert!(!path.exists());
+    }
+}